@@ -0,0 +1,67 @@
+//! Pushing into a full history, the common case once a series has run for a
+//! while: the `SampleBuffer` evicting from the front against the `Vec` it
+//! replaced, which shifted every sample with `remove(0)`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use pressure_monitor::buffer::{Retention, SampleBuffer};
+
+/// The default `[data] length`, and a long history
+const CAPACITIES: [usize; 2] = [1_000, 100_000];
+
+fn push_full(c: &mut Criterion) {
+    let mut group = c.benchmark_group("push_full");
+    for capacity in CAPACITIES {
+        group.bench_with_input(
+            BenchmarkId::new("sample_buffer", capacity),
+            &capacity,
+            |b, &capacity| {
+                let mut buffer = SampleBuffer::new(Retention::Count(capacity), capacity);
+                for i in 0..capacity {
+                    buffer.push(i as f64, 0.0);
+                }
+                let mut t = capacity as f64;
+                b.iter(|| {
+                    buffer.push(black_box(t), black_box(101_325.0));
+                    t += 1.0;
+                });
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("vec", capacity),
+            &capacity,
+            |b, &capacity| {
+                let mut samples: Vec<(f64, f64)> = (0..capacity).map(|i| (i as f64, 0.0)).collect();
+                let mut t = capacity as f64;
+                b.iter(|| {
+                    if samples.len() >= capacity {
+                        samples.remove(0);
+                    }
+                    samples.push((black_box(t), black_box(101_325.0)));
+                    t += 1.0;
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+fn push_window(c: &mut Criterion) {
+    // 10 samples a second over the window, one evicted per push
+    let window = 120.0;
+    let interval = 0.1;
+    let capacity = (window / interval) as usize;
+    c.bench_function("push_window", |b| {
+        let mut buffer = SampleBuffer::new(Retention::Window(window), capacity);
+        for i in 0..capacity {
+            buffer.push(i as f64 * interval, 0.0);
+        }
+        let mut t = capacity as f64 * interval;
+        b.iter(|| {
+            buffer.push(black_box(t), black_box(101_325.0));
+            t += interval;
+        });
+    });
+}
+
+criterion_group!(benches, push_full, push_window);
+criterion_main!(benches);
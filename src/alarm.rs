@@ -0,0 +1,210 @@
+//! High/low threshold alarms with hysteresis.
+
+use crate::config::AlarmConfig;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+use std::io::{self, Write};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmState {
+    Normal,
+    High,
+    Low,
+}
+
+impl AlarmState {
+    pub fn label(self) -> &'static str {
+        match self {
+            AlarmState::Normal => "NORMAL",
+            AlarmState::High => "HIGH",
+            AlarmState::Low => "LOW",
+        }
+    }
+}
+
+/// Alarm state of one signal. An alarm is raised once the value crosses a
+/// threshold and only cleared once it is back by more than the hysteresis,
+/// so a noisy signal sitting on the threshold doesn't retrigger.
+#[derive(Debug, Clone)]
+pub struct Alarm {
+    high: Option<f64>,
+    low: Option<f64>,
+    hysteresis: f64,
+    state: AlarmState,
+}
+
+impl Alarm {
+    pub fn new(config: &AlarmConfig) -> Alarm {
+        Alarm::with_thresholds(config.high, config.low, config.hysteresis)
+    }
+
+    pub fn with_thresholds(high: Option<f64>, low: Option<f64>, hysteresis: f64) -> Alarm {
+        Alarm {
+            high,
+            low,
+            hysteresis: hysteresis.abs(),
+            state: AlarmState::Normal,
+        }
+    }
+
+    pub fn state(&self) -> AlarmState {
+        self.state
+    }
+
+    /// New thresholds, in effect from the next value on. The state is kept
+    /// so a raised alarm isn't reported again.
+    pub fn set_thresholds(&mut self, high: Option<f64>, low: Option<f64>) {
+        self.high = high;
+        self.low = low;
+    }
+
+    /// The threshold of an alarm state, `None` for `Normal`.
+    pub fn threshold(&self, state: AlarmState) -> Option<f64> {
+        match state {
+            AlarmState::Normal => None,
+            AlarmState::High => self.high,
+            AlarmState::Low => self.low,
+        }
+    }
+
+    /// The threshold of the last change, from `previous`: the one crossed,
+    /// or the one of the alarm cleared.
+    pub fn crossed(&self, previous: AlarmState) -> Option<f64> {
+        match self.state {
+            AlarmState::Normal => self.threshold(previous),
+            state => self.threshold(state),
+        }
+    }
+
+    /// Returns the new state when it changed.
+    pub fn update(&mut self, value: f64) -> Option<AlarmState> {
+        let high = |margin: f64| self.high.is_some_and(|t| value > t - margin);
+        let low = |margin: f64| self.low.is_some_and(|t| value < t + margin);
+
+        let next = match self.state {
+            AlarmState::High if high(self.hysteresis) => AlarmState::High,
+            AlarmState::Low if low(self.hysteresis) => AlarmState::Low,
+            _ if high(0.0) => AlarmState::High,
+            _ if low(0.0) => AlarmState::Low,
+            _ => AlarmState::Normal,
+        };
+
+        if next == self.state {
+            None
+        } else {
+            self.state = next;
+            Some(next)
+        }
+    }
+}
+
+/// An alarm raised or cleared, as sent to notifiers.
+#[derive(Debug, Clone)]
+pub struct AlarmEvent {
+    pub topic: String,
+    /// `Normal` once cleared
+    pub state: AlarmState,
+    pub value: f64,
+    /// Of the value and threshold, Pa or Pa/s
+    pub unit: &'static str,
+    /// Crossed by the value, or of the alarm cleared
+    pub threshold: Option<f64>,
+    pub ts: SystemTime,
+}
+
+impl AlarmEvent {
+    /// One line for humans, e.g. `Alarm HIGH on pressure/data: 251000 Pa`.
+    pub fn summary(&self) -> String {
+        match self.state {
+            AlarmState::Normal => format!(
+                "Alarm cleared on {}: {} {}",
+                self.topic, self.value, self.unit
+            ),
+            state => format!(
+                "Alarm {} on {}: {} {}",
+                state.label(),
+                self.topic,
+                self.value,
+                self.unit
+            ),
+        }
+    }
+
+    /// As sent to webhooks and republished over MQTT. `text` is what chat
+    /// services show, other receivers use the fields.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "text": self.summary(),
+            "sensor": self.topic,
+            "state": self.state.label(),
+            "value": self.value,
+            "threshold": self.threshold,
+            "unit": self.unit,
+            "timestamp": DateTime::<Utc>::from(self.ts).to_rfc3339_opts(SecondsFormat::Millis, true),
+        })
+    }
+}
+
+/// Rings the terminal bell.
+pub fn beep() {
+    print!("\x07");
+    io::stdout().flush().ok();
+}
+
+/// Pops up a desktop notification without waiting for it, a no-op unless
+/// built with the `desktop-notify` feature.
+pub fn notify(summary: &str, body: &str) {
+    #[cfg(feature = "desktop-notify")]
+    {
+        let (summary, body) = (summary.to_string(), body.to_string());
+        std::thread::spawn(move || {
+            let shown = notify_rust::Notification::new()
+                .appname("Pressure Monitor")
+                .summary(&summary)
+                .body(&body)
+                .show();
+            if let Err(e) = shown {
+                tracing::warn!("Cannot show notification: {}", e);
+            }
+        });
+    }
+    #[cfg(not(feature = "desktop-notify"))]
+    let _ = (summary, body);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hysteresis() {
+        let mut alarm = Alarm::with_thresholds(Some(100.0), Some(10.0), 5.0);
+        assert_eq!(alarm.update(50.0), None);
+        assert_eq!(alarm.update(101.0), Some(AlarmState::High));
+        // Within the hysteresis the alarm stays
+        assert_eq!(alarm.update(99.0), None);
+        assert_eq!(alarm.update(96.0), None);
+        assert_eq!(alarm.state(), AlarmState::High);
+        assert_eq!(alarm.update(94.0), Some(AlarmState::Normal));
+
+        assert_eq!(alarm.update(9.0), Some(AlarmState::Low));
+        assert_eq!(alarm.update(14.0), None);
+        assert_eq!(alarm.update(16.0), Some(AlarmState::Normal));
+    }
+
+    #[test]
+    fn straight_across() {
+        let mut alarm = Alarm::with_thresholds(Some(100.0), Some(10.0), 5.0);
+        assert_eq!(alarm.update(120.0), Some(AlarmState::High));
+        assert_eq!(alarm.update(0.0), Some(AlarmState::Low));
+    }
+
+    #[test]
+    fn without_thresholds() {
+        let mut alarm = Alarm::with_thresholds(None, None, 5.0);
+        assert_eq!(alarm.update(f64::MAX), None);
+        assert_eq!(alarm.update(f64::MIN), None);
+        assert_eq!(alarm.state(), AlarmState::Normal);
+    }
+}
@@ -0,0 +1,146 @@
+//! Bounded sample history of one series.
+//!
+//! Samples are `(t, value)` with `t` in seconds on the `Clock` time line.
+
+use crate::config::DataConfig;
+use std::collections::vec_deque::{self, VecDeque};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Retention {
+    /// Keep this many samples
+    Count(usize),
+    /// Keep the samples up to this many seconds older than the newest one
+    Window(f64),
+}
+
+impl Retention {
+    pub fn new(config: &DataConfig) -> Retention {
+        match config.window {
+            Some(window) => Retention::Window(window.max(0.0)),
+            None => Retention::Count(config.length.max(1)),
+        }
+    }
+}
+
+/// Samples oldest first, evicted from the front in O(1) as new ones come in.
+#[derive(Debug, Clone)]
+pub struct SampleBuffer {
+    samples: VecDeque<(f64, f64)>,
+    retention: Retention,
+}
+
+impl SampleBuffer {
+    /// Room for `capacity` samples is allocated up front, a count retention
+    /// never grows beyond it.
+    pub fn new(retention: Retention, capacity: usize) -> SampleBuffer {
+        let capacity = match retention {
+            Retention::Count(count) => count,
+            Retention::Window(_) => capacity,
+        };
+        SampleBuffer {
+            samples: VecDeque::with_capacity(capacity),
+            retention,
+        }
+    }
+
+    pub fn push(&mut self, t: f64, value: f64) {
+        match self.retention {
+            Retention::Count(count) => {
+                while self.samples.len() >= count {
+                    self.samples.pop_front();
+                }
+                self.samples.push_back((t, value));
+            }
+            Retention::Window(window) => {
+                self.samples.push_back((t, value));
+                while self.samples.front().is_some_and(|s| s.0 < t - window) {
+                    self.samples.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Evicts what the new retention doesn't keep right away.
+    pub fn set_retention(&mut self, retention: Retention) {
+        self.retention = retention;
+        let excess = match retention {
+            Retention::Count(count) => self.samples.len().saturating_sub(count),
+            Retention::Window(window) => match self.samples.back() {
+                Some(&(newest, _)) => self
+                    .samples
+                    .iter()
+                    .take_while(|s| s.0 < newest - window)
+                    .count(),
+                None => 0,
+            },
+        };
+        self.samples.drain(..excess);
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn first(&self) -> Option<&(f64, f64)> {
+        self.samples.front()
+    }
+
+    pub fn last(&self) -> Option<&(f64, f64)> {
+        self.samples.back()
+    }
+
+    pub fn iter(&self) -> vec_deque::Iter<'_, (f64, f64)> {
+        self.samples.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a SampleBuffer {
+    type Item = &'a (f64, f64);
+    type IntoIter = vec_deque::Iter<'a, (f64, f64)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.samples.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn times(buffer: &SampleBuffer) -> Vec<f64> {
+        buffer.iter().map(|&(t, _)| t).collect()
+    }
+
+    #[test]
+    fn count_evicts_the_oldest() {
+        let mut buffer = SampleBuffer::new(Retention::Count(3), 0);
+        for t in 0..5 {
+            buffer.push(t as f64, 0.0);
+        }
+        assert_eq!(times(&buffer), [2.0, 3.0, 4.0]);
+        assert_eq!(buffer.first(), Some(&(2.0, 0.0)));
+        assert_eq!(buffer.last(), Some(&(4.0, 0.0)));
+    }
+
+    #[test]
+    fn window_keeps_the_samples_within_it() {
+        let mut buffer = SampleBuffer::new(Retention::Window(2.0), 8);
+        for t in 0..6 {
+            buffer.push(t as f64, 0.0);
+        }
+        assert_eq!(times(&buffer), [3.0, 4.0, 5.0]);
+    }
+    #[test]
+    fn new_retention_evicts_right_away() {
+        let mut buffer = SampleBuffer::new(Retention::Count(10), 0);
+        for t in 0..10 {
+            buffer.push(t as f64, 0.0);
+        }
+        buffer.set_retention(Retention::Count(4));
+        assert_eq!(times(&buffer), [6.0, 7.0, 8.0, 9.0]);
+        buffer.set_retention(Retention::Window(1.5));
+        assert_eq!(times(&buffer), [8.0, 9.0]);
+        buffer.clear();
+        assert_eq!(buffer.first(), None);
+    }
+}
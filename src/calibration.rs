@@ -0,0 +1,164 @@
+//! Two point calibration of a sensor against reference pressures.
+//!
+//! The decoder applies it to every reading of the topics it is configured
+//! for, see the `[payload.calibration]` and `[topics]` tables of the config
+//! file.
+
+use crate::config;
+use serde::Deserialize;
+use std::error::Error;
+use std::path::Path;
+use toml_edit::{value, Item};
+
+/// `reading * scale + offset`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Calibration {
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+}
+
+impl Calibration {
+    /// The line through two `(reading, reference)` points.
+    pub fn two_point(
+        (reading1, reference1): (f64, f64),
+        (reading2, reference2): (f64, f64),
+    ) -> Result<Calibration, String> {
+        let scale = (reference2 - reference1) / (reading2 - reading1);
+        let offset = reference1 - scale * reading1;
+        if !scale.is_finite() || !offset.is_finite() {
+            return Err(format!(
+                "Cannot calibrate with readings {} and {}, they must differ",
+                reading1, reading2
+            ));
+        }
+        Ok(Calibration { scale, offset })
+    }
+
+    pub fn apply(self, reading: f64) -> f64 {
+        reading * self.scale + self.offset
+    }
+
+    /// This calibration followed by `next`, for refining one with readings
+    /// it was already applied to.
+    pub fn then(self, next: Calibration) -> Calibration {
+        Calibration {
+            scale: self.scale * next.scale,
+            offset: self.offset * next.scale + next.offset,
+        }
+    }
+}
+
+/// Stores the calibration of `topic` in the config file at `path`, created
+/// when missing. Comments and the rest of the file stay as they are.
+///
+/// A `[topics]` table of the topic gets it, that overrides
+/// `[payload.calibration]` when the config is loaded.
+pub fn save(path: &Path, topic: &str, calibration: Calibration) -> Result<(), Box<dyn Error>> {
+    config::edit(path, |doc| {
+        let in_topics = doc
+            .get("topics")
+            .and_then(|topics| topics.get(topic))
+            .is_some_and(Item::is_table_like);
+        if in_topics {
+            // Inline tables too
+            let entry = &mut doc["topics"][topic];
+            entry["scale"] = value(calibration.scale);
+            entry["offset"] = value(calibration.offset);
+            return Ok(());
+        }
+        let payload = config::child(doc, "payload")?;
+        let entry = config::child(config::child(payload, "calibration")?, topic)?;
+        entry.insert("scale", value(calibration.scale));
+        entry.insert("offset", value(calibration.offset));
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// A config file of its own per test, they run in parallel.
+    fn config_file(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "pressure_monitor_{}_{}.toml",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn two_points() {
+        let calibration = Calibration::two_point((0.0, 100.0), (10.0, 200.0)).unwrap();
+        assert_eq!(
+            calibration,
+            Calibration {
+                scale: 10.0,
+                offset: 100.0
+            }
+        );
+        assert_eq!(calibration.apply(5.0), 150.0);
+        assert!(Calibration::two_point((1.0, 100.0), (1.0, 200.0)).is_err());
+    }
+
+    #[test]
+    fn then_composes() {
+        let first = Calibration {
+            scale: 2.0,
+            offset: 1.0,
+        };
+        let next = Calibration {
+            scale: 0.5,
+            offset: -3.0,
+        };
+        let both = first.then(next);
+        for reading in [-10.0, 0.0, 4.0, 1000.0] {
+            assert_eq!(both.apply(reading), next.apply(first.apply(reading)));
+        }
+    }
+
+    #[test]
+    fn saves_to_payload_calibration() {
+        let path = config_file("calibration_payload", "# kept\n");
+        let calibration = Calibration {
+            scale: 1.5,
+            offset: -20.0,
+        };
+        save(&path, "lab/adc", calibration).unwrap();
+        let config = Config::load(&path).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert!(text.contains("# kept"));
+        assert_eq!(
+            config.payload.calibration.get("lab/adc"),
+            Some(&calibration)
+        );
+    }
+    #[test]
+    fn saves_to_topics_when_configured_there() {
+        let path = config_file(
+            "calibration_topics",
+            "[topics.\"lab/adc\"]\nscale = 2.0\nunit = \"kpa\"\n",
+        );
+        let calibration = Calibration {
+            scale: 1.5,
+            offset: -20.0,
+        };
+        save(&path, "lab/adc", calibration).unwrap();
+        // `[topics]` overrides `[payload.calibration]` when loading
+        let config = Config::load(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(
+            config.payload.calibration.get("lab/adc"),
+            Some(&calibration)
+        );
+        assert!(config.topics["lab/adc"].unit.is_some());
+    }
+}
@@ -0,0 +1,49 @@
+//! Monotonic time line for the chart.
+//!
+//! Samples are placed at seconds since startup measured with `Instant`, so
+//! NTP stepping the system clock mid-run can't reorder or squash the trace.
+//! Wall clock time is only derived from it for axis labels.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Clock {
+    start: Instant,
+    /// Wall clock time at `start`
+    wall: SystemTime,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock {
+            start: Instant::now(),
+            wall: SystemTime::now(),
+        }
+    }
+}
+
+impl Clock {
+    /// Seconds since startup.
+    pub fn now(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+
+    /// A wall clock time, e.g. from the sensor or the database, on the time
+    /// line. Negative before startup.
+    pub fn offset(&self, ts: SystemTime) -> f64 {
+        match ts.duration_since(self.wall) {
+            Ok(after) => after.as_secs_f64(),
+            Err(e) => -e.duration().as_secs_f64(),
+        }
+    }
+
+    /// Wall clock time of a point on the time line.
+    pub fn wall(&self, t: f64) -> SystemTime {
+        let offset = Duration::from_secs_f64(t.abs());
+        if t >= 0.0 {
+            self.wall + offset
+        } else {
+            self.wall.checked_sub(offset).unwrap_or(UNIX_EPOCH)
+        }
+    }
+}
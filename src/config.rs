@@ -0,0 +1,1896 @@
+//! Optional TOML configuration.
+//!
+//! Every section and key is optional, anything missing falls back to the
+//! built-in defaults. Command line options take precedence over the file.
+//!
+//! ```toml
+//! source = "mqtt"                # or "serial", "serial:/dev/ttyUSB0", "sim", "http",
+//!                                # "udp", "udp:0.0.0.0:9999", "websocket", "wss://gw.lan",
+//!                                # "modbus", "modbus:192.168.1.50:502", "i2c"
+//!
+//! [mqtt]
+//! broker = "raspberrypi.local"   # or "mqtts://broker.lan" for TLS, or
+//!                                # "wss://broker.example.com/mqtt" for
+//!                                # MQTT over WebSockets
+//! port = 1883                    # defaults to 8883 for mqtts://, 80 for
+//!                                # ws:// and 443 for wss://
+//! topics = ["pressure/data"]   # wildcards such as "pressure/+/data" work
+//! client_id = "pressure_data_receiver"
+//! username = "monitor"           # password via MQTT_PASSWORD or --mqtt-pass
+//! version = "3.1.1"              # or "5", with the mqtt5 feature: honors
+//!                                # message expiry, takes a "unit" user
+//!                                # property as the unit of the payload
+//! topic_aliases = 16             # MQTT 5 topic aliases the broker may use
+//! qos = 0                        # 0, 1 or 2, per topic in [mqtt.topic_qos]
+//! retained = "accept"            # the value a broker kept from before:
+//!                                # "ignore" it, or "mark" it on the chart
+//!                                # with an open circle
+//!
+//! [mqtt.topic_qos]
+//! "pressure/critical" = 1        # entries of topics
+//!
+//! [mqtt.tls]
+//! ca = "ca.pem"                  # bundled Mozilla roots when omitted
+//! cert = "client.pem"            # cert and key enable mutual TLS
+//! key = "client.key"
+//! verify_hostname = true
+//!
+//! [serial]
+//! port = "/dev/ttyUSB0"
+//! baud = 9600
+//! framing = "line"               # text lines, or "frame" for binary
+//! frame_len = 4                  # bytes per frame, decoded as [payload]
+//! topic = "serial"               # series name
+//!
+//! [sim]                           # synthetic data for demos
+//! topic = "sim"
+//! rate = 20.0                    # samples per second
+//! offset = 101325.0              # Pa
+//! amplitude = 500.0              # Pa, of the sine
+//! period = 10.0                  # seconds
+//! noise = 50.0                   # Pa, standard deviation
+//! step_size = 2000.0             # Pa
+//! step_interval = 30.0           # seconds between steps on average, 0 for none
+//!
+//! [http]                          # polls a gauge's JSON endpoint
+//! url = "http://gauge.lan/api/reading"
+//! interval = 1.0                 # seconds between requests
+//! timeout = 5.0                  # seconds per request
+//! pointer = "/data/pressure"     # JSON pointer to the value, in Pa
+//! timestamp_pointer = "/data/ts" # seconds since the epoch, arrival time when omitted
+//! topic = "http"                 # series name
+//!
+//! [http.headers]                  # sent with every request
+//! Authorization = "Bearer ..."
+//!
+//! [udp]                           # datagrams in the [payload] formats
+//! listen = "0.0.0.0:9999"
+//! topic = "udp"                  # series name
+//! per_sender = false             # a series per sender, e.g. "udp/192.168.1.20"
+//!
+//! [websocket]                     # text or binary messages in the [payload] formats
+//! url = "wss://gateway.lan/stream"   # or ws://
+//! topic = "websocket"            # series name
+//! subscribe = '{"subscribe": "pressure"}'   # sent after connecting, if the gateway wants one
+//!
+//! [websocket.tls]                 # for wss://, as [mqtt.tls]
+//! ca = "ca.pem"
+//!
+//! [modbus]                        # Modbus TCP transmitter
+//! address = "192.168.1.50:502"
+//! unit_id = 1
+//! register = 0                   # zero based address
+//! kind = "holding"               # or "input"
+//! data_type = "u16"              # "i16", "u32", "i32" or "f32"
+//! word_order = "big"             # of 32 bit values, "little" for low word first
+//! scale = 1.0                    # to Pa, value = raw * scale + offset
+//! offset = 0.0
+//! interval = 1.0                 # seconds between reads
+//! topic = "modbus"               # series name
+//!
+//! [i2c]                           # BMP280 or BME280, needs the `i2c` feature
+//! bus = 1                        # /dev/i2c-1 on a Raspberry Pi
+//! address = 0x76                 # 0x77 with SDO high
+//! rate = 10.0                    # samples per second
+//! topic = "i2c"                  # series name
+//!
+//! [[sources]]                     # several at once, replaces `source`
+//! name = "bench-a"               # series are "bench-a/<topic>"
+//! source = "mqtt"                # as `source`
+//!
+//! [sources.mqtt]                 # sections of this source, the ones above when omitted
+//! broker = "pi-a.local"
+//!
+//! [[sources]]
+//! name = "bench-b"
+//! source = "serial:/dev/ttyUSB1"
+//!
+//! [watchdog]
+//! timeout = 10.0                 # seconds without data until flagged stale
+//! beep = false
+//! notify = false
+//!
+//! [payload]
+//! format = "auto"                # "i32le", "i32be", "f32le", "f32be", "f64le",
+//!                                # "f64be", "i16scaled", "seqf32", "json", "cbor",
+//!                                # "msgpack", "protobuf", "sparkplug" or "auto",
+//!                                # i16scaled and seqf32 as in [payload.topics]
+//! value_field = "pressure"       # JSON, CBOR, MessagePack and protobuf, a dotted path
+//!                                # such as "data.0.p", numbers index arrays
+//! timestamp_field = "ts"         # likewise, seconds since the epoch
+//! sequence_field = "seq"         # likewise, message counter for loss detection
+//!
+//! [payload.topics]               # formats per topic, wildcards allowed
+//! "lab/+/f32" = "f32be"
+//! "lab/adc" = { i16scaled = { scale = 2.5, offset = -1000.0, big_endian = true } }
+//! "lab/node" = { seqf32 = { big_endian = true } }   # 16 bit counter, then f32
+//! "spBv1.0/#" = "sparkplug"      # Sparkplug B, MQTT only
+//!
+//! [payload.protobuf]              # for "protobuf", needs the protobuf feature
+//! descriptor = "firmware.desc"   # protoc --include_imports -o firmware.desc ...
+//! message = "sensor.Reading"     # full name of the payload message
+//!
+//! [payload.sparkplug.metrics]     # metric name to series, every numeric metric
+//! "Pressure/Inlet" = "inlet"     # as <edge node>[/<device>]/<metric> when empty
+//!
+//! [payload.units]                # unit of the payload values, Pa when omitted
+//! "lab/gauge" = "kpa"
+//!
+//! [payload.calibration."pressure/data"]   # per topic filter, see `calibrate`
+//! scale = 1.002                  # reading * scale + offset, in Pa
+//! offset = -35.0
+//!
+//! [payload.sanity]                # rejects corrupted readings, after calibration
+//! min = -100000.0                # Pa, bounds off when omitted, NaN and
+//! max = 1000000.0                # infinities are always rejected
+//! spike_window = 9               # recent readings the median is taken of, at least
+//!                                # 3, 0 for off
+//! spike_threshold = 10.0         # spikes are this many deviations off the median
+//! min_deviation = 100.0          # Pa, closer to the median is never a spike
+//! log = true                     # warn of every rejected reading
+//!
+//! [alarm]                         # thresholds in Pa, off when omitted
+//! high = 250000.0
+//! low = 50000.0
+//! hysteresis = 1000.0            # how far back a value must go to clear
+//! beep = false
+//! notify = false                 # desktop notification, see `desktop-notify`
+//! snapshot = false               # save a PNG when an alarm is raised
+//!
+//! [rate]                          # dP/dt, off unless shown or alarmed on
+//! window = 1.0                   # seconds the slope is fitted over
+//! show = true                    # a series on the secondary axis
+//! low = -500.0                   # alarm when falling faster, in Pa/s
+//! high = 1000.0                  # or rising faster, like [alarm] otherwise
+//! hysteresis = 50.0
+//!
+//! # Keep these outside the subscribed topics, or the monitor reads its own output
+//! [publish]                       # to the [mqtt] broker, off when omitted
+//! alarm_topic = "pressure/alarms"        # alarm events as JSON
+//! stats_topic = "pressure/stats/1m"      # min/max/mean per sensor as JSON
+//! stats_interval = 60.0          # seconds
+//! status_topic = "pressure_monitor/status"  # of the monitor itself: retained
+//! online = "online"              # on connecting, and on exiting or going
+//! offline = "offline"            # down, as the last will
+//!
+//! [publish.home_assistant]        # MQTT discovery, every series becomes a
+//! discovery = false              # pressure sensor and an alarm binary_sensor
+//! prefix = "homeassistant"
+//! node_id = "pressure_monitor"   # of the device, and the state topics under it
+//! state_interval = 1.0           # seconds between the values published
+//!
+//! [webhook]                       # POST alarms as JSON, off without urls
+//! urls = ["https://hooks.slack.com/services/..."]
+//! timeout = 10.0                 # seconds per request
+//! retries = 3
+//!
+//! [filter]                        # smoothed curve drawn over the raw data
+//! show = true                    # key `f`
+//!
+//! [[filter.stages]]              # applied in order, none by default
+//! kind = "sma"                   # moving average over `window` samples
+//! window = 10
+//!
+//! [[filter.stages]]
+//! kind = "median"                # median of an odd `window`, removes spikes
+//! window = 5
+//!
+//! [[filter.stages]]
+//! kind = "ema"                   # exponential, smaller `alpha` is smoother
+//! alpha = 0.2
+//!
+//! [[filter.stages]]
+//! kind = "double_ema"            # also smooths the trend, `beta`, follows ramps
+//! alpha = 0.2
+//! beta = 0.1
+//!
+//! [[filter.stages]]
+//! kind = "kalman"                # variances in Pa² of the change per sample and
+//! process_noise = 1.0            # of the measurement noise, smoother the smaller
+//! measurement_noise = 100.0      # their ratio
+//!
+//! [leak]                          # pressure decay test, key `k` starts and stops
+//! model = "linear"               # or "exponential", decay towards 0 Pa
+//! duration = 300.0               # seconds, stops by itself when given
+//! volume = 2.5                   # litres, adds the volumetric leak rate
+//! report_dir = "reports"         # where the reports go, default "."
+//!
+//! [trend]                         # line fitted to the visible samples, key `e`
+//! show = false
+//! projection = 3600.0            # seconds it is extended past them, dashed, with
+//!                                # the time left to the alarm thresholds
+//!
+//! [reference_curve]               # golden curve from the last marker on, key `m`,
+//! file = "golden.csv"            # of seconds, pressure and optional tolerance columns
+//! unit = "kpa"                   # of the pressures and tolerances in the file
+//! tolerance = 5.0                # ± of the rows without one, 0 detects nothing
+//! topic = "lab/tank"             # series compared, wildcards allowed, all when omitted
+//!
+//! [sequence]                      # pass/fail test steps, key `g` starts and aborts
+//! name = "Burst test"
+//! topic = "pressure/data"        # sensor tested, the first pressure when omitted
+//! operator = "J. Smith"          # named in the sign-off of the report
+//! report_dir = "reports"         # HTML reports with the chart, default "."
+//!
+//! [[sequence.steps]]             # in order, pressures in Pa
+//! kind = "confirm"               # until Enter is pressed
+//! prompt = "Connect the part and close the vent"
+//!
+//! [[sequence.steps]]
+//! kind = "reach"                 # until within min..max, fails after timeout
+//! prompt = "Pressurize to 200 kPa"
+//! min = 200000.0
+//! timeout = 120.0
+//!
+//! [[sequence.steps]]
+//! kind = "hold"                  # within min..max for duration seconds
+//! prompt = "Hold at 200 kPa"
+//! min = 200000.0
+//! duration = 60.0
+//!
+//! [[sequence.steps]]
+//! kind = "decay"                 # falls by at most max_drop over duration
+//! prompt = "Close the supply"
+//! max_drop = 1000.0
+//! duration = 120.0
+//!
+//! [session]                       # recordings for audits, key `w` starts and stops
+//! dir = "sessions"               # a directory per session in here
+//! operator = "J. Smith"
+//! record = false                 # start one right away, as --record
+//! parquet = false                # also write data.parquet, needs the parquet feature
+//!
+//! [session.sensors]              # IDs noted in the metadata, by topic
+//! "pressure/data" = "PT-0042"
+//!
+//! [export]
+//! dir = "."                      # where `s` saves pressure_data_<time>.csv
+//! scope = "buffer"               # what `s` saves: buffer, visible or session,
+//!                                # Shift+S the visible range, Ctrl+S the session log
+//! summary_interval = 60.0        # seconds per row of the percentiles, variance and
+//!                                # peak-to-peak in <file>_summary.csv, off when omitted
+//!
+//! [log]
+//! file = "pressure_log.csv"      # append every sample, off when omitted
+//! jsonl = "pressure_log.jsonl"   # likewise as JSON Lines
+//! flush_interval = 1.0           # seconds
+//!
+//! [log.rotation]                  # of both logs, off unless daily or max_size_mb is set
+//! daily = true                   # rotated to e.g. pressure_log_2024-05-03.csv
+//! max_size_mb = 100.0            # or once larger, stamped with the start time
+//! keep = 30                      # rotated files kept, all when omitted
+//! gzip = false                   # compress rotated files to .csv.gz
+//!
+//! [history]
+//! path = "pressure_history.csv"  # recent samples kept across restarts, off when omitted
+//! hours = 24.0                   # kept and drawn again on startup
+//!
+//! [sqlite]
+//! path = "pressure.db"           # store every sample, off when omitted
+//! batch_size = 100               # samples per transaction
+//! flush_interval = 1.0           # seconds, at the latest
+//! reload_minutes = 10.0          # history drawn on startup
+//!
+//! [influx]
+//! url = "http://localhost:8086"  # InfluxDB v2, off when omitted
+//! org = "lab"
+//! bucket = "pressure"
+//! token = "..."                  # or INFLUX_TOKEN
+//! measurement = "pressure"
+//! batch_size = 500               # samples per write
+//! flush_interval = 1.0           # seconds, at the latest
+//! max_buffer = 100000            # samples kept while the server is down
+//!
+//! [influx.tags]                   # added to every sample
+//! sensor = "ps-01"
+//! location = "lab"
+//!
+//! [web]
+//! listen = "0.0.0.0:8080"        # live dashboard, off when omitted
+//!
+//! [metrics]
+//! listen = "0.0.0.0:9100"        # Prometheus /metrics, off when omitted
+//!
+//! [window]
+//! width = 1600
+//! height = 800
+//!
+//! [chart]
+//! x_range = [0.0, 120.0]
+//! y_range = [-100000.0, 2500.0]   # always in Pa
+//! unit = "pa"                    # "kpa", "bar", "psi", "mmhg", key `u`
+//! autoscale = false              # fit the Y range to the data, key `a`
+//! log_y = false                  # logarithmic Y axis for vacuum, key `y`
+//! time_axis = "relative"         # or "wall_clock", key `t`
+//! max_gap = 5.0                  # seconds between samples drawn as a gap,
+//!                                # a few sample intervals when omitted
+//! layout = "overlay"             # or "grid", a chart per topic, key `l`
+//! downsample = true              # draw at most 2 points per pixel column (LTTB)
+//! histogram_bins = 50            # of the histogram of the visible values, key `b`
+//! incremental = false            # scroll the drawn data and only draw the new
+//!                                # samples, for slow machines; full redraws
+//!                                # while zoomed, autoscaled, in a grid, on a
+//!                                # wall clock axis, with filtered curves,
+//!                                # auxiliary signals, the spectrum, the
+//!                                # histogram, a leak test, the trend or an
+//!                                # aligned reference curve
+//! # y_range, unit, autoscale, the moving average, alarm thresholds and
+//! # retention can also be set, and saved, with key `o`
+//!
+//! [[chart.references]]            # horizontal lines behind the data
+//! label = "Max working pressure"
+//! value = 250.0
+//! unit = "kpa"                   # of `value`, Pa when omitted
+//! color = [255, 128, 0]          # axis color when omitted
+//!
+//! [secondary]                     # right hand axis for other signals
+//! topics = ["pressure/temperature"]   # wildcards allowed, also subscribe to them
+//! label = "Temperature"
+//! unit = "°C"                    # values are drawn as they arrive
+//! range = [0.0, 50.0]            # used when autoscale is off
+//!
+//! [readout]                       # big live value above the chart
+//! show = false
+//! font_size = 64
+//! position = "center"            # or "left", "right"
+//!
+//! [level]                         # pressures as heights in m, off when omitted
+//! kind = "altitude"              # ISA altitude of barometric pressure, or "depth"
+//! topics = ["weather/baro"]      # wildcards allowed, all pressures when empty
+//! sea_level = 101325.0           # Pa, the QNH, for altitude
+//! density = 1000.0               # kg/m³ of the fluid, for depth
+//! surface = 0.0                  # Pa at the surface, e.g. 101325 for absolute sensors
+//! axis = true                    # on the right hand axis, unless [secondary] uses it
+//! readout = true                 # after the values of [readout]
+//!
+//! [resample]                      # samples on a fixed time grid, for the spectrum,
+//! rate = 10.0                    # filters and exports; Hz, off when omitted
+//! method = "linear"              # or "hold", the last value until the next
+//! topics = ["lab/piezo"]         # wildcards allowed, all series when empty
+//! max_gap = 1.0                  # seconds not filled in, 10 intervals when omitted
+//!
+//! [[derived]]                     # a series computed from others on each sample
+//! name = "diff"
+//! expression = "tank_a - tank_b" # of their values in Pa, see evalexpr
+//! unit = "pa"                    # of the result
+//!
+//! [derived.inputs]                # series of the variables, those not given
+//! tank_a = "lab/tank/a"          # are series names themselves
+//! tank_b = "lab/tank/b"
+//!
+//! [[differential]]                # upstream minus downstream, e.g. across a filter
+//! name = "filter/dp"
+//! upstream = "lab/filter/in"
+//! downstream = "lab/filter/out"
+//! align = "interpolate"          # or "nearest", how samples at other times are paired
+//! max_gap = 1.0                  # seconds, samples further apart are not paired
+//! high = 5000.0                  # alarm thresholds of the difference, in Pa,
+//! low = -500.0                   # like [alarm]
+//! hysteresis = 100.0
+//!
+//! [status_bar]                   # frame rate, samples/s, channel backlog
+//! show = false                   # and latency along the bottom, key F2
+//!
+//! [data]
+//! length = 1000                  # samples kept per series
+//! window = 120.0                 # or seconds kept and drawn, overrides length
+//! queue = 100000                 # samples waiting to be drawn, the oldest are dropped beyond
+//! scrollback = 24.0              # hours kept beyond those to scroll back, 0 turns it off
+//! scrollback_bucket = 1.0        # seconds of them per lowest and highest sample
+//!
+//! [colors]
+//! theme = "dark"                 # or "light", for printed reports
+//! background = [0, 0, 0]         # these override the theme's colors
+//! axis = [0, 255, 0]
+//! trace = [0, 255, 0]            # the first series
+//!
+//! [colors.series]                # colors of topics, wildcards allowed
+//! "pressure/inlet" = [255, 128, 0]
+//!
+//! [topics."lab/adc"]              # all of a topic in one place, wildcards allowed,
+//! format = { i16scaled = { scale = 2.5 } }   # over [payload.topics],
+//! scale = 1.002                  # [payload.calibration], [payload.units]
+//! offset = -35.0                 # and [colors.series]
+//! unit = "kpa"
+//! name = "Inlet"                 # series name, not for wildcards
+//! color = [255, 128, 0]
+//! ```
+
+use crate::calibration::Calibration;
+use crate::decode::{self, PayloadFormat};
+use crate::filter::FilterStage;
+use crate::leak::DecayModel;
+use crate::level::{self, LevelKind};
+use crate::sequence::Step;
+use crate::source::modbus::{DataType, RegisterKind, WordOrder};
+use crate::theme::Preset;
+use crate::units::PressureUnit;
+use plotters::style::RGBColor;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use toml_edit::{Document, Item, Table};
+
+pub const DEFAULT_PATH: &str = "pressure_monitor.toml";
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// See `source::from_spec`
+    pub source: String,
+    /// Used instead of `source` when there are any
+    pub sources: Vec<SourceConfig>,
+    pub mqtt: MqttConfig,
+    pub serial: SerialConfig,
+    pub sim: SimConfig,
+    pub http: HttpConfig,
+    pub udp: UdpConfig,
+    pub websocket: WebSocketConfig,
+    pub modbus: ModbusConfig,
+    pub i2c: I2cConfig,
+    pub payload: PayloadConfig,
+    pub alarm: AlarmConfig,
+    pub rate: RateConfig,
+    pub watchdog: WatchdogConfig,
+    pub webhook: WebhookConfig,
+    pub publish: PublishConfig,
+    pub filter: FilterConfig,
+    pub leak: LeakConfig,
+    pub trend: TrendConfig,
+    pub reference_curve: ReferenceCurveConfig,
+    pub sequence: SequenceConfig,
+    pub session: SessionConfig,
+    pub export: ExportConfig,
+    pub log: LogConfig,
+    pub history: HistoryConfig,
+    pub sqlite: SqliteConfig,
+    pub influx: InfluxConfig,
+    pub web: WebConfig,
+    pub metrics: MetricsConfig,
+    pub window: WindowConfig,
+    pub chart: ChartConfig,
+    pub secondary: SecondaryConfig,
+    pub readout: ReadoutConfig,
+    pub level: LevelConfig,
+    pub resample: ResampleConfig,
+    pub status_bar: StatusBarConfig,
+    pub data: DataConfig,
+    pub colors: ColorConfig,
+    /// By topic filter, see `TopicConfig`
+    pub topics: BTreeMap<String, TopicConfig>,
+    pub derived: Vec<DerivedConfig>,
+    pub differential: Vec<DifferentialConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            source: "mqtt".to_string(),
+            sources: Vec::new(),
+            mqtt: MqttConfig::default(),
+            serial: SerialConfig::default(),
+            sim: SimConfig::default(),
+            http: HttpConfig::default(),
+            udp: UdpConfig::default(),
+            websocket: WebSocketConfig::default(),
+            modbus: ModbusConfig::default(),
+            i2c: I2cConfig::default(),
+            payload: PayloadConfig::default(),
+            alarm: AlarmConfig::default(),
+            rate: RateConfig::default(),
+            watchdog: WatchdogConfig::default(),
+            webhook: WebhookConfig::default(),
+            publish: PublishConfig::default(),
+            filter: FilterConfig::default(),
+            leak: LeakConfig::default(),
+            trend: TrendConfig::default(),
+            reference_curve: ReferenceCurveConfig::default(),
+            sequence: SequenceConfig::default(),
+            session: SessionConfig::default(),
+            export: ExportConfig::default(),
+            log: LogConfig::default(),
+            history: HistoryConfig::default(),
+            sqlite: SqliteConfig::default(),
+            influx: InfluxConfig::default(),
+            web: WebConfig::default(),
+            metrics: MetricsConfig::default(),
+            window: WindowConfig::default(),
+            chart: ChartConfig::default(),
+            secondary: SecondaryConfig::default(),
+            readout: ReadoutConfig::default(),
+            level: LevelConfig::default(),
+            resample: ResampleConfig::default(),
+            status_bar: StatusBarConfig::default(),
+            data: DataConfig::default(),
+            colors: ColorConfig::default(),
+            topics: BTreeMap::new(),
+            derived: Vec::new(),
+            differential: Vec::new(),
+        }
+    }
+}
+
+/// The settings of one topic filter, taken over into the sections they
+/// belong to when loading, see `Config::fold_topics`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TopicConfig {
+    pub format: Option<PayloadFormat>,
+    /// Calibration, the identity for what is omitted
+    pub scale: Option<f64>,
+    pub offset: Option<f64>,
+    /// Of the payload values
+    pub unit: Option<PressureUnit>,
+    /// Of the series, instead of the topic
+    pub name: Option<String>,
+    pub color: Option<Color>,
+}
+
+/// Samples on a fixed time grid, see `resample`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResampleConfig {
+    /// Hz, off when `None`
+    pub rate: Option<f64>,
+    pub method: ResampleMethod,
+    /// Topic filters of the series resampled, all when empty
+    pub topics: Vec<String>,
+    /// Seconds, 10 grid intervals when `None`
+    pub max_gap: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResampleMethod {
+    /// Zero-order hold, the last value until the next sample
+    Hold,
+    /// Between the samples around each grid point
+    #[default]
+    Linear,
+}
+
+/// A series computed from others, see `derive`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DerivedConfig {
+    pub name: String,
+    pub expression: String,
+    /// Variable to series name
+    #[serde(default)]
+    pub inputs: BTreeMap<String, String>,
+    /// Of the result
+    #[serde(default)]
+    pub unit: PressureUnit,
+}
+
+/// The difference of two series, see `differential`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DifferentialConfig {
+    pub name: String,
+    pub upstream: String,
+    pub downstream: String,
+    #[serde(default)]
+    pub align: Alignment,
+    /// Seconds, 1 when not given
+    pub max_gap: Option<f64>,
+    /// Alarm thresholds, Pa
+    pub high: Option<f64>,
+    pub low: Option<f64>,
+    #[serde(default)]
+    pub hysteresis: f64,
+}
+
+/// How a sample is paired with the other series of a differential.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Alignment {
+    /// With its nearest sample
+    Nearest,
+    /// With the two around it, interpolated
+    #[default]
+    Interpolate,
+}
+
+/// One of several sources, see `source::from_sources`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceConfig {
+    /// Sensor name, prefixed to the topics of its series
+    pub name: String,
+    /// See `source::from_spec`
+    pub source: String,
+    pub mqtt: Option<MqttConfig>,
+    pub serial: Option<SerialConfig>,
+    pub sim: Option<SimConfig>,
+    pub http: Option<HttpConfig>,
+    pub udp: Option<UdpConfig>,
+    pub websocket: Option<WebSocketConfig>,
+    pub modbus: Option<ModbusConfig>,
+    pub i2c: Option<I2cConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    pub broker: String,
+    pub port: Option<u16>,
+    /// One series is drawn per topic a message arrives on
+    pub topics: Vec<String>,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub version: MqttVersion,
+    /// Most topic aliases the broker may send with MQTT 5, 0 for none
+    pub topic_aliases: u16,
+    /// Of the subscriptions, for those not in `topic_qos`
+    pub qos: u8,
+    pub topic_qos: BTreeMap<String, u8>,
+    pub retained: RetainedMessages,
+    pub tls: TlsConfig,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        MqttConfig {
+            broker: "raspberrypi.local".to_string(),
+            port: None,
+            topics: vec!["pressure/data".to_string()],
+            client_id: "pressure_data_receiver".to_string(),
+            username: None,
+            password: None,
+            version: MqttVersion::V311,
+            topic_aliases: 16,
+            qos: 0,
+            topic_qos: BTreeMap::new(),
+            retained: RetainedMessages::Accept,
+            tls: TlsConfig::default(),
+        }
+    }
+}
+
+/// Protocol version spoken with the broker, the publisher stays on 3.1.1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum MqttVersion {
+    #[serde(rename = "3.1.1")]
+    V311,
+    #[serde(rename = "5")]
+    V5,
+}
+
+/// What to do with retained messages, which the broker sends on subscribing
+/// however old they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetainedMessages {
+    Accept,
+    Ignore,
+    /// Accept, drawn with an open circle and flagged in the session
+    Mark,
+}
+
+/// Only used for `mqtts://` brokers and `wss://` endpoints.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM bundle of trusted CAs
+    pub ca: Option<PathBuf>,
+    /// PEM client certificate chain for mutual TLS
+    pub cert: Option<PathBuf>,
+    /// PEM (PKCS#8 or RSA) client private key for mutual TLS
+    pub key: Option<PathBuf>,
+    /// Set to false to accept certificates issued for another host name
+    pub verify_hostname: bool,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            ca: None,
+            cert: None,
+            key: None,
+            verify_hostname: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SerialConfig {
+    pub port: String,
+    pub baud: u32,
+    pub framing: Framing,
+    /// Bytes per frame with `framing = "frame"`
+    pub frame_len: usize,
+    /// Name of the series the samples are drawn as
+    pub topic: String,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        SerialConfig {
+            port: "/dev/ttyUSB0".to_string(),
+            baud: 9600,
+            framing: Framing::Line,
+            frame_len: 4,
+            topic: "serial".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Framing {
+    /// Newline terminated text
+    Line,
+    /// Fixed size binary frames
+    Frame,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimConfig {
+    pub topic: String,
+    /// Samples per second
+    pub rate: f64,
+    pub offset: f64,
+    pub amplitude: f64,
+    /// Seconds, 0 for no sine
+    pub period: f64,
+    /// Standard deviation
+    pub noise: f64,
+    pub step_size: f64,
+    /// Mean seconds between steps, 0 for none
+    pub step_interval: f64,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            topic: "sim".to_string(),
+            rate: 20.0,
+            offset: 101_325.0,
+            amplitude: 500.0,
+            period: 10.0,
+            noise: 50.0,
+            step_size: 2000.0,
+            step_interval: 30.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub url: String,
+    /// Seconds between requests
+    pub interval: f64,
+    /// Seconds per request
+    pub timeout: f64,
+    /// JSON pointer (RFC 6901) to the value in the response
+    pub pointer: String,
+    pub timestamp_pointer: Option<String>,
+    /// Name of the series the samples are drawn as
+    pub topic: String,
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            url: "http://localhost/pressure".to_string(),
+            interval: 1.0,
+            timeout: 5.0,
+            pointer: "/pressure".to_string(),
+            timestamp_pointer: None,
+            topic: "http".to_string(),
+            headers: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UdpConfig {
+    /// Address and port to bind
+    pub listen: String,
+    /// Name of the series the samples are drawn as
+    pub topic: String,
+    /// Suffix the topic with the sender's IP address
+    pub per_sender: bool,
+}
+
+impl Default for UdpConfig {
+    fn default() -> Self {
+        UdpConfig {
+            listen: "0.0.0.0:9999".to_string(),
+            topic: "udp".to_string(),
+            per_sender: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebSocketConfig {
+    /// `ws://` or `wss://`
+    pub url: String,
+    /// Name of the series the samples are drawn as
+    pub topic: String,
+    /// Text message sent after connecting
+    pub subscribe: Option<String>,
+    pub tls: TlsConfig,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        WebSocketConfig {
+            url: "ws://localhost:8080/stream".to_string(),
+            topic: "websocket".to_string(),
+            subscribe: None,
+            tls: TlsConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModbusConfig {
+    /// Host and port of the transmitter or gateway
+    pub address: String,
+    pub unit_id: u8,
+    /// Zero based address of the first register
+    pub register: u16,
+    pub kind: RegisterKind,
+    pub data_type: DataType,
+    pub word_order: WordOrder,
+    /// Pa per raw unit
+    pub scale: f64,
+    /// Pa, added after scaling
+    pub offset: f64,
+    /// Seconds between reads
+    pub interval: f64,
+    /// Name of the series the samples are drawn as
+    pub topic: String,
+}
+
+impl Default for ModbusConfig {
+    fn default() -> Self {
+        ModbusConfig {
+            address: "192.168.1.50:502".to_string(),
+            unit_id: 1,
+            register: 0,
+            kind: RegisterKind::Holding,
+            data_type: DataType::U16,
+            word_order: WordOrder::Big,
+            scale: 1.0,
+            offset: 0.0,
+            interval: 1.0,
+            topic: "modbus".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct I2cConfig {
+    pub bus: u8,
+    /// 7 bit address of the sensor
+    pub address: u16,
+    /// Samples per second
+    pub rate: f64,
+    /// Name of the series the samples are drawn as
+    pub topic: String,
+}
+
+impl Default for I2cConfig {
+    fn default() -> Self {
+        I2cConfig {
+            bus: 1,
+            address: 0x76,
+            rate: 10.0,
+            topic: "i2c".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PayloadConfig {
+    pub format: PayloadFormat,
+    /// Formats of topics that don't use `format`, keyed by topic filter
+    pub topics: BTreeMap<String, PayloadFormat>,
+    /// Applied to the decoded readings, keyed by topic filter
+    pub calibration: BTreeMap<String, Calibration>,
+    /// Of the values in the payloads, keyed by topic filter
+    pub units: BTreeMap<String, PressureUnit>,
+    pub sanity: SanityConfig,
+    pub value_field: String,
+    pub timestamp_field: String,
+    pub sequence_field: String,
+    pub sparkplug: SparkplugConfig,
+    pub protobuf: ProtobufConfig,
+}
+
+impl Default for PayloadConfig {
+    fn default() -> Self {
+        PayloadConfig {
+            format: PayloadFormat::Auto,
+            topics: BTreeMap::new(),
+            calibration: BTreeMap::new(),
+            units: BTreeMap::new(),
+            sanity: SanityConfig::default(),
+            value_field: "pressure".to_string(),
+            timestamp_field: "ts".to_string(),
+            sequence_field: "seq".to_string(),
+            sparkplug: SparkplugConfig::default(),
+            protobuf: ProtobufConfig::default(),
+        }
+    }
+}
+
+/// Of the `protobuf` payload format, see `protobuf`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProtobufConfig {
+    /// Compiled descriptor set holding the message type
+    pub descriptor: Option<PathBuf>,
+    pub message: String,
+}
+
+/// Of the `sparkplug` payload format, see `sparkplug`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SparkplugConfig {
+    /// Metric name to series, all numeric metrics are taken when empty
+    pub metrics: BTreeMap<String, String>,
+}
+
+/// Rejection of readings that can't be real, see `outlier`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SanityConfig {
+    /// Pa
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// Readings per topic the median is taken of, 0 turns spike rejection off
+    pub spike_window: usize,
+    /// Median absolute deviations, scaled to a standard deviation, that a
+    /// spike is off the median
+    pub spike_threshold: f64,
+    /// Pa, so a flat signal with no deviation doesn't reject every change
+    pub min_deviation: f64,
+    /// Warn of rejected readings, they are only counted otherwise
+    pub log: bool,
+}
+
+impl Default for SanityConfig {
+    fn default() -> Self {
+        SanityConfig {
+            min: None,
+            max: None,
+            spike_window: 0,
+            spike_threshold: 10.0,
+            min_deviation: 100.0,
+            log: true,
+        }
+    }
+}
+
+impl SanityConfig {
+    /// Rejects a window too short for a median to tell a spike.
+    fn check(&self) -> Result<(), String> {
+        if (1..3).contains(&self.spike_window) {
+            return Err(format!(
+                "payload.sanity: spike_window must be 0 or at least 3, not {}",
+                self.spike_window
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlarmConfig {
+    pub high: Option<f64>,
+    pub low: Option<f64>,
+    pub hysteresis: f64,
+    /// Ring the terminal bell when an alarm is raised
+    pub beep: bool,
+    /// Show a desktop notification when an alarm is raised
+    pub notify: bool,
+    /// Save a screenshot of the first frame showing a raised alarm
+    pub snapshot: bool,
+}
+
+/// Rate of change of the pressures. Its alarms beep, notify and snapshot as
+/// configured in `[alarm]`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateConfig {
+    /// Seconds
+    pub window: f64,
+    pub show: bool,
+    /// Pa/s
+    pub high: Option<f64>,
+    pub low: Option<f64>,
+    pub hysteresis: f64,
+}
+
+impl RateConfig {
+    /// Whether rates are computed at all.
+    pub fn enabled(&self) -> bool {
+        self.show || self.high.is_some() || self.low.is_some()
+    }
+}
+
+impl Default for RateConfig {
+    fn default() -> Self {
+        RateConfig {
+            window: 1.0,
+            show: false,
+            high: None,
+            low: None,
+            hysteresis: 0.0,
+        }
+    }
+}
+
+/// Flags series that stopped receiving data.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchdogConfig {
+    /// Seconds, the watchdog is off when omitted
+    pub timeout: Option<f64>,
+    /// Ring the terminal bell when a series goes stale
+    pub beep: bool,
+    /// Show a desktop notification when a series goes stale
+    pub notify: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// Every alarm raised or cleared is POSTed to each of them
+    pub urls: Vec<String>,
+    /// Seconds per request
+    pub timeout: f64,
+    /// Further attempts after a failed request
+    pub retries: u32,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            urls: Vec::new(),
+            timeout: 10.0,
+            retries: 3,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PublishConfig {
+    pub alarm_topic: Option<String>,
+    pub stats_topic: Option<String>,
+    /// Seconds aggregated per stats message
+    pub stats_interval: f64,
+    /// Birth and last will messages go here, retained
+    pub status_topic: Option<String>,
+    pub online: String,
+    pub offline: String,
+    pub home_assistant: HomeAssistantConfig,
+}
+
+impl Default for PublishConfig {
+    fn default() -> Self {
+        PublishConfig {
+            alarm_topic: None,
+            stats_topic: None,
+            stats_interval: 60.0,
+            status_topic: None,
+            online: "online".to_string(),
+            offline: "offline".to_string(),
+            home_assistant: HomeAssistantConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HomeAssistantConfig {
+    pub discovery: bool,
+    /// Of the discovery topics, as set up in Home Assistant
+    pub prefix: String,
+    pub node_id: String,
+    /// Seconds between the states of a sensor, the latest value wins
+    pub state_interval: f64,
+}
+
+impl Default for HomeAssistantConfig {
+    fn default() -> Self {
+        HomeAssistantConfig {
+            discovery: false,
+            prefix: "homeassistant".to_string(),
+            node_id: "pressure_monitor".to_string(),
+            state_interval: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    pub stages: Vec<FilterStage>,
+    /// Draw the filtered curve at startup
+    pub show: bool,
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        FilterConfig {
+            stages: Vec::new(),
+            show: true,
+        }
+    }
+}
+
+impl FilterConfig {
+    /// Rejects stages that would not filter as configured.
+    fn check(&self) -> Result<(), String> {
+        for (i, stage) in self.stages.iter().enumerate() {
+            if let FilterStage::Median { window } = stage {
+                if window % 2 == 0 {
+                    return Err(format!(
+                        "filter.stages[{}]: the median window must be odd, not {}",
+                        i, window
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LeakConfig {
+    pub model: DecayModel,
+    /// Seconds after which a test stops by itself
+    pub duration: Option<f64>,
+    /// Litres
+    pub volume: Option<f64>,
+    pub report_dir: PathBuf,
+}
+
+impl Default for LeakConfig {
+    fn default() -> Self {
+        LeakConfig {
+            model: DecayModel::Linear,
+            duration: None,
+            volume: None,
+            report_dir: PathBuf::from("."),
+        }
+    }
+}
+
+/// A line fitted to the samples zoomed and panned to, extrapolated.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrendConfig {
+    pub show: bool,
+    /// Seconds past the last sample fitted
+    pub projection: f64,
+}
+
+impl Default for TrendConfig {
+    fn default() -> Self {
+        TrendConfig {
+            show: false,
+            projection: 3600.0,
+        }
+    }
+}
+
+/// The qualified curve tests are compared against, see `reference`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReferenceCurveConfig {
+    /// Off when `None`
+    pub file: Option<PathBuf>,
+    /// Of the pressures and tolerances in the file
+    pub unit: PressureUnit,
+    /// Of the rows without one
+    pub tolerance: f64,
+    /// Topic filter of the series compared, all pressures when `None`
+    pub topic: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SequenceConfig {
+    pub name: String,
+    /// Topic filter of the sensor tested, the first pressure when omitted
+    pub topic: Option<String>,
+    /// Named in the sign-off of the report
+    pub operator: Option<String>,
+    pub report_dir: PathBuf,
+    pub steps: Vec<Step>,
+}
+
+impl Default for SequenceConfig {
+    fn default() -> Self {
+        SequenceConfig {
+            name: "Test sequence".to_string(),
+            topic: None,
+            operator: None,
+            report_dir: PathBuf::from("."),
+            steps: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    pub dir: PathBuf,
+    pub operator: Option<String>,
+    /// Serial numbers or tags of the sensors, by topic
+    pub sensors: BTreeMap<String, String>,
+    /// Start recording at launch
+    pub record: bool,
+    /// Also write the samples to `data.parquet`
+    pub parquet: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            dir: PathBuf::from("sessions"),
+            operator: None,
+            sensors: BTreeMap::new(),
+            record: false,
+            parquet: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportConfig {
+    /// Created when missing
+    pub dir: PathBuf,
+    pub scope: ExportScope,
+    /// Seconds, no summary is saved when `None`
+    pub summary_interval: Option<f64>,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        ExportConfig {
+            dir: PathBuf::from("."),
+            scope: ExportScope::default(),
+            summary_interval: None,
+        }
+    }
+}
+
+/// The samples saved as CSV.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportScope {
+    /// Everything still in the chart buffer
+    #[default]
+    Buffer,
+    /// The time range of the chart as zoomed and panned
+    Visible,
+    /// The CSV log of the session being recorded, or `[log] file`
+    Session,
+}
+
+impl ExportScope {
+    pub fn label(self) -> &'static str {
+        match self {
+            ExportScope::Buffer => "buffer",
+            ExportScope::Visible => "visible range",
+            ExportScope::Session => "session log",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub file: Option<PathBuf>,
+    /// JSON Lines log, `{"ts": ..., "topic": ..., "pressure_pa": ...}`
+    pub jsonl: Option<PathBuf>,
+    /// Seconds between flushes to disk
+    pub flush_interval: f64,
+    pub rotation: RotationConfig,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            file: None,
+            jsonl: None,
+            flush_interval: 1.0,
+            rotation: RotationConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RotationConfig {
+    /// Start a new file every day
+    pub daily: bool,
+    /// Megabytes, start a new file once the current one is larger
+    pub max_size_mb: Option<f64>,
+    /// Number of rotated files kept, the oldest are removed
+    pub keep: Option<usize>,
+    pub gzip: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    pub path: Option<PathBuf>,
+    pub hours: f64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            path: None,
+            hours: 24.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SqliteConfig {
+    pub path: Option<PathBuf>,
+    pub batch_size: usize,
+    /// Seconds between commits when fewer than `batch_size` samples arrive
+    pub flush_interval: f64,
+    /// Minutes of stored history loaded into the chart on startup
+    pub reload_minutes: f64,
+}
+
+impl Default for SqliteConfig {
+    fn default() -> Self {
+        SqliteConfig {
+            path: None,
+            batch_size: 100,
+            flush_interval: 1.0,
+            reload_minutes: 10.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InfluxConfig {
+    /// Server base URL
+    pub url: Option<String>,
+    pub org: String,
+    pub bucket: String,
+    pub token: Option<String>,
+    pub measurement: String,
+    pub tags: BTreeMap<String, String>,
+    pub batch_size: usize,
+    /// Seconds between writes when fewer than `batch_size` samples arrive
+    pub flush_interval: f64,
+    /// Samples buffered while writes fail, the oldest are dropped beyond
+    pub max_buffer: usize,
+}
+
+impl Default for InfluxConfig {
+    fn default() -> Self {
+        InfluxConfig {
+            url: None,
+            org: String::new(),
+            bucket: "pressure".to_string(),
+            token: None,
+            measurement: "pressure".to_string(),
+            tags: BTreeMap::new(),
+            batch_size: 500,
+            flush_interval: 1.0,
+            max_buffer: 100_000,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebConfig {
+    /// Address the dashboard listens on
+    pub listen: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Address the Prometheus endpoint listens on
+    pub listen: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            width: 1600,
+            height: 800,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChartConfig {
+    pub x_range: (f64, f64),
+    /// In Pa, used when autoscale is off or there is no data yet
+    pub y_range: (f64, f64),
+    /// Display unit
+    pub unit: PressureUnit,
+    pub autoscale: bool,
+    /// Logarithmic Y axis, only positive pressures are drawn
+    pub log_y: bool,
+    pub time_axis: TimeAxis,
+    /// Seconds, consecutive samples further apart aren't connected.
+    /// Defaults to the watchdog timeout, or is derived from the sample rate.
+    pub max_gap: Option<f64>,
+    pub layout: Layout,
+    /// Cut the drawn points down to twice the plot width
+    pub downsample: bool,
+    pub histogram_bins: usize,
+    /// Scroll the previous frame instead of redrawing it, where possible
+    pub incremental: bool,
+    pub references: Vec<ReferenceLine>,
+}
+
+impl Default for ChartConfig {
+    fn default() -> Self {
+        ChartConfig {
+            x_range: (0.0, 120.0),
+            y_range: (-100_000.0, 2_500.0),
+            unit: PressureUnit::Pa,
+            autoscale: false,
+            log_y: false,
+            time_axis: TimeAxis::Relative,
+            max_gap: None,
+            layout: Layout::Overlay,
+            downsample: true,
+            histogram_bins: 50,
+            incremental: false,
+            references: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeAxis {
+    /// Seconds since the oldest sample on screen
+    Relative,
+    /// Local time of day, HH:MM:SS
+    WallClock,
+}
+
+/// A labelled pressure marked across the chart.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReferenceLine {
+    pub label: String,
+    pub value: f64,
+    #[serde(default)]
+    pub unit: PressureUnit,
+    pub color: Option<Color>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// All series on one chart
+    Overlay,
+    /// A chart per series, side by side
+    Grid,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecondaryConfig {
+    /// Topic filters of the series drawn against the right hand axis. They
+    /// aren't pressures, so skip unit conversion and alarms.
+    pub topics: Vec<String>,
+    pub label: String,
+    pub unit: String,
+    /// Used when autoscale is off or there is no data yet
+    pub range: (f64, f64),
+}
+
+impl Default for SecondaryConfig {
+    fn default() -> Self {
+        SecondaryConfig {
+            topics: Vec::new(),
+            label: "Temperature".to_string(),
+            unit: "°C".to_string(),
+            range: (0.0, 50.0),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReadoutConfig {
+    pub show: bool,
+    /// Pixels, the chart moves down to make room
+    pub font_size: u32,
+    pub position: ReadoutPosition,
+}
+
+impl Default for ReadoutConfig {
+    fn default() -> Self {
+        ReadoutConfig {
+            show: false,
+            font_size: 64,
+            position: ReadoutPosition::Center,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadoutPosition {
+    Left,
+    Center,
+    Right,
+}
+
+/// Pressures shown as heights, see `level`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LevelConfig {
+    /// Off when `None`
+    pub kind: Option<LevelKind>,
+    /// Topic filters of the series converted, all pressures when empty
+    pub topics: Vec<String>,
+    /// Pa
+    pub sea_level: f64,
+    /// kg/m³
+    pub density: f64,
+    /// Pa
+    pub surface: f64,
+    pub axis: bool,
+    pub readout: bool,
+}
+
+impl LevelConfig {
+    /// Whether the series `topic` is converted.
+    pub fn applies(&self, topic: &str) -> bool {
+        self.kind.is_some()
+            && (self.topics.is_empty()
+                || self
+                    .topics
+                    .iter()
+                    .any(|filter| decode::topic_matches(filter, topic)))
+    }
+
+    /// Meters of `pa`, `None` when off.
+    pub fn height(&self, pa: f64) -> Option<f64> {
+        Some(match self.kind? {
+            LevelKind::Altitude => level::altitude(pa, self.sea_level),
+            LevelKind::Depth => level::depth(pa, self.density, self.surface),
+        })
+    }
+}
+
+impl Default for LevelConfig {
+    fn default() -> Self {
+        LevelConfig {
+            kind: None,
+            topics: Vec::new(),
+            sea_level: 101_325.0,
+            density: 1_000.0,
+            surface: 0.0,
+            axis: true,
+            readout: true,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatusBarConfig {
+    pub show: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DataConfig {
+    /// Number of samples kept in memory and drawn
+    pub length: usize,
+    /// Seconds of samples kept instead, whatever the sample rate
+    pub window: Option<f64>,
+    /// Samples waiting for the render loop at most
+    pub queue: usize,
+    /// Hours of decimated history to zoom and pan back through
+    pub scrollback: f64,
+    /// Seconds the history keeps only the lowest and highest sample of
+    pub scrollback_bucket: f64,
+}
+
+impl Default for DataConfig {
+    fn default() -> Self {
+        DataConfig {
+            length: 1000,
+            window: None,
+            queue: 100_000,
+            scrollback: 24.0,
+            scrollback_bucket: 1.0,
+        }
+    }
+}
+
+/// Parses a time span such as `90`, `120s`, `10m` or `1h` into seconds.
+pub fn parse_seconds(text: &str) -> Result<f64, String> {
+    let text = text.trim();
+    let (number, scale) = match text.char_indices().last() {
+        Some((i, 's')) => (&text[..i], 1.0),
+        Some((i, 'm')) => (&text[..i], 60.0),
+        Some((i, 'h')) => (&text[..i], 3600.0),
+        _ => (text, 1.0),
+    };
+
+    match number.trim().parse::<f64>() {
+        Ok(value) if value > 0.0 && value.is_finite() => Ok(value * scale),
+        _ => Err(format!(
+            "Invalid time span \"{}\", expected e.g. 120s or 10m",
+            text
+        )),
+    }
+}
+
+/// An `[r, g, b]` triple.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Color(pub u8, pub u8, pub u8);
+
+impl Color {
+    pub fn rgb(self) -> RGBColor {
+        RGBColor(self.0, self.1, self.2)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorConfig {
+    pub theme: Preset,
+    pub background: Option<Color>,
+    pub axis: Option<Color>,
+    pub trace: Option<Color>,
+    /// Color per topic filter
+    pub series: BTreeMap<String, Color>,
+}
+
+impl Default for ColorConfig {
+    fn default() -> Self {
+        ColorConfig {
+            theme: Preset::Dark,
+            background: None,
+            axis: None,
+            trace: None,
+            series: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, Box<dyn Error>> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read config {}: {}", path.display(), e))?;
+        let mut config: Config = toml::from_str(&text)
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+        config
+            .fold_topics()
+            .and_then(|()| config.check())
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+        Ok(config)
+    }
+
+    /// Rejects settings that would not work as configured, of a loaded
+    /// config or one built in code.
+    pub fn check(&self) -> Result<(), String> {
+        self.filter.check()?;
+        self.payload.sanity.check()
+    }
+
+    /// Moves the settings of `[topics]` into the sections that apply them,
+    /// overriding what those have for the same filter. Names stay, the
+    /// monitor renames the series.
+    fn fold_topics(&mut self) -> Result<(), String> {
+        for (filter, topic) in &self.topics {
+            if topic.name.is_some() && (filter.contains('+') || filter.contains('#')) {
+                return Err(format!(
+                    "topics.\"{}\": no name for a wildcard filter",
+                    filter
+                ));
+            }
+            if let Some(format) = topic.format {
+                self.payload.topics.insert(filter.clone(), format);
+            }
+            if topic.scale.is_some() || topic.offset.is_some() {
+                let calibration = Calibration {
+                    scale: topic.scale.unwrap_or(1.0),
+                    offset: topic.offset.unwrap_or(0.0),
+                };
+                self.payload.calibration.insert(filter.clone(), calibration);
+            }
+            if let Some(unit) = topic.unit {
+                self.payload.units.insert(filter.clone(), unit);
+            }
+            // Colors go by series name
+            if let Some(color) = topic.color {
+                let series = topic.name.as_ref().unwrap_or(filter);
+                self.colors.series.insert(series.clone(), color);
+            }
+        }
+        Ok(())
+    }
+
+    /// Load the file given on the command line, or the default one when it
+    /// exists in the working directory.
+    pub fn load_or_default(path: Option<&Path>) -> Result<Config, Box<dyn Error>> {
+        match path {
+            Some(path) => Config::load(path),
+            None if Path::new(DEFAULT_PATH).exists() => Config::load(Path::new(DEFAULT_PATH)),
+            None => Ok(Config::default()),
+        }
+    }
+}
+
+/// Changes the config file at `path` in place, created when missing.
+/// Comments and everything `change` leaves alone stay as they are.
+pub(crate) fn edit(
+    path: &Path,
+    change: impl FnOnce(&mut Table) -> Result<(), String>,
+) -> Result<(), Box<dyn Error>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("Cannot read config {}: {}", path.display(), e).into()),
+    };
+    let mut doc: Document = text
+        .parse()
+        .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+
+    change(doc.as_table_mut())?;
+
+    fs::write(path, doc.to_string())
+        .map_err(|e| format!("Cannot write config {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+/// The table under `key`, added when missing.
+pub(crate) fn child<'a>(parent: &'a mut Table, key: &str) -> Result<&'a mut Table, String> {
+    let mut table = Table::new();
+    // No empty `[payload]` header just for its subtables
+    table.set_implicit(true);
+    parent
+        .entry(key)
+        .or_insert(Item::Table(table))
+        .as_table_mut()
+        .ok_or_else(|| format!("Config key {} is not a table", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folded(text: &str) -> Result<Config, String> {
+        let mut config: Config = toml::from_str(text).map_err(|e| e.to_string())?;
+        config.fold_topics()?;
+        Ok(config)
+    }
+
+    #[test]
+    fn topics_fold_into_their_sections() {
+        let config = folded(
+            r#"
+            [payload.calibration."lab/adc"]
+            scale = 3.0
+
+            [topics."lab/adc"]
+            format = "f32le"
+            scale = 2.0
+            unit = "kpa"
+            name = "Inlet"
+            color = [255, 128, 0]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.payload.topics["lab/adc"], PayloadFormat::F32Le);
+        // `[topics]` wins, what is left out is the identity
+        let calibration = config.payload.calibration["lab/adc"];
+        assert_eq!((calibration.scale, calibration.offset), (2.0, 0.0));
+        assert_eq!(config.payload.units["lab/adc"], PressureUnit::KPa);
+        let color = config.colors.series["Inlet"];
+        assert_eq!((color.0, color.1, color.2), (255, 128, 0));
+        assert!(!config.colors.series.contains_key("lab/adc"));
+    }
+
+    #[test]
+    fn no_names_for_wildcards() {
+        assert!(folded("[topics.\"lab/+\"]\nname = \"Inlet\"\n").is_err());
+        assert!(folded("[topics.\"lab/+\"]\nunit = \"kpa\"\n").is_ok());
+    }
+
+    #[test]
+    fn time_spans() {
+        assert_eq!(parse_seconds("90"), Ok(90.0));
+        assert_eq!(parse_seconds("120s"), Ok(120.0));
+        assert_eq!(parse_seconds(" 10m "), Ok(600.0));
+        assert_eq!(parse_seconds("1.5h"), Ok(5400.0));
+        assert_eq!(parse_seconds("10 m"), Ok(600.0));
+        for text in ["", "m", "0", "-5s", "ten", "10d", "inf"] {
+            assert!(parse_seconds(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn spike_window_of_at_least_three() {
+        for (window, valid) in [(0, true), (1, false), (2, false), (3, true), (9, true)] {
+            let sanity = SanityConfig {
+                spike_window: window,
+                ..SanityConfig::default()
+            };
+            assert_eq!(sanity.check().is_ok(), valid, "{}", window);
+        }
+    }
+
+    #[test]
+    fn median_windows_must_be_odd() {
+        for (window, valid) in [(0, false), (3, true), (4, false), (5, true)] {
+            let config = Config {
+                filter: FilterConfig {
+                    stages: vec![FilterStage::Median { window }],
+                    ..FilterConfig::default()
+                },
+                ..Config::default()
+            };
+            assert_eq!(config.check().is_ok(), valid, "{}", window);
+        }
+    }
+}
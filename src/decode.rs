@@ -0,0 +1,492 @@
+//! Payload decoding, turns the bytes of a message into a pressure reading.
+
+use crate::calibration::Calibration;
+use crate::config::PayloadConfig;
+use crate::outlier::OutlierFilter;
+#[cfg(feature = "protobuf")]
+use crate::protobuf::Schema;
+use crate::sparkplug::Sparkplug;
+use crate::units::PressureUnit;
+use serde::Deserialize;
+use serde_json::Value;
+use std::error::Error;
+use std::iter;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadFormat {
+    /// JSON when the payload starts with `{`, `I32Le` otherwise
+    Auto,
+    /// 4 byte little endian signed integer
+    I32Le,
+    /// 4 byte big endian signed integer
+    I32Be,
+    /// 4 byte little endian float
+    F32Le,
+    /// 4 byte big endian float
+    F32Be,
+    /// 8 byte little endian float
+    F64Le,
+    /// 8 byte big endian float
+    F64Be,
+    /// 2 byte signed raw count, e.g. from an ADC, as `count * scale + offset`
+    I16Scaled {
+        scale: f64,
+        #[serde(default)]
+        offset: f64,
+        #[serde(default)]
+        big_endian: bool,
+    },
+    /// 2 byte message counter followed by a 4 byte float
+    SeqF32 {
+        #[serde(default)]
+        big_endian: bool,
+    },
+    /// An object holding the value and optionally a timestamp and counter
+    Json,
+    /// As `Json`, in CBOR
+    Cbor,
+    /// As `Json`, in MessagePack
+    #[serde(rename = "msgpack")]
+    MessagePack,
+    /// As `Json`, in the protobuf message of `[payload.protobuf]`
+    Protobuf,
+    /// Sparkplug B protobuf, a reading per metric, see `decode_all`
+    Sparkplug,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Reading {
+    pub value: f64,
+    /// Sensor side timestamp, if the payload carries one
+    pub timestamp: Option<SystemTime>,
+    /// Message counter, if the payload carries one
+    pub sequence: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Decoder {
+    format: PayloadFormat,
+    /// Topic filters with their own format, the first match in key order wins
+    topics: Vec<(String, PayloadFormat)>,
+    /// Likewise per topic filter
+    calibrations: Vec<(String, Calibration)>,
+    units: Vec<(String, PressureUnit)>,
+    outliers: OutlierFilter,
+    value_field: String,
+    timestamp_field: String,
+    sequence_field: String,
+    /// Metric aliases learned from births
+    sparkplug: Sparkplug,
+    #[cfg(feature = "protobuf")]
+    schema: Option<Schema>,
+}
+
+impl Decoder {
+    /// Fails on a protobuf descriptor that can't be read, or the protobuf
+    /// format without one.
+    pub fn new(config: &PayloadConfig) -> Result<Decoder, Box<dyn Error>> {
+        let protobuf = iter::once(&config.format)
+            .chain(config.topics.values())
+            .any(|&format| format == PayloadFormat::Protobuf);
+        if protobuf && config.protobuf.descriptor.is_none() {
+            return Err("The protobuf format needs a [payload.protobuf] descriptor".into());
+        }
+        #[cfg(feature = "protobuf")]
+        let schema = match &config.protobuf.descriptor {
+            Some(path) => Some(Schema::load(path, &config.protobuf.message)?),
+            None => None,
+        };
+        #[cfg(not(feature = "protobuf"))]
+        if config.protobuf.descriptor.is_some() {
+            return Err("Built without the protobuf feature, no protobuf payloads".into());
+        }
+
+        Ok(Decoder {
+            format: config.format,
+            topics: config
+                .topics
+                .iter()
+                .map(|(filter, format)| (filter.clone(), *format))
+                .collect(),
+            calibrations: config
+                .calibration
+                .iter()
+                .map(|(filter, calibration)| (filter.clone(), *calibration))
+                .collect(),
+            units: config
+                .units
+                .iter()
+                .map(|(filter, unit)| (filter.clone(), *unit))
+                .collect(),
+            outliers: OutlierFilter::new(&config.sanity),
+            value_field: config.value_field.clone(),
+            timestamp_field: config.timestamp_field.clone(),
+            sequence_field: config.sequence_field.clone(),
+            sparkplug: Sparkplug::new(&config.sparkplug),
+            #[cfg(feature = "protobuf")]
+            schema,
+        })
+    }
+
+    /// The format configured for `topic`, or the default one.
+    pub fn format(&self, topic: &str) -> PayloadFormat {
+        self.topics
+            .iter()
+            .find(|(filter, _)| topic_matches(filter, topic))
+            .map_or(self.format, |&(_, format)| format)
+    }
+
+    /// The unit configured for the payloads of `topic`, or Pa.
+    pub fn unit(&self, topic: &str) -> PressureUnit {
+        self.units
+            .iter()
+            .find(|(filter, _)| topic_matches(filter, topic))
+            .map_or(PressureUnit::Pa, |&(_, unit)| unit)
+    }
+
+    pub fn decode(&self, topic: &str, payload: &[u8]) -> Result<Reading, Box<dyn Error>> {
+        self.decode_in(topic, payload, self.unit(topic))
+    }
+
+    /// As `decode`, for a payload in `unit` rather than the configured one.
+    pub fn decode_in(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        unit: PressureUnit,
+    ) -> Result<Reading, Box<dyn Error>> {
+        let mut reading = self.decode_raw(topic, payload)?;
+        reading.value = self.calibrate(topic, unit.to_pa(reading.value));
+        Ok(reading)
+    }
+
+    /// The readings of a message with the series each goes to: the one of
+    /// `topic` for most formats, those of its metrics for Sparkplug B. In
+    /// `unit`, if the message gives one, in the configured one otherwise.
+    pub fn decode_all(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        unit: Option<PressureUnit>,
+    ) -> Result<Vec<(String, Reading)>, Box<dyn Error>> {
+        let unit = unit.unwrap_or_else(|| self.unit(topic));
+        if self.format(topic) != PayloadFormat::Sparkplug {
+            return Ok(vec![(
+                topic.to_string(),
+                self.decode_in(topic, payload, unit)?,
+            )]);
+        }
+        let values = self.sparkplug.decode(topic, payload)?;
+        Ok(values
+            .into_iter()
+            .map(|v| {
+                let reading = Reading {
+                    value: self.calibrate(&v.series, unit.to_pa(v.value)),
+                    timestamp: v.timestamp,
+                    sequence: None,
+                };
+                (v.series, reading)
+            })
+            .collect())
+    }
+
+    /// Applies the calibration of `topic`, for readings not decoded from a
+    /// payload.
+    pub fn calibrate(&self, topic: &str, value: f64) -> f64 {
+        self.calibrations
+            .iter()
+            .find(|(filter, _)| topic_matches(filter, topic))
+            .map_or(value, |(_, calibration)| calibration.apply(value))
+    }
+
+    /// Runs a decoded, or otherwise parsed, reading through the sanity
+    /// filter, `Err` with the reason when it is rejected.
+    pub fn check(&mut self, topic: &str, value: f64) -> Result<(), String> {
+        self.outliers.check(topic, value)
+    }
+
+    /// Whether readings rejected by `check` are to be logged.
+    pub fn log_rejected(&self) -> bool {
+        self.outliers.log()
+    }
+
+    fn decode_raw(&self, topic: &str, payload: &[u8]) -> Result<Reading, Box<dyn Error>> {
+        let value = match self.format(topic) {
+            PayloadFormat::Auto => {
+                if payload.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{') {
+                    return self.decode_json(payload);
+                }
+                i32::from_le_bytes(bytes(payload)?) as f64
+            }
+            PayloadFormat::I32Le => i32::from_le_bytes(bytes(payload)?) as f64,
+            PayloadFormat::I32Be => i32::from_be_bytes(bytes(payload)?) as f64,
+            PayloadFormat::F32Le => f32::from_le_bytes(bytes(payload)?) as f64,
+            PayloadFormat::F32Be => f32::from_be_bytes(bytes(payload)?) as f64,
+            PayloadFormat::F64Le => f64::from_le_bytes(bytes(payload)?),
+            PayloadFormat::F64Be => f64::from_be_bytes(bytes(payload)?),
+            PayloadFormat::I16Scaled {
+                scale,
+                offset,
+                big_endian,
+            } => {
+                let raw = bytes(payload)?;
+                let count = if big_endian {
+                    i16::from_be_bytes(raw)
+                } else {
+                    i16::from_le_bytes(raw)
+                };
+                count as f64 * scale + offset
+            }
+            PayloadFormat::SeqF32 { big_endian } => {
+                let raw: [u8; 6] = bytes(payload)?;
+                let (counter, value) = ([raw[0], raw[1]], [raw[2], raw[3], raw[4], raw[5]]);
+                let (sequence, value) = if big_endian {
+                    (u16::from_be_bytes(counter), f32::from_be_bytes(value))
+                } else {
+                    (u16::from_le_bytes(counter), f32::from_le_bytes(value))
+                };
+                return Ok(Reading {
+                    value: value as f64,
+                    timestamp: None,
+                    sequence: Some(sequence as u64),
+                });
+            }
+            PayloadFormat::Json => return self.decode_json(payload),
+            PayloadFormat::Cbor => {
+                let object = ciborium::de::from_reader(payload)
+                    .map_err(|e| format!("Bad CBOR payload: {}", e))?;
+                return self.decode_object(&object);
+            }
+            PayloadFormat::MessagePack => {
+                let object = rmp_serde::from_slice(payload)
+                    .map_err(|e| format!("Bad MessagePack payload: {}", e))?;
+                return self.decode_object(&object);
+            }
+            #[cfg(feature = "protobuf")]
+            PayloadFormat::Protobuf => {
+                let schema = self.schema.as_ref().ok_or("No protobuf descriptor")?;
+                return schema.decode(
+                    payload,
+                    &self.value_field,
+                    &self.timestamp_field,
+                    &self.sequence_field,
+                );
+            }
+            #[cfg(not(feature = "protobuf"))]
+            PayloadFormat::Protobuf => {
+                return Err("Built without the protobuf feature, no protobuf payloads".into())
+            }
+            PayloadFormat::Sparkplug => {
+                return Err("Sparkplug B payloads are only read from MQTT".into())
+            }
+        };
+
+        Ok(Reading {
+            value,
+            timestamp: None,
+            sequence: None,
+        })
+    }
+
+    fn decode_json(&self, payload: &[u8]) -> Result<Reading, Box<dyn Error>> {
+        self.decode_object(&serde_json::from_slice(payload)?)
+    }
+
+    /// Reads the configured fields of a JSON, CBOR or MessagePack payload.
+    fn decode_object(&self, json: &Value) -> Result<Reading, Box<dyn Error>> {
+        let value = field(json, &self.value_field)
+            .and_then(Value::as_f64)
+            .ok_or_else(|| format!("No numeric \"{}\" field in payload", self.value_field))?;
+
+        let timestamp = field(json, &self.timestamp_field)
+            .and_then(Value::as_f64)
+            .and_then(epoch_time);
+        let sequence = field(json, &self.sequence_field).and_then(Value::as_u64);
+
+        Ok(Reading {
+            value,
+            timestamp,
+            sequence,
+        })
+    }
+}
+
+/// Seconds since the epoch, possibly fractional. Values too large to be
+/// seconds are taken as milliseconds.
+pub(crate) fn epoch_time(ts: f64) -> Option<SystemTime> {
+    if !ts.is_finite() || ts < 0.0 {
+        return None;
+    }
+    let ts = if ts > 1e11 { ts / 1000.0 } else { ts };
+    Some(UNIX_EPOCH + Duration::from_secs_f64(ts))
+}
+
+/// The payload as exactly `N` bytes.
+fn bytes<const N: usize>(payload: &[u8]) -> Result<[u8; N], Box<dyn Error>> {
+    payload
+        .try_into()
+        .map_err(|_| format!("Expected a {} byte payload, got {} bytes", N, payload.len()).into())
+}
+
+/// MQTT style filter match, `+` matches one level and `#` all below.
+pub(crate) fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut levels = topic.split('/');
+    for part in filter.split('/') {
+        match (part, levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (part, Some(level)) if part == level => {}
+            _ => return false,
+        }
+    }
+    levels.next().is_none()
+}
+
+/// Looks up a dotted path such as `data.pressure`, or `data.0` for the
+/// first element of an array.
+fn field<'a>(json: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(json, |value, key| match value {
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => value.get(key),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoder(format: PayloadFormat) -> Decoder {
+        Decoder::new(&PayloadConfig {
+            format,
+            ..PayloadConfig::default()
+        })
+        .unwrap()
+    }
+
+    fn value(format: PayloadFormat, payload: &[u8]) -> f64 {
+        decoder(format)
+            .decode("pressure/data", payload)
+            .unwrap()
+            .value
+    }
+
+    #[test]
+    fn topic_filters() {
+        assert!(topic_matches("pressure/data", "pressure/data"));
+        assert!(!topic_matches("pressure/data", "pressure/other"));
+        assert!(topic_matches("pressure/+", "pressure/data"));
+        assert!(!topic_matches("pressure/+", "pressure/data/raw"));
+        assert!(!topic_matches("pressure/+", "pressure"));
+        assert!(topic_matches("pressure/#", "pressure/data/raw"));
+        assert!(topic_matches("#", "pressure/data"));
+        assert!(topic_matches("+/data", "inlet/data"));
+        assert!(!topic_matches("pressure/data/raw", "pressure/data"));
+    }
+
+    #[test]
+    fn binary_formats() {
+        assert_eq!(
+            value(PayloadFormat::I32Le, &(-1200_i32).to_le_bytes()),
+            -1200.0
+        );
+        assert_eq!(value(PayloadFormat::I32Be, &1200_i32.to_be_bytes()), 1200.0);
+        assert_eq!(value(PayloadFormat::F32Le, &2.5_f32.to_le_bytes()), 2.5);
+        assert_eq!(value(PayloadFormat::F32Be, &2.5_f32.to_be_bytes()), 2.5);
+        assert_eq!(
+            value(PayloadFormat::F64Be, &101_325.0_f64.to_be_bytes()),
+            101_325.0
+        );
+        let short = decoder(PayloadFormat::F64Le).decode("pressure/data", &[0; 4]);
+        assert!(short.is_err());
+    }
+
+    #[test]
+    fn scaled_counts() {
+        let little = PayloadFormat::I16Scaled {
+            scale: 2.5,
+            offset: -100.0,
+            big_endian: false,
+        };
+        assert_eq!(value(little, &(-40_i16).to_le_bytes()), -200.0);
+        let big = PayloadFormat::I16Scaled {
+            scale: 0.5,
+            offset: 0.0,
+            big_endian: true,
+        };
+        assert_eq!(value(big, &1000_i16.to_be_bytes()), 500.0);
+    }
+
+    #[test]
+    fn formats_by_topic() {
+        let mut config = PayloadConfig::default();
+        config
+            .topics
+            .insert("lab/+".to_string(), PayloadFormat::F32Le);
+        let decoder = Decoder::new(&config).unwrap();
+        assert_eq!(decoder.format("lab/adc"), PayloadFormat::F32Le);
+        assert_eq!(decoder.format("pressure/data"), PayloadFormat::Auto);
+    }
+
+    #[test]
+    fn auto_tells_json_from_binary() {
+        assert_eq!(value(PayloadFormat::Auto, br#" {"pressure": 12.5}"#), 12.5);
+        assert_eq!(value(PayloadFormat::Auto, &7_i32.to_le_bytes()), 7.0);
+    }
+
+    #[test]
+    fn counter_and_float() {
+        let mut payload = 513_u16.to_le_bytes().to_vec();
+        payload.extend(2.5_f32.to_le_bytes());
+        let reading = decoder(PayloadFormat::SeqF32 { big_endian: false })
+            .decode("pressure/data", &payload)
+            .unwrap();
+        assert_eq!((reading.value, reading.sequence), (2.5, Some(513)));
+
+        let mut payload = 513_u16.to_be_bytes().to_vec();
+        payload.extend(2.5_f32.to_be_bytes());
+        let reading = decoder(PayloadFormat::SeqF32 { big_endian: true })
+            .decode("pressure/data", &payload)
+            .unwrap();
+        assert_eq!((reading.value, reading.sequence), (2.5, Some(513)));
+    }
+
+    #[test]
+    fn json_counter() {
+        let reading = decoder(PayloadFormat::Json)
+            .decode("pressure/data", br#"{"pressure": 1.0, "seq": 42}"#)
+            .unwrap();
+        assert_eq!(reading.sequence, Some(42));
+    }
+
+    #[test]
+    fn cbor_and_messagepack() {
+        let object = serde_json::json!({"pressure": 101.5, "ts": 1_700_000_000, "seq": 7});
+        let mut cbor = Vec::new();
+        ciborium::ser::into_writer(&object, &mut cbor).unwrap();
+        let reading = decoder(PayloadFormat::Cbor)
+            .decode("pressure/data", &cbor)
+            .unwrap();
+        assert_eq!((reading.value, reading.sequence), (101.5, Some(7)));
+        assert!(reading.timestamp.is_some());
+
+        let msgpack = rmp_serde::to_vec(&object).unwrap();
+        let reading = decoder(PayloadFormat::MessagePack)
+            .decode("pressure/data", &msgpack)
+            .unwrap();
+        assert_eq!((reading.value, reading.sequence), (101.5, Some(7)));
+
+        let json = decoder(PayloadFormat::Cbor).decode("pressure/data", br#"{"pressure": 1}"#);
+        assert!(json.is_err());
+    }
+
+    #[test]
+    fn protobuf_needs_a_descriptor() {
+        let mut config = PayloadConfig::default();
+        config
+            .topics
+            .insert("pressure/pb".to_string(), PayloadFormat::Protobuf);
+        assert!(Decoder::new(&config).is_err());
+    }
+}
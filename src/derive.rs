@@ -0,0 +1,175 @@
+//! Derived series, computed from the latest values of others with an
+//! expression such as `tank_a - tank_b`, each time one of them gets a
+//! sample. They are recorded, alarmed on and drawn like any other series.
+//!
+//! The values given to the expression are in Pa, the result is in the
+//! `unit` of the derived series.
+
+use crate::config::DerivedConfig;
+use crate::units::PressureUnit;
+use evalexpr::{ContextWithMutableVariables, HashMapContext, Node};
+use std::collections::HashMap;
+use std::error::Error;
+use tracing::warn;
+
+struct Derived {
+    name: String,
+    expression: Node,
+    /// Variable of the expression and the series whose value it is
+    inputs: Vec<(String, String)>,
+    unit: PressureUnit,
+    /// Whether the last evaluation failed, to warn once per failure
+    failed: bool,
+}
+
+pub struct Derive {
+    derived: Vec<Derived>,
+    /// Latest value of every input, in Pa
+    latest: HashMap<String, f64>,
+}
+
+impl Derive {
+    pub fn new(configs: &[DerivedConfig]) -> Result<Derive, Box<dyn Error>> {
+        let mut derived = Vec::new();
+        for config in configs {
+            let expression = evalexpr::build_operator_tree(&config.expression)
+                .map_err(|e| format!("Derived series {}: {}", config.name, e))?;
+            // Variables not mapped are series names themselves
+            let mut inputs: Vec<(String, String)> = expression
+                .iter_variable_identifiers()
+                .map(|variable| {
+                    let series = config.inputs.get(variable).map_or(variable, String::as_str);
+                    (variable.to_string(), series.to_string())
+                })
+                .collect();
+            inputs.sort();
+            inputs.dedup();
+            derived.push(Derived {
+                name: config.name.clone(),
+                expression,
+                inputs,
+                unit: config.unit,
+                failed: false,
+            });
+        }
+
+        // Each derived value would set off the next one otherwise
+        for d in &derived {
+            if let Some((_, series)) = d
+                .inputs
+                .iter()
+                .find(|(_, series)| derived.iter().any(|other| other.name == *series))
+            {
+                return Err(format!(
+                    "Derived series {} takes the derived series {}, \
+                     only received ones can be inputs",
+                    d.name, series
+                )
+                .into());
+            }
+        }
+        Ok(Derive {
+            derived,
+            latest: HashMap::new(),
+        })
+    }
+
+    /// A sample of `series`, in Pa: the values, in Pa, of the derived series
+    /// taking it, once all their inputs have a value.
+    pub fn update(&mut self, series: &str, value: f64) -> Vec<(String, f64)> {
+        if !self
+            .derived
+            .iter()
+            .any(|d| d.inputs.iter().any(|(_, s)| s == series))
+        {
+            return Vec::new();
+        }
+        self.latest.insert(series.to_string(), value);
+
+        let mut values = Vec::new();
+        for d in &mut self.derived {
+            if !d.inputs.iter().any(|(_, s)| s == series) {
+                continue;
+            }
+            let mut context = HashMapContext::new();
+            let mut complete = true;
+            for (variable, input) in &d.inputs {
+                match self.latest.get(input) {
+                    Some(&value) => {
+                        context.set_value(variable.clone(), value.into()).ok();
+                    }
+                    None => complete = false,
+                }
+            }
+            if !complete {
+                continue;
+            }
+
+            match d.expression.eval_number_with_context(&context) {
+                Ok(result) => {
+                    d.failed = false;
+                    values.push((d.name.clone(), d.unit.to_pa(result)));
+                }
+                Err(e) if !d.failed => {
+                    d.failed = true;
+                    warn!("Cannot derive {}: {}", d.name, e);
+                }
+                Err(_) => {}
+            }
+        }
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn derived(name: &str, expression: &str, inputs: &[(&str, &str)]) -> DerivedConfig {
+        DerivedConfig {
+            name: name.to_string(),
+            expression: expression.to_string(),
+            inputs: inputs
+                .iter()
+                .map(|&(variable, series)| (variable.to_string(), series.to_string()))
+                .collect(),
+            unit: PressureUnit::Pa,
+        }
+    }
+
+    #[test]
+    fn once_all_inputs_have_a_value() {
+        let mut derive = Derive::new(&[derived("drop", "a - b", &[])]).unwrap();
+        assert!(derive.update("a", 300.0).is_empty());
+        assert!(derive.update("other", 1.0).is_empty());
+        assert_eq!(derive.update("b", 100.0), [("drop".to_string(), 200.0)]);
+        assert_eq!(derive.update("a", 150.0), [("drop".to_string(), 50.0)]);
+    }
+
+    #[test]
+    fn mapped_inputs_and_units() {
+        let mut config = derived("ratio", "up / down", &[("up", "inlet"), ("down", "outlet")]);
+        config.unit = PressureUnit::KPa;
+        let mut derive = Derive::new(&[config]).unwrap();
+        assert!(derive.update("up", 4.0).is_empty());
+        derive.update("inlet", 4.0);
+        assert_eq!(
+            derive.update("outlet", 2.0),
+            [("ratio".to_string(), 2000.0)]
+        );
+    }
+
+    #[test]
+    fn invalid_series() {
+        assert!(Derive::new(&[derived("bad", "a -", &[])]).is_err());
+        let chained = [
+            derived("first", "a + 1", &[]),
+            derived("second", "first * 2", &[]),
+        ];
+        assert!(Derive::new(&chained).is_err());
+
+        // Fails to evaluate, and so gives no value
+        let mut derive = Derive::new(&[derived("text", "a + \"x\"", &[])]).unwrap();
+        assert!(derive.update("a", 1.0).is_empty());
+    }
+}
@@ -0,0 +1,216 @@
+//! Differential pressure, an upstream series minus a downstream one, e.g.
+//! across a filter. The samples of the two rarely arrive at the same time,
+//! so each is paired with the other series at its own time: with the
+//! nearest sample, or the two around it interpolated.
+//!
+//! Interpolated differences of a sample wait for the next sample of the
+//! other series, so they lag by up to one sample interval.
+
+use crate::config::{Alignment, DifferentialConfig};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Samples of one side, as seconds since the epoch and Pa.
+type History = VecDeque<(f64, f64)>;
+
+struct Pair {
+    name: String,
+    upstream: String,
+    downstream: String,
+    align: Alignment,
+    /// Seconds, samples further apart are not paired
+    max_gap: f64,
+    sides: [History; 2],
+}
+
+pub struct Differential {
+    pairs: Vec<Pair>,
+}
+
+impl Differential {
+    pub fn new(configs: &[DifferentialConfig]) -> Result<Differential, Box<dyn Error>> {
+        for config in configs {
+            if let Some(input) = [&config.upstream, &config.downstream]
+                .into_iter()
+                .find(|input| configs.iter().any(|other| other.name == **input))
+            {
+                return Err(format!(
+                    "Differential {} takes the differential {}, \
+                     only received series can be inputs",
+                    config.name, input
+                )
+                .into());
+            }
+            if config.upstream == config.downstream {
+                return Err(
+                    format!("Differential {} of {} itself", config.name, config.upstream).into(),
+                );
+            }
+        }
+        let pairs = configs
+            .iter()
+            .map(|config| Pair {
+                name: config.name.clone(),
+                upstream: config.upstream.clone(),
+                downstream: config.downstream.clone(),
+                align: config.align,
+                max_gap: config.max_gap.unwrap_or(1.0).max(0.0),
+                sides: [History::new(), History::new()],
+            })
+            .collect();
+        Ok(Differential { pairs })
+    }
+
+    /// A sample of `series` in Pa at `timestamp`: the differences, in Pa and
+    /// with their times, that it completes, oldest first.
+    pub fn update(
+        &mut self,
+        series: &str,
+        timestamp: SystemTime,
+        value: f64,
+    ) -> Vec<(String, SystemTime, f64)> {
+        let t = timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let mut differences = Vec::new();
+        for pair in &mut self.pairs {
+            let side = if series == pair.upstream {
+                0
+            } else if series == pair.downstream {
+                1
+            } else {
+                continue;
+            };
+            for (t, difference) in pair.update(side, t, value) {
+                let at = UNIX_EPOCH + Duration::from_secs_f64(t.max(0.0));
+                differences.push((pair.name.clone(), at, difference));
+            }
+        }
+        differences
+    }
+}
+
+impl Pair {
+    /// Upstream minus downstream for a sample of `side`, 0 upstream.
+    fn update(&mut self, side: usize, t: f64, value: f64) -> Vec<(f64, f64)> {
+        let sign = if side == 0 { 1.0 } else { -1.0 };
+        let [this, other] = if side == 0 {
+            [&self.sides[0], &self.sides[1]]
+        } else {
+            [&self.sides[1], &self.sides[0]]
+        };
+
+        let mut differences = Vec::new();
+        match self.align {
+            Alignment::Nearest => {
+                let nearest = other
+                    .iter()
+                    .min_by(|a, b| (a.0 - t).abs().total_cmp(&(b.0 - t).abs()))
+                    .filter(|(at, _)| (at - t).abs() <= self.max_gap);
+                if let Some(&(_, v)) = nearest {
+                    differences.push((t, sign * (value - v)));
+                }
+            }
+            Alignment::Interpolate => {
+                // Samples of the other side since the last one of this side,
+                // which this one completes
+                if let Some(&previous) = this.back().filter(|(at, _)| t - at <= self.max_gap) {
+                    for &(at, v) in other.iter().filter(|(at, _)| previous.0 < *at && *at < t) {
+                        let interpolated = interpolate(previous, (t, value), at);
+                        differences.push((at, sign * (interpolated - v)));
+                    }
+                }
+                let before = other.iter().rev().find(|(at, _)| *at <= t);
+                let after = other.iter().find(|(at, _)| *at > t);
+                match (before, after) {
+                    (Some(&(at, v)), _) if at == t => differences.push((t, sign * (value - v))),
+                    (Some(&a), Some(&b)) if b.0 - a.0 <= self.max_gap => {
+                        differences.push((t, sign * (value - interpolate(a, b, t))));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        self.sides[side].push_back((t, value));
+        // The last of each side stays, the next sample is interpolated from it
+        for history in &mut self.sides {
+            while history.len() > 1 && history.front().is_some_and(|(at, _)| t - at > self.max_gap)
+            {
+                history.pop_front();
+            }
+        }
+        differences
+    }
+}
+
+/// The value at `t` on the line through `a` and `b`.
+fn interpolate(a: (f64, f64), b: (f64, f64), t: f64) -> f64 {
+    if b.0 == a.0 {
+        return b.1;
+    }
+    a.1 + (b.1 - a.1) * (t - a.0) / (b.0 - a.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(align: Alignment, max_gap: f64) -> DifferentialConfig {
+        DifferentialConfig {
+            name: "filter".to_string(),
+            upstream: "inlet".to_string(),
+            downstream: "outlet".to_string(),
+            align,
+            max_gap: Some(max_gap),
+            high: None,
+            low: None,
+            hysteresis: 0.0,
+        }
+    }
+
+    fn at(secs: f64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs_f64(secs)
+    }
+
+    fn differences(differences: Vec<(String, SystemTime, f64)>) -> Vec<(SystemTime, f64)> {
+        differences.into_iter().map(|(_, t, d)| (t, d)).collect()
+    }
+
+    #[test]
+    fn nearest() {
+        let mut differential = Differential::new(&[config(Alignment::Nearest, 1.0)]).unwrap();
+        assert!(differential.update("inlet", at(10.0), 1000.0).is_empty());
+        assert!(differential.update("other", at(10.2), 0.0).is_empty());
+        let paired = differential.update("outlet", at(10.5), 400.0);
+        assert_eq!(differences(paired), [(at(10.5), 600.0)]);
+        // Too far from the last inlet sample
+        assert!(differential.update("outlet", at(12.0), 300.0).is_empty());
+    }
+
+    #[test]
+    fn interpolated() {
+        let mut differential = Differential::new(&[config(Alignment::Interpolate, 2.0)]).unwrap();
+        differential.update("inlet", at(10.0), 1000.0);
+        // Waits for the next inlet sample
+        assert!(differential.update("outlet", at(11.0), 500.0).is_empty());
+        let completed = differential.update("inlet", at(12.0), 1200.0);
+        assert_eq!(differences(completed), [(at(11.0), 600.0)]);
+        let same_time = differential.update("outlet", at(12.0), 700.0);
+        assert_eq!(differences(same_time), [(at(12.0), 500.0)]);
+    }
+
+    #[test]
+    fn received_inputs_only() {
+        let mut itself = config(Alignment::Nearest, 1.0);
+        itself.downstream = "inlet".to_string();
+        assert!(Differential::new(&[itself]).is_err());
+
+        let mut chained = config(Alignment::Nearest, 1.0);
+        chained.name = "across".to_string();
+        chained.upstream = "filter".to_string();
+        assert!(Differential::new(&[config(Alignment::Nearest, 1.0), chained]).is_err());
+    }
+}
@@ -0,0 +1,188 @@
+//! Home Assistant MQTT discovery: every series is announced as a pressure
+//! sensor and its alarm as a binary_sensor of one device, so they show up in
+//! Home Assistant without any YAML. Announced on the first sample of a
+//! series and again after every reconnect, as the broker may have lost the
+//! retained configs.
+//!
+//! A series `pressure/data` of the default node id is announced on
+//! `homeassistant/sensor/pressure_monitor/pressure_data/config`, its values
+//! go to `pressure_monitor/pressure_data/state` and its alarm to
+//! `pressure_monitor/pressure_data/alarm`, `ON` or `OFF`.
+
+use crate::alarm::{AlarmEvent, AlarmState};
+use crate::config::HomeAssistantConfig;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// One message for the publisher to send.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub topic: String,
+    pub payload: String,
+    pub retain: bool,
+}
+
+#[derive(Debug)]
+pub struct Discovery {
+    config: HomeAssistantConfig,
+    /// Status topic with its online and offline messages, the availability
+    /// of every entity
+    availability: Option<(String, String, String)>,
+    interval: Duration,
+    announced: HashSet<String>,
+    /// When the state of a series was published last
+    published: HashMap<String, Instant>,
+    /// Of the alarms raised, by topic
+    raised: HashSet<String>,
+}
+
+impl Discovery {
+    pub fn new(
+        config: &HomeAssistantConfig,
+        availability: Option<(String, String, String)>,
+    ) -> Discovery {
+        Discovery {
+            config: config.clone(),
+            availability,
+            interval: Duration::from_secs_f64(config.state_interval.max(0.0)),
+            announced: HashSet::new(),
+            published: HashMap::new(),
+            raised: HashSet::new(),
+        }
+    }
+
+    /// Announces everything again with the next samples.
+    pub fn reconnected(&mut self) {
+        self.announced.clear();
+        self.published.clear();
+    }
+
+    /// A sample of `series` in Pa: its announcement the first time, and its
+    /// state once per interval.
+    pub fn sample(&mut self, series: &str, value: f64) -> Vec<Message> {
+        let mut messages = self.announce(series, true);
+        let due = self
+            .published
+            .get(series)
+            .is_none_or(|at| at.elapsed() >= self.interval);
+        if due {
+            self.published.insert(series.to_string(), Instant::now());
+            messages.push(Message {
+                topic: self.state_topic(series, "state"),
+                payload: value.to_string(),
+                retain: false,
+            });
+        }
+        messages
+    }
+
+    /// The alarm of `event` raised or cleared. Rate alarms are announced on
+    /// their first event, without a pressure sensor.
+    pub fn alarm(&mut self, event: &AlarmEvent) -> Vec<Message> {
+        if event.state == AlarmState::Normal {
+            self.raised.remove(&event.topic);
+        } else {
+            self.raised.insert(event.topic.clone());
+        }
+        let mut messages = self.announce(&event.topic, false);
+        // The announcement carries the state already
+        if messages.is_empty() {
+            messages.push(self.alarm_state(&event.topic));
+        }
+        messages
+    }
+
+    /// The configs of `series`, if a `sensor`, and of its alarm, and the
+    /// alarm state, unless announced already.
+    fn announce(&mut self, series: &str, sensor: bool) -> Vec<Message> {
+        if !self.announced.insert(series.to_string()) {
+            return Vec::new();
+        }
+        let object = object_id(series);
+        let node = &self.config.node_id;
+
+        let mut pressure = json!({
+            "name": series,
+            "unique_id": format!("{}_{}", node, object),
+            "state_topic": self.state_topic(series, "state"),
+            "unit_of_measurement": "Pa",
+            "device_class": "pressure",
+            "state_class": "measurement",
+            "device": self.device(),
+        });
+        let mut binary_sensor = json!({
+            "name": format!("{} alarm", series),
+            "unique_id": format!("{}_{}_alarm", node, object),
+            "state_topic": self.state_topic(series, "alarm"),
+            "device_class": "problem",
+            "payload_on": "ON",
+            "payload_off": "OFF",
+            "device": self.device(),
+        });
+        if let Some((topic, online, offline)) = &self.availability {
+            for entity in [&mut pressure, &mut binary_sensor] {
+                entity["availability_topic"] = json!(topic);
+                entity["payload_available"] = json!(online);
+                entity["payload_not_available"] = json!(offline);
+            }
+        }
+
+        let mut messages = Vec::new();
+        if sensor {
+            messages.push(self.config_message("sensor", &object, pressure));
+        }
+        let alarm = format!("{}_alarm", object);
+        messages.push(self.config_message("binary_sensor", &alarm, binary_sensor));
+        messages.push(self.alarm_state(series));
+        messages
+    }
+
+    fn device(&self) -> Value {
+        json!({
+            "identifiers": [self.config.node_id],
+            "name": "Pressure monitor",
+            "model": env!("CARGO_PKG_NAME"),
+            "sw_version": env!("CARGO_PKG_VERSION"),
+        })
+    }
+
+    fn config_message(&self, component: &str, object: &str, config: Value) -> Message {
+        Message {
+            topic: format!(
+                "{}/{}/{}/{}/config",
+                self.config.prefix, component, self.config.node_id, object
+            ),
+            payload: config.to_string(),
+            retain: true,
+        }
+    }
+
+    fn alarm_state(&self, series: &str) -> Message {
+        let on = self.raised.contains(series);
+        Message {
+            topic: self.state_topic(series, "alarm"),
+            payload: if on { "ON" } else { "OFF" }.to_string(),
+            retain: true,
+        }
+    }
+
+    fn state_topic(&self, series: &str, kind: &str) -> String {
+        format!("{}/{}/{}", self.config.node_id, object_id(series), kind)
+    }
+}
+
+/// `series` with all but ASCII letters, digits, `-` and `_` replaced by
+/// `_`, as discovery topics take.
+fn object_id(series: &str) -> String {
+    series
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
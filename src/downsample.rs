@@ -0,0 +1,63 @@
+//! Fewer points to draw when there are many more than pixel columns. Unlike
+//! taking every n-th point, peaks and dips survive.
+
+/// Largest-Triangle-Three-Buckets. Keeps the first and last point and, of
+/// each of `threshold - 2` buckets in between, the one spanning the largest
+/// triangle with the point kept before it and the mean of the next bucket.
+/// The points as they are when there are no more than `threshold`.
+pub fn lttb(points: &[(f64, f64)], threshold: usize) -> Vec<(f64, f64)> {
+    if threshold < 3 || points.len() <= threshold {
+        return points.to_vec();
+    }
+
+    // Bucket `i` starts at `bucket(i)`, the first and last point are apart
+    let every = (points.len() - 2) as f64 / (threshold - 2) as f64;
+    let bucket = |i: usize| ((i as f64 * every) as usize + 1).min(points.len() - 1);
+
+    let mut kept = Vec::with_capacity(threshold);
+    kept.push(points[0]);
+    let mut previous = points[0];
+    for i in 0..threshold - 2 {
+        let next = &points[bucket(i + 1)..bucket(i + 2).max(bucket(i + 1) + 1)];
+        let n = next.len() as f64;
+        let mean = next
+            .iter()
+            .fold((0.0, 0.0), |(x, y), p| (x + p.0 / n, y + p.1 / n));
+
+        let (ax, ay) = previous;
+        let area =
+            |&(x, y): &(f64, f64)| ((ax - mean.0) * (y - ay) - (ax - x) * (mean.1 - ay)).abs();
+        let candidates = &points[bucket(i)..bucket(i + 1).max(bucket(i) + 1)];
+        if let Some(&best) = candidates.iter().max_by(|a, b| area(a).total_cmp(&area(b))) {
+            kept.push(best);
+            previous = best;
+        }
+    }
+    kept.push(points[points.len() - 1]);
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn few_points_stay() {
+        let points: Vec<(f64, f64)> = (0..5).map(|i| (i as f64, i as f64)).collect();
+        assert_eq!(lttb(&points, 5), points);
+        assert_eq!(lttb(&points, 10), points);
+        assert_eq!(lttb(&points, 2), points);
+    }
+
+    #[test]
+    fn keeps_the_ends_and_peaks() {
+        let mut points: Vec<(f64, f64)> = (0..100).map(|i| (i as f64, 0.0)).collect();
+        points[50].1 = 10.0;
+        let kept = lttb(&points, 10);
+        assert_eq!(kept.len(), 10);
+        assert_eq!(kept.first(), points.first());
+        assert_eq!(kept.last(), points.last());
+        assert!(kept.contains(&(50.0, 10.0)));
+        assert!(kept.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+}
@@ -0,0 +1,22 @@
+//! Failures the monitor recovers from. They are logged and shown in the
+//! status bar for a while, see `Status::report`, instead of ending the
+//! monitor the way startup errors do.
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MonitorError {
+    /// Retried until the broker accepts the subscription
+    #[error("Subscribe to {topic} failed: {reason}")]
+    Subscribe { topic: String, reason: String },
+    /// Samples keep coming on the topic
+    #[error("Unsubscribe from {topic} failed: {reason}")]
+    Unsubscribe { topic: String, reason: String },
+    /// A sample that didn't make it into a store
+    #[error("Cannot record a sample: {0}")]
+    Store(String),
+    #[error("Cannot save {path}: {reason}")]
+    Save { path: String, reason: String },
+    #[error("Cannot copy to the clipboard: {0}")]
+    Copy(String),
+}
@@ -0,0 +1,249 @@
+//! Smoothing filters for noisy signals.
+//!
+//! A pipeline runs every sample through its stages in order, the output of
+//! one stage feeding the next.
+
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+/// One stage as written in the `[[filter.stages]]` config tables.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum FilterStage {
+    /// Simple moving average over the last `window` samples
+    Sma { window: usize },
+    /// Median of the last `window` samples, an odd number. Removes spikes
+    /// shorter than half the window, which the averages would smear.
+    Median { window: usize },
+    /// Exponential smoothing, `alpha` in (0, 1], smaller is smoother
+    Ema { alpha: f64 },
+    /// Double exponential smoothing (Holt), of the level like `Ema` and of
+    /// its trend with `beta`, so a ramp isn't lagged behind
+    #[serde(rename = "double_ema")]
+    DoubleEma { alpha: f64, beta: f64 },
+    /// Kalman filter of a slowly wandering value. The variances of the
+    /// change per sample and of the measurement noise, in Pa²; a smaller
+    /// ratio of the first to the second is smoother.
+    Kalman {
+        process_noise: f64,
+        measurement_noise: f64,
+    },
+}
+
+trait Filter {
+    fn apply(&mut self, value: f64) -> f64;
+}
+
+struct MovingAverage {
+    window: usize,
+    values: VecDeque<f64>,
+    sum: f64,
+}
+
+impl Filter for MovingAverage {
+    fn apply(&mut self, value: f64) -> f64 {
+        self.values.push_back(value);
+        self.sum += value;
+        if self.values.len() > self.window {
+            self.sum -= self.values.pop_front().unwrap_or_default();
+        }
+        self.sum / self.values.len() as f64
+    }
+}
+
+struct Median {
+    window: usize,
+    values: VecDeque<f64>,
+    sorted: Vec<f64>,
+}
+
+impl Filter for Median {
+    fn apply(&mut self, value: f64) -> f64 {
+        self.values.push_back(value);
+        if self.values.len() > self.window {
+            self.values.pop_front();
+        }
+        self.sorted.clear();
+        self.sorted.extend(&self.values);
+        self.sorted.sort_by(f64::total_cmp);
+        self.sorted[self.sorted.len() / 2]
+    }
+}
+
+struct Exponential {
+    alpha: f64,
+    state: Option<f64>,
+}
+
+impl Filter for Exponential {
+    fn apply(&mut self, value: f64) -> f64 {
+        let next = match self.state {
+            Some(prev) => prev + self.alpha * (value - prev),
+            None => value,
+        };
+        self.state = Some(next);
+        next
+    }
+}
+
+struct DoubleExponential {
+    alpha: f64,
+    beta: f64,
+    /// Level and trend per sample
+    state: Option<(f64, f64)>,
+}
+
+impl Filter for DoubleExponential {
+    fn apply(&mut self, value: f64) -> f64 {
+        let (level, trend) = match self.state {
+            Some((level, trend)) => {
+                let next = level + trend + self.alpha * (value - level - trend);
+                (next, trend + self.beta * (next - level - trend))
+            }
+            None => (value, 0.0),
+        };
+        self.state = Some((level, trend));
+        level
+    }
+}
+
+struct Kalman {
+    process_noise: f64,
+    measurement_noise: f64,
+    /// Estimate and its variance
+    state: Option<(f64, f64)>,
+}
+
+impl Filter for Kalman {
+    fn apply(&mut self, value: f64) -> f64 {
+        let (estimate, variance) = match self.state {
+            Some((estimate, variance)) => {
+                let predicted = variance + self.process_noise;
+                let gain = predicted / (predicted + self.measurement_noise);
+                (
+                    estimate + gain * (value - estimate),
+                    (1.0 - gain) * predicted,
+                )
+            }
+            None => (value, self.measurement_noise),
+        };
+        self.state = Some((estimate, variance));
+        estimate
+    }
+}
+
+/// The filters of one series.
+pub struct Pipeline {
+    stages: Vec<Box<dyn Filter>>,
+}
+
+impl Pipeline {
+    pub fn new(stages: &[FilterStage]) -> Pipeline {
+        let stages = stages
+            .iter()
+            .map(|stage| -> Box<dyn Filter> {
+                match *stage {
+                    FilterStage::Sma { window } => Box::new(MovingAverage {
+                        window: window.max(1),
+                        values: VecDeque::with_capacity(window.max(1) + 1),
+                        sum: 0.0,
+                    }),
+                    FilterStage::Median { window } => {
+                        // Odd when loaded, see `FilterConfig::check`
+                        let window = window.max(1);
+                        Box::new(Median {
+                            window,
+                            values: VecDeque::with_capacity(window + 1),
+                            sorted: Vec::with_capacity(window + 1),
+                        })
+                    }
+                    FilterStage::Ema { alpha } => Box::new(Exponential {
+                        alpha: alpha.clamp(f64::EPSILON, 1.0),
+                        state: None,
+                    }),
+                    FilterStage::DoubleEma { alpha, beta } => Box::new(DoubleExponential {
+                        alpha: alpha.clamp(f64::EPSILON, 1.0),
+                        beta: beta.clamp(0.0, 1.0),
+                        state: None,
+                    }),
+                    FilterStage::Kalman {
+                        process_noise,
+                        measurement_noise,
+                    } => Box::new(Kalman {
+                        process_noise: process_noise.abs(),
+                        // At least some noise, or the gain would be 0 / 0
+                        measurement_noise: measurement_noise.abs().max(f64::MIN_POSITIVE),
+                        state: None,
+                    }),
+                }
+            })
+            .collect();
+        Pipeline { stages }
+    }
+
+    /// No stages configured, the output would equal the raw data.
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    pub fn apply(&mut self, value: f64) -> f64 {
+        self.stages
+            .iter_mut()
+            .fold(value, |value, stage| stage.apply(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filtered(stages: &[FilterStage], values: &[f64]) -> Vec<f64> {
+        let mut pipeline = Pipeline::new(stages);
+        values.iter().map(|&value| pipeline.apply(value)).collect()
+    }
+
+    #[test]
+    fn no_stages() {
+        assert!(Pipeline::new(&[]).is_empty());
+        assert_eq!(filtered(&[], &[1.0, -2.0]), [1.0, -2.0]);
+    }
+
+    #[test]
+    fn moving_average() {
+        let stages = [FilterStage::Sma { window: 3 }];
+        assert_eq!(
+            filtered(&stages, &[3.0, 6.0, 9.0, 12.0]),
+            [3.0, 4.5, 6.0, 9.0]
+        );
+    }
+
+    #[test]
+    fn stages_in_order() {
+        let stages = [
+            FilterStage::Sma { window: 2 },
+            FilterStage::Ema { alpha: 0.5 },
+        ];
+        // Averages 0, 5, 15, then smoothed
+        assert_eq!(filtered(&stages, &[0.0, 10.0, 20.0]), [0.0, 2.5, 8.75]);
+    }
+    #[test]
+    fn constant_stays() {
+        let stages = [
+            FilterStage::DoubleEma {
+                alpha: 0.3,
+                beta: 0.1,
+            },
+            FilterStage::Kalman {
+                process_noise: 1.0,
+                measurement_noise: 100.0,
+            },
+        ];
+        assert!(filtered(&stages, &[5.0; 20]).iter().all(|&v| v == 5.0));
+    }
+    #[test]
+    fn median_removes_spikes() {
+        let stages = [FilterStage::Median { window: 3 }];
+        let values = [1.0, 1.0, 100.0, 1.0, 1.0];
+        assert_eq!(filtered(&stages, &values), [1.0; 5]);
+    }
+}
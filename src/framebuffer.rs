@@ -0,0 +1,86 @@
+//! Pixel buffer shared by plotters, which draws into it as BGRX bytes, and
+//! minifb, which shows it as `u32` pixels.
+
+use crate::overlay::Root;
+use plotters::prelude::*;
+use plotters_bitmap::bitmap_pixel::BGRXPixel;
+use plotters_bitmap::BitMapBackend;
+use std::error::Error;
+
+#[derive(Debug, Clone, Default)]
+pub struct FrameBuffer {
+    pixels: Vec<u32>,
+    width: usize,
+    height: usize,
+}
+
+impl FrameBuffer {
+    pub fn new(width: usize, height: usize) -> FrameBuffer {
+        FrameBuffer {
+            pixels: vec![0; width * height],
+            width,
+            height,
+        }
+    }
+
+    /// Without pixels, e.g. a default one not drawn into yet.
+    pub fn is_empty(&self) -> bool {
+        self.pixels.is_empty()
+    }
+
+    /// Changes the size, clearing the pixels.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.pixels.clear();
+        self.pixels.resize(width * height, 0);
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Takes size and pixels of `other`, reusing the allocation.
+    pub fn copy_from(&mut self, other: &FrameBuffer) {
+        self.pixels.clone_from(&other.pixels);
+        self.width = other.width;
+        self.height = other.height;
+    }
+
+    /// Moves the pixels `dx` columns to the left, filling the emptied ones
+    /// on the right with `fill`.
+    pub fn scroll_left(&mut self, dx: usize, fill: u32) {
+        let dx = dx.min(self.width);
+        for row in self.pixels.chunks_exact_mut(self.width.max(1)) {
+            row.copy_within(dx.., 0);
+            row[row.len() - dx..].fill(fill);
+        }
+    }
+
+    /// Copies the pixels of `layer` other than `transparent` onto this one,
+    /// with its top left corner at `at`.
+    pub fn blend(&mut self, layer: &FrameBuffer, at: (usize, usize), transparent: u32) {
+        let (x, y) = at;
+        let width = layer.width.min(self.width.saturating_sub(x));
+        for (row, from) in layer.pixels.chunks_exact(layer.width.max(1)).enumerate() {
+            if y + row >= self.height {
+                break;
+            }
+            let start = (y + row) * self.width + x;
+            let to = &mut self.pixels[start..start + width];
+            for (pixel, &p) in to.iter_mut().zip(&from[..width]) {
+                if p != transparent {
+                    *pixel = p;
+                }
+            }
+        }
+    }
+
+    /// Row by row, in the `0RGB` layout minifb shows.
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+
+    /// The whole buffer to draw on.
+    pub fn root(&mut self) -> Result<Root<'_>, Box<dyn Error>> {
+        let size = (self.width as u32, self.height as u32);
+        let bytes: &mut [u8] = bytemuck::cast_slice_mut(&mut self.pixels);
+        Ok(BitMapBackend::<BGRXPixel>::with_buffer_and_format(bytes, size)?.into_drawing_area())
+    }
+}
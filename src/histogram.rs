@@ -0,0 +1,85 @@
+//! Distribution of the values of a series, for checking the noise of a
+//! sensor or how tightly a regulator holds its setpoint.
+
+pub struct Histogram {
+    /// Lower edge of the first bin
+    pub min: f64,
+    /// Of every bin
+    pub width: f64,
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    /// `values` in `bins` bins of equal width from `min` to `max`, those
+    /// outside left out.
+    pub fn of(values: impl IntoIterator<Item = f64>, min: f64, max: f64, bins: usize) -> Histogram {
+        let bins = bins.max(1);
+        // A constant value still gets a bin
+        let width = match (max - min) / bins as f64 {
+            w if w > 0.0 => w,
+            _ => 1.0,
+        };
+        let mut counts = vec![0; bins];
+        for value in values {
+            if !(min..=max).contains(&value) {
+                continue;
+            }
+            let bin = (((value - min) / width) as usize).min(bins - 1);
+            counts[bin] += 1;
+        }
+        Histogram { min, width, counts }
+    }
+
+    pub fn max_count(&self) -> usize {
+        self.counts.iter().copied().max().unwrap_or(0)
+    }
+
+    /// Outline of the bars, as `(value, count)` points.
+    pub fn outline(&self) -> Vec<(f64, f64)> {
+        let mut points = vec![(self.min, 0.0)];
+        for (i, &count) in self.counts.iter().enumerate() {
+            let left = self.min + i as f64 * self.width;
+            points.push((left, count as f64));
+            points.push((left + self.width, count as f64));
+        }
+        points.push((self.min + self.counts.len() as f64 * self.width, 0.0));
+        points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bins() {
+        let histogram = Histogram::of([0.0, 0.5, 1.0, 2.5, 4.0, 5.0, -1.0], 0.0, 4.0, 4);
+        assert_eq!(histogram.width, 1.0);
+        // The maximum falls in the last bin, the values outside in none
+        assert_eq!(histogram.counts, [2, 1, 1, 1]);
+        assert_eq!(histogram.max_count(), 2);
+    }
+
+    #[test]
+    fn constant_values() {
+        let histogram = Histogram::of([3.0, 3.0], 3.0, 3.0, 0);
+        assert_eq!(histogram.counts, [2]);
+        assert_eq!(histogram.width, 1.0);
+    }
+
+    #[test]
+    fn outline() {
+        let histogram = Histogram::of([0.5, 1.5, 1.5], 0.0, 2.0, 2);
+        assert_eq!(
+            histogram.outline(),
+            [
+                (0.0, 0.0),
+                (0.0, 1.0),
+                (1.0, 1.0),
+                (1.0, 2.0),
+                (2.0, 2.0),
+                (2.0, 0.0)
+            ]
+        );
+    }
+}
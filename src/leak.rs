@@ -0,0 +1,285 @@
+//! Leak testing by pressure decay. From a marked start the pressures are
+//! fitted with a line, or an exponential decay, whose slope at the start is
+//! the leak rate.
+
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DecayModel {
+    /// Constant leak rate, for short tests or a small fraction lost
+    Linear,
+    /// Towards zero, as a gauge pressure leaking to atmosphere does
+    Exponential,
+}
+
+impl DecayModel {
+    pub fn label(self) -> &'static str {
+        match self {
+            DecayModel::Linear => "linear",
+            DecayModel::Exponential => "exponential",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Fit {
+    pub model: DecayModel,
+    /// Time of the first sample, the fit is relative to it
+    pub t0: f64,
+    /// Fitted pressure at `t0`, Pa
+    pub p0: f64,
+    /// Leak rate at `t0` in Pa/s, negative for a falling pressure
+    pub rate: f64,
+    /// Half width of the 95 % confidence interval of `rate`
+    pub rate_ci: f64,
+    /// Of the fitted against the measured pressures
+    pub r_squared: f64,
+    pub samples: usize,
+    /// Seconds from the first to the last sample
+    pub duration: f64,
+    /// Pressure of the last sample, Pa
+    pub last: f64,
+}
+
+impl Fit {
+    /// Of `(t, pressure)` samples, `None` with fewer than three samples or
+    /// no time between them. The exponential model needs positive
+    /// pressures and leaves out the others.
+    pub fn of(samples: &[(f64, f64)], model: DecayModel) -> Option<Fit> {
+        let &(t0, _) = samples.first()?;
+        let &(t_last, last) = samples.last()?;
+        let points: Vec<(f64, f64)> = match model {
+            DecayModel::Linear => samples.iter().map(|&(t, p)| (t - t0, p)).collect(),
+            DecayModel::Exponential => samples
+                .iter()
+                .filter(|&&(_, p)| p > 0.0)
+                .map(|&(t, p)| (t - t0, p.ln()))
+                .collect(),
+        };
+        let line = regress(&points)?;
+
+        // The derivative at t0, for the exponential p0 * k
+        let (p0, scale) = match model {
+            DecayModel::Linear => (line.intercept, 1.0),
+            DecayModel::Exponential => (line.intercept.exp(), line.intercept.exp()),
+        };
+        let mut fit = Fit {
+            model,
+            t0,
+            p0,
+            rate: line.slope * scale,
+            // The exponential's ignores the uncertainty of p0, small next
+            // to that of the slope over a test
+            rate_ci: t_975(points.len() as f64 - 2.0) * line.slope_se * scale,
+            r_squared: 0.0,
+            samples: samples.len(),
+            duration: t_last - t0,
+            last,
+        };
+
+        let mean = samples.iter().map(|s| s.1).sum::<f64>() / samples.len() as f64;
+        let (sse, sst) = samples.iter().fold((0.0, 0.0), |(sse, sst), &(t, p)| {
+            (
+                sse + (p - fit.value_at(t)).powi(2),
+                sst + (p - mean).powi(2),
+            )
+        });
+        fit.r_squared = if sst > 0.0 { 1.0 - sse / sst } else { 1.0 };
+        Some(fit)
+    }
+
+    /// The fitted pressure at `t`, Pa.
+    pub fn value_at(&self, t: f64) -> f64 {
+        let dt = t - self.t0;
+        match self.model {
+            DecayModel::Linear => self.p0 + self.rate * dt,
+            DecayModel::Exponential => self.p0 * (self.rate / self.p0 * dt).exp(),
+        }
+    }
+
+    /// Seconds for the exponential decay to fall by a factor of e.
+    pub fn time_constant(&self) -> Option<f64> {
+        match self.model {
+            DecayModel::Linear => None,
+            DecayModel::Exponential => Some(-self.p0 / self.rate),
+        }
+    }
+}
+
+struct Line {
+    intercept: f64,
+    slope: f64,
+    /// Standard error of the slope
+    slope_se: f64,
+}
+
+/// Least squares line through `(x, y)` points.
+fn regress(points: &[(f64, f64)]) -> Option<Line> {
+    if points.len() < 3 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let (sxx, sxy) = points.iter().fold((0.0, 0.0), |(sxx, sxy), &(x, y)| {
+        (
+            sxx + (x - mean_x).powi(2),
+            sxy + (x - mean_x) * (y - mean_y),
+        )
+    });
+    if sxx <= 0.0 {
+        return None;
+    }
+
+    let slope = sxy / sxx;
+    let intercept = mean_y - slope * mean_x;
+    let sse: f64 = points
+        .iter()
+        .map(|&(x, y)| (y - intercept - slope * x).powi(2))
+        .sum();
+    Some(Line {
+        intercept,
+        slope,
+        slope_se: (sse / (n - 2.0) / sxx).sqrt(),
+    })
+}
+
+/// 97.5 % quantile of Student's t distribution with `df` degrees of
+/// freedom, by the Cornish-Fisher expansion. Within 4 % from 3 degrees of
+/// freedom on, and closer the more there are.
+fn t_975(df: f64) -> f64 {
+    const Z: f64 = 1.959_964;
+    let df = df.max(1.0);
+    Z + (Z.powi(3) + Z) / (4.0 * df)
+        + (5.0 * Z.powi(5) + 16.0 * Z.powi(3) + 3.0 * Z) / (96.0 * df * df)
+}
+
+/// Writes a plain text report of the fits, one section per sensor. With the
+/// `volume` of the tested part in litres the volumetric leak rate is given
+/// too.
+pub fn write_report(
+    path: &Path,
+    started: SystemTime,
+    volume: Option<f64>,
+    fits: &[(&str, Fit)],
+) -> Result<(), Box<dyn Error>> {
+    let mut text = String::new();
+    let started = DateTime::<Local>::from(started);
+    writeln!(text, "Leak test report")?;
+    writeln!(text, "Started    {}", started.format("%Y-%m-%d %H:%M:%S"))?;
+    if let Some(volume) = volume {
+        writeln!(text, "Volume     {} l", volume)?;
+    }
+    if fits.is_empty() {
+        writeln!(text, "\nToo few samples to fit")?;
+    }
+
+    for (topic, fit) in fits {
+        writeln!(text, "\n{}", topic)?;
+        writeln!(text, "  Model          {}", fit.model.label())?;
+        writeln!(text, "  Duration       {:.1} s", fit.duration)?;
+        writeln!(text, "  Samples        {}", fit.samples)?;
+        writeln!(text, "  Start          {:.1} Pa (fitted)", fit.p0)?;
+        writeln!(text, "  End            {:.1} Pa", fit.last)?;
+        writeln!(
+            text,
+            "  Leak rate      {:.4} ± {:.4} Pa/s (95 %), {:.2} Pa/min",
+            fit.rate,
+            fit.rate_ci,
+            fit.rate * 60.0
+        )?;
+        if let Some(volume) = volume {
+            // Pa/s times litres is 1e-3 Pa m³/s, or 1e-2 mbar l/s
+            writeln!(
+                text,
+                "  Volumetric     {:.4e} ± {:.4e} mbar l/s",
+                fit.rate * volume * 0.01,
+                fit.rate_ci * volume * 0.01
+            )?;
+        }
+        if let Some(tau) = fit.time_constant() {
+            writeln!(text, "  Time constant  {:.1} s", tau)?;
+        }
+        writeln!(text, "  R²             {:.4}", fit.r_squared)?;
+    }
+
+    fs::write(path, text)
+        .map_err(|e| format!("Cannot write leak test report {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn linear() {
+        let samples: Vec<(f64, f64)> = (0..10)
+            .map(|i| (100.0 + i as f64, 2000.0 - 0.5 * i as f64))
+            .collect();
+        let fit = Fit::of(&samples, DecayModel::Linear).unwrap();
+        assert!(close(fit.rate, -0.5));
+        assert!(close(fit.p0, 2000.0));
+        assert!(close(fit.r_squared, 1.0));
+        assert!(fit.rate_ci.abs() < 1e-9);
+        assert_eq!((fit.t0, fit.duration, fit.samples), (100.0, 9.0, 10));
+        assert_eq!(fit.last, 1995.5);
+        assert!(close(fit.value_at(120.0), 1990.0));
+        assert_eq!(fit.time_constant(), None);
+    }
+
+    #[test]
+    fn exponential() {
+        let tau = 50.0;
+        let samples: Vec<(f64, f64)> = (0..20)
+            .map(|i| (i as f64, 1000.0 * (-(i as f64) / tau).exp()))
+            .collect();
+        let fit = Fit::of(&samples, DecayModel::Exponential).unwrap();
+        assert!(close(fit.p0, 1000.0));
+        // The rate at the start, p0 / tau
+        assert!(close(fit.rate, -20.0));
+        assert!(close(fit.time_constant().unwrap(), tau));
+        assert!(close(fit.value_at(10.0), samples[10].1));
+    }
+
+    #[test]
+    fn noise_widens_the_interval() {
+        let samples: Vec<(f64, f64)> = (0..10)
+            .map(|i| {
+                (
+                    i as f64,
+                    1000.0 - i as f64 + if i % 2 == 0 { 1.0 } else { -1.0 },
+                )
+            })
+            .collect();
+        let fit = Fit::of(&samples, DecayModel::Linear).unwrap();
+        assert!(fit.rate_ci > 0.0);
+        assert!((fit.rate - -1.0).abs() < fit.rate_ci);
+        assert!(fit.r_squared < 1.0);
+    }
+
+    #[test]
+    fn too_few_samples() {
+        assert!(Fit::of(&[], DecayModel::Linear).is_none());
+        assert!(Fit::of(&[(0.0, 1.0), (1.0, 2.0)], DecayModel::Linear).is_none());
+        // All at one time
+        assert!(Fit::of(&[(1.0, 1.0); 5], DecayModel::Linear).is_none());
+        // Nothing positive to take the logarithm of
+        assert!(Fit::of(
+            &[(0.0, -1.0), (1.0, -2.0), (2.0, -3.0)],
+            DecayModel::Exponential
+        )
+        .is_none());
+    }
+}
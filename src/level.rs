@@ -0,0 +1,57 @@
+//! Pressures as heights: the altitude of a barometric pressure after the
+//! International Standard Atmosphere, or the depth below a fluid surface of
+//! a hydrostatic pressure.
+
+use serde::Deserialize;
+
+/// Standard gravity, m/s²
+const G: f64 = 9.806_65;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LevelKind {
+    Altitude,
+    Depth,
+}
+
+impl LevelKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            LevelKind::Altitude => "Altitude",
+            LevelKind::Depth => "Depth",
+        }
+    }
+}
+
+/// Meters above the level where the pressure is `sea_level`, both in Pa.
+/// The ISA troposphere, so up to 11 km.
+pub fn altitude(pa: f64, sea_level: f64) -> f64 {
+    // T0 / L, and R L / (g M) of air
+    44_330.77 * (1.0 - (pa / sea_level).powf(0.190_263))
+}
+
+/// Meters below the surface of a fluid of `density` in kg/m³, where the
+/// pressure is `surface` in Pa, 0 for gauge sensors.
+pub fn depth(pa: f64, density: f64, surface: f64) -> f64 {
+    (pa - surface) / (density * G)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standard_atmosphere() {
+        assert_eq!(altitude(101_325.0, 101_325.0), 0.0);
+        // 1000 m of the ISA
+        assert!((altitude(89_874.6, 101_325.0) - 1000.0).abs() < 1.0);
+        assert!(altitude(102_000.0, 101_325.0) < 0.0);
+    }
+
+    #[test]
+    fn hydrostatic() {
+        assert!((depth(98_066.5, 1000.0, 0.0) - 10.0).abs() < 1e-9);
+        assert!((depth(101_325.0 + 98_066.5, 1000.0, 101_325.0) - 10.0).abs() < 1e-9);
+        assert!(depth(101_325.0, 1000.0, 101_325.0).abs() < 1e-9);
+    }
+}
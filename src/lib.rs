@@ -0,0 +1,53 @@
+//! Live pressure chart for MQTT and serial sensors.
+//!
+//! The binary is a thin command line front end, [`PressureMonitor`] runs
+//! the ingest, recording and charting pipeline and can be embedded as well.
+
+pub mod alarm;
+pub mod buffer;
+pub mod calibration;
+pub mod clock;
+pub mod config;
+pub mod decode;
+mod derive;
+mod differential;
+mod discovery;
+pub mod downsample;
+pub mod error;
+pub mod filter;
+mod framebuffer;
+mod histogram;
+pub mod leak;
+pub mod level;
+mod metrics;
+mod monitor;
+mod outlier;
+mod overlay;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+mod publish;
+pub mod rate;
+pub mod recorder;
+mod reference;
+mod resample;
+mod rotate;
+mod scale;
+mod screenshot;
+pub mod scrollback;
+pub mod sequence;
+mod session;
+mod settings;
+pub mod source;
+pub mod sparkplug;
+mod spectrum;
+pub mod stats;
+pub mod store;
+pub mod theme;
+mod throughput;
+mod topic_list;
+pub mod units;
+mod view;
+mod web;
+mod webhook;
+
+pub use monitor::{Builder, PressureMonitor};
@@ -1,200 +1,285 @@
-use clap::Parser;
-use minifb::{Key, KeyRepeat, Window, WindowOptions};
-use plotters::prelude::*;
-use plotters_bitmap::bitmap_pixel::BGRXPixel;
-use plotters_bitmap::BitMapBackend;
-use rumqttc::{v4::Packet, Client, Event, MqttOptions, QoS};
-use std::borrow::{Borrow, BorrowMut};
+use clap::{Parser, Subcommand};
+use pressure_monitor::calibration::{self, Calibration};
+use pressure_monitor::config::{self, Config};
+use pressure_monitor::source::replay::{ReplaySource, Speed};
+use pressure_monitor::source::{self, DataSource, Status};
+use pressure_monitor::units::PressureUnit;
+use pressure_monitor::PressureMonitor;
 use std::error::Error;
-use std::sync::mpsc;
-use std::thread;
-use std::time::{Duration, SystemTime};
-
-const W: usize = 1600;
-const H: usize = 800;
-
-const DATA_LENGTH: usize = 1000;
+use std::path::{Path, PathBuf};
+use tracing_subscriber::EnvFilter;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
-    #[clap(short, long, default_value = "raspberrypi.local")]
-    addr: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
 
-    #[clap(short, long, default_value_t = 1883)]
-    port: u16,
-}
+    /// Configuration file [default: pressure_monitor.toml if present]
+    #[clap(long)]
+    config: Option<PathBuf>,
 
-struct BufferWrapper(Vec<u32>);
-impl Borrow<[u8]> for BufferWrapper {
-    fn borrow(&self) -> &[u8] {
-        // Safe for alignment: align_of(u8) <= align_of(u32)
-        // Safe for cast: u32 can be thought of as being transparent over [u8; 4]
-        unsafe { std::slice::from_raw_parts(self.0.as_ptr() as *const u8, self.0.len() * 4) }
-    }
-}
-impl BorrowMut<[u8]> for BufferWrapper {
-    fn borrow_mut(&mut self) -> &mut [u8] {
-        // Safe for alignment: align_of(u8) <= align_of(u32)
-        // Safe for cast: u32 can be thought of as being transparent over [u8; 4]
-        unsafe { std::slice::from_raw_parts_mut(self.0.as_mut_ptr() as *mut u8, self.0.len() * 4) }
-    }
+    /// Where samples come from: mqtt, serial[:<port>], sim, http, udp[:<address>],
+    /// websocket, a ws(s):// URL, modbus[:<address>] or i2c [default: mqtt]
+    #[clap(short, long)]
+    source: Option<String>,
+
+    /// MQTT broker host or mqtt(s)://host[:port] URL [default: raspberrypi.local]
+    #[clap(short, long, alias = "addr")]
+    broker: Option<String>,
+
+    /// MQTT broker port [default: 1883, 8883 for mqtts://]
+    #[clap(short, long)]
+    port: Option<u16>,
+
+    /// Topic to subscribe to, wildcards allowed, may be repeated [default: pressure/data]
+    #[clap(short, long)]
+    topic: Vec<String>,
+
+    /// Client id presented to the broker [default: pressure_data_receiver]
+    #[clap(short, long)]
+    client_id: Option<String>,
+
+    /// Log filter: error, warn, info, debug, trace or e.g. pressure_monitor=debug
+    #[clap(long, env = "RUST_LOG", default_value = "info")]
+    log_level: String,
+
+    /// Run without a window, only recording and watching alarms
+    #[clap(long)]
+    headless: bool,
+
+    /// Display unit: Pa, kPa, bar, psi or mmHg [default: Pa]
+    #[clap(short, long)]
+    unit: Option<PressureUnit>,
+
+    /// Time span of data kept and drawn, e.g. 120s or 10m, instead of a sample count
+    #[clap(short, long, parse(try_from_str = config::parse_seconds))]
+    window: Option<f64>,
+
+    /// Start recording a session right away, see [session]
+    #[clap(long)]
+    record: bool,
+
+    /// Append every received sample to this CSV file
+    #[clap(long)]
+    log_file: Option<PathBuf>,
+
+    /// Append every received sample to this JSON Lines file
+    #[clap(long)]
+    jsonl_file: Option<PathBuf>,
+
+    /// Store every sample in this SQLite database
+    #[clap(long)]
+    db: Option<PathBuf>,
+
+    /// Serve a live web dashboard on this address, e.g. 0.0.0.0:8080
+    #[clap(long)]
+    web: Option<String>,
+
+    /// Serve Prometheus metrics on this address, e.g. 0.0.0.0:9100
+    #[clap(long)]
+    metrics: Option<String>,
+
+    /// User name for broker authentication
+    #[clap(long, env = "MQTT_USER")]
+    mqtt_user: Option<String>,
+
+    /// Password for broker authentication
+    #[clap(long, env = "MQTT_PASSWORD", hide_env_values = true)]
+    mqtt_pass: Option<String>,
+
+    /// CA bundle (PEM) used to verify an mqtts:// broker
+    #[clap(long)]
+    tls_ca: Option<PathBuf>,
+
+    /// Client certificate (PEM) for mutual TLS
+    #[clap(long)]
+    tls_cert: Option<PathBuf>,
+
+    /// Client private key (PEM) for mutual TLS
+    #[clap(long)]
+    tls_key: Option<PathBuf>,
+
+    /// Accept a broker certificate issued for another host name
+    #[clap(long)]
+    tls_no_verify_hostname: bool,
 }
-impl Borrow<[u32]> for BufferWrapper {
-    fn borrow(&self) -> &[u32] {
-        self.0.as_slice()
-    }
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Play back a CSV written by this tool instead of connecting to a broker
+    Replay {
+        /// A --log-file log or an `s` snapshot
+        file: PathBuf,
+
+        /// Playback rate, e.g. 1x for real time or 10x
+        #[clap(long, default_value = "1x")]
+        speed: Speed,
+    },
+    /// Fit a two point calibration of a topic and save it to the configuration file
+    #[clap(allow_negative_numbers = true)]
+    Calibrate {
+        /// Topic, or topic filter, of the sensor
+        topic: String,
+
+        /// Value shown at the first reference pressure
+        reading1: f64,
+
+        /// The first reference pressure, e.g. from a reference gauge
+        reference1: f64,
+
+        /// Value shown at the second reference pressure
+        reading2: f64,
+
+        /// The second reference pressure
+        reference2: f64,
+
+        /// Unit of the readings and references
+        #[clap(short, long, default_value = "pa")]
+        unit: PressureUnit,
+    },
 }
-impl BorrowMut<[u32]> for BufferWrapper {
-    fn borrow_mut(&mut self) -> &mut [u32] {
-        self.0.as_mut_slice()
+
+impl Args {
+    /// Command line options win over the configuration file.
+    fn apply(self, config: &mut Config) {
+        if let Some(source) = self.source {
+            config.source = source;
+            // One source given on the command line replaces several
+            config.sources.clear();
+        }
+        if let Some(broker) = self.broker {
+            config.mqtt.broker = broker;
+        }
+        if self.port.is_some() {
+            config.mqtt.port = self.port;
+        }
+        if !self.topic.is_empty() {
+            config.mqtt.topics = self.topic;
+        }
+        if let Some(client_id) = self.client_id {
+            config.mqtt.client_id = client_id;
+        }
+        if let Some(unit) = self.unit {
+            config.chart.unit = unit;
+        }
+        if self.window.is_some() {
+            config.data.window = self.window;
+        }
+        if self.record {
+            config.session.record = true;
+        }
+        if self.log_file.is_some() {
+            config.log.file = self.log_file;
+        }
+        if self.jsonl_file.is_some() {
+            config.log.jsonl = self.jsonl_file;
+        }
+        if self.db.is_some() {
+            config.sqlite.path = self.db;
+        }
+        if self.web.is_some() {
+            config.web.listen = self.web;
+        }
+        if self.metrics.is_some() {
+            config.metrics.listen = self.metrics;
+        }
+        if self.mqtt_user.is_some() {
+            config.mqtt.username = self.mqtt_user;
+        }
+        if self.mqtt_pass.is_some() {
+            config.mqtt.password = self.mqtt_pass;
+        }
+        if self.tls_ca.is_some() {
+            config.mqtt.tls.ca = self.tls_ca;
+        }
+        if self.tls_cert.is_some() {
+            config.mqtt.tls.cert = self.tls_cert;
+        }
+        if self.tls_key.is_some() {
+            config.mqtt.tls.key = self.tls_key;
+        }
+        if self.tls_no_verify_hostname {
+            config.mqtt.tls.verify_hostname = false;
+        }
     }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
-
-    let mut mqttoptions = MqttOptions::new("pressure_data_receiver", args.addr, args.port);
-    mqttoptions.set_keep_alive(Duration::from_secs(5));
-    mqttoptions.set_clean_session(true);
-
-    let (mut client, mut connection) = Client::new(mqttoptions, 10);
-    client
-        .subscribe("pressure/data", QoS::AtMostOnce)
-        .expect("Mqtt subscribe failed");
-
-    let (tx, rx) = mpsc::channel();
-
-    thread::spawn(move || {
-        for notification in connection.iter() {
-            // debug:
-            // println!("notification: {:?}", notification);
-
-            // get pressure data
-            if let Ok(event) = notification {
-                match event {
-                    Event::Incoming(Packet::Publish(publish)) => {
-                        let bytes = publish.payload;
-                        if bytes.len() == 4 {
-                            let pressure =
-                                i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64;
-                            tx.send(pressure).ok();
-                        }
-                    }
-                    _ => {
-                        continue;
-                    }
-                }
-            };
-        }
-    });
-
-    let mut buf = BufferWrapper(vec![0u32; W * H]);
-
-    let mut window = Window::new(
-        "Pressure Data         s=Save    <Esc>=Exit",
-        W,
-        H,
-        WindowOptions::default(),
-    )?;
-    let root =
-        BitMapBackend::<BGRXPixel>::with_buffer_and_format(buf.borrow_mut(), (W as u32, H as u32))?
-            .into_drawing_area();
-    root.fill(&BLACK)?;
-
-    let mut chart = ChartBuilder::on(&root)
-        .margin(10)
-        .set_all_label_area_size(50)
-        .build_cartesian_2d(0.0..120.0, -100_000.0..2_500.0)?;
-
-    chart
-        .configure_mesh()
-        .label_style(("sans-serif", 15).into_font().color(&GREEN))
-        .axis_style(&GREEN)
-        .draw()?;
-
-    let cs = chart.into_chart_state();
-    drop(root);
-
-    let mut data: Vec<(SystemTime, f64)> = Vec::new();
-
-    let mut start_ts = SystemTime::now();
-
-    while window.is_open() && !window.is_key_down(Key::Escape) {
-        if let Ok(pressure) = rx.try_recv() {
-            // debug:
-            println!("Pressure: {}", pressure);
-
-            let now = SystemTime::now();
-
-            if data.len() == 0 {
-                start_ts = now;
-            }
-
-            if data.len() > DATA_LENGTH {
-                data.remove(0);
-                start_ts = data[0].0;
-            }
-
-            data.push((now, pressure));
-
-            let root = BitMapBackend::<BGRXPixel>::with_buffer_and_format(
-                buf.borrow_mut(),
-                (W as u32, H as u32),
-            )?
-            .into_drawing_area();
-            let mut chart = cs.clone().restore(&root);
-            chart.plotting_area().fill(&BLACK)?;
-
-            chart
-                .configure_mesh()
-                .bold_line_style(&GREEN.mix(0.2))
-                .light_line_style(&TRANSPARENT)
-                .draw()?;
-
-            let chart_data: Vec<(f64, f64)> = data
-                .iter()
-                .map(|d| {
-                    (
-                        d.0.duration_since(start_ts)
-                            .expect("Duration calculate failed")
-                            .as_secs_f64(),
-                        d.1,
-                    )
-                })
-                .collect();
-
-            chart.draw_series(chart_data.iter().zip(chart_data.iter().skip(1)).map(
-                |(&(t0, p0), &(t1, p1))| PathElement::new(vec![(t0, p0), (t1, p1)], &GREEN),
-            ))?;
-
-            drop(root);
-            drop(chart);
-
-            if let Some(keys) = window.get_keys_pressed(KeyRepeat::Yes) {
-                for key in keys {
-                    match key {
-                        Key::S => {
-                            let mut wtr = csv::Writer::from_path("pressure_data.csv")?;
-                            wtr.write_record(&["Time(s)", "Pressure(Pa)"])?;
-
-                            for data in &chart_data {
-                                wtr.write_record(&[data.0.to_string(), data.1.to_string()])?;
-                            }
-
-                            wtr.flush()?;
-                            continue;
-                        }
-                        _ => {
-                            continue;
-                        }
-                    }
-                }
-            }
-        }
-        window.update_with_buffer(buf.borrow(), W, H)?;
-
-        thread::sleep(Duration::from_millis(15));
-    }
+    let mut args = Args::parse();
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_new(&args.log_level)?)
+        .with_writer(std::io::stderr)
+        .init();
+
+    let command = args.command.take();
+    let config_path = args
+        .config
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(config::DEFAULT_PATH));
+    let mut config = Config::load_or_default(args.config.as_deref())?;
+    let headless = args.headless;
+    args.apply(&mut config);
+
+    let status = Status::default();
+    let source: Box<dyn DataSource> = match command {
+        Some(Command::Replay { file, speed }) => {
+            Box::new(ReplaySource::open(&file, speed, status.clone())?)
+        }
+        Some(Command::Calibrate {
+            topic,
+            reading1,
+            reference1,
+            reading2,
+            reference2,
+            unit,
+        }) => {
+            let points = [(reading1, reference1), (reading2, reference2)]
+                .map(|(reading, reference)| (unit.to_pa(reading), unit.to_pa(reference)));
+            return calibrate(&config_path, &config, &topic, points);
+        }
+        None if config.sources.is_empty() => {
+            source::from_spec(&config.source, &config, status.clone())?
+        }
+        None => source::from_sources(&config, status.clone())?,
+    };
+
+    let monitor = PressureMonitor::builder()
+        .config(config)
+        .source(source)
+        .status(status)
+        .headless(headless)
+        .config_path(config_path)
+        .build()?;
+
+    // Esc in the window or Ctrl-C, headless runs have only the latter
+    let shutdown = monitor.shutdown_handle();
+    ctrlc::set_handler(move || shutdown.request())?;
+
+    monitor.run()
+}
+
+/// Saves the calibration fitted to `points` of readings and references in
+/// Pa, applied from the next start on.
+fn calibrate(
+    path: &Path,
+    config: &Config,
+    topic: &str,
+    [point1, point2]: [(f64, f64); 2],
+) -> Result<(), Box<dyn Error>> {
+    let fit = Calibration::two_point(point1, point2)?;
+    // The readings shown had the current calibration applied already
+    let calibration = match config.payload.calibration.get(topic) {
+        Some(current) => current.then(fit),
+        None => fit,
+    };
+    calibration::save(path, topic, calibration)?;
+    println!(
+        "Saved calibration of {} to {}: scale {}, offset {} Pa",
+        topic,
+        path.display(),
+        calibration.scale,
+        calibration.offset
+    );
     Ok(())
 }
@@ -0,0 +1,144 @@
+//! Prometheus `/metrics` endpoint.
+
+use crate::source::{ConnectionState, Status};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tracing::debug;
+
+#[derive(Debug, Default)]
+struct Values {
+    /// Latest value in Pa and samples received, per topic
+    topics: BTreeMap<String, (f64, u64)>,
+    fps: f64,
+}
+
+/// Values kept for scraping, updated by the render loop.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    values: Arc<Mutex<Values>>,
+    status: Status,
+}
+
+impl Metrics {
+    pub fn new(status: Status) -> Metrics {
+        Metrics {
+            values: Arc::default(),
+            status,
+        }
+    }
+
+    pub fn record(&self, topic: &str, value: f64) {
+        let mut values = self.values.lock().unwrap();
+        match values.topics.get_mut(topic) {
+            Some(entry) => *entry = (value, entry.1 + 1),
+            None => {
+                values.topics.insert(topic.to_string(), (value, 1));
+            }
+        }
+    }
+
+    pub fn set_fps(&self, fps: f64) {
+        self.values.lock().unwrap().fps = fps;
+    }
+
+    /// Binds `addr` and answers scrapes on a background thread.
+    pub fn serve(&self, addr: &str) -> Result<(), Box<dyn Error>> {
+        let listener =
+            TcpListener::bind(addr).map_err(|e| format!("Cannot listen on {}: {}", addr, e))?;
+        let metrics = self.clone();
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(e) = metrics.handle(stream) {
+                    debug!("Metrics client error: {}", e);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn handle(&self, mut stream: TcpStream) -> Result<(), Box<dyn Error>> {
+        let mut head = [0u8; 512];
+        let n = stream.read(&mut head)?;
+        let request = String::from_utf8_lossy(&head[..n]);
+        let path = request.split_whitespace().nth(1).unwrap_or("/");
+
+        let (status, body) = match path {
+            "/metrics" => ("200 OK", self.render()),
+            _ => ("404 Not Found", "Not found\n".to_string()),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )?;
+        Ok(())
+    }
+
+    /// The text exposition format.
+    fn render(&self) -> String {
+        let values = self.values.lock().unwrap();
+        let mut out = String::new();
+
+        out.push_str("# HELP pressure_pa Latest pressure in Pa.\n");
+        out.push_str("# TYPE pressure_pa gauge\n");
+        for (topic, (value, _)) in &values.topics {
+            writeln!(out, "pressure_pa{{topic=\"{}\"}} {}", escape(topic), value).ok();
+        }
+
+        out.push_str("# HELP pressure_samples_received_total Samples received.\n");
+        out.push_str("# TYPE pressure_samples_received_total counter\n");
+        for (topic, (_, count)) in &values.topics {
+            writeln!(
+                out,
+                "pressure_samples_received_total{{topic=\"{}\"}} {}",
+                escape(topic),
+                count
+            )
+            .ok();
+        }
+
+        let connected = (self.status.get() == ConnectionState::Connected) as u8;
+        writeln!(
+            out,
+            "# HELP pressure_messages_dropped_total Messages that could not be decoded.\n\
+             # TYPE pressure_messages_dropped_total counter\n\
+             pressure_messages_dropped_total {}\n\
+             # HELP pressure_samples_rejected_total Readings rejected by the sanity filter.\n\
+             # TYPE pressure_samples_rejected_total counter\n\
+             pressure_samples_rejected_total {}\n\
+             # HELP pressure_source_reconnects_total Reconnections of the data source.\n\
+             # TYPE pressure_source_reconnects_total counter\n\
+             pressure_source_reconnects_total {}\n\
+             # HELP pressure_source_connected Whether the data source is connected.\n\
+             # TYPE pressure_source_connected gauge\n\
+             pressure_source_connected {}\n\
+             # HELP pressure_render_fps Frames drawn per second.\n\
+             # TYPE pressure_render_fps gauge\n\
+             pressure_render_fps {:.1}",
+            self.status.dropped(),
+            self.status.rejected(),
+            self.status.reconnects(),
+            connected,
+            values.fps
+        )
+        .ok();
+
+        out
+    }
+}
+
+/// Label values escape backslashes, quotes and newlines.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
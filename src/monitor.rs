@@ -0,0 +1,3689 @@
+//! The monitor itself: ingests samples from a data source, records them,
+//! watches alarms and draws the chart window.
+
+use crate::alarm::{self, Alarm, AlarmEvent, AlarmState};
+use crate::buffer::{Retention, SampleBuffer};
+use crate::clock::Clock;
+use crate::config::{
+    Config, DataConfig, ExportScope, Layout, LeakConfig, SequenceConfig, TimeAxis,
+};
+use crate::decode;
+use crate::derive::Derive;
+use crate::differential::Differential;
+use crate::downsample;
+use crate::error::MonitorError;
+use crate::filter::{FilterStage, Pipeline};
+use crate::framebuffer::FrameBuffer;
+use crate::histogram::Histogram;
+use crate::leak::{self, DecayModel, Fit};
+use crate::metrics::Metrics;
+use crate::overlay;
+use crate::publish::Publisher;
+use crate::rate::{RateOfChange, RATE_UNIT};
+use crate::recorder::Recorder;
+use crate::reference::ReferenceCurve;
+use crate::resample::Resampler;
+use crate::scale::AutoScale;
+use crate::screenshot;
+use crate::scrollback::Scrollback;
+use crate::sequence::{self, Runner, StepResult, Verdict};
+use crate::session::{
+    Session, QUALITY_AFTER_LOSS, QUALITY_AFTER_STALE, QUALITY_ALARM, QUALITY_RETAINED,
+};
+use crate::settings::{self, Adjust, Menu, Setting};
+use crate::source::{self, channel, replay, DataSource, Sample, Shutdown, Status, Topics};
+use crate::spectrum::Spectrum;
+use crate::stats::{Stats, Summary};
+use crate::store::influx::InfluxStore;
+use crate::store::jsonl::JsonlStore;
+use crate::store::ring::RingStore;
+use crate::store::sqlite::SqliteStore;
+use crate::store::Store;
+use crate::theme::Theme;
+use crate::throughput::Throughput;
+use crate::topic_list::{self, TopicList};
+use crate::units::PressureUnit;
+use crate::view::{Bounds, View};
+use crate::web::WebServer;
+use crate::webhook::Webhooks;
+use arboard::Clipboard;
+use chrono::{DateTime, Local, SecondsFormat};
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+use plotters::coord::cartesian::Cartesian2d;
+use plotters::coord::types::RangedCoordf64;
+use plotters::coord::ReverseCoordTranslate;
+use plotters::prelude::*;
+use plotters_bitmap::bitmap_pixel::BGRXPixel;
+use plotters_bitmap::BitMapBackend;
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Write;
+use std::iter;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, error, info, trace, warn};
+
+/// At most ~30 redraws a second, however fast samples arrive.
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+
+/// A scrolled chart is drawn in full again this often, so the samples added
+/// one by one match a full redraw, downsampling and gaps included.
+const SCROLL_REBUILD: Duration = Duration::from_secs(10);
+
+/// Samples are summarized in the log at most this often.
+const SAMPLE_LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Readings averaged when taring, so a noise spike isn't taken as zero.
+const TARE_SAMPLES: usize = 10;
+
+/// Shortcuts as listed in the help overlay.
+const KEYMAP: &[(&str, &str)] = &[
+    ("S", "Save the data as CSV"),
+    (
+        "Shift+S / Ctrl+S",
+        "Save the visible range / the session log",
+    ),
+    ("C", "Copy the visible samples as TSV"),
+    ("P", "Save a screenshot"),
+    ("A", "Toggle autoscale"),
+    ("Y", "Toggle linear / log Y axis"),
+    ("T", "Toggle relative / wall clock time"),
+    ("U", "Next pressure unit"),
+    ("Z / Shift+Z", "Tare / clear the tare"),
+    ("M", "Drop a marker"),
+    ("K", "Start / stop a leak test"),
+    ("E", "Show / hide the trend line and its projection"),
+    ("W", "Start / stop recording a session"),
+    (
+        "G / Enter",
+        "Start / abort the test sequence, confirm a step",
+    ),
+    ("F", "Show / hide the filtered curve"),
+    ("L", "Toggle overlay / grid layout"),
+    ("V", "Toggle time series / spectrum"),
+    ("B", "Toggle time series / histogram"),
+    ("+ / - / Wheel", "Zoom"),
+    ("Arrows / Drag", "Pan"),
+    ("R", "Reset zoom and pan"),
+    ("End", "Jump back to live, after scrolling back"),
+    ("Space", "Pause drawing"),
+    ("O", "Settings menu"),
+    ("H / F1", "This help, any key closes it"),
+    ("F2", "Show / hide the status bar"),
+    ("F3", "Topics, subscribe / unsubscribe while running"),
+    ("Esc", "Exit"),
+];
+
+/// Points of a fitted leak test curve as drawn.
+const LEAK_CURVE_POINTS: usize = 50;
+
+/// Pixels, of the circles around retained samples.
+const RETAINED_RADIUS: i32 = 4;
+
+/// How long the data source gets to disconnect on exit.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a recovered error stays in the status bar.
+const ERROR_SHOWN: Duration = Duration::from_secs(10);
+
+/// How long the path of a saved file stays in the status bar.
+const NOTICE_SHOWN: Duration = Duration::from_secs(5);
+
+/// Configures a [`PressureMonitor`], see [`PressureMonitor::builder`].
+#[derive(Default)]
+pub struct Builder {
+    config: Config,
+    source: Option<Box<dyn DataSource>>,
+    status: Status,
+    headless: bool,
+    config_path: Option<PathBuf>,
+}
+
+impl Builder {
+    /// Defaults to [`Config::default`].
+    pub fn config(mut self, config: Config) -> Builder {
+        self.config = config;
+        self
+    }
+
+    /// Where samples come from, required.
+    pub fn source(mut self, source: Box<dyn DataSource>) -> Builder {
+        self.source = Some(source);
+        self
+    }
+
+    /// The status the source reports its connection state to, shown in the
+    /// window.
+    pub fn status(mut self, status: Status) -> Builder {
+        self.status = status;
+        self
+    }
+
+    /// Run without a window, only recording and watching alarms.
+    pub fn headless(mut self, headless: bool) -> Builder {
+        self.headless = headless;
+        self
+    }
+
+    /// Where the settings menu saves to, saving is unavailable without.
+    pub fn config_path(mut self, path: impl Into<PathBuf>) -> Builder {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    /// Fails without a source, or on a config that [`Config::check`]
+    /// rejects.
+    pub fn build(self) -> Result<PressureMonitor, Box<dyn Error>> {
+        self.config
+            .check()
+            .map_err(|e| format!("Invalid config: {}", e))?;
+        Ok(PressureMonitor {
+            config: self.config,
+            source: self.source.ok_or("No data source given")?,
+            status: self.status,
+            headless: self.headless,
+            config_path: self.config_path,
+            shutdown: Shutdown::default(),
+        })
+    }
+}
+
+/// A configured monitor, started with [`PressureMonitor::run`].
+///
+/// ```no_run
+/// use pressure_monitor::config::Config;
+/// use pressure_monitor::source::{self, Status};
+/// use pressure_monitor::PressureMonitor;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let config = Config::default();
+/// let status = Status::default();
+/// let source = source::from_spec("mqtt", &config, status.clone())?;
+///
+/// PressureMonitor::builder()
+///     .config(config)
+///     .source(source)
+///     .status(status)
+///     .build()?
+///     .run()
+/// # }
+/// ```
+pub struct PressureMonitor {
+    config: Config,
+    source: Box<dyn DataSource>,
+    status: Status,
+    headless: bool,
+    config_path: Option<PathBuf>,
+    shutdown: Shutdown,
+}
+
+impl PressureMonitor {
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    /// Requesting it makes [`PressureMonitor::run`] return, as Esc in the
+    /// window does.
+    pub fn shutdown_handle(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// Runs until the window is closed or shutdown is requested, then stops
+    /// the source and flushes the stores.
+    pub fn run(self) -> Result<(), Box<dyn Error>> {
+        let PressureMonitor {
+            config,
+            source,
+            status,
+            headless,
+            config_path,
+            shutdown,
+        } = self;
+
+        let (mut w, mut h) = (config.window.width, config.window.height);
+        let topics = source.topics();
+        let mut state = State::new(config, status, config_path, topics)?;
+        let (background, axis) = (state.theme.background, state.theme.axis);
+
+        let (tx, rx) = channel::bounded(state.config.data.queue);
+        let reader = source.spawn(tx, shutdown.clone())?;
+
+        let mut buf = FrameBuffer::new(w, h);
+
+        let mut window = if headless {
+            None
+        } else {
+            let window = Window::new(
+                "Pressure Data    h=Help    <Esc>=Exit",
+                w,
+                h,
+                WindowOptions {
+                    resize: true,
+                    ..WindowOptions::default()
+                },
+            )
+            .map_err(|e| format!("Cannot open window: {} (try --headless)", e))?;
+            Some(window)
+        };
+        let typed = window.as_mut().map(topic_list::typed);
+
+        // Of the last second, also for the metrics
+        let mut status_line: Option<String> = None;
+        let mut shown_connection = None;
+        let mut shown_counts = (0, 0, 0, 0, false, false);
+        let mut cursor = None;
+        let mut scroller: Option<Scroller> = None;
+        let mut dragged_from = None;
+        // Of the time axis, as of the last loop
+        let mut axis_start = None;
+        let mut last_report = Instant::now();
+
+        loop {
+            let frame_start = Instant::now();
+
+            if shutdown.requested() {
+                break;
+            }
+            if let Some(window) = &window {
+                if !window.is_open() || window.is_key_down(Key::Escape) {
+                    break;
+                }
+            }
+
+            // Everything that arrived since the last frame, drawn once below
+            state.throughput.queued(rx.queued());
+            for sample in rx.try_iter() {
+                for event in state.ingest(sample) {
+                    state.raise(&event);
+                }
+            }
+
+            // The time axis starts at the oldest sample kept, which moves as
+            // samples are evicted. Zoomed and panned charts stay on the same
+            // moments, e.g. back in the scrollback.
+            let start = start_time(&state.series, &state.clock);
+            if let Some(moved) = axis_start.map(|previous| start - previous) {
+                if moved != 0.0 {
+                    state.panels.iter_mut().for_each(|p| p.view.shift(-moved));
+                }
+            }
+            axis_start = Some(start);
+
+            if let Some(timeout) = state.config.watchdog.timeout {
+                for s in &mut state.series {
+                    let stale = s.last_seen.elapsed().as_secs_f64() > timeout;
+                    if stale == s.stale {
+                        continue;
+                    }
+                    s.stale = stale;
+                    state.redraw = true;
+                    if stale {
+                        s.lost_when_stale = s.lost;
+                        let message = format!("No data on {} for {} s", s.topic, timeout);
+                        warn!("{}", message);
+                        if state.config.watchdog.beep {
+                            alarm::beep();
+                        }
+                        if state.config.watchdog.notify {
+                            alarm::notify("Pressure data stale", &message);
+                        }
+                    } else if s.sequence.is_none() {
+                        info!("Data on {} again", s.topic);
+                    } else if s.lost > s.lost_when_stale {
+                        // The sensor kept counting, its messages got lost
+                        let lost = s.lost - s.lost_when_stale;
+                        info!("Data on {} again, {} messages were lost", s.topic, lost);
+                    } else {
+                        info!("Data on {} again, none lost, the sensor paused", s.topic);
+                    }
+                }
+            }
+
+            if let Some(publisher) = &mut state.publisher {
+                publisher.tick();
+            }
+
+            if let (Some(test), Some(duration)) = (&state.leak_test, state.config.leak.duration) {
+                if state.clock.now() - test.start >= duration {
+                    finish_leak_test(test, &state.series, &state.config.leak);
+                    state.leak_test = None;
+                    state.redraw = true;
+                }
+            }
+
+            // A line per sample would flood the terminal at high rates
+            if last_report.elapsed() >= SAMPLE_LOG_INTERVAL {
+                if state.received > 0 {
+                    info!(
+                        "{} samples in the last {:.0?}",
+                        state.received,
+                        last_report.elapsed()
+                    );
+                    for s in &state.series {
+                        if let Some(&(_, value)) = s.data.last() {
+                            let unit = s.aux_unit.as_deref().unwrap_or("Pa");
+                            debug!("Latest on {}: {} {}", s.topic, value, unit);
+                        }
+                    }
+                }
+                state.received = 0;
+                last_report = Instant::now();
+            }
+
+            // Headless runs stop here, after recording and alarms
+            let window = match &mut window {
+                Some(window) => window,
+                None => {
+                    thread::sleep(Duration::from_millis(15));
+                    continue;
+                }
+            };
+
+            // The chart is laid out for the buffer size, so a new size means a
+            // new buffer and a full redraw, even while paused.
+            let resized = match window.get_size() {
+                (width, height) if width > 0 && height > 0 && (width, height) != (w, h) => {
+                    w = width;
+                    h = height;
+                    buf.resize(w, h);
+                    true
+                }
+                _ => false,
+            };
+
+            state.panels.resize_with(
+                grid_shape(state.layout, state.series.len()).map_or(1, |_| state.series.len()),
+                Panel::default,
+            );
+
+            // Also redraw on connection changes, no data arrives while offline.
+            let connection = state.status.get();
+            let counts = (
+                state.status.dropped(),
+                state.status.rejected(),
+                rx.overflowed(),
+                state.status.errors(),
+                state.status.last_error(ERROR_SHOWN).is_some(),
+                state
+                    .notice
+                    .as_ref()
+                    .is_some_and(|(at, _)| at.elapsed() < NOTICE_SHOWN),
+            );
+            if shown_connection != Some(connection) || shown_counts != counts {
+                shown_connection = Some(connection);
+                shown_counts = counts;
+                state.redraw = true;
+            }
+
+            let mouse = window
+                .get_mouse_pos(MouseMode::Discard)
+                .map(|(x, y)| (x as i32, y as i32));
+            if mouse != cursor {
+                cursor = mouse;
+                state.redraw = true;
+            }
+
+            if let Some((_, scroll)) = window.get_scroll_wheel() {
+                if let Some(panel) = panel_at(&mut state.panels, cursor).filter(|_| scroll != 0.0) {
+                    panel.view.zoom(0.9f64.powf(scroll.signum() as f64), cursor);
+                    state.redraw = true;
+                }
+            }
+            dragged_from = match (window.get_mouse_down(MouseButton::Left), cursor) {
+                (true, Some(pos)) => {
+                    if let Some(from) = dragged_from.filter(|&from| from != pos) {
+                        if let Some(panel) = panel_at(&mut state.panels, Some(from)) {
+                            panel.view.drag(from, pos);
+                            state.redraw = true;
+                        }
+                    }
+                    Some(pos)
+                }
+                _ => None,
+            };
+
+            // Settings changes show even while paused
+            let mut settings_changed = false;
+            for c in typed.iter().flat_map(|typed| typed.try_iter()) {
+                if let Some(list) = &mut state.topic_list {
+                    list.type_char(c);
+                    settings_changed = true;
+                }
+            }
+
+            if let Some(mut keys) = window.get_keys_pressed(KeyRepeat::No) {
+                // Any key only closes the help
+                if state.help.is_some() && !keys.is_empty() {
+                    keys.clear();
+                    state.help = None;
+                }
+                for key in keys {
+                    settings_changed |= state.handle_key(key, window, &mut buf, (w, h))?;
+                }
+            }
+
+            let draw = (state.redraw && !state.paused) || resized || settings_changed;
+            if draw {
+                state.redraw = false;
+
+                let start = start_time(&state.series, &state.clock);
+                let mut chart_data = chart_points(&state.series, start, state.unit);
+                prepend_scrollback(
+                    &mut chart_data,
+                    &state.series,
+                    &state.panels,
+                    start,
+                    state.unit,
+                );
+                let log_data;
+                let plot_data = if state.log_y {
+                    log_data = log_chart_points(&state.series, &chart_data);
+                    &log_data
+                } else {
+                    &chart_data
+                };
+
+                let leak_fits = match &state.leak_test {
+                    Some(test) => fit_leak_test(test, &state.series, state.config.leak.model),
+                    None => Vec::new(),
+                };
+                let trend_fits = if state.trend {
+                    fit_trends(&state.series, &state.panels, start)
+                } else {
+                    Vec::new()
+                };
+                let frame = Frame {
+                    config: &state.config,
+                    clock: &state.clock,
+                    series: &state.series,
+                    chart_data: &chart_data,
+                    plot_data,
+                    leak_fits: &leak_fits,
+                    trend_fits: &trend_fits,
+                    reference: state.reference.as_ref(),
+                    markers: &markers_since(&state.markers, start),
+                    start,
+                    unit: state.unit,
+                    log_y: state.log_y,
+                    time_axis: state.time_axis,
+                    autoscale: state.autoscale,
+                    show_filtered: state.show_filtered,
+                    cursor,
+                    theme: &state.theme,
+                    data: true,
+                };
+
+                // Only what is new is drawn while following the data
+                let shape = grid_shape(state.layout, state.series.len());
+                let scrolls = state.config.chart.incremental
+                    && !state.spectrum
+                    && !state.histogram
+                    && !state.trend
+                    && state.reference.as_ref().is_none_or(|r| r.start().is_none())
+                    && !state.autoscale
+                    && !state.show_filtered
+                    && state.leak_test.is_none()
+                    && state.time_axis == TimeAxis::Relative
+                    && shape.is_none()
+                    && state.series.iter().all(|s| s.aux_unit.is_none())
+                    && state.panels.first().is_some_and(|p| {
+                        p.view.is_live() && cursor.is_none_or(|pos| !p.view.contains(pos))
+                    });
+                if scrolls {
+                    let all: Vec<usize> = (0..state.series.len()).collect();
+                    let key = ScrollKey::of(&frame, &mut state.panels[0], (w, h));
+                    let stale = resized
+                        || settings_changed
+                        || scroller.as_ref().is_none_or(|s| {
+                            s.key != key || start < s.left || s.built.elapsed() >= SCROLL_REBUILD
+                        });
+                    if stale {
+                        scroller = Some(Scroller::build(&frame, &all, &mut state.panels[0], key)?);
+                    } else if let Some(s) = &mut scroller {
+                        s.update(&frame)?;
+                    }
+                    if let Some(s) = &scroller {
+                        s.compose(&mut buf);
+                    }
+                } else {
+                    scroller = None;
+                }
+
+                let root = buf.root()?;
+                if scroller.is_none() {
+                    root.fill(&background)?;
+                }
+                let areas = chart_areas(&root, &state.config, shape);
+                let groups: Vec<Vec<usize>> = match areas.len() {
+                    1 => vec![(0..state.series.len()).collect()],
+                    _ => (0..state.series.len()).map(|i| vec![i]).collect(),
+                };
+
+                let mut plot_top = None;
+                let mut stats: Vec<(&str, String, Stats)> = Vec::new();
+                let mut cursor_lines = Vec::new();
+                for ((area, group), panel) in areas.iter().zip(&groups).zip(&mut state.panels) {
+                    if state.spectrum {
+                        plot_top.get_or_insert(draw_spectrum(area, &frame, group)?);
+                        continue;
+                    }
+                    if state.histogram {
+                        plot_top.get_or_insert(draw_histogram(area, &frame, group, panel)?);
+                        continue;
+                    }
+                    let drawn = match &scroller {
+                        Some(s) => draw_decorations(area, &frame, group, s.bounds)?,
+                        None => draw_chart(area, &frame, group, panel)?,
+                    };
+                    plot_top.get_or_insert(drawn.plot_top);
+
+                    // Over the visible time span only
+                    let (x_min, x_max) = drawn.bounds.x;
+                    let visible = |&&(t, _): &&(f64, f64)| x_min <= t && t <= x_max;
+                    stats.extend(group.iter().filter_map(|&i| {
+                        let points = chart_data[i].iter().filter(visible);
+                        let stats = Stats::of(points.map(|&(_, p)| p))?;
+                        let s = &state.series[i];
+                        Some((s.topic.as_str(), s.unit_label(state.unit), stats))
+                    }));
+
+                    if let Some((t, p)) = drawn.hovered {
+                        let time = match state.time_axis {
+                            TimeAxis::Relative => format!("{:.2} s", t),
+                            TimeAxis::WallClock => wall_clock(&state.clock, start + t),
+                        };
+                        cursor_lines.push(format!("{}  {:.3} {}", time, p, state.unit));
+                        cursor_lines.extend(group.iter().filter_map(|&i| {
+                            let s = &state.series[i];
+                            interpolate(&chart_data[i], t).map(|v| {
+                                format!("{}  {:.3} {}", s.topic, v, s.unit_label(state.unit))
+                            })
+                        }));
+                    }
+                }
+
+                let mut alarms: Vec<String> = state
+                    .series
+                    .iter()
+                    .zip(&chart_data)
+                    .filter(|(s, _)| s.alarm.state() != AlarmState::Normal)
+                    .map(|(s, points)| {
+                        let value = points.last().map_or(0.0, |&(_, p)| p);
+                        format!(
+                            "ALARM {}  {}  {:.3} {}",
+                            s.alarm.state().label(),
+                            s.topic,
+                            value,
+                            state.unit
+                        )
+                    })
+                    .collect();
+                alarms.extend(
+                    state
+                        .series
+                        .iter()
+                        .filter(|s| s.rate_alarm.state() != AlarmState::Normal)
+                        .map(|s| {
+                            let rate = s.rate.as_ref().and_then(RateOfChange::last);
+                            format!(
+                                "ALARM {}  {}  {:.3} {}",
+                                s.rate_alarm.state().label(),
+                                rate_topic(&s.topic),
+                                rate.unwrap_or_default(),
+                                RATE_UNIT
+                            )
+                        }),
+                );
+                alarms.extend(state.series.iter().filter(|s| s.stale).map(
+                    |s| match s.data.last() {
+                        Some(&(t, _)) => {
+                            format!(
+                                "NO DATA on {} since {}",
+                                s.topic,
+                                wall_clock(&state.clock, t)
+                            )
+                        }
+                        None => format!("NO DATA on {}", s.topic),
+                    },
+                ));
+                overlay::draw_alarm_banner(
+                    &root,
+                    &alarms,
+                    plot_top.unwrap_or_default(),
+                    state.theme.alarm,
+                )?;
+                overlay::draw_stats(&root, &stats, axis, background)?;
+                if !cursor_lines.is_empty() {
+                    overlay::draw_cursor_readout(&root, &cursor_lines, axis, background)?;
+                }
+
+                if state.config.readout.show {
+                    let values: Vec<(String, RGBColor)> = state
+                        .series
+                        .iter()
+                        .filter_map(|s| {
+                            let &(_, value) = s.data.last()?;
+                            let color = match s.alarm.state() {
+                                AlarmState::Normal => s.color,
+                                _ => state.theme.alarm,
+                            };
+                            let mut text = format!(
+                                "{:.3} {}",
+                                s.convert(value, state.unit),
+                                s.unit_label(state.unit)
+                            );
+                            let level = &state.config.level;
+                            if level.readout && s.aux_unit.is_none() && level.applies(&s.topic) {
+                                if let Some(height) = level.height(value) {
+                                    text.push_str(&format!(" ({:.1} m)", height));
+                                }
+                            }
+                            Some((text, color))
+                        })
+                        .collect();
+                    overlay::draw_readout(
+                        &root,
+                        &values,
+                        state.config.readout.font_size,
+                        state.config.readout.position,
+                    )?;
+                }
+
+                let mut box_top = plot_top.unwrap_or_default();
+                if let Some(test) = &state.leak_test {
+                    let mut lines = vec![format!(
+                        "LEAK TEST  {:.0} s  {} fit",
+                        state.clock.now() - test.start,
+                        state.config.leak.model.label()
+                    )];
+                    lines.extend(state.series.iter().zip(&leak_fits).filter_map(|(s, fit)| {
+                        let fit = fit.as_ref()?;
+                        Some(format!(
+                            "{}  {:.3} ± {:.3} {}/s  R² {:.3}",
+                            s.topic,
+                            state.unit.from_pa(fit.rate),
+                            state.unit.from_pa(fit.rate_ci),
+                            state.unit,
+                            fit.r_squared
+                        ))
+                    }));
+                    box_top = overlay::draw_text_box(
+                        &root,
+                        &lines,
+                        box_top,
+                        state.theme.warning,
+                        background,
+                    )?;
+                }
+                if state.trend {
+                    let mut lines = vec!["TREND  of the visible samples".to_string()];
+                    lines.extend(state.series.iter().zip(&trend_fits).filter_map(|(s, fit)| {
+                        let fit = fit.as_ref()?;
+                        Some(trend_line(s, fit, state.unit))
+                    }));
+                    box_top = overlay::draw_text_box(&root, &lines, box_top, axis, background)?;
+                }
+                if let Some(curve) = state.reference.as_ref().filter(|r| r.start().is_some()) {
+                    let mut lines = vec![format!("REFERENCE  {}", curve.name)];
+                    let mut left = false;
+                    for (topic, deviation) in curve.deviations() {
+                        left |= deviation.excursions > 0;
+                        lines.push(format!(
+                            "{}  {}  {} excursions  max {:.3} {}",
+                            topic,
+                            if deviation.outside {
+                                "OUTSIDE"
+                            } else {
+                                "within"
+                            },
+                            deviation.excursions,
+                            state.unit.from_pa(deviation.max),
+                            state.unit
+                        ));
+                    }
+                    let color = if left {
+                        state.theme.alarm
+                    } else {
+                        state.theme.ok
+                    };
+                    box_top = overlay::draw_text_box(&root, &lines, box_top, color, background)?;
+                }
+                if let Some(runner) = &state.sequence {
+                    let color = match (runner.finished(), runner.passed()) {
+                        (false, _) => axis,
+                        (true, true) => state.theme.ok,
+                        (true, false) => state.theme.alarm,
+                    };
+                    let lines = runner.status(&state.config.sequence.name, state.clock.now());
+                    overlay::draw_text_box(&root, &lines, box_top, color, background)?;
+                }
+
+                let tares: Vec<String> = state
+                    .series
+                    .iter()
+                    .filter(|s| s.tare != 0.0)
+                    .map(|s| {
+                        format!(
+                            "{} {:.3} {}",
+                            s.topic,
+                            state.unit.from_pa(s.tare),
+                            state.unit
+                        )
+                    })
+                    .collect();
+                if !tares.is_empty() {
+                    overlay::draw_tare(&root, &format!("TARE  {}", tares.join("   ")), axis)?;
+                }
+
+                if let Some(menu) = &state.menu {
+                    overlay::draw_settings(
+                        &root,
+                        &menu.rows(&state.config, state.unit, state.autoscale),
+                        menu.selected(),
+                        menu.message.as_deref(),
+                        axis,
+                        background,
+                    )?;
+                }
+                if let Some(list) = &state.topic_list {
+                    overlay::draw_topics(
+                        &root,
+                        &list.rows(),
+                        list.selected(),
+                        &list.entry,
+                        list.message.as_deref(),
+                        axis,
+                        background,
+                    )?;
+                }
+
+                overlay::draw_connection_state(&root, connection, &state.theme)?;
+                let counts = [
+                    ("LOST", state.series.iter().map(|s| s.lost).sum::<u64>()),
+                    ("BAD", state.status.dropped()),
+                    ("REJECTED", state.status.rejected()),
+                    ("OVERFLOW", rx.overflowed()),
+                    ("ERRORS", state.status.errors()),
+                ];
+                let counts: Vec<String> = counts
+                    .iter()
+                    .filter(|&&(_, count)| count > 0)
+                    .map(|(label, count)| format!("{} {}", label, count))
+                    .collect();
+                if !counts.is_empty() {
+                    overlay::draw_message_counts(&root, &counts.join("  "), state.theme.warning)?;
+                }
+                if let Some(error) = state.status.last_error(ERROR_SHOWN) {
+                    overlay::draw_error(&root, &error, state.theme.warning)?;
+                }
+                if let Some((_, text)) = state
+                    .notice
+                    .as_ref()
+                    .filter(|(at, _)| at.elapsed() < NOTICE_SHOWN)
+                {
+                    overlay::draw_notice(&root, text, state.theme.ok)?;
+                }
+                if state.session.is_some() {
+                    overlay::draw_recording(&root, state.theme.alarm)?;
+                }
+                if state.paused {
+                    overlay::draw_paused(&root, state.theme.warning)?;
+                }
+                if let Some(text) = status_line.as_ref().filter(|_| state.show_status_bar) {
+                    overlay::draw_status_bar(&root, text, axis, background)?;
+                }
+
+                drop(areas);
+                drop(root);
+                state.throughput.drawn(state.clock.now());
+
+                if state.snapshot_pending {
+                    state.snapshot_pending = false;
+                    snapshot(buf.pixels(), w, h);
+                }
+                if let Some(runner) = state
+                    .sequence
+                    .as_ref()
+                    .filter(|_| state.sequence_report_pending)
+                {
+                    state.sequence_report_pending = false;
+                    save_sequence_report(runner, &state.config.sequence, buf.pixels(), w, h);
+                }
+            }
+
+            // Over a copy, so a paused chart stays as it was and screenshots
+            // leave the help out
+            if let Some(frame) = &mut state.help {
+                if draw || frame.is_empty() {
+                    frame.copy_from(&buf);
+                    let root = frame.root()?;
+                    let settings = help_settings(
+                        &state.config,
+                        state.unit,
+                        state.log_y,
+                        state.time_axis,
+                        state.autoscale,
+                        state.layout,
+                        state.show_filtered,
+                    );
+                    overlay::draw_help(&root, KEYMAP, &settings, axis, background)?;
+                }
+            }
+            window.update_with_buffer(state.help.as_ref().unwrap_or(&buf).pixels(), w, h)?;
+
+            if let Some(summary) = state.throughput.summary() {
+                if let Some(metrics) = &state.metrics {
+                    metrics.set_fps(summary.fps);
+                }
+                status_line = Some(summary.text());
+                state.redraw |= state.show_status_bar;
+            }
+
+            thread::sleep(FRAME_INTERVAL.saturating_sub(frame_start.elapsed()));
+        }
+
+        shutdown.request();
+        drop(window);
+        if !source::join_timeout(reader, SHUTDOWN_TIMEOUT) {
+            warn!("Data source did not stop within {:?}", SHUTDOWN_TIMEOUT);
+        }
+        // Samples that came in while stopping are taken like all others
+        for sample in rx.try_iter() {
+            for event in state.ingest(sample) {
+                state.raise(&event);
+            }
+        }
+        if let Some(session) = state.session.take() {
+            finish_session(session);
+        }
+
+        for store in &mut state.stores {
+            if let Err(e) = store.flush() {
+                state.status.report(MonitorError::Store(e.to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a running monitor keeps between frames, besides the window: the
+/// series with everything their samples go through, and how they are shown.
+struct State {
+    config: Config,
+    /// Where the settings menu saves to
+    config_path: Option<PathBuf>,
+    status: Status,
+    theme: Theme,
+    clock: Clock,
+    /// Of a source that subscribes to topics, for the topic list
+    topics: Option<Topics>,
+    stores: Vec<Box<dyn Store>>,
+    /// A failing store is reported once, until it works again
+    store_failed: Vec<bool>,
+    session: Option<Session>,
+    web: Option<WebServer>,
+    metrics: Option<Metrics>,
+    webhooks: Option<Webhooks>,
+    publisher: Option<Publisher>,
+    throughput: Throughput,
+    /// Series names of `[topics]`
+    names: Vec<(String, String)>,
+    derive: Derive,
+    differential: Differential,
+    resampler: Option<Resampler>,
+    series: Vec<Series>,
+    markers: Vec<Marker>,
+    /// Set with key `m`, numbered apart from the other markers
+    marker_count: usize,
+    leak_test: Option<LeakTest>,
+    reference: Option<ReferenceCurve>,
+    sequence: Option<Runner>,
+    /// Save the report of a finished sequence once its last frame is drawn
+    sequence_report_pending: bool,
+    /// Samples since the last log line
+    received: usize,
+    /// Save a screenshot once the next frame is drawn
+    snapshot_pending: bool,
+    redraw: bool,
+    autoscale: bool,
+    log_y: bool,
+    time_axis: TimeAxis,
+    unit: PressureUnit,
+    layout: Layout,
+    /// Amplitude over frequency instead of the time series
+    spectrum: bool,
+    /// Or the distribution of the visible values
+    histogram: bool,
+    show_filtered: bool,
+    trend: bool,
+    show_status_bar: bool,
+    /// Samples keep being recorded while paused, only drawing stops
+    paused: bool,
+    /// One per chart on screen
+    panels: Vec<Panel>,
+    /// The chart with the help drawn over it, while that is shown
+    help: Option<FrameBuffer>,
+    menu: Option<Menu>,
+    topic_list: Option<TopicList>,
+    /// Shown in the status bar until NOTICE_SHOWN has passed
+    notice: Option<(Instant, String)>,
+    /// Created on the first copy, and kept: on X11 what was copied is
+    /// only there as long as it is
+    clipboard: Option<Clipboard>,
+}
+
+impl State {
+    /// Opens the stores and servers of `config`, with the series reloaded
+    /// from a store that keeps them.
+    fn new(
+        config: Config,
+        status: Status,
+        config_path: Option<PathBuf>,
+        topics: Option<Topics>,
+    ) -> Result<State, Box<dyn Error>> {
+        let theme = Theme::new(&config.colors);
+
+        let mut stores: Vec<Box<dyn Store>> = Vec::new();
+        if let Some(path) = &config.log.file {
+            stores.push(Box::new(Recorder::open(
+                path,
+                Duration::from_secs_f64(config.log.flush_interval),
+                &config.log.rotation,
+            )?));
+        }
+        if let Some(path) = &config.log.jsonl {
+            stores.push(Box::new(JsonlStore::open(
+                path,
+                Duration::from_secs_f64(config.log.flush_interval),
+                &config.log.rotation,
+            )?));
+        }
+
+        let mut history = Vec::new();
+        if let Some(path) = &config.sqlite.path {
+            let store = SqliteStore::open(
+                path,
+                config.sqlite.batch_size,
+                Duration::from_secs_f64(config.sqlite.flush_interval),
+            )?;
+            // Don't start with an empty chart after a restart
+            if config.sqlite.reload_minutes > 0.0 {
+                history =
+                    store.recent(Duration::from_secs_f64(config.sqlite.reload_minutes * 60.0))?;
+            }
+            stores.push(Box::new(store));
+        }
+        if let Some(path) = &config.history.path {
+            let store = RingStore::open(
+                path,
+                Duration::from_secs_f64(config.history.hours * 3600.0),
+                Duration::from_secs_f64(config.log.flush_interval),
+            )?;
+            // Both have the same samples, draw them once
+            if history.is_empty() {
+                history = store.recent()?;
+                info!("Reloaded {} samples from {}", history.len(), path.display());
+            }
+            stores.push(Box::new(store));
+        }
+
+        if let Some(url) = &config.influx.url {
+            stores.push(Box::new(InfluxStore::open(url, &config.influx)));
+        }
+
+        let web = match &config.web.listen {
+            Some(addr) => Some(WebServer::start(addr)?),
+            None => None,
+        };
+        let metrics = match &config.metrics.listen {
+            Some(addr) => {
+                let metrics = Metrics::new(status.clone());
+                metrics.serve(addr)?;
+                Some(metrics)
+            }
+            None => None,
+        };
+        if (config.alarm.notify || config.watchdog.notify) && !cfg!(feature = "desktop-notify") {
+            warn!("Built without the desktop-notify feature, no desktop notifications");
+        }
+        let webhooks = (!config.webhook.urls.is_empty()).then(|| Webhooks::start(&config.webhook));
+        let published = [
+            &config.publish.alarm_topic,
+            &config.publish.stats_topic,
+            &config.publish.status_topic,
+        ];
+        let publisher = if published.iter().all(|topic| topic.is_none())
+            && !config.publish.home_assistant.discovery
+        {
+            None
+        } else {
+            Some(Publisher::start(&config.mqtt, &config.publish)?)
+        };
+        let names = config
+            .topics
+            .iter()
+            .filter_map(|(filter, topic)| Some((filter.clone(), topic.name.clone()?)))
+            .collect();
+
+        let clock = Clock::default();
+        let mut series: Vec<Series> = Vec::new();
+        for (ts, topic, value) in history {
+            let index = series_index(&mut series, topic, &config, &theme);
+            series[index].push(clock.offset(ts), value);
+        }
+
+        let reference = match &config.reference_curve.file {
+            Some(path) => Some(ReferenceCurve::load(path, &config.reference_curve)?),
+            None => None,
+        };
+        let session = if config.session.record {
+            start_session(&config, config_path.as_deref(), config.chart.unit, &status)
+        } else {
+            None
+        };
+
+        Ok(State {
+            store_failed: vec![false; stores.len()],
+            stores,
+            session,
+            web,
+            metrics,
+            webhooks,
+            publisher,
+            throughput: Throughput::default(),
+            names,
+            derive: Derive::new(&config.derived)?,
+            differential: Differential::new(&config.differential)?,
+            resampler: Resampler::new(&config.resample),
+            series,
+            markers: Vec::new(),
+            marker_count: 0,
+            leak_test: None,
+            reference,
+            sequence: None,
+            sequence_report_pending: false,
+            received: 0,
+            snapshot_pending: false,
+            redraw: true,
+            autoscale: config.chart.autoscale,
+            log_y: config.chart.log_y,
+            time_axis: config.chart.time_axis,
+            unit: config.chart.unit,
+            layout: config.chart.layout,
+            spectrum: false,
+            histogram: false,
+            show_filtered: config.filter.show && !config.filter.stages.is_empty(),
+            trend: config.trend.show,
+            show_status_bar: config.status_bar.show,
+            paused: false,
+            panels: Vec::new(),
+            help: None,
+            menu: None,
+            topic_list: None,
+            notice: None,
+            clipboard: None,
+            theme,
+            clock,
+            topics,
+            config_path,
+            status,
+            config,
+        })
+    }
+
+    /// Takes `sample` off the queue into its series, along with the samples
+    /// derived from it: records them, runs what follows the series and gives
+    /// the alarm events they set off, in order.
+    fn ingest(&mut self, mut sample: Sample) -> Vec<AlarmEvent> {
+        rename(&self.names, &mut sample);
+        // Derived samples go right after the one they were derived from
+        let mut incoming = match &mut self.resampler {
+            Some(resampler) => VecDeque::from(resampler.resample(sample)),
+            None => VecDeque::from([sample]),
+        };
+        let mut events = Vec::new();
+        while let Some(sample) = incoming.pop_front() {
+            let topic = sample.series();
+            if sample.expires.is_some_and(|at| at <= SystemTime::now()) {
+                debug!(%topic, "expired while queued");
+                self.status.drop_message();
+                continue;
+            }
+            // Not to be confused with the test sequence
+            let Sample {
+                value: pressure,
+                timestamp,
+                sequence: counter,
+                retained,
+                ..
+            } = sample;
+            trace!(%topic, pressure, "sample");
+            self.received += 1;
+            let derived = self
+                .derive
+                .update(&topic, pressure)
+                .into_iter()
+                .map(|(name, value)| (name, timestamp, value));
+            let differences = self
+                .differential
+                .update(&topic, timestamp.unwrap_or_else(SystemTime::now), pressure)
+                .into_iter()
+                .map(|(name, at, value)| (name, Some(at), value));
+            let computed: Vec<_> = derived.chain(differences).collect();
+            for (name, timestamp, value) in computed.into_iter().rev() {
+                incoming.push_front(Sample {
+                    topic: name,
+                    value,
+                    timestamp,
+                    sequence: None,
+                    sensor: None,
+                    expires: None,
+                    retained: false,
+                });
+            }
+
+            // Wall clock for the records, the time line for the chart
+            let now = timestamp.unwrap_or_else(SystemTime::now);
+            let t = match timestamp {
+                Some(ts) => self.clock.offset(ts),
+                None => self.clock.now(),
+            };
+            self.throughput
+                .received(self.clock.now(), timestamp.map(|_| t));
+
+            for (store, failed) in self.stores.iter_mut().zip(&mut self.store_failed) {
+                match store.write(now, &topic, pressure) {
+                    Ok(()) => *failed = false,
+                    Err(e) if !*failed => {
+                        *failed = true;
+                        self.status.report(MonitorError::Store(e.to_string()));
+                    }
+                    Err(_) => {}
+                }
+            }
+            if let Some(web) = &self.web {
+                web.publish(now, &topic, pressure);
+            }
+            if let Some(metrics) = &self.metrics {
+                metrics.record(&topic, pressure);
+            }
+            if let Some(publisher) = &mut self.publisher {
+                publisher.record(&topic, pressure);
+            }
+
+            let index = series_index(&mut self.series, topic, &self.config, &self.theme);
+            let s = &mut self.series[index];
+            let lost_before = s.lost;
+
+            if let Some(counter) = counter {
+                match s.sequence.map(|previous| sequence_gap(previous, counter)) {
+                    Some(Some(0)) | None => {}
+                    Some(Some(lost)) => {
+                        s.lost += lost;
+                        warn!(
+                            "Lost {} messages on {} before sequence {}",
+                            lost, s.topic, counter
+                        );
+                    }
+                    Some(None) => info!("Sequence of {} restarted at {}", s.topic, counter),
+                }
+                s.sequence = Some(counter);
+            }
+
+            // Alarm thresholds are pressures
+            let previous = s.alarm.state();
+            let alarm_state = match s.aux_unit {
+                Some(_) => None,
+                None => s.alarm.update(pressure),
+            };
+            if let Some(state) = alarm_state {
+                events.push(AlarmEvent {
+                    topic: s.topic.clone(),
+                    state,
+                    value: pressure,
+                    unit: "Pa",
+                    threshold: s.alarm.crossed(previous),
+                    ts: now,
+                });
+            }
+            let rate = s.rate.as_mut().and_then(|r| r.update(t, pressure));
+            if let Some(rate) = rate {
+                let previous = s.rate_alarm.state();
+                if let Some(state) = s.rate_alarm.update(rate) {
+                    events.push(AlarmEvent {
+                        topic: rate_topic(&s.topic),
+                        state,
+                        value: rate,
+                        unit: RATE_UNIT,
+                        threshold: s.rate_alarm.crossed(previous),
+                        ts: now,
+                    });
+                }
+            }
+
+            if let Some(runner) = self.sequence.as_mut().filter(|r| r.topic == s.topic) {
+                if let Some(result) = runner.update(t, pressure) {
+                    log_step(result);
+                    self.sequence_report_pending |= runner.finished();
+                }
+            }
+
+            // Against the values as drawn
+            if let Some(reference) = &mut self.reference {
+                match reference.update(&s.topic, t, pressure - s.tare) {
+                    Some(true) => {
+                        warn!("{} left the tolerance band of {}", s.topic, reference.name);
+                        self.markers.push(Marker {
+                            t,
+                            label: "out of tolerance".to_string(),
+                        });
+                    }
+                    Some(false) => {
+                        info!(
+                            "{} back within the tolerance band of {}",
+                            s.topic, reference.name
+                        )
+                    }
+                    None => {}
+                }
+            }
+
+            s.push(t, pressure);
+            if retained {
+                s.retained.push(t);
+            }
+            let recorded = self.session.as_mut().map(|session| {
+                let filtered = if s.filter.is_empty() {
+                    None
+                } else {
+                    s.filtered.last().map(|&(_, value)| value)
+                };
+                let quality = quality(s, lost_before, retained);
+                session.record(now, &s.topic, pressure, filtered, quality)
+            });
+            if let Some(Err(e)) = recorded {
+                self.status.report(MonitorError::Store(e.to_string()));
+                if let Some(session) = self.session.take() {
+                    finish_session(session);
+                }
+            }
+            s.last_seen = Instant::now();
+            self.redraw = true;
+            if let Some(rate) = rate.filter(|_| self.config.rate.show) {
+                let index = rate_series_index(&mut self.series, index, &self.config, &self.theme);
+                self.series[index].push(t, rate);
+                self.series[index].last_seen = Instant::now();
+            }
+        }
+        events
+    }
+
+    /// Logs an alarm event and passes it on as configured.
+    fn raise(&mut self, event: &AlarmEvent) {
+        if event.state == AlarmState::Normal {
+            info!("{}", event.summary());
+        } else {
+            warn!("{}", event.summary());
+        }
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.notify(event);
+        }
+        if let Some(publisher) = &mut self.publisher {
+            publisher.alarm(event);
+        }
+        if event.state != AlarmState::Normal {
+            if self.config.alarm.beep {
+                alarm::beep();
+            }
+            if self.config.alarm.notify {
+                alarm::notify("Pressure alarm", &event.summary());
+            }
+            self.snapshot_pending |= self.config.alarm.snapshot;
+        }
+    }
+
+    /// Acts on a key pressed over the chart, `size` is that of `buf`.
+    /// Whether it changed what the menus and lists show, to be drawn even
+    /// while paused.
+    fn handle_key(
+        &mut self,
+        key: Key,
+        window: &Window,
+        buf: &mut FrameBuffer,
+        size: (usize, usize),
+    ) -> Result<bool, Box<dyn Error>> {
+        // The open menu takes all keys
+        if let Some(open) = &mut self.menu {
+            let mut close = false;
+            let adjust = match key {
+                Key::Down => {
+                    open.select_next();
+                    None
+                }
+                Key::Up => {
+                    open.select_prev();
+                    None
+                }
+                Key::Right => Some(Adjust::Increase),
+                Key::Left => Some(Adjust::Decrease),
+                Key::Delete | Key::Backspace => Some(Adjust::Off),
+                Key::Enter => {
+                    let message = match &self.config_path {
+                        Some(path) => {
+                            match settings::save(path, &self.config, self.unit, self.autoscale) {
+                                Ok(()) => {
+                                    info!("Saved settings to {}", path.display());
+                                    format!("Saved to {}", path.display())
+                                }
+                                Err(e) => {
+                                    error!("Cannot save settings: {}", e);
+                                    e.to_string()
+                                }
+                            }
+                        }
+                        None => "No config file to save to".to_string(),
+                    };
+                    open.message = Some(message);
+                    None
+                }
+                Key::O => {
+                    close = true;
+                    None
+                }
+                _ => None,
+            };
+            if let Some(adjust) = adjust {
+                let setting = open.adjust(
+                    adjust,
+                    &mut self.config,
+                    &mut self.unit,
+                    &mut self.autoscale,
+                );
+                apply_setting(setting, &self.config, &mut self.series);
+                if setting == Setting::Smoothing {
+                    self.show_filtered = !self.config.filter.stages.is_empty();
+                }
+                // The zoomed Y range would hide the change
+                if matches!(
+                    setting,
+                    Setting::Autoscale | Setting::YMin | Setting::YMax | Setting::Unit
+                ) {
+                    self.panels.iter_mut().for_each(Panel::reset);
+                }
+            }
+            if close {
+                self.menu = None;
+            }
+            return Ok(true);
+        }
+        // So does the topic list, typing a topic
+        if let Some(list) = &mut self.topic_list {
+            match key {
+                Key::Down => list.select_next(),
+                Key::Up => list.select_prev(),
+                Key::Enter | Key::NumPadEnter => list.add(),
+                Key::Backspace => list.erase(),
+                Key::Delete => list.remove(),
+                Key::F3 => self.topic_list = None,
+                _ => {}
+            }
+            return Ok(true);
+        }
+        match key {
+            Key::O => {
+                self.menu = Some(Menu::default());
+                return Ok(true);
+            }
+            Key::S => {
+                let scope = if window.is_key_down(Key::LeftCtrl)
+                    || window.is_key_down(Key::RightCtrl)
+                {
+                    ExportScope::Session
+                } else if window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift)
+                {
+                    ExportScope::Visible
+                } else {
+                    self.config.export.scope
+                };
+                // The log on disk has to be complete to be read back
+                if scope == ExportScope::Session {
+                    for store in &mut self.stores {
+                        store.flush().ok();
+                    }
+                    if let Some(session) = &mut self.session {
+                        session.flush().ok();
+                    }
+                }
+                let log = match &self.session {
+                    Some(session) => Some(session.data_path()),
+                    None => self.config.log.file.clone(),
+                };
+
+                let start = start_time(&self.series, &self.clock);
+                let path = export_path(&self.config.export.dir);
+                let saved = export_points(
+                    scope,
+                    &self.series,
+                    &self.panels,
+                    &self.clock,
+                    start,
+                    self.unit,
+                    log.as_deref(),
+                )
+                .and_then(|(mut chart_data, range)| {
+                    let exported = markers_within(&self.markers, start, range);
+                    fs::create_dir_all(&self.config.export.dir)?;
+                    let filtered = filtered_points(&self.series, &chart_data, start, self.unit);
+                    chart_data.extend(filtered);
+                    save_csv(
+                        &path,
+                        &self.series,
+                        &chart_data,
+                        &exported,
+                        self.unit,
+                        |t| self.clock.wall(start + t),
+                        &self.config.session.sensors,
+                    )?;
+                    match self.config.export.summary_interval {
+                        Some(interval) => save_summary(
+                            &summary_path(&path),
+                            &self.series,
+                            &chart_data[..self.series.len()],
+                            interval,
+                            self.unit,
+                            |t| self.clock.wall(start + t),
+                        ),
+                        None => Ok(()),
+                    }
+                });
+                match saved {
+                    Ok(()) => {
+                        info!("Saved the {} to {}", scope.label(), path.display());
+                        self.notice = Some((Instant::now(), format!("Saved {}", path.display())));
+                    }
+                    Err(e) => self.status.report(MonitorError::Save {
+                        path: path.display().to_string(),
+                        reason: e.to_string(),
+                    }),
+                }
+            }
+            Key::C => {
+                let start = start_time(&self.series, &self.clock);
+                let copied = export_points(
+                    ExportScope::Visible,
+                    &self.series,
+                    &self.panels,
+                    &self.clock,
+                    start,
+                    self.unit,
+                    None,
+                )
+                .and_then(|(mut chart_data, range)| {
+                    let filtered = filtered_points(&self.series, &chart_data, start, self.unit);
+                    chart_data.extend(filtered);
+                    let mut tsv = csv::WriterBuilder::new()
+                        .delimiter(b'\t')
+                        .from_writer(Vec::new());
+                    write_table(
+                        &mut tsv,
+                        &table_columns(&self.series, self.unit),
+                        &chart_data,
+                        &markers_within(&self.markers, start, range),
+                        |t| self.clock.wall(start + t),
+                    )?;
+                    let tsv = tsv.into_inner().map_err(|e| e.error().to_string())?;
+                    copy_text(&mut self.clipboard, String::from_utf8(tsv)?)?;
+                    Ok(chart_data.iter().map(Vec::len).sum::<usize>())
+                });
+                match copied {
+                    Ok(count) => {
+                        info!("Copied {} samples to the clipboard", count);
+                        self.notice = Some((Instant::now(), format!("Copied {} samples", count)));
+                    }
+                    Err(e) => self.status.report(MonitorError::Copy(e.to_string())),
+                }
+            }
+            Key::W => {
+                self.session = match self.session.take() {
+                    Some(session) => {
+                        finish_session(session);
+                        None
+                    }
+                    None => start_session(
+                        &self.config,
+                        self.config_path.as_deref(),
+                        self.unit,
+                        &self.status,
+                    ),
+                };
+                self.redraw = true;
+            }
+            Key::E => {
+                self.trend = !self.trend;
+                self.redraw = true;
+            }
+            Key::K => {
+                match self.leak_test.take() {
+                    Some(test) => finish_leak_test(&test, &self.series, &self.config.leak),
+                    None => {
+                        info!("Leak test started");
+                        self.leak_test = Some(LeakTest {
+                            start: self.clock.now(),
+                            started: SystemTime::now(),
+                        });
+                    }
+                }
+                self.redraw = true;
+            }
+            Key::G => {
+                match self.sequence.as_ref().map(Runner::finished) {
+                    None => {
+                        self.sequence = start_sequence(
+                            &self.config.sequence,
+                            &self.series,
+                            self.clock.now(),
+                            self.unit,
+                        );
+                    }
+                    Some(false) => {
+                        if let Some(runner) = &mut self.sequence {
+                            if let Some(result) = runner.abort(self.clock.now()) {
+                                log_step(result);
+                            }
+                        }
+                        self.sequence_report_pending = true;
+                    }
+                    // Closes the results
+                    Some(true) => self.sequence = None,
+                }
+                self.redraw = true;
+            }
+            Key::Enter => {
+                if let Some(runner) = &mut self.sequence {
+                    if let Some(result) = runner.confirm(self.clock.now()) {
+                        log_step(result);
+                        self.sequence_report_pending |= runner.finished();
+                    }
+                }
+                self.redraw = true;
+            }
+            Key::M => {
+                self.marker_count += 1;
+                let marker = Marker {
+                    t: self.clock.now(),
+                    label: format!("M{}", self.marker_count),
+                };
+                info!("Marker {} set", marker.label);
+                if let Some(reference) = &mut self.reference {
+                    reference.align(marker.t);
+                    info!("{} starts at marker {}", reference.name, marker.label);
+                }
+                if let Some(session) = &mut self.session {
+                    if let Err(e) = session.annotate(SystemTime::now(), &marker.label) {
+                        self.status.report(MonitorError::Store(e.to_string()));
+                    }
+                }
+                self.markers.push(marker);
+                self.redraw = true;
+            }
+            Key::P => {
+                snapshot(buf.pixels(), size.0, size.1);
+            }
+            Key::A => {
+                self.autoscale = !self.autoscale;
+                self.panels.iter_mut().for_each(|p| {
+                    p.y_scale.reset();
+                    p.secondary_scale.reset();
+                });
+                self.redraw = true;
+            }
+            Key::Y => {
+                self.log_y = !self.log_y;
+                // Zoom and autoscale ranges are in the old scale
+                self.panels.iter_mut().for_each(Panel::reset);
+                self.redraw = true;
+            }
+            Key::U => {
+                self.unit = self.unit.next();
+                // A zoomed Y range is in the old unit
+                self.panels.iter_mut().for_each(Panel::reset);
+                self.redraw = true;
+            }
+            Key::Z => {
+                let clear =
+                    window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+                for s in self.series.iter_mut().filter(|s| s.aux_unit.is_none()) {
+                    s.tare = if clear {
+                        0.0
+                    } else {
+                        s.recent_mean(TARE_SAMPLES)
+                    };
+                    info!("Tare of {} set to {} Pa", s.topic, s.tare);
+                }
+                // The zoomed Y range is of the old zero
+                self.panels.iter_mut().for_each(Panel::reset);
+                self.redraw = true;
+            }
+            Key::F => {
+                self.show_filtered = !self.show_filtered && !self.config.filter.stages.is_empty();
+                self.redraw = true;
+            }
+            Key::V => {
+                self.spectrum = !self.spectrum;
+                self.histogram = false;
+                self.redraw = true;
+            }
+            Key::B => {
+                self.histogram = !self.histogram;
+                self.spectrum = false;
+                self.redraw = true;
+            }
+            Key::L => {
+                self.layout = match self.layout {
+                    Layout::Overlay => Layout::Grid,
+                    Layout::Grid => Layout::Overlay,
+                };
+                // The zoom was of different charts
+                self.panels.clear();
+                self.redraw = true;
+            }
+            Key::Equal | Key::NumPadPlus => {
+                self.panels.iter_mut().for_each(|p| p.view.zoom(0.8, None));
+                self.redraw = true;
+            }
+            Key::Minus | Key::NumPadMinus => {
+                self.panels.iter_mut().for_each(|p| p.view.zoom(1.25, None));
+                self.redraw = true;
+            }
+            Key::Left | Key::Right | Key::Up | Key::Down => {
+                let (fx, fy) = match key {
+                    Key::Left => (-0.1, 0.0),
+                    Key::Right => (0.1, 0.0),
+                    Key::Up => (0.0, 0.1),
+                    _ => (0.0, -0.1),
+                };
+                self.panels.iter_mut().for_each(|p| p.view.pan(fx, fy));
+                self.redraw = true;
+            }
+            Key::R => {
+                self.panels.iter_mut().for_each(Panel::reset);
+                self.redraw = true;
+            }
+            Key::End => {
+                self.panels.iter_mut().for_each(|p| p.view.reset());
+                self.redraw = true;
+            }
+            Key::T => {
+                self.time_axis = match self.time_axis {
+                    TimeAxis::Relative => TimeAxis::WallClock,
+                    TimeAxis::WallClock => TimeAxis::Relative,
+                };
+                self.redraw = true;
+            }
+            Key::H | Key::F1 => {
+                self.help = Some(FrameBuffer::default());
+            }
+            Key::F2 => {
+                self.show_status_bar = !self.show_status_bar;
+                self.redraw = true;
+            }
+            Key::F3 => {
+                self.topic_list = Some(TopicList::new(self.topics.clone()));
+                return Ok(true);
+            }
+            Key::Space => {
+                self.paused = !self.paused;
+                if self.paused {
+                    // Mark the frozen frame as is
+                    let root = buf.root()?;
+                    overlay::draw_paused(&root, self.theme.warning)?;
+                } else {
+                    self.redraw = true;
+                }
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+}
+
+/// What the charts of one frame share.
+struct Frame<'a> {
+    config: &'a Config,
+    clock: &'a Clock,
+    series: &'a [Series],
+    chart_data: &'a [Vec<(f64, f64)>],
+    /// `chart_data` as drawn, in decades on a log Y axis
+    plot_data: &'a [Vec<(f64, f64)>],
+    /// Of the running leak test, like `series`, empty without one
+    leak_fits: &'a [Option<Fit>],
+    /// Of the visible samples, like `series`, empty when not shown
+    trend_fits: &'a [Option<Fit>],
+    reference: Option<&'a ReferenceCurve>,
+    /// On the time axis, like `chart_data`
+    markers: &'a [(f64, &'a str)],
+    /// Where on the time line the time axis starts
+    start: f64,
+    unit: PressureUnit,
+    log_y: bool,
+    time_axis: TimeAxis,
+    autoscale: bool,
+    show_filtered: bool,
+    cursor: Option<(i32, i32)>,
+    theme: &'a Theme,
+    /// Draw the series, markers and legend, not only the axes and grid
+    data: bool,
+}
+
+/// A leak test in progress.
+struct LeakTest {
+    /// On the time line
+    start: f64,
+    started: SystemTime,
+}
+
+/// Fits of the pressures since the test started, like `series`. Only the
+/// samples still held are fitted, the retention limits the test length.
+fn fit_leak_test(test: &LeakTest, series: &[Series], model: DecayModel) -> Vec<Option<Fit>> {
+    series
+        .iter()
+        .map(|s| {
+            if s.aux_unit.is_some() {
+                return None;
+            }
+            let samples: Vec<(f64, f64)> = s
+                .data
+                .iter()
+                .copied()
+                .filter(|&(t, _)| t >= test.start)
+                .collect();
+            Fit::of(&samples, model)
+        })
+        .collect()
+}
+
+/// The slope of `fit` per hour, and the time until the pressure reaches
+/// the alarm threshold it is heading for.
+fn trend_line(s: &Series, fit: &Fit, unit: PressureUnit) -> String {
+    let mut line = format!(
+        "{}  {:+.3} {}/h",
+        s.topic,
+        unit.from_pa(fit.rate * 3600.0),
+        unit
+    );
+    let last = fit.value_at(fit.t0 + fit.duration);
+    let heading = if fit.rate < 0.0 {
+        AlarmState::Low
+    } else {
+        AlarmState::High
+    };
+    if let Some(threshold) = s.alarm.threshold(heading) {
+        let seconds = (threshold - last) / fit.rate;
+        if seconds.is_finite() && seconds > 0.0 {
+            line.push_str(&format!(
+                "  {} alarm in {:.1} h",
+                heading.label().to_lowercase(),
+                seconds / 3600.0
+            ));
+        }
+    }
+    line
+}
+
+/// Lines fitted to the samples of each series shown the last frame, like
+/// `series`.
+fn fit_trends(series: &[Series], panels: &[Panel], start: f64) -> Vec<Option<Fit>> {
+    series
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            if s.aux_unit.is_some() {
+                return None;
+            }
+            // In a grid every series has a chart of its own
+            let (x0, x1) = panels.get(i).or(panels.first())?.view.shown().x;
+            let samples: Vec<(f64, f64)> = s
+                .data
+                .iter()
+                .copied()
+                .filter(|&(t, _)| (x0..=x1).contains(&(t - start)))
+                .collect();
+            Fit::of(&samples, DecayModel::Linear)
+        })
+        .collect()
+}
+
+/// Starts recording a session, reporting why it can't.
+fn start_session(
+    config: &Config,
+    config_path: Option<&Path>,
+    unit: PressureUnit,
+    status: &Status,
+) -> Option<Session> {
+    match Session::start(config, config_path, unit) {
+        Ok(session) => {
+            info!("Recording session to {}", session.dir().display());
+            Some(session)
+        }
+        Err(e) => {
+            status.report(MonitorError::Save {
+                path: config.session.dir.display().to_string(),
+                reason: e.to_string(),
+            });
+            None
+        }
+    }
+}
+
+fn finish_session(session: Session) {
+    match session.finish() {
+        Ok(dir) => info!("Saved session {}", dir.display()),
+        Err(e) => error!("{}", e),
+    }
+}
+
+/// Logs the results of a test and writes its report.
+fn finish_leak_test(test: &LeakTest, series: &[Series], config: &LeakConfig) {
+    let fits: Vec<(&str, Fit)> = series
+        .iter()
+        .zip(fit_leak_test(test, series, config.model))
+        .filter_map(|(s, fit)| Some((s.topic.as_str(), fit?)))
+        .collect();
+    for (topic, fit) in &fits {
+        info!(
+            "Leak rate of {}: {:.4} ± {:.4} Pa/s over {:.1} s",
+            topic, fit.rate, fit.rate_ci, fit.duration
+        );
+    }
+
+    let name = Local::now().format("leak_test_%Y-%m-%dT%H-%M-%S.txt");
+    let path = config.report_dir.join(name.to_string());
+    match leak::write_report(&path, test.started, config.volume, &fits) {
+        Ok(()) => info!("Saved leak test report {}", path.display()),
+        Err(e) => error!("{}", e),
+    }
+}
+
+/// Starts the configured sequence on its sensor, or the first pressure.
+fn start_sequence(
+    config: &SequenceConfig,
+    series: &[Series],
+    t: f64,
+    unit: PressureUnit,
+) -> Option<Runner> {
+    if config.steps.is_empty() {
+        warn!("No [sequence] steps configured");
+        return None;
+    }
+    let tested = series.iter().filter(|s| s.aux_unit.is_none()).find(|s| {
+        config
+            .topic
+            .as_deref()
+            .is_none_or(|filter| decode::topic_matches(filter, &s.topic))
+    });
+    match tested {
+        Some(s) => {
+            info!("Test sequence {} started on {}", config.name, s.topic);
+            Some(Runner::new(&config.steps, &s.topic, t, unit))
+        }
+        None => {
+            warn!("No data to run the test sequence on yet");
+            None
+        }
+    }
+}
+
+fn log_step(result: &StepResult) {
+    let verdict = result.verdict.label();
+    match result.verdict {
+        Verdict::Pass => info!("{}: {}, {}", result.prompt, verdict, result.detail),
+        Verdict::Fail => warn!("{}: {}, {}", result.prompt, verdict, result.detail),
+    }
+}
+
+/// Writes the report of a finished sequence, with the frame showing its
+/// results as the chart.
+fn save_sequence_report(
+    runner: &Runner,
+    config: &SequenceConfig,
+    frame: &[u32],
+    w: usize,
+    h: usize,
+) {
+    let verdict = if runner.passed() { "passed" } else { "failed" };
+    info!("Test sequence {} {}", config.name, verdict);
+
+    let name = Local::now().format("sequence_%Y-%m-%dT%H-%M-%S.html");
+    let path = config.report_dir.join(name.to_string());
+    let saved = screenshot::encode_png(frame, w, h).and_then(|png| {
+        sequence::write_report(
+            &path,
+            &config.name,
+            runner,
+            config.operator.as_deref(),
+            &png,
+        )
+    });
+    match saved {
+        Ok(()) => info!("Saved test sequence report {}", path.display()),
+        Err(e) => error!("{}", e),
+    }
+}
+
+/// Messages missing between the counters `previous` and `sequence`, `None`
+/// when the counter went back, as after a restart of the sensor. 16 bit
+/// counters wrap around.
+fn sequence_gap(previous: u64, sequence: u64) -> Option<u64> {
+    const WRAP: u64 = u16::MAX as u64 + 1;
+    if sequence >= previous {
+        // A repeated counter is a duplicate, nothing lost
+        return Some((sequence - previous).saturating_sub(1));
+    }
+    if previous < WRAP && previous - sequence > WRAP / 2 {
+        return Some(sequence + WRAP - previous - 1);
+    }
+    None
+}
+
+/// Zoom and autoscale state of one chart.
+#[derive(Default)]
+struct Panel {
+    view: View,
+    y_scale: AutoScale,
+    /// Of the right hand axis, which doesn't zoom
+    secondary_scale: AutoScale,
+}
+
+impl Panel {
+    fn reset(&mut self) {
+        self.view.reset();
+        self.y_scale.reset();
+        self.secondary_scale.reset();
+    }
+}
+
+/// A moment marked with `m`, e.g. when a valve was opened, or where a
+/// series left the reference curve.
+struct Marker {
+    /// On the `Clock` time line
+    t: f64,
+    label: String,
+}
+
+/// The markers on the time axis starting at `start`.
+fn markers_since(markers: &[Marker], start: f64) -> Vec<(f64, &str)> {
+    markers
+        .iter()
+        .map(|m| (m.t - start, m.label.as_str()))
+        .collect()
+}
+
+/// `markers_since` within the time range on that axis, if any.
+fn markers_within(markers: &[Marker], start: f64, range: Option<(f64, f64)>) -> Vec<(f64, &str)> {
+    let mut within = markers_since(markers, start);
+    if let Some((x0, x1)) = range {
+        within.retain(|&(t, _)| (x0..=x1).contains(&t));
+    }
+    within
+}
+
+/// Where a chart ended up on screen.
+struct Drawn {
+    bounds: Bounds,
+    /// First pixel row of the plotting area
+    plot_top: i32,
+    /// The plotting area in pixels
+    plot: (Range<i32>, Range<i32>),
+    /// Chart coordinates under the cursor, if it is over the chart
+    hovered: Option<(f64, f64)>,
+}
+
+/// Rows and columns of the grid, `None` when everything goes on one chart.
+fn grid_shape(layout: Layout, count: usize) -> Option<(usize, usize)> {
+    if layout == Layout::Overlay || count < 2 {
+        return None;
+    }
+    let cols = (count as f64).sqrt().ceil() as usize;
+    Some((count.div_ceil(cols), cols))
+}
+
+/// Where the charts go on `root`, below the readout.
+fn chart_areas<'a>(
+    root: &overlay::Root<'a>,
+    config: &Config,
+    shape: Option<(usize, usize)>,
+) -> Vec<overlay::Root<'a>> {
+    let charts = if config.readout.show {
+        root.margin(config.readout.font_size + 30, 0, 0, 0)
+    } else {
+        root.clone()
+    };
+    match shape {
+        Some(shape) => charts.split_evenly(shape),
+        None => vec![charts],
+    }
+}
+
+/// What scrolled data was drawn with, it is drawn anew once any changes.
+#[derive(PartialEq)]
+struct ScrollKey {
+    size: (usize, usize),
+    unit: PressureUnit,
+    log_y: bool,
+    bounds: Bounds,
+    /// Line color and tare of each series
+    series: Vec<(RGBColor, f64)>,
+}
+
+impl ScrollKey {
+    fn of(frame: &Frame, panel: &mut Panel, size: (usize, usize)) -> ScrollKey {
+        let all: Vec<usize> = (0..frame.series.len()).collect();
+        let live = live_bounds(frame, &all, panel);
+        ScrollKey {
+            size,
+            unit: frame.unit,
+            log_y: frame.log_y,
+            bounds: panel.view.bounds(live),
+            series: frame
+                .series
+                .iter()
+                .map(|s| (line_colors(s, frame.theme, false).0, s.tare))
+                .collect(),
+        }
+    }
+}
+
+/// A single chart following the data, kept between frames: the axes and
+/// grid, and the data lines on a layer the size of the plotting area that
+/// is shifted left as time goes by, so only new samples need drawing.
+struct Scroller {
+    chrome: FrameBuffer,
+    layer: FrameBuffer,
+    /// The plotting area in pixels
+    plot: (Range<i32>, Range<i32>),
+    bounds: Bounds,
+    /// The `start` the left edge of the layer is at
+    left: f64,
+    /// Time of the newest sample drawn, per series
+    drawn_until: Vec<f64>,
+    /// The background pixel, where the layer shows the chrome
+    fill: u32,
+    key: ScrollKey,
+    built: Instant,
+}
+
+impl Scroller {
+    fn build(
+        frame: &Frame,
+        group: &[usize],
+        panel: &mut Panel,
+        key: ScrollKey,
+    ) -> Result<Scroller, Box<dyn Error>> {
+        let (w, h) = key.size;
+        let mut chrome = FrameBuffer::new(w, h);
+        let drawn = {
+            let root = chrome.root()?;
+            root.fill(&frame.theme.background)?;
+            let areas = chart_areas(&root, frame.config, None);
+            draw_chart(
+                &areas[0],
+                &Frame {
+                    data: false,
+                    ..*frame
+                },
+                group,
+                panel,
+            )?
+        };
+
+        let (x, y) = &drawn.plot;
+        let mut layer = FrameBuffer::new(
+            (x.end - x.start).max(1) as usize,
+            (y.end - y.start).max(1) as usize,
+        );
+        layer.root()?.fill(&frame.theme.background)?;
+        let fill = layer.pixels().first().copied().unwrap_or_default();
+        let mut scroller = Scroller {
+            chrome,
+            layer,
+            plot: drawn.plot,
+            bounds: drawn.bounds,
+            left: frame.start,
+            drawn_until: vec![f64::NEG_INFINITY; frame.series.len()],
+            fill,
+            key,
+            built: Instant::now(),
+        };
+        scroller.draw_new(frame)?;
+        Ok(scroller)
+    }
+
+    /// Shifts the layer by the whole pixels `start` moved, then draws the
+    /// samples added since.
+    fn update(&mut self, frame: &Frame) -> Result<(), Box<dyn Error>> {
+        let width = (self.plot.0.end - self.plot.0.start).max(1);
+        let per_pixel = (self.bounds.x.1 - self.bounds.x.0) / width as f64;
+        let dx = ((frame.start - self.left) / per_pixel).floor();
+        if dx >= 1.0 {
+            self.layer.scroll_left(dx as usize, self.fill);
+            self.left += dx * per_pixel;
+        }
+        self.draw_new(frame)
+    }
+
+    fn draw_new(&mut self, frame: &Frame) -> Result<(), Box<dyn Error>> {
+        let ((x_min, x_max), (y_min, y_max)) = (self.bounds.x, self.bounds.y);
+        // On the time line, the layer doesn't move with `start`
+        let x = (self.left + x_min, self.left + x_max);
+        let root = self.layer.root()?;
+        let mut chart = ChartBuilder::on(&root).build_cartesian_2d(x.0..x.1, y_min..y_max)?;
+        let budget = draw_budget(frame.config, &self.plot);
+        for (i, s) in frame.series.iter().enumerate() {
+            let points = &frame.plot_data[i];
+            let drawn = points.partition_point(|&(t, _)| frame.start + t <= self.drawn_until[i]);
+            let Some(&(last, _)) = points.last().filter(|_| drawn < points.len()) else {
+                continue;
+            };
+
+            // From the last one drawn, to continue its line
+            let new: Vec<(f64, f64)> = points[drawn.saturating_sub(1)..]
+                .iter()
+                .map(|&(t, p)| (frame.start + t, p))
+                .collect();
+            let (_, color) = line_colors(s, frame.theme, false);
+            let max_gap = max_gap(frame.config, &frame.chart_data[i]);
+            chart.draw_series(
+                runs(&new, x, max_gap, budget)
+                    .into_iter()
+                    .map(|line| PathElement::new(line, &color)),
+            )?;
+            let circles = retained_points(s, points, frame.start)
+                .into_iter()
+                .map(|(t, p)| (frame.start + t, p))
+                .filter(|&(t, _)| t > self.drawn_until[i])
+                .map(|at| Circle::new(at, RETAINED_RADIUS, color.stroke_width(2)));
+            chart.draw_series(circles)?;
+            self.drawn_until[i] = frame.start + last;
+        }
+        Ok(())
+    }
+
+    /// The axes with the data over them into `buf`, of the same size.
+    fn compose(&self, buf: &mut FrameBuffer) {
+        buf.copy_from(&self.chrome);
+        let at = (
+            self.plot.0.start.max(0) as usize,
+            self.plot.1.start.max(0) as usize,
+        );
+        buf.blend(&self.layer, at, self.fill);
+    }
+}
+
+/// The markers and legend of a chart `Scroller` composed in `area`.
+fn draw_decorations(
+    area: &overlay::Root<'_>,
+    frame: &Frame,
+    group: &[usize],
+    bounds: Bounds,
+) -> Result<Drawn, Box<dyn Error>> {
+    let ((x_min, x_max), (y_min, y_max)) = (bounds.x, bounds.y);
+    let mut chart = ChartBuilder::on(area)
+        .margin(10)
+        .set_all_label_area_size(50)
+        .build_cartesian_2d(x_min..x_max, y_min..y_max)?;
+    draw_markers(&mut chart, frame.markers, bounds, frame.theme.axis)?;
+    draw_legend(&mut chart, frame, group)?;
+
+    let plot = chart.plotting_area().get_pixel_range();
+    Ok(Drawn {
+        bounds,
+        plot_top: plot.1.start,
+        plot,
+        hovered: None,
+    })
+}
+
+/// The chart under the mouse, or the first one.
+fn panel_at(panels: &mut [Panel], pos: Option<(i32, i32)>) -> Option<&mut Panel> {
+    let index = pos
+        .and_then(|pos| panels.iter().position(|p| p.view.contains(pos)))
+        .unwrap_or(0);
+    panels.get_mut(index)
+}
+
+type Chart<'a, 'b> =
+    ChartContext<'a, BitMapBackend<'b, BGRXPixel>, Cartesian2d<RangedCoordf64, RangedCoordf64>>;
+
+/// Draws the series with the indices in `group` on a chart filling `area`.
+fn draw_chart(
+    area: &overlay::Root<'_>,
+    frame: &Frame,
+    group: &[usize],
+    panel: &mut Panel,
+) -> Result<Drawn, Box<dyn Error>> {
+    let Frame {
+        config,
+        unit,
+        log_y,
+        theme,
+        show_filtered,
+        ..
+    } = *frame;
+    let axis = theme.axis;
+    let (secondary, primary): (Vec<usize>, Vec<usize>) = group
+        .iter()
+        .partition(|&&i| frame.series[i].aux_unit.is_some());
+
+    let live = live_bounds(frame, &primary, panel);
+    let bounds = panel.view.bounds(live);
+    let ((x_min, x_max), (y_min, y_max)) = (bounds.x, bounds.y);
+    let rates_only = secondary
+        .iter()
+        .all(|&i| frame.series[i].aux_unit.as_deref() == Some(RATE_UNIT));
+    // Heights of the pressures on the right hand axis, if not taken
+    let level = &config.level;
+    let level_axis = level.axis
+        && secondary.is_empty()
+        && primary
+            .iter()
+            .any(|&i| level.applies(&frame.series[i].topic));
+    let (y2_min, y2_max) = match data_bounds(secondary.iter().map(|&i| &frame.chart_data[i])) {
+        // The configured range is of the auxiliary signals
+        Some((min, max)) if frame.autoscale || rates_only => panel.secondary_scale.update(min, max),
+        _ if level_axis => (y_min, y_max),
+        _ => config.secondary.range,
+    };
+
+    let mut chart = ChartBuilder::on(area)
+        .margin(10)
+        .set_all_label_area_size(50)
+        .build_cartesian_2d(x_min..x_max, y_min..y_max)?
+        .set_secondary_coord(x_min..x_max, y2_min..y2_max);
+
+    let format_wall_clock = |x: &f64| wall_clock(frame.clock, frame.start + *x);
+    let format_log = |y: &f64| log_label(*y);
+    let mut mesh = chart.configure_mesh();
+    mesh.label_style(("sans-serif", 15).into_font().color(&axis))
+        .axis_style(&axis)
+        .y_desc(if log_y {
+            format!("Pressure ({}, log)", unit)
+        } else {
+            format!("Pressure ({})", unit)
+        })
+        .bold_line_style(&axis.mix(0.2))
+        .light_line_style(&TRANSPARENT);
+    if frame.time_axis == TimeAxis::WallClock {
+        mesh.x_label_formatter(&format_wall_clock);
+    }
+    if log_y {
+        mesh.y_label_formatter(&format_log);
+    }
+    mesh.draw()?;
+    if !secondary.is_empty() {
+        chart
+            .configure_secondary_axes()
+            .label_style(("sans-serif", 15).into_font().color(&axis))
+            .axis_style(&axis)
+            .x_labels(0)
+            .y_desc(if rates_only {
+                format!("dP/dt ({})", RATE_UNIT)
+            } else {
+                format!("{} ({})", config.secondary.label, config.secondary.unit)
+            })
+            .draw()?;
+    } else if let (true, Some(kind)) = (level_axis, level.kind) {
+        let format_height = |y: &f64| {
+            let value = if log_y { 10f64.powf(*y) } else { *y };
+            level
+                .height(unit.to_pa(value))
+                .map_or_else(String::new, |m| format!("{:.1}", m))
+        };
+        chart
+            .configure_secondary_axes()
+            .label_style(("sans-serif", 15).into_font().color(&axis))
+            .axis_style(&axis)
+            .x_labels(0)
+            .y_label_formatter(&format_height)
+            .y_desc(format!("{} (m)", kind.label()))
+            .draw()?;
+    }
+    let plot = chart.plotting_area().get_pixel_range();
+    panel.view.drawn(bounds, plot.clone());
+
+    // Behind the data
+    let curve = frame.reference.filter(|r| r.start().is_some());
+    let compared = curve.filter(|r| primary.iter().any(|&i| r.applies(&frame.series[i].topic)));
+    if let Some(curve) = compared {
+        let y = |pa: f64| {
+            let p = unit.from_pa(pa);
+            if log_y {
+                p.log10()
+            } else {
+                p
+            }
+        };
+        let points: Vec<(f64, f64, f64, f64)> = curve
+            .points()
+            .into_iter()
+            .map(|(t, p, tolerance)| (t - frame.start, y(p - tolerance), y(p), y(p + tolerance)))
+            .filter(|&(t, low, _, _)| x_min <= t && t <= x_max && low.is_finite())
+            .collect();
+        let band: Vec<(f64, f64)> = points
+            .iter()
+            .map(|&(t, _, _, high)| (t, high))
+            .chain(points.iter().rev().map(|&(t, low, _, _)| (t, low)))
+            .collect();
+        let line: Vec<(f64, f64)> = points.iter().map(|&(t, _, p, _)| (t, p)).collect();
+        chart.draw_series([Polygon::new(band, theme.ok.mix(0.15).filled())])?;
+        chart.draw_series([PathElement::new(line, theme.ok.stroke_width(2))])?;
+    }
+    for reference in &config.chart.references {
+        let value = unit.from_pa(reference.unit.to_pa(reference.value));
+        let value = if log_y { value.log10() } else { value };
+        // NaN below zero on a log axis
+        if !(y_min..=y_max).contains(&value) {
+            continue;
+        }
+        let color = reference.color.map_or(axis, |color| color.rgb());
+        let line = color.mix(0.8);
+        chart.draw_series([PathElement::new(
+            vec![(x_min, value), (x_max, value)],
+            &line,
+        )])?;
+        chart.draw_series([EmptyElement::at((x_min, value))
+            + Text::new(
+                format!(
+                    "{}  {} {}",
+                    reference.label, reference.value, reference.unit
+                ),
+                (5, -18),
+                ("sans-serif", 15).into_font().color(&color),
+            )])?;
+    }
+    if !frame.data {
+        return Ok(Drawn {
+            bounds,
+            plot_top: plot.1.start,
+            plot,
+            hovered: None,
+        });
+    }
+    draw_markers(&mut chart, frame.markers, bounds, axis)?;
+
+    let budget = draw_budget(config, &plot);
+    for &i in group {
+        let (s, points) = (&frame.series[i], &frame.chart_data[i]);
+        let plotted = &frame.plot_data[i];
+        let (alarm_color, color) = line_colors(s, theme, show_filtered);
+        let max_gap = max_gap(config, points);
+        let filtered = if show_filtered {
+            to_points(&s.filtered, frame.start, |v| s.convert(v, unit))
+        } else {
+            Vec::new()
+        };
+        let filtered_plotted = if log_y && s.aux_unit.is_none() {
+            log10_points(&filtered)
+        } else {
+            filtered.clone()
+        };
+
+        let lines = runs(plotted, (x_min, x_max), max_gap, budget)
+            .into_iter()
+            .map(|line| PathElement::new(line, &color));
+        match s.aux_unit {
+            Some(_) => chart.draw_secondary_series(lines)?,
+            None => chart.draw_series(lines)?,
+        };
+        let circles = retained_points(s, plotted, frame.start)
+            .into_iter()
+            .filter(|&(t, _)| x_min <= t && t <= x_max)
+            .map(|at| Circle::new(at, RETAINED_RADIUS, color.stroke_width(2)));
+        match s.aux_unit {
+            Some(_) => chart.draw_secondary_series(circles)?,
+            None => chart.draw_series(circles)?,
+        };
+
+        if show_filtered {
+            let lines = runs(&filtered_plotted, (x_min, x_max), max_gap, budget)
+                .into_iter()
+                .map(|line| PathElement::new(line, alarm_color.stroke_width(2)));
+            match s.aux_unit {
+                Some(_) => chart.draw_secondary_series(lines)?,
+                None => chart.draw_series(lines)?,
+            };
+        }
+    }
+
+    for &i in &primary {
+        if let Some(Some(fit)) = frame.leak_fits.get(i) {
+            let s = &frame.series[i];
+            let curve: Vec<(f64, f64)> = (0..=LEAK_CURVE_POINTS)
+                .map(|k| {
+                    let t = fit.t0 + fit.duration * k as f64 / LEAK_CURVE_POINTS as f64;
+                    let p = s.convert(fit.value_at(t), unit);
+                    (t - frame.start, if log_y { p.log10() } else { p })
+                })
+                .collect();
+            chart.draw_series([PathElement::new(curve, theme.warning.stroke_width(2))])?;
+        }
+    }
+
+    for &i in &primary {
+        let Some(Some(fit)) = frame.trend_fits.get(i) else {
+            continue;
+        };
+        let s = &frame.series[i];
+        let at = |t: f64| {
+            let p = s.convert(fit.value_at(t), unit);
+            (t - frame.start, if log_y { p.log10() } else { p })
+        };
+        let end = fit.t0 + fit.duration;
+        let color = s.color.stroke_width(2);
+        chart.draw_series([PathElement::new(vec![at(fit.t0), at(end)], color)])?;
+        // Dashes of about 8 pixels, up to the edge of the chart
+        let until = (end + config.trend.projection).min(frame.start + x_max);
+        if until <= end {
+            continue;
+        }
+        let (a, b) = (
+            chart.backend_coord(&at(end)),
+            chart.backend_coord(&at(until)),
+        );
+        let pixels = (b.0 as f64 - a.0 as f64).hypot(b.1 as f64 - a.1 as f64);
+        let n = ((pixels / 16.0) as usize).clamp(1, 1000);
+        let point = |k: f64| at(end + (until - end) * k / n as f64);
+        let within = |&(_, p): &(f64, f64)| y_min <= p && p <= y_max;
+        let dashes = (0..n)
+            .map(|k| [point(k as f64), point(k as f64 + 0.5)])
+            .filter(|dash| dash.iter().all(within))
+            .map(|dash| PathElement::new(dash.to_vec(), color));
+        chart.draw_series(dashes)?;
+    }
+
+    draw_legend(&mut chart, frame, group)?;
+
+    let hovered = frame
+        .cursor
+        .and_then(|pos| chart.as_coord_spec().reverse_translate(pos))
+        .filter(|&(t, p)| x_min <= t && t <= x_max && y_min <= p && p <= y_max);
+    if let Some((t, p)) = hovered {
+        let style = axis.mix(0.6);
+        chart.draw_series([
+            PathElement::new(vec![(t, y_min), (t, y_max)], &style),
+            PathElement::new(vec![(x_min, p), (x_max, p)], &style),
+        ])?;
+    }
+    // Readouts are of pressures, not decades
+    let hovered = hovered.map(|(t, p)| (t, if log_y { 10f64.powf(p) } else { p }));
+
+    Ok(Drawn {
+        bounds,
+        plot_top: plot.1.start,
+        plot,
+        hovered,
+    })
+}
+
+/// The ranges a chart of the `primary` series shows while following the
+/// data.
+fn live_bounds(frame: &Frame, primary: &[usize], panel: &mut Panel) -> Bounds {
+    let Frame {
+        config,
+        unit,
+        log_y,
+        ..
+    } = *frame;
+    Bounds {
+        // A time window always fills the chart
+        x: match config.data.window {
+            Some(window) => (0.0, window),
+            None => config.chart.x_range,
+        },
+        y: match data_bounds(primary.iter().map(|&i| &frame.plot_data[i])) {
+            Some((min, max)) if frame.autoscale => panel.y_scale.update(min, max),
+            bounds => {
+                let (min, max) = config.chart.y_range;
+                let (min, max) = (unit.from_pa(min), unit.from_pa(max));
+                match (log_y, bounds) {
+                    (false, _) => (min, max),
+                    (true, _) if min > 0.0 => (min.log10(), max.log10()),
+                    // A range reaching zero has no log, whole decades
+                    // around the data instead
+                    (true, Some((lo, hi))) => (lo.floor(), hi.ceil().max(lo.floor() + 1.0)),
+                    (true, None) => (-3.0, 5.0),
+                }
+            }
+        },
+    }
+}
+
+/// Lines spanning the plotting area at each marker within `bounds`.
+fn draw_markers(
+    chart: &mut Chart<'_, '_>,
+    markers: &[(f64, &str)],
+    bounds: Bounds,
+    axis: RGBColor,
+) -> Result<(), Box<dyn Error>> {
+    let ((x_min, x_max), (y_min, y_max)) = (bounds.x, bounds.y);
+    for &(t, label) in markers {
+        if t < x_min || x_max < t {
+            continue;
+        }
+        let line = axis.mix(0.8);
+        chart.draw_series([PathElement::new(vec![(t, y_min), (t, y_max)], &line)])?;
+        chart.draw_series([EmptyElement::at((t, y_max))
+            + Text::new(label, (4, 4), ("sans-serif", 15).into_font().color(&axis))])?;
+    }
+    Ok(())
+}
+
+/// The series of `group` with their latest values, in the upper right
+/// corner.
+fn draw_legend(
+    chart: &mut Chart<'_, '_>,
+    frame: &Frame,
+    group: &[usize],
+) -> Result<(), Box<dyn Error>> {
+    let Frame {
+        unit,
+        theme,
+        show_filtered,
+        ..
+    } = *frame;
+    if group.is_empty() {
+        return Ok(());
+    }
+
+    // Only the labels, the lines are drawn already
+    let none = || iter::empty::<PathElement<(f64, f64)>>();
+    for &i in group {
+        let s = &frame.series[i];
+        let (alarm_color, color) = line_colors(s, theme, show_filtered);
+        let unit_label = s.unit_label(unit);
+        chart
+            .draw_series(none())?
+            .label(match frame.chart_data[i].last() {
+                Some(&(_, p)) => format!("{}  {:.3} {}", s.topic, p, unit_label),
+                None => s.topic.clone(),
+            })
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &color));
+
+        if show_filtered {
+            let filtered = s.filtered.last().map(|&(_, v)| s.convert(v, unit));
+            chart
+                .draw_series(none())?
+                .label(match filtered {
+                    Some(p) => format!("{} filtered  {:.3} {}", s.topic, p, unit_label),
+                    None => format!("{} filtered", s.topic),
+                })
+                .legend(move |(x, y)| {
+                    PathElement::new(vec![(x, y), (x + 20, y)], alarm_color.stroke_width(2))
+                });
+        }
+    }
+
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(&theme.background.mix(0.8))
+        .border_style(&theme.axis)
+        .label_font(("sans-serif", 15).into_font().color(&theme.axis))
+        .draw()?;
+    Ok(())
+}
+
+/// The color of a series, the alarm color while in alarm, and that of its
+/// raw data, which steps back behind the smoothed curve.
+fn line_colors(s: &Series, theme: &Theme, show_filtered: bool) -> (RGBColor, RGBAColor) {
+    let alarm_color = match s.alarm.state() {
+        AlarmState::Normal => s.color,
+        _ => theme.alarm,
+    };
+    let color = if show_filtered {
+        alarm_color.mix(0.4)
+    } else {
+        alarm_color.to_rgba()
+    };
+    (alarm_color, color)
+}
+
+/// The retained samples of `s` among its `points` as drawn, on the time
+/// axis starting at `start`.
+fn retained_points(s: &Series, points: &[(f64, f64)], start: f64) -> Vec<(f64, f64)> {
+    s.retained
+        .iter()
+        .filter_map(|&t| Some((t - start, interpolate(points, t - start)?)))
+        .collect()
+}
+
+/// Seconds between samples not connected, so an outage shows as a gap.
+fn max_gap(config: &Config, points: &[(f64, f64)]) -> f64 {
+    config
+        .chart
+        .max_gap
+        .or(config.watchdog.timeout)
+        .unwrap_or_else(|| auto_gap(points))
+}
+
+/// Points drawn of each series at most, two per pixel column when
+/// downsampling.
+fn draw_budget(config: &Config, plot: &(Range<i32>, Range<i32>)) -> usize {
+    if config.chart.downsample {
+        2 * (plot.0.end - plot.0.start).max(1) as usize
+    } else {
+        usize::MAX
+    }
+}
+
+/// Draws the amplitude spectra of the pressure series in `group` on a chart
+/// filling `area`, returns the first pixel row of the plotting area.
+fn draw_spectrum(
+    area: &overlay::Root<'_>,
+    frame: &Frame,
+    group: &[usize],
+) -> Result<i32, Box<dyn Error>> {
+    let Frame { unit, theme, .. } = *frame;
+    let axis = theme.axis;
+    let spectra: Vec<(usize, Spectrum)> = group
+        .iter()
+        .filter(|&&i| frame.series[i].aux_unit.is_none())
+        .filter_map(|&i| Some((i, Spectrum::of(&frame.chart_data[i])?)))
+        .collect();
+
+    let f_max = spectra
+        .iter()
+        .map(|(_, s)| s.max_frequency())
+        .fold(0.0, f64::max);
+    let a_max = spectra
+        .iter()
+        .filter_map(|(_, s)| s.peak())
+        .map(|(_, a)| a)
+        .fold(0.0, f64::max);
+    // Something to draw the axes with before there is data
+    let (f_max, a_max) = (f_max.max(1.0), if a_max > 0.0 { a_max * 1.1 } else { 1.0 });
+
+    let mut chart = ChartBuilder::on(area)
+        .margin(10)
+        .set_all_label_area_size(50)
+        .build_cartesian_2d(0.0..f_max, 0.0..a_max)?;
+    chart
+        .configure_mesh()
+        .label_style(("sans-serif", 15).into_font().color(&axis))
+        .axis_style(&axis)
+        .x_desc("Frequency (Hz)")
+        .y_desc(format!("Amplitude ({})", unit))
+        .bold_line_style(&axis.mix(0.2))
+        .light_line_style(&TRANSPARENT)
+        .draw()?;
+
+    for (i, spectrum) in &spectra {
+        let s = &frame.series[*i];
+        let color = s.color;
+        let peak = spectrum.peak();
+        let label = match peak {
+            Some((f, a)) => format!("{}  peak {:.2} Hz  {:.3} {}", s.topic, f, a, unit),
+            None => s.topic.clone(),
+        };
+        chart
+            .draw_series([PathElement::new(spectrum.bins.clone(), &color)])?
+            .label(label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &color));
+        if let Some((f, a)) = peak {
+            chart.draw_series([EmptyElement::at((f, a))
+                + Circle::new((0, 0), 3, color.filled())
+                + Text::new(
+                    format!("{:.2} Hz", f),
+                    (5, -18),
+                    ("sans-serif", 15).into_font().color(&color),
+                )])?;
+        }
+    }
+
+    if !spectra.is_empty() {
+        chart
+            .configure_series_labels()
+            .position(SeriesLabelPosition::UpperRight)
+            .background_style(&theme.background.mix(0.8))
+            .border_style(&axis)
+            .label_font(("sans-serif", 15).into_font().color(&axis))
+            .draw()?;
+    }
+
+    let (_, plot_y) = chart.plotting_area().get_pixel_range();
+    Ok(plot_y.start)
+}
+
+/// Draws the histograms of the visible values of the pressure series in
+/// `group` on a chart filling `area`, returns the first pixel row of the
+/// plotting area.
+fn draw_histogram(
+    area: &overlay::Root<'_>,
+    frame: &Frame,
+    group: &[usize],
+    panel: &mut Panel,
+) -> Result<i32, Box<dyn Error>> {
+    let Frame {
+        config,
+        unit,
+        theme,
+        chart_data,
+        ..
+    } = *frame;
+    let axis = theme.axis;
+    let primary: Vec<usize> = group
+        .iter()
+        .copied()
+        .filter(|&i| frame.series[i].aux_unit.is_none())
+        .collect();
+    let live = live_bounds(frame, &primary, panel);
+    let (x_min, x_max) = panel.view.bounds(live).x;
+    let visible = move |i: usize| {
+        chart_data[i]
+            .iter()
+            .filter(move |&&(t, _)| x_min <= t && t <= x_max)
+            .map(|&(_, p)| p)
+    };
+
+    // The same bins for all, over the values of all
+    let range =
+        primary
+            .iter()
+            .flat_map(|&i| visible(i))
+            .fold(None, |range: Option<(f64, f64)>, p| match range {
+                Some((min, max)) => Some((min.min(p), max.max(p))),
+                None => Some((p, p)),
+            });
+    let (min, max) = range.unwrap_or((0.0, 1.0));
+    let bins = config.chart.histogram_bins;
+    let histograms: Vec<(usize, Histogram)> = primary
+        .iter()
+        .map(|&i| (i, Histogram::of(visible(i), min, max, bins)))
+        .collect();
+    let (v_min, v_max) = match histograms.first() {
+        Some((_, h)) => (h.min, h.min + h.width * h.counts.len() as f64),
+        None => (min, max),
+    };
+    let c_max = histograms
+        .iter()
+        .map(|(_, h)| h.max_count())
+        .max()
+        .unwrap_or(0);
+    let c_max = (c_max.max(1) as f64) * 1.1;
+
+    let mut chart = ChartBuilder::on(area)
+        .margin(10)
+        .set_all_label_area_size(50)
+        .build_cartesian_2d(v_min..v_max, 0.0..c_max)?;
+    chart
+        .configure_mesh()
+        .label_style(("sans-serif", 15).into_font().color(&axis))
+        .axis_style(&axis)
+        .x_desc(format!("Pressure ({})", unit))
+        .y_desc("Samples")
+        .bold_line_style(&axis.mix(0.2))
+        .light_line_style(&TRANSPARENT)
+        .draw()?;
+
+    for (i, histogram) in &histograms {
+        let s = &frame.series[*i];
+        let color = s.color;
+        let label = match Stats::of(visible(*i)) {
+            Some(stats) => format!(
+                "{}  mean {:.3}  std dev {:.3} {}",
+                s.topic, stats.mean, stats.std_dev, unit
+            ),
+            None => s.topic.clone(),
+        };
+        chart
+            .draw_series([PathElement::new(histogram.outline(), &color)])?
+            .label(label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &color));
+    }
+
+    if !histograms.is_empty() {
+        chart
+            .configure_series_labels()
+            .position(SeriesLabelPosition::UpperRight)
+            .background_style(&theme.background.mix(0.8))
+            .border_style(&axis)
+            .label_font(("sans-serif", 15).into_font().color(&axis))
+            .draw()?;
+    }
+
+    let (_, plot_y) = chart.plotting_area().get_pixel_range();
+    Ok(plot_y.start)
+}
+
+/// Samples received on one topic.
+struct Series {
+    topic: String,
+    color: RGBColor,
+    data: SampleBuffer,
+    /// `data` run through `filter`
+    filtered: SampleBuffer,
+    /// Decimated, reaching back before `data`
+    scrollback: Scrollback,
+    filter: Pipeline,
+    alarm: Alarm,
+    /// dP/dt of a pressure, when rates are enabled
+    rate: Option<RateOfChange>,
+    rate_alarm: Alarm,
+    /// Unit of an auxiliary signal on the secondary axis, `None` for pressure
+    aux_unit: Option<String>,
+    /// Pa subtracted from the displayed pressures, alarms and stores get
+    /// the readings as they are
+    tare: f64,
+    /// Arrival of the latest live sample, for the watchdog
+    last_seen: Instant,
+    stale: bool,
+    /// Latest message counter, for payloads that carry one
+    sequence: Option<u64>,
+    /// Messages missing from the counter
+    lost: u64,
+    /// `lost` when the watchdog flagged the series, to tell lost messages
+    /// from a sensor that stopped sending
+    lost_when_stale: u64,
+    /// Times of the retained samples among `data`, drawn as open circles
+    retained: Vec<f64>,
+}
+
+impl Series {
+    fn new(
+        topic: String,
+        color: RGBColor,
+        data: &DataConfig,
+        filter: Pipeline,
+        alarm: Alarm,
+        aux_unit: Option<String>,
+    ) -> Series {
+        let retention = Retention::new(data);
+        // An empty pipeline never fills `filtered`, don't reserve room for it
+        let filtered_capacity = if filter.is_empty() { 0 } else { data.length };
+        Series {
+            topic,
+            color,
+            data: SampleBuffer::new(retention, data.length),
+            filtered: SampleBuffer::new(retention, filtered_capacity),
+            scrollback: Scrollback::new(data.scrollback_bucket, data.scrollback * 3600.0),
+            filter,
+            alarm,
+            rate: None,
+            rate_alarm: Alarm::with_thresholds(None, None, 0.0),
+            aux_unit,
+            tare: 0.0,
+            last_seen: Instant::now(),
+            stale: false,
+            sequence: None,
+            lost: 0,
+            lost_when_stale: 0,
+            retained: Vec::new(),
+        }
+    }
+
+    /// Runs the history through new filter stages.
+    fn set_filter(&mut self, stages: &[FilterStage]) {
+        self.filter = Pipeline::new(stages);
+        self.filtered.clear();
+        if !self.filter.is_empty() {
+            for &(t, value) in &self.data {
+                self.filtered.push(t, self.filter.apply(value));
+            }
+        }
+    }
+
+    /// `t` on the `Clock` time line.
+    fn push(&mut self, t: f64, value: f64) {
+        self.data.push(t, value);
+        if let Some(&(oldest, _)) = self.data.first() {
+            self.retained.retain(|&at| at >= oldest);
+        }
+        self.scrollback.push(t, value);
+        if !self.filter.is_empty() {
+            self.filtered.push(t, self.filter.apply(value));
+        }
+    }
+
+    /// A stored value as displayed, pressures tared and in `unit`.
+    fn convert(&self, value: f64, unit: PressureUnit) -> f64 {
+        match self.aux_unit {
+            Some(_) => value,
+            None => unit.from_pa(value - self.tare),
+        }
+    }
+
+    /// Mean of the latest `count` raw readings, zero without any.
+    fn recent_mean(&self, count: usize) -> f64 {
+        let recent: Vec<f64> = self.data.iter().rev().take(count).map(|d| d.1).collect();
+        match recent.len() {
+            0 => 0.0,
+            n => recent.iter().sum::<f64>() / n as f64,
+        }
+    }
+
+    fn unit_label(&self, unit: PressureUnit) -> String {
+        match &self.aux_unit {
+            Some(aux_unit) => aux_unit.clone(),
+            None => unit.to_string(),
+        }
+    }
+}
+
+/// Gives `sample` the series name of the first of `names` matching its
+/// topic, if any.
+fn rename(names: &[(String, String)], sample: &mut Sample) {
+    if let Some((_, name)) = names
+        .iter()
+        .find(|(filter, _)| decode::topic_matches(filter, &sample.topic))
+    {
+        sample.topic = name.clone();
+    }
+}
+
+/// Brings the series in line with a setting changed in the menu, the
+/// others are read from the config as they are drawn.
+fn apply_setting(setting: Setting, config: &Config, series: &mut [Series]) {
+    match setting {
+        Setting::Smoothing => series
+            .iter_mut()
+            .for_each(|s| s.set_filter(&config.filter.stages)),
+        Setting::HighAlarm | Setting::LowAlarm => series
+            .iter_mut()
+            .for_each(|s| s.alarm.set_thresholds(config.alarm.high, config.alarm.low)),
+        Setting::Retention => {
+            let retention = Retention::new(&config.data);
+            for s in series {
+                s.data.set_retention(retention);
+                s.filtered.set_retention(retention);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Index of the series for `topic`, created on first use.
+fn series_index(series: &mut Vec<Series>, topic: String, config: &Config, theme: &Theme) -> usize {
+    match series.iter().position(|s| s.topic == topic) {
+        Some(index) => index,
+        None => {
+            let color = theme.series_color(series.len(), &topic);
+            let secondary = &config.secondary;
+            let aux_unit = secondary
+                .topics
+                .iter()
+                .any(|filter| decode::topic_matches(filter, &topic))
+                .then(|| secondary.unit.clone());
+            // A differential has its own thresholds
+            let alarm = match config.differential.iter().find(|d| d.name == topic) {
+                Some(d) => Alarm::with_thresholds(d.high, d.low, d.hysteresis),
+                None => Alarm::new(&config.alarm),
+            };
+            let mut s = Series::new(
+                topic,
+                color,
+                &config.data,
+                Pipeline::new(&config.filter.stages),
+                alarm,
+                aux_unit,
+            );
+            let rate = &config.rate;
+            if s.aux_unit.is_none() && rate.enabled() {
+                s.rate = Some(RateOfChange::new(rate.window));
+                s.rate_alarm = Alarm::with_thresholds(rate.high, rate.low, rate.hysteresis);
+            }
+            series.push(s);
+            series.len() - 1
+        }
+    }
+}
+
+/// Index of the series drawing the rate of `series[of]`, created on first
+/// use.
+fn rate_series_index(series: &mut Vec<Series>, of: usize, config: &Config, theme: &Theme) -> usize {
+    let topic = rate_topic(&series[of].topic);
+    match series.iter().position(|s| s.topic == topic) {
+        Some(index) => index,
+        None => {
+            let color = theme.series_color(series.len(), &topic);
+            series.push(Series::new(
+                topic,
+                color,
+                &config.data,
+                Pipeline::new(&[]),
+                Alarm::with_thresholds(None, None, 0.0),
+                Some(RATE_UNIT.to_string()),
+            ));
+            series.len() - 1
+        }
+    }
+}
+
+/// `QUALITY_*` bits of the sample `s` just took, `lost_before` is its
+/// count of lost messages before.
+fn quality(s: &Series, lost_before: u64, retained: bool) -> u8 {
+    let mut quality = 0;
+    if s.alarm.state() != AlarmState::Normal {
+        quality |= QUALITY_ALARM;
+    }
+    if s.lost > lost_before {
+        quality |= QUALITY_AFTER_LOSS;
+    }
+    // The watchdog clears it after the samples are taken
+    if s.stale {
+        quality |= QUALITY_AFTER_STALE;
+    }
+    if retained {
+        quality |= QUALITY_RETAINED;
+    }
+    quality
+}
+
+/// Name of the rate series of `topic`, not a valid MQTT topic so it can't
+/// clash with one.
+fn rate_topic(topic: &str) -> String {
+    format!("{} dP/dt", topic)
+}
+
+/// All series share the time axis, starting at the oldest sample.
+fn start_time(series: &[Series], clock: &Clock) -> f64 {
+    series
+        .iter()
+        .filter_map(|s| s.data.first().map(|d| d.0))
+        .reduce(f64::min)
+        .unwrap_or_else(|| clock.now())
+}
+
+/// Seconds since `start` and the displayed values, pressures in `unit`,
+/// per series.
+fn chart_points(series: &[Series], start: f64, unit: PressureUnit) -> Vec<Vec<(f64, f64)>> {
+    series
+        .iter()
+        .map(|s| to_points(&s.data, start, |value| s.convert(value, unit)))
+        .collect()
+}
+
+fn to_points(data: &SampleBuffer, start: f64, convert: impl Fn(f64) -> f64) -> Vec<(f64, f64)> {
+    data.iter()
+        .map(|&(t, value)| (t - start, convert(value)))
+        .collect()
+}
+
+/// Puts the scrollback before the points of the series whose chart is
+/// zoomed or panned, instead of following the data.
+fn prepend_scrollback(
+    chart_data: &mut [Vec<(f64, f64)>],
+    series: &[Series],
+    panels: &[Panel],
+    start: f64,
+    unit: PressureUnit,
+) {
+    for (i, (s, points)) in series.iter().zip(chart_data.iter_mut()).enumerate() {
+        let live = panels
+            .get(i)
+            .or(panels.first())
+            .is_none_or(|p| p.view.is_live());
+        if live {
+            continue;
+        }
+        let until = s.data.first().map_or(f64::INFINITY, |d| d.0);
+        let older: Vec<(f64, f64)> = s
+            .scrollback
+            .before(until)
+            .map(|(t, value)| (t - start, s.convert(value, unit)))
+            .collect();
+        points.splice(0..0, older);
+    }
+}
+
+/// `chart_data` with the pressures in decades, for a log Y axis. What
+/// isn't positive has no log and is left out, secondary series stay linear.
+fn log_chart_points(series: &[Series], chart_data: &[Vec<(f64, f64)>]) -> Vec<Vec<(f64, f64)>> {
+    series
+        .iter()
+        .zip(chart_data)
+        .map(|(s, points)| match s.aux_unit {
+            Some(_) => points.clone(),
+            None => log10_points(points),
+        })
+        .collect()
+}
+
+fn log10_points(points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    points
+        .iter()
+        .filter(|&&(_, p)| p > 0.0)
+        .map(|&(t, p)| (t, p.log10()))
+        .collect()
+}
+
+/// Tick label of a log Y axis at `exponent`, e.g. `1e-3`.
+fn log_label(exponent: f64) -> String {
+    let decade = exponent.round();
+    if (exponent - decade).abs() < 1e-6 {
+        format!("1e{}", decade as i32)
+    } else {
+        format!("{:.2e}", 10f64.powf(exponent))
+    }
+}
+
+/// The lines through the points within `x`, broken where they are more
+/// than `max_gap` seconds apart so outages show as gaps. Each is cut down
+/// to its share of `budget` points.
+fn runs(points: &[(f64, f64)], x: (f64, f64), max_gap: f64, budget: usize) -> Vec<Vec<(f64, f64)>> {
+    // One more on each side, so the lines reach the edges
+    let first = points.partition_point(|p| p.0 < x.0).saturating_sub(1);
+    let last = (points.partition_point(|p| p.0 <= x.1) + 1).min(points.len());
+    let visible = &points[first.min(last)..last];
+
+    let mut runs = Vec::new();
+    let mut from = 0;
+    for i in 1..=visible.len() {
+        if i == visible.len() || visible[i].0 - visible[i - 1].0 > max_gap {
+            let run = &visible[from..i];
+            if run.len() > 1 {
+                let share = budget.saturating_mul(run.len()) / visible.len();
+                runs.push(downsample::lttb(run, share.max(3)));
+            }
+            from = i;
+        }
+    }
+    runs
+}
+
+/// Gaps longer than this many typical sample intervals are outages.
+const AUTO_GAP_INTERVALS: f64 = 5.0;
+
+/// Gap threshold from the median spacing of the samples, so it adapts to
+/// the publish rate.
+fn auto_gap(points: &[(f64, f64)]) -> f64 {
+    let mut intervals: Vec<f64> = points
+        .iter()
+        .zip(points.iter().skip(1))
+        .map(|(a, b)| b.0 - a.0)
+        .collect();
+    if intervals.is_empty() {
+        return f64::INFINITY;
+    }
+
+    let mid = intervals.len() / 2;
+    let (_, median, _) = intervals.select_nth_unstable_by(mid, |a, b| a.total_cmp(b));
+    match *median * AUTO_GAP_INTERVALS {
+        // Bursts of equal timestamps would make every real interval a gap
+        gap if gap > 0.0 => gap,
+        _ => f64::INFINITY,
+    }
+}
+
+/// Value at time `t`, linearly interpolated between the samples around it.
+fn interpolate(points: &[(f64, f64)], t: f64) -> Option<f64> {
+    let i = points.partition_point(|&(pt, _)| pt < t);
+    match (i.checked_sub(1).map(|i| points[i]), points.get(i)) {
+        (Some((t0, p0)), Some(&(t1, p1))) if t1 > t0 => Some(p0 + (p1 - p0) * (t - t0) / (t1 - t0)),
+        (_, Some(&(t1, p1))) if t1 == t => Some(p1),
+        _ => None,
+    }
+}
+
+/// Local time of day at `t` on the time line.
+fn wall_clock(clock: &Clock, t: f64) -> String {
+    DateTime::<Local>::from(clock.wall(t))
+        .format("%H:%M:%S")
+        .to_string()
+}
+
+fn data_bounds<'a>(
+    chart_data: impl IntoIterator<Item = &'a Vec<(f64, f64)>>,
+) -> Option<(f64, f64)> {
+    chart_data
+        .into_iter()
+        .flatten()
+        .map(|&(_, p)| p)
+        .fold(None, |bounds, p| match bounds {
+            None => Some((p, p)),
+            Some((min, max)) => Some((p.min(min), p.max(max))),
+        })
+}
+
+/// Screenshot to a timestamped file in the working directory. Failing to
+/// write one isn't worth stopping the monitor for.
+fn snapshot(frame: &[u32], w: usize, h: usize) {
+    let path = screenshot::timestamped_path(Path::new("."));
+    match screenshot::save_png(&path, frame, w, h) {
+        Ok(()) => info!("Saved {}", path.display()),
+        Err(e) => error!("Cannot save screenshot: {}", e),
+    }
+}
+
+/// The settings in effect, as listed in the help overlay.
+fn help_settings(
+    config: &Config,
+    unit: PressureUnit,
+    log_y: bool,
+    time_axis: TimeAxis,
+    autoscale: bool,
+    layout: Layout,
+    show_filtered: bool,
+) -> Vec<(&'static str, String)> {
+    let on_off = |on: bool| String::from(if on { "on" } else { "off" });
+    let threshold = |pa: Option<f64>| match pa {
+        Some(pa) => format!("{:.3} {}", unit.from_pa(pa), unit),
+        None => "off".to_string(),
+    };
+    let path = |path: &Option<PathBuf>| match path {
+        Some(path) => path.display().to_string(),
+        None => "off".to_string(),
+    };
+
+    vec![
+        (
+            "Source",
+            if config.sources.is_empty() {
+                config.source.clone()
+            } else {
+                let names: Vec<&str> = config.sources.iter().map(|s| s.name.as_str()).collect();
+                names.join(", ")
+            },
+        ),
+        ("Unit", unit.to_string()),
+        ("Y axis", String::from(if log_y { "log" } else { "linear" })),
+        (
+            "Time axis",
+            match time_axis {
+                TimeAxis::Relative => "relative",
+                TimeAxis::WallClock => "wall clock",
+            }
+            .to_string(),
+        ),
+        ("Autoscale", on_off(autoscale)),
+        (
+            "Layout",
+            match layout {
+                Layout::Overlay => "overlay",
+                Layout::Grid => "grid",
+            }
+            .to_string(),
+        ),
+        (
+            "Filter",
+            match config.filter.stages.len() {
+                0 => "none".to_string(),
+                n => format!("{} stages, shown {}", n, on_off(show_filtered)),
+            },
+        ),
+        ("High alarm", threshold(config.alarm.high)),
+        ("Low alarm", threshold(config.alarm.low)),
+        (
+            "Retention",
+            match config.data.window {
+                Some(window) => format!("{} s", window),
+                None => format!("{} samples", config.data.length),
+            },
+        ),
+        ("CSV log", path(&config.log.file)),
+        ("JSONL log", path(&config.log.jsonl)),
+        (
+            "Export",
+            format!(
+                "{} to {}",
+                config.export.scope.label(),
+                config.export.dir.display()
+            ),
+        ),
+    ]
+}
+
+/// The points of each series to save, and the time range they were cut to
+/// if any. `log` is the CSV log read for the `Session` scope.
+fn export_points(
+    scope: ExportScope,
+    series: &[Series],
+    panels: &[Panel],
+    clock: &Clock,
+    start: f64,
+    unit: PressureUnit,
+    log: Option<&Path>,
+) -> Result<(Vec<Vec<(f64, f64)>>, Option<(f64, f64)>), Box<dyn Error>> {
+    match scope {
+        ExportScope::Buffer => Ok((chart_points(series, start, unit), None)),
+        ExportScope::Visible => {
+            // In a grid every series has a chart of its own
+            let mut chart_data = chart_points(series, start, unit);
+            prepend_scrollback(&mut chart_data, series, panels, start, unit);
+            for (i, points) in chart_data.iter_mut().enumerate() {
+                if let Some(panel) = panels.get(i).or(panels.first()) {
+                    let (x0, x1) = panel.view.shown().x;
+                    points.retain(|&(t, _)| (x0..=x1).contains(&t));
+                }
+            }
+            let range = panels.first().map(|panel| panel.view.shown().x);
+            Ok((chart_data, range))
+        }
+        ExportScope::Session => {
+            let path = log.ok_or("No session recorded and no [log] file to export")?;
+            let records = replay::load(path)?;
+            let chart_data = series
+                .iter()
+                .map(|s| {
+                    records
+                        .iter()
+                        .filter(|r| r.topic == s.topic)
+                        .map(|r| (clock.offset(r.ts) - start, s.convert(r.value, unit)))
+                        .collect()
+                })
+                .collect();
+            Ok((chart_data, None))
+        }
+    }
+}
+
+/// `pressure_data_2024-05-03T10-22-31.csv` in `dir`, numbered when saved
+/// more than once in a second.
+fn export_path(dir: &Path) -> PathBuf {
+    let stem = Local::now()
+        .format("pressure_data_%Y-%m-%dT%H-%M-%S")
+        .to_string();
+    let mut path = dir.join(format!("{}.csv", stem));
+    let mut n = 1;
+    while path.exists() {
+        n += 1;
+        path = dir.join(format!("{}_{}.csv", stem, n));
+    }
+    path
+}
+
+/// The filtered values of the series with filter stages, within the time
+/// span of their points in `chart_data`, as the columns after theirs.
+fn filtered_points(
+    series: &[Series],
+    chart_data: &[Vec<(f64, f64)>],
+    start: f64,
+    unit: PressureUnit,
+) -> Vec<Vec<(f64, f64)>> {
+    series
+        .iter()
+        .zip(chart_data)
+        .filter(|(s, _)| !s.filter.is_empty())
+        .map(|(s, points)| match (points.first(), points.last()) {
+            (Some(&(first, _)), Some(&(last, _))) => {
+                let mut filtered = to_points(&s.filtered, start, |v| s.convert(v, unit));
+                filtered.retain(|&(t, _)| first <= t && t <= last);
+                filtered
+            }
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+/// A `topic (unit)` column per series, then those of `filtered_points`,
+/// left out when played back.
+fn table_columns(series: &[Series], unit: PressureUnit) -> Vec<String> {
+    let raw = series
+        .iter()
+        .map(|s| format!("{} ({})", s.topic, s.unit_label(unit)));
+    let filtered = series
+        .iter()
+        .filter(|s| !s.filter.is_empty())
+        .map(|s| format!("{} filtered [{}]", s.topic, s.unit_label(unit)));
+    raw.chain(filtered).collect()
+}
+
+/// One row per sample, with the value in the column of its series and the
+/// filtered value, if any, in the one of `filtered_points`. Rows start with
+/// the wall clock time `wall` gives for a point, then the chart time.
+fn save_csv(
+    path: &Path,
+    series: &[Series],
+    chart_data: &[Vec<(f64, f64)>],
+    markers: &[(f64, &str)],
+    unit: PressureUnit,
+    wall: impl Fn(f64) -> SystemTime,
+    sensors: &BTreeMap<String, String>,
+) -> Result<(), Box<dyn Error>> {
+    let mut file = File::create(path)?;
+    writeln!(file, "# unit {}", unit)?;
+    for s in series {
+        if let Some(id) = sensors.get(&s.topic) {
+            writeln!(file, "# sensor {} {}", s.topic, id)?;
+        }
+    }
+    // Values are tared, note by how much
+    for s in series.iter().filter(|s| s.tare != 0.0) {
+        writeln!(file, "# tare {} {} {}", s.topic, unit.from_pa(s.tare), unit)?;
+    }
+    let mut wtr = csv::Writer::from_writer(file);
+    write_table(
+        &mut wtr,
+        &table_columns(series, unit),
+        chart_data,
+        markers,
+        wall,
+    )
+}
+
+/// `pressure_data_<time>_summary.csv` next to the export at `path`.
+fn summary_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}_summary.csv", stem))
+}
+
+/// One row per series and `interval` seconds of chart time with data, with
+/// the distribution of its values, the variance in the unit squared.
+fn save_summary(
+    path: &Path,
+    series: &[Series],
+    chart_data: &[Vec<(f64, f64)>],
+    interval: f64,
+    unit: PressureUnit,
+    wall: impl Fn(f64) -> SystemTime,
+) -> Result<(), Box<dyn Error>> {
+    if interval <= 0.0 {
+        return Err("[export] summary_interval must be positive".into());
+    }
+    // Values by interval, then series
+    let mut intervals: BTreeMap<i64, Vec<Vec<f64>>> = BTreeMap::new();
+    for (i, points) in chart_data.iter().enumerate() {
+        for &(t, p) in points {
+            let values = intervals
+                .entry((t / interval).floor() as i64)
+                .or_insert_with(|| vec![Vec::new(); series.len()]);
+            values[i].push(p);
+        }
+    }
+
+    let mut wtr = csv::Writer::from_path(path)?;
+    wtr.write_record([
+        replay::WALL_CLOCK,
+        "Start(s)",
+        "Series",
+        "Unit",
+        "Count",
+        "Mean",
+        "Variance",
+        "Min",
+        "Max",
+        "PeakToPeak",
+        "P5",
+        "P50",
+        "P95",
+        "P99",
+    ])?;
+    for (n, values) in intervals {
+        let t = n as f64 * interval;
+        let time = DateTime::<Local>::from(wall(t)).to_rfc3339_opts(SecondsFormat::Millis, false);
+        for (s, values) in series.iter().zip(values) {
+            let Some(summary) = Summary::of(values) else {
+                continue;
+            };
+            wtr.write_record([
+                time.clone(),
+                t.to_string(),
+                s.topic.clone(),
+                s.unit_label(unit),
+                summary.count.to_string(),
+                summary.mean.to_string(),
+                summary.variance.to_string(),
+                summary.min.to_string(),
+                summary.max.to_string(),
+                summary.peak_to_peak().to_string(),
+                summary.p5.to_string(),
+                summary.p50.to_string(),
+                summary.p95.to_string(),
+                summary.p99.to_string(),
+            ])?;
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Puts `text` on the clipboard, opening it the first time.
+fn copy_text(clipboard: &mut Option<Clipboard>, text: String) -> Result<(), Box<dyn Error>> {
+    if clipboard.is_none() {
+        *clipboard = Some(Clipboard::new()?);
+    }
+    if let Some(clipboard) = clipboard {
+        clipboard.set_text(text)?;
+    }
+    Ok(())
+}
+
+/// The header and rows of `save_csv`, also copied to the clipboard.
+fn write_table(
+    wtr: &mut csv::Writer<impl Write>,
+    columns: &[String],
+    chart_data: &[Vec<(f64, f64)>],
+    markers: &[(f64, &str)],
+    wall: impl Fn(f64) -> SystemTime,
+) -> Result<(), Box<dyn Error>> {
+    // Time, column and its text, the markers go last
+    let mut rows: Vec<(f64, usize, String)> = chart_data
+        .iter()
+        .enumerate()
+        .flat_map(|(i, points)| points.iter().map(move |&(t, p)| (t, i, p.to_string())))
+        .chain(
+            markers
+                .iter()
+                .map(|&(t, label)| (t, columns.len(), label.to_string())),
+        )
+        .collect();
+    rows.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut header = vec![replay::WALL_CLOCK.to_string(), "Time(s)".to_string()];
+    header.extend(columns.iter().cloned());
+    header.push("Marker".to_string());
+    wtr.write_record(&header)?;
+
+    for (t, i, text) in rows {
+        let mut record = vec![String::new(); columns.len() + 3];
+        record[0] = DateTime::<Local>::from(wall(t)).to_rfc3339_opts(SecondsFormat::Millis, false);
+        record[1] = t.to_string();
+        record[i + 2] = text;
+        wtr.write_record(&record)?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequence_gaps() {
+        assert_eq!(sequence_gap(1, 2), Some(0));
+        assert_eq!(sequence_gap(2, 5), Some(2));
+        // A duplicate
+        assert_eq!(sequence_gap(3, 3), Some(0));
+        // 65535 and 0 missing
+        assert_eq!(sequence_gap(65_534, 1), Some(2));
+        // Restarts
+        assert_eq!(sequence_gap(1_000, 3), None);
+        assert_eq!(sequence_gap(100_000, 5), None);
+    }
+
+    #[test]
+    fn builder_checks_the_config() {
+        let builder = |window| {
+            let mut config = Config::default();
+            config.filter.stages = vec![FilterStage::Median { window }];
+            let source = source::sim::SimSource::new(&config.sim, Status::default());
+            PressureMonitor::builder()
+                .config(config)
+                .source(Box::new(source))
+                .headless(true)
+                .build()
+        };
+        assert!(builder(4).is_err());
+        assert!(builder(0).is_err());
+        assert!(builder(5).is_ok());
+    }
+
+    #[test]
+    fn ingest_samples() {
+        let mut config = Config::default();
+        config.alarm.high = Some(1_000.0);
+        config.derived = vec![crate::config::DerivedConfig {
+            name: "double".to_string(),
+            expression: "2.0 * a".to_string(),
+            inputs: [("a".to_string(), "pressure/data".to_string())].into(),
+            unit: PressureUnit::Pa,
+        }];
+        let mut state = State::new(config, Status::default(), None, None).unwrap();
+        let sample = |value, sequence, retained| Sample {
+            topic: "pressure/data".to_string(),
+            value,
+            timestamp: None,
+            sequence: Some(sequence),
+            sensor: None,
+            expires: None,
+            retained,
+        };
+
+        assert!(state.ingest(sample(500.0, 1, true)).is_empty());
+        let topics: Vec<&str> = state.series.iter().map(|s| s.topic.as_str()).collect();
+        assert_eq!(topics, ["pressure/data", "double"]);
+        assert_eq!(state.series[0].retained.len(), 1);
+        assert_eq!(state.series[1].data.last().map(|&(_, v)| v), Some(1_000.0));
+
+        // Three lost, and the alarms raised, the derived series' after
+        let events = state.ingest(sample(1_500.0, 5, false));
+        assert_eq!(state.series[0].lost, 3);
+        let raised: Vec<(&str, AlarmState)> =
+            events.iter().map(|e| (e.topic.as_str(), e.state)).collect();
+        assert_eq!(
+            raised,
+            [
+                ("pressure/data", AlarmState::High),
+                ("double", AlarmState::High)
+            ]
+        );
+
+        let expired = Sample {
+            expires: Some(SystemTime::UNIX_EPOCH),
+            ..sample(100.0, 6, false)
+        };
+        assert!(state.ingest(expired).is_empty());
+        assert_eq!(state.status.dropped(), 1);
+        assert_eq!(state.series[0].data.iter().count(), 2);
+        assert_eq!(state.series[0].sequence, Some(5));
+    }
+
+    #[test]
+    fn retained_samples_are_flagged() {
+        let config = Config::default();
+        let theme = Theme::new(&config.colors);
+        let mut series = Vec::new();
+        let index = series_index(&mut series, "pressure/data".to_string(), &config, &theme);
+        let s = &mut series[index];
+        s.push(10.0, 100.0);
+        s.retained.push(10.0);
+        s.push(11.0, 110.0);
+        assert_eq!(quality(s, 0, true), QUALITY_RETAINED);
+        assert_eq!(quality(s, 0, false), 0);
+        let points = to_points(&s.data, 5.0, |v| v);
+        assert_eq!(retained_points(s, &points, 5.0), [(5.0, 100.0)]);
+    }
+}
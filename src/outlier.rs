@@ -0,0 +1,145 @@
+//! Sanity filter of decoded readings. Corrupted messages can decode to
+//! absurd values, e.g. 1e38, that wreck autoscaling and the statistics, so
+//! readings outside absolute bounds or far from the recent median of their
+//! topic are rejected before they become samples.
+
+use crate::config::SanityConfig;
+use std::collections::{HashMap, VecDeque};
+
+/// Median absolute deviation to standard deviation, for normal noise.
+const MAD_SCALE: f64 = 1.4826;
+
+#[derive(Debug, Clone)]
+pub struct OutlierFilter {
+    config: SanityConfig,
+    /// Latest readings within the bounds per topic, spikes included so a
+    /// real step is accepted once it fills half the window
+    recent: HashMap<String, VecDeque<f64>>,
+}
+
+impl OutlierFilter {
+    pub fn new(config: &SanityConfig) -> OutlierFilter {
+        OutlierFilter {
+            config: config.clone(),
+            recent: HashMap::new(),
+        }
+    }
+
+    /// Whether rejected readings are logged as warnings.
+    pub fn log(&self) -> bool {
+        self.config.log
+    }
+
+    /// `Err` with the reason when `value` of `topic` is rejected.
+    pub fn check(&mut self, topic: &str, value: f64) -> Result<(), String> {
+        if !value.is_finite() {
+            return Err("not a finite number".to_string());
+        }
+        if let Some(min) = self.config.min.filter(|&min| value < min) {
+            return Err(format!("below the minimum of {} Pa", min));
+        }
+        if let Some(max) = self.config.max.filter(|&max| value > max) {
+            return Err(format!("above the maximum of {} Pa", max));
+        }
+
+        let window = self.config.spike_window;
+        if window == 0 {
+            return Ok(());
+        }
+        let recent = self.recent.entry(topic.to_string()).or_default();
+        let spike = match spike_limit(recent, &self.config) {
+            Some((median, limit)) if (value - median).abs() > limit => Some(median),
+            _ => None,
+        };
+        if recent.len() >= window {
+            recent.pop_front();
+        }
+        recent.push_back(value);
+
+        match spike {
+            Some(median) => Err(format!("spike, the median is {} Pa", median)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The median of `recent` and how far from it a spike starts, once the
+/// window, of at least 3, is full.
+fn spike_limit(recent: &VecDeque<f64>, config: &SanityConfig) -> Option<(f64, f64)> {
+    if recent.len() < config.spike_window {
+        return None;
+    }
+    let mut values: Vec<f64> = recent.iter().copied().collect();
+    let center = median(&mut values);
+    let mut deviations: Vec<f64> = values.iter().map(|v| (v - center).abs()).collect();
+    let mad = median(&mut deviations);
+    let limit = (config.spike_threshold * MAD_SCALE * mad).max(config.min_deviation);
+    Some((center, limit))
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_unstable_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(spike_window: usize) -> OutlierFilter {
+        OutlierFilter::new(&SanityConfig {
+            min: Some(-1_000.0),
+            max: Some(1_000_000.0),
+            spike_window,
+            spike_threshold: 10.0,
+            min_deviation: 100.0,
+            log: false,
+        })
+    }
+
+    #[test]
+    fn bounds() {
+        let mut filter = filter(0);
+        assert!(filter.check("p", f64::NAN).is_err());
+        assert!(filter.check("p", f64::INFINITY).is_err());
+        assert!(filter.check("p", -1_001.0).is_err());
+        assert!(filter.check("p", 1e38).is_err());
+        assert!(filter.check("p", 101_325.0).is_ok());
+    }
+
+    #[test]
+    fn spikes() {
+        let mut filter = filter(5);
+        for value in [1000.0, 1010.0, 990.0, 1005.0, 995.0] {
+            assert!(filter.check("p", value).is_ok());
+        }
+        assert!(filter.check("p", 50_000.0).is_err());
+        // Within the minimum deviation
+        assert!(filter.check("p", 1080.0).is_ok());
+        // Other topics have medians of their own
+        assert!(filter.check("q", 50_000.0).is_ok());
+    }
+
+    #[test]
+    fn a_step_is_accepted_once_it_fills_half_the_window() {
+        let mut filter = filter(5);
+        for value in [1000.0; 5] {
+            filter.check("p", value).unwrap();
+        }
+        for _ in 0..3 {
+            assert!(filter.check("p", 5000.0).is_err());
+        }
+        assert!(filter.check("p", 5000.0).is_ok());
+    }
+
+    #[test]
+    fn medians() {
+        assert_eq!(median(&mut [3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(&mut [4.0, 1.0, 3.0, 2.0]), 2.5);
+    }
+}
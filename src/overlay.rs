@@ -0,0 +1,441 @@
+//! Text widgets drawn on top of the chart.
+
+use crate::config::ReadoutPosition;
+use crate::source::ConnectionState;
+use crate::stats::Stats;
+use crate::theme::Theme;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters_bitmap::bitmap_pixel::BGRXPixel;
+use plotters_bitmap::BitMapBackend;
+use std::error::Error;
+
+pub type Root<'a> = DrawingArea<BitMapBackend<'a, BGRXPixel>, Shift>;
+
+/// Top right corner, in the margin above the plotting area.
+pub fn draw_connection_state(
+    root: &Root<'_>,
+    state: ConnectionState,
+    theme: &Theme,
+) -> Result<(), Box<dyn Error>> {
+    let (w, _) = root.dim_in_pixel();
+    let (x, y) = (w as i32 - 170, 15);
+
+    let color = match state {
+        ConnectionState::Connected => theme.ok,
+        ConnectionState::Reconnecting => theme.warning,
+        ConnectionState::Offline => theme.alarm,
+    };
+    root.draw(&Text::new(
+        state.label(),
+        (x, y),
+        ("sans-serif", 20).into_font().color(&color),
+    ))?;
+
+    Ok(())
+}
+
+/// Counters of lost and bad messages, below the connection state and
+/// reaching further left.
+pub fn draw_message_counts(
+    root: &Root<'_>,
+    text: &str,
+    color: RGBColor,
+) -> Result<(), Box<dyn Error>> {
+    let (w, _) = root.dim_in_pixel();
+    root.draw(&Text::new(
+        text,
+        (w as i32 - 250, 40),
+        ("sans-serif", 15).into_font().color(&color),
+    ))?;
+
+    Ok(())
+}
+
+/// The latest recovered error, right aligned below the message counts.
+pub fn draw_error(root: &Root<'_>, text: &str, color: RGBColor) -> Result<(), Box<dyn Error>> {
+    let (w, _) = root.dim_in_pixel();
+    let style = ("sans-serif", 15)
+        .into_font()
+        .color(&color)
+        .pos(Pos::new(HPos::Right, VPos::Top));
+    root.draw(&Text::new(text, (w as i32 - 10, 50), style))?;
+
+    Ok(())
+}
+
+/// Confirms e.g. a saved file, right aligned below the error.
+pub fn draw_notice(root: &Root<'_>, text: &str, color: RGBColor) -> Result<(), Box<dyn Error>> {
+    let (w, _) = root.dim_in_pixel();
+    let style = ("sans-serif", 15)
+        .into_font()
+        .color(&color)
+        .pos(Pos::new(HPos::Right, VPos::Top));
+    root.draw(&Text::new(text, (w as i32 - 10, 68), style))?;
+
+    Ok(())
+}
+
+/// Right of where `draw_paused` goes, while a session is recorded.
+pub fn draw_recording(root: &Root<'_>, color: RGBColor) -> Result<(), Box<dyn Error>> {
+    let (w, _) = root.dim_in_pixel();
+
+    root.draw(&Text::new(
+        "REC",
+        (w as i32 / 2 + 60, 15),
+        ("sans-serif", 20).into_font().color(&color),
+    ))?;
+
+    Ok(())
+}
+
+/// Top center, in the margin above the plotting area.
+pub fn draw_paused(root: &Root<'_>, color: RGBColor) -> Result<(), Box<dyn Error>> {
+    let (w, _) = root.dim_in_pixel();
+
+    root.draw(&Text::new(
+        "PAUSED",
+        (w as i32 / 2 - 40, 15),
+        ("sans-serif", 20).into_font().color(&color),
+    ))?;
+
+    Ok(())
+}
+
+/// A line along the bottom edge, below the time axis labels.
+pub fn draw_status_bar(
+    root: &Root<'_>,
+    text: &str,
+    color: RGBColor,
+    background: RGBColor,
+) -> Result<(), Box<dyn Error>> {
+    let (w, h) = root.dim_in_pixel();
+    let (w, h) = (w as i32, h as i32);
+    root.draw(&Rectangle::new(
+        [(0, h - 20), (w, h)],
+        background.mix(0.8).filled(),
+    ))?;
+    root.draw(&Text::new(
+        text,
+        (10, h - 17),
+        ("sans-serif", 15).into_font().color(&color),
+    ))?;
+
+    Ok(())
+}
+
+/// Top left corner, in the margin above the plotting area.
+pub fn draw_tare(root: &Root<'_>, text: &str, color: RGBColor) -> Result<(), Box<dyn Error>> {
+    root.draw(&Text::new(
+        text,
+        (75, 15),
+        ("sans-serif", 20).into_font().color(&color),
+    ))?;
+
+    Ok(())
+}
+
+/// One line per active alarm, in the top left corner of the plotting area
+/// starting at `top`.
+pub fn draw_alarm_banner(
+    root: &Root<'_>,
+    alarms: &[String],
+    top: i32,
+    color: RGBColor,
+) -> Result<(), Box<dyn Error>> {
+    let (x, mut y) = (75, top + 10);
+
+    for alarm in alarms {
+        root.draw(&Rectangle::new([(x, y), (x + 420, y + 24)], color.filled()))?;
+        root.draw(&Text::new(
+            alarm.as_str(),
+            (x + 6, y + 2),
+            ("sans-serif", 20).into_font().color(&WHITE),
+        ))?;
+        y += 28;
+    }
+
+    Ok(())
+}
+
+/// A box of statistics per series, in the lower left corner of the
+/// plotting area.
+pub fn draw_stats(
+    root: &Root<'_>,
+    stats: &[(&str, String, Stats)],
+    color: RGBColor,
+    background: RGBColor,
+) -> Result<(), Box<dyn Error>> {
+    if stats.is_empty() {
+        return Ok(());
+    }
+
+    let (_, h) = root.dim_in_pixel();
+    let line = 20;
+    let height = 10 + 2 * line * stats.len() as i32;
+    let (x, y) = (75, h as i32 - 75 - height);
+
+    root.draw(&Rectangle::new(
+        [(x, y), (x + 460, y + height)],
+        background.mix(0.8).filled(),
+    ))?;
+    root.draw(&Rectangle::new([(x, y), (x + 460, y + height)], &color))?;
+
+    let font = ("sans-serif", 15).into_font().color(&color);
+    for (i, (topic, unit, s)) in stats.iter().enumerate() {
+        let y = y + 6 + 2 * line * i as i32;
+        root.draw(&Text::new(
+            format!("{}  now {:.3} {}", topic, s.current, unit),
+            (x + 6, y),
+            font.clone(),
+        ))?;
+        root.draw(&Text::new(
+            format!(
+                "min {:.3}  max {:.3}  mean {:.3}  sd {:.3}",
+                s.min, s.max, s.mean, s.std_dev
+            ),
+            (x + 6, y + line),
+            font.clone(),
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Lines of text in a box centered below `top`, as for a running leak test
+/// or test sequence. Returns the bottom of the box.
+pub fn draw_text_box(
+    root: &Root<'_>,
+    lines: &[String],
+    top: i32,
+    color: RGBColor,
+    background: RGBColor,
+) -> Result<i32, Box<dyn Error>> {
+    let (w, _) = root.dim_in_pixel();
+    let line = 20;
+    // Roughly as wide as the longest line
+    let chars = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as i32;
+    let width = (chars * 8 + 12).clamp(440, w as i32 - 20);
+    let height = 10 + line * lines.len() as i32;
+    let (x, y) = ((w as i32 - width) / 2, top + 10);
+
+    root.draw(&Rectangle::new(
+        [(x, y), (x + width, y + height)],
+        background.mix(0.8).filled(),
+    ))?;
+    root.draw(&Rectangle::new([(x, y), (x + width, y + height)], &color))?;
+
+    let font = ("sans-serif", 15).into_font().color(&color);
+    for (i, text) in lines.iter().enumerate() {
+        root.draw(&Text::new(
+            text.as_str(),
+            (x + 6, y + 6 + line * i as i32),
+            font.clone(),
+        ))?;
+    }
+
+    Ok(y + height)
+}
+
+/// Values under the mouse cursor, in the lower right corner of the
+/// plotting area.
+pub fn draw_cursor_readout(
+    root: &Root<'_>,
+    lines: &[String],
+    color: RGBColor,
+    background: RGBColor,
+) -> Result<(), Box<dyn Error>> {
+    let (w, h) = root.dim_in_pixel();
+    let line = 20;
+    let height = 10 + line * lines.len() as i32;
+    let (x, y) = (w as i32 - 80 - 320, h as i32 - 75 - height);
+
+    root.draw(&Rectangle::new(
+        [(x, y), (x + 320, y + height)],
+        background.mix(0.8).filled(),
+    ))?;
+    root.draw(&Rectangle::new([(x, y), (x + 320, y + height)], &color))?;
+
+    let font = ("sans-serif", 15).into_font().color(&color);
+    for (i, text) in lines.iter().enumerate() {
+        root.draw(&Text::new(
+            text.as_str(),
+            (x + 6, y + 6 + line * i as i32),
+            font.clone(),
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// The keymap and the settings in effect, in two columns in a box in the
+/// middle of the window.
+pub fn draw_help(
+    root: &Root<'_>,
+    keys: &[(&str, &str)],
+    settings: &[(&str, String)],
+    color: RGBColor,
+    background: RGBColor,
+) -> Result<(), Box<dyn Error>> {
+    let (w, h) = root.dim_in_pixel();
+    let line = 20;
+    let (width, height) = (760, 50 + line * keys.len().max(settings.len()) as i32);
+    let (x, y) = ((w as i32 - width) / 2, (h as i32 - height).max(0) / 2);
+
+    root.draw(&Rectangle::new(
+        [(x, y), (x + width, y + height)],
+        background.mix(0.9).filled(),
+    ))?;
+    root.draw(&Rectangle::new([(x, y), (x + width, y + height)], &color))?;
+
+    let title = ("sans-serif", 18).into_font().color(&color);
+    let font = ("sans-serif", 15).into_font().color(&color);
+    let columns = [
+        ("Keys", x + 10, x + 130, keys.to_vec()),
+        (
+            "Settings",
+            x + 420,
+            x + 520,
+            settings.iter().map(|(k, v)| (*k, v.as_str())).collect(),
+        ),
+    ];
+    for (heading, name_x, value_x, rows) in &columns {
+        root.draw(&Text::new(*heading, (*name_x, y + 10), title.clone()))?;
+        for (i, (name, value)) in rows.iter().enumerate() {
+            let y = y + 40 + line * i as i32;
+            root.draw(&Text::new(*name, (*name_x, y), font.clone()))?;
+            root.draw(&Text::new(*value, (*value_x, y), font.clone()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The settings menu, a `(label, value)` row per setting with `selected`
+/// highlighted, in the top right corner of the plotting area.
+pub fn draw_settings(
+    root: &Root<'_>,
+    rows: &[(&str, String)],
+    selected: usize,
+    message: Option<&str>,
+    color: RGBColor,
+    background: RGBColor,
+) -> Result<(), Box<dyn Error>> {
+    let (w, _) = root.dim_in_pixel();
+    let line = 20;
+    let (width, height) = (340, 80 + line * rows.len() as i32);
+    let (x, y) = (w as i32 - 80 - width, 60);
+
+    root.draw(&Rectangle::new(
+        [(x, y), (x + width, y + height)],
+        background.mix(0.9).filled(),
+    ))?;
+    root.draw(&Rectangle::new([(x, y), (x + width, y + height)], &color))?;
+
+    let font = ("sans-serif", 15).into_font().color(&color);
+    root.draw(&Text::new(
+        "Settings",
+        (x + 10, y + 8),
+        ("sans-serif", 18).into_font().color(&color),
+    ))?;
+    for (i, (label, value)) in rows.iter().enumerate() {
+        let y = y + 36 + line * i as i32;
+        if i == selected {
+            root.draw(&Rectangle::new(
+                [(x + 4, y - 2), (x + width - 4, y + line - 2)],
+                color.mix(0.3).filled(),
+            ))?;
+        }
+        root.draw(&Text::new(*label, (x + 10, y), font.clone()))?;
+        root.draw(&Text::new(value.as_str(), (x + 140, y), font.clone()))?;
+    }
+
+    let y = y + 44 + line * rows.len() as i32;
+    let hint = "Up/Down select, Left/Right change, Del off, Enter save";
+    root.draw(&Text::new(message.unwrap_or(hint), (x + 10, y - 20), font))?;
+
+    Ok(())
+}
+
+/// The topic list, a row per topic with `selected` highlighted and the
+/// `entry` being typed below, where the settings menu goes.
+pub fn draw_topics(
+    root: &Root<'_>,
+    rows: &[String],
+    selected: usize,
+    entry: &str,
+    message: Option<&str>,
+    color: RGBColor,
+    background: RGBColor,
+) -> Result<(), Box<dyn Error>> {
+    let (w, _) = root.dim_in_pixel();
+    let line = 20;
+    let (width, height) = (420, 104 + line * rows.len() as i32);
+    let (x, y) = (w as i32 - 80 - width, 60);
+
+    root.draw(&Rectangle::new(
+        [(x, y), (x + width, y + height)],
+        background.mix(0.9).filled(),
+    ))?;
+    root.draw(&Rectangle::new([(x, y), (x + width, y + height)], &color))?;
+
+    let font = ("sans-serif", 15).into_font().color(&color);
+    root.draw(&Text::new(
+        "Topics",
+        (x + 10, y + 8),
+        ("sans-serif", 18).into_font().color(&color),
+    ))?;
+    for (i, topic) in rows.iter().enumerate() {
+        let y = y + 36 + line * i as i32;
+        if i == selected {
+            root.draw(&Rectangle::new(
+                [(x + 4, y - 2), (x + width - 4, y + line - 2)],
+                color.mix(0.3).filled(),
+            ))?;
+        }
+        root.draw(&Text::new(topic.as_str(), (x + 10, y), font.clone()))?;
+    }
+
+    let y = y + 44 + line * rows.len() as i32;
+    root.draw(&Text::new(
+        format!("Add: {}_", entry),
+        (x + 10, y - 4),
+        font.clone(),
+    ))?;
+    let hint = "Type and Enter add, Del removes the selected, F3 closes";
+    root.draw(&Text::new(message.unwrap_or(hint), (x + 10, y + 20), font))?;
+
+    Ok(())
+}
+
+/// The latest value of every series in large type, side by side below the
+/// status line like a panel meter.
+pub fn draw_readout(
+    root: &Root<'_>,
+    values: &[(String, RGBColor)],
+    font_size: u32,
+    position: ReadoutPosition,
+) -> Result<(), Box<dyn Error>> {
+    let (w, _) = root.dim_in_pixel();
+    let gap = font_size as i32;
+    let y = 35;
+    let font = TextStyle::from(("sans-serif", font_size).into_font());
+
+    let mut widths = Vec::with_capacity(values.len());
+    for (text, _) in values {
+        widths.push(root.estimate_text_size(text, &font)?.0 as i32);
+    }
+    let total = widths.iter().sum::<i32>() + gap * (values.len() as i32 - 1).max(0);
+
+    let mut x = match position {
+        ReadoutPosition::Left => 75,
+        ReadoutPosition::Center => (w as i32 - total) / 2,
+        ReadoutPosition::Right => w as i32 - 80 - total,
+    };
+    for ((text, color), width) in values.iter().zip(widths) {
+        root.draw(&Text::new(text.as_str(), (x, y), font.color(color)))?;
+        x += width + gap;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,199 @@
+//! Protobuf payloads of any schema, read at runtime from a compiled
+//! descriptor set, e.g. `protoc --include_imports -o firmware.desc
+//! reading.proto`, so a new firmware schema needs no new build. The value,
+//! timestamp and counter are found by the `[payload]` field paths, which
+//! may go through nested messages and repeated fields.
+
+use crate::decode::{epoch_time, Reading};
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor, Value};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Seconds and nanoseconds, taken as a timestamp.
+const TIMESTAMP: &str = "google.protobuf.Timestamp";
+
+#[derive(Debug, Clone)]
+pub struct Schema {
+    message: MessageDescriptor,
+}
+
+impl Schema {
+    /// The message type named `message`, e.g. `sensor.Reading`, of the
+    /// descriptor set at `path`.
+    pub fn load(path: &Path, message: &str) -> Result<Schema, Box<dyn Error>> {
+        let bytes = fs::read(path).map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+        let pool = DescriptorPool::decode(bytes.as_slice())
+            .map_err(|e| format!("Bad descriptor set {}: {}", path.display(), e))?;
+        let message = pool
+            .get_message_by_name(message)
+            .ok_or_else(|| format!("No message {} in {}", message, path.display()))?;
+        Ok(Schema { message })
+    }
+
+    pub fn decode(
+        &self,
+        payload: &[u8],
+        value_field: &str,
+        timestamp_field: &str,
+        sequence_field: &str,
+    ) -> Result<Reading, Box<dyn Error>> {
+        let message = DynamicMessage::decode(self.message.clone(), payload)
+            .map_err(|e| format!("Bad {} payload: {}", self.message.full_name(), e))?;
+
+        let value = field(&message, value_field)
+            .and_then(|v| number(&v))
+            .ok_or_else(|| format!("No numeric \"{}\" field in payload", value_field))?;
+        let timestamp = field(&message, timestamp_field)
+            .and_then(|v| match &v {
+                Value::Message(ts) if ts.descriptor().full_name() == TIMESTAMP => {
+                    let seconds = field(ts, "seconds").and_then(|v| number(&v))?;
+                    let nanos = field(ts, "nanos").and_then(|v| number(&v))?;
+                    Some(seconds + nanos * 1e-9)
+                }
+                v => number(v),
+            })
+            .and_then(epoch_time);
+        let sequence = field(&message, sequence_field)
+            .and_then(|v| number(&v))
+            .filter(|&n| n >= 0.0)
+            .map(|n| n as u64);
+
+        Ok(Reading {
+            value,
+            timestamp,
+            sequence,
+        })
+    }
+}
+
+/// Looks up a dotted path such as `data.pressure`, or `samples.0` for the
+/// first element of a repeated field.
+fn field(message: &DynamicMessage, path: &str) -> Option<Value> {
+    let mut keys = path.split('.');
+    let mut value = message.get_field_by_name(keys.next()?)?.into_owned();
+    for key in keys {
+        value = match value {
+            Value::Message(inner) => inner.get_field_by_name(key)?.into_owned(),
+            Value::List(mut items) => {
+                let index = key.parse::<usize>().ok()?;
+                (index < items.len()).then(|| items.swap_remove(index))?
+            }
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match *value {
+        Value::F64(v) => Some(v),
+        Value::F32(v) => Some(v as f64),
+        Value::I32(v) => Some(v as f64),
+        Value::I64(v) => Some(v as f64),
+        Value::U32(v) => Some(v as f64),
+        Value::U64(v) => Some(v as f64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn bytes(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+        varint(out, (field << 3) | 2);
+        varint(out, bytes.len() as u64);
+        out.extend_from_slice(bytes);
+    }
+
+    fn varint_field(out: &mut Vec<u8>, field: u64, value: u64) {
+        varint(out, field << 3);
+        varint(out, value);
+    }
+
+    /// A field of a `DescriptorProto`, of the label and type numbers given.
+    fn field_descriptor(name: &str, tag: u64, label: u64, kind: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        bytes(&mut out, 1, name.as_bytes());
+        varint_field(&mut out, 3, tag);
+        varint_field(&mut out, 4, label);
+        varint_field(&mut out, 5, kind);
+        out
+    }
+
+    /// The descriptor set of
+    /// `message sensor.Reading { double pressure = 1; uint64 seq = 2;
+    /// repeated double samples = 3; }`, written to a file of `name`.
+    fn descriptor_file(name: &str) -> std::path::PathBuf {
+        let mut message = Vec::new();
+        bytes(&mut message, 1, b"Reading");
+        bytes(&mut message, 2, &field_descriptor("pressure", 1, 1, 1));
+        bytes(&mut message, 2, &field_descriptor("seq", 2, 1, 4));
+        bytes(&mut message, 2, &field_descriptor("samples", 3, 3, 1));
+        let mut file = Vec::new();
+        bytes(&mut file, 1, b"reading.proto");
+        bytes(&mut file, 2, b"sensor");
+        bytes(&mut file, 4, &message);
+        bytes(&mut file, 12, b"proto3");
+        let mut set = Vec::new();
+        bytes(&mut set, 1, &file);
+
+        let path = env::temp_dir().join(format!(
+            "pressure_monitor_{}_{}.desc",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, set).unwrap();
+        path
+    }
+
+    /// A `sensor.Reading` of the values given.
+    fn reading(pressure: f64, seq: u64, samples: &[f64]) -> Vec<u8> {
+        let mut out = Vec::new();
+        varint(&mut out, (1 << 3) | 1);
+        out.extend_from_slice(&pressure.to_le_bytes());
+        varint_field(&mut out, 2, seq);
+        for sample in samples {
+            varint(&mut out, (3 << 3) | 1);
+            out.extend_from_slice(&sample.to_le_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn fields_by_path() {
+        let path = descriptor_file("fields_by_path");
+        let schema = Schema::load(&path, "sensor.Reading").unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let payload = reading(101.5, 7, &[1.0, 2.0]);
+        let decoded = schema.decode(&payload, "pressure", "ts", "seq").unwrap();
+        assert_eq!((decoded.value, decoded.sequence), (101.5, Some(7)));
+        assert_eq!(decoded.timestamp, None);
+
+        let second = schema.decode(&payload, "samples.1", "ts", "seq").unwrap();
+        assert_eq!(second.value, 2.0);
+        assert!(schema.decode(&payload, "samples.2", "ts", "seq").is_err());
+        assert!(schema.decode(&payload, "seq.0", "ts", "seq").is_err());
+        assert!(schema.decode(&[0xff], "pressure", "ts", "seq").is_err());
+    }
+
+    #[test]
+    fn unknown_messages() {
+        let path = descriptor_file("unknown_messages");
+        let missing = Schema::load(&path, "sensor.Other");
+        fs::remove_file(&path).unwrap();
+        assert!(missing.is_err());
+        assert!(Schema::load(&path, "sensor.Reading").is_err());
+    }
+}
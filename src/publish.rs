@@ -0,0 +1,219 @@
+//! Republishes alarm events and periodic aggregates to the MQTT broker, for
+//! automation downstream of the monitor, and whether the monitor itself is
+//! up: a retained birth message on every connect, and the same topic as the
+//! last will, which the broker sends once the connection drops. With
+//! `[publish.home_assistant] discovery` the series also show up in Home
+//! Assistant, see `discovery`.
+//!
+//! Both are JSON. Aggregates go out once per interval and sensor:
+//!
+//! ```text
+//! {"sensor":"pressure/data","start":"...","end":"...","count":600,"min":...,"max":...,"mean":...}
+//! ```
+
+use crate::alarm::AlarmEvent;
+use crate::config::{MqttConfig, PublishConfig};
+use crate::discovery::{Discovery, Message};
+use crate::source::mqtt;
+use crate::source::{BACKOFF_MAX, BACKOFF_MIN};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rumqttc::v4::Packet;
+use rumqttc::{Client, Event, LastWill, Outgoing, QoS};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, info_span, warn};
+
+/// Running min, max and mean of one sensor.
+struct Aggregate {
+    count: u64,
+    min: f64,
+    max: f64,
+    sum: f64,
+}
+
+pub struct Publisher {
+    client: Client,
+    alarm_topic: Option<String>,
+    stats_topic: Option<String>,
+    interval: Duration,
+    aggregates: BTreeMap<String, Aggregate>,
+    since: (Instant, SystemTime),
+    /// Status topic and offline message, sent on a clean exit too
+    offline: Option<(String, String)>,
+    discovery: Option<Discovery>,
+    /// Set by the connection thread, for discovery to announce again
+    reconnected: Arc<AtomicBool>,
+}
+
+impl Publisher {
+    /// Connects as `<client_id>_publisher`, reconnecting in the background.
+    pub fn start(mqtt: &MqttConfig, config: &PublishConfig) -> Result<Publisher, Box<dyn Error>> {
+        let mut options = mqtt::client_options(mqtt, format!("{}_publisher", mqtt.client_id))?;
+        if let Some(topic) = &config.status_topic {
+            let will = LastWill::new(topic, config.offline.clone(), QoS::AtLeastOnce, true);
+            options.set_last_will(will);
+        }
+        let (client, mut connection) = Client::new(options, 100);
+
+        let mut birth = config
+            .status_topic
+            .clone()
+            .map(|topic| (client.clone(), topic, config.online.clone()));
+        let reconnected = Arc::new(AtomicBool::new(false));
+        let connected = reconnected.clone();
+        thread::spawn(move || {
+            let _span = info_span!("publish").entered();
+            let mut backoff = BACKOFF_MIN;
+            for notification in connection.iter() {
+                match notification {
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        info!("Connected to broker");
+                        backoff = BACKOFF_MIN;
+                        connected.store(true, Ordering::Relaxed);
+                        if let Some((client, topic, online)) = &mut birth {
+                            let online = online.clone();
+                            if let Err(e) =
+                                client.try_publish(&*topic, QoS::AtLeastOnce, true, online)
+                            {
+                                warn!("Publish to {} failed: {}", topic, e);
+                            }
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!("Connection error: {}, retrying in {:?}", e, backoff);
+                        thread::sleep(backoff);
+                        backoff = (backoff * 2).min(BACKOFF_MAX);
+                    }
+                }
+            }
+        });
+
+        Ok(Publisher {
+            client,
+            alarm_topic: config.alarm_topic.clone(),
+            stats_topic: config.stats_topic.clone(),
+            interval: Duration::from_secs_f64(config.stats_interval.max(1.0)),
+            aggregates: BTreeMap::new(),
+            since: (Instant::now(), SystemTime::now()),
+            offline: config
+                .status_topic
+                .clone()
+                .map(|topic| (topic, config.offline.clone())),
+            discovery: config.home_assistant.discovery.then(|| {
+                let availability = config
+                    .status_topic
+                    .clone()
+                    .map(|topic| (topic, config.online.clone(), config.offline.clone()));
+                Discovery::new(&config.home_assistant, availability)
+            }),
+            reconnected,
+        })
+    }
+
+    pub fn alarm(&mut self, event: &AlarmEvent) {
+        if let Some(topic) = self.alarm_topic.clone() {
+            self.publish(&topic, event.to_json().to_string(), false);
+        }
+        if let Some(discovery) = &mut self.discovery {
+            let messages = discovery.alarm(event);
+            self.send(messages);
+        }
+    }
+
+    /// Adds a sample, in Pa, to the aggregate of its sensor, and passes it
+    /// on to discovery.
+    pub fn record(&mut self, topic: &str, value: f64) {
+        if let Some(discovery) = &mut self.discovery {
+            if self.reconnected.swap(false, Ordering::Relaxed) {
+                discovery.reconnected();
+            }
+            let messages = discovery.sample(topic, value);
+            self.send(messages);
+        }
+        if self.stats_topic.is_none() {
+            return;
+        }
+        match self.aggregates.get_mut(topic) {
+            Some(aggregate) => {
+                aggregate.count += 1;
+                aggregate.min = aggregate.min.min(value);
+                aggregate.max = aggregate.max.max(value);
+                aggregate.sum += value;
+            }
+            None => {
+                let aggregate = Aggregate {
+                    count: 1,
+                    min: value,
+                    max: value,
+                    sum: value,
+                };
+                self.aggregates.insert(topic.to_string(), aggregate);
+            }
+        }
+    }
+
+    /// Publishes the aggregates once the interval is over, call every frame.
+    pub fn tick(&mut self) {
+        let topic = match &self.stats_topic {
+            Some(topic) if self.since.0.elapsed() >= self.interval => topic.clone(),
+            _ => return,
+        };
+        let (start, end) = (rfc3339(self.since.1), rfc3339(SystemTime::now()));
+        self.since = (Instant::now(), SystemTime::now());
+
+        for (sensor, aggregate) in std::mem::take(&mut self.aggregates) {
+            let payload = json!({
+                "sensor": sensor,
+                "start": start,
+                "end": end,
+                "count": aggregate.count,
+                "min": aggregate.min,
+                "max": aggregate.max,
+                "mean": aggregate.sum / aggregate.count as f64,
+                "unit": "Pa",
+            });
+            self.publish(&topic, payload.to_string(), false);
+        }
+    }
+
+    fn send(&mut self, messages: Vec<Message>) {
+        for message in messages {
+            self.publish(&message.topic, message.payload, message.retain);
+        }
+    }
+
+    fn publish(&mut self, topic: &str, payload: String, retain: bool) {
+        // Never block the render loop, a full queue means the broker is gone
+        if let Err(e) = self
+            .client
+            .try_publish(topic, QoS::AtLeastOnce, retain, payload)
+        {
+            warn!("Publish to {} failed: {}", topic, e);
+        }
+    }
+}
+
+impl Drop for Publisher {
+    /// Queued after the last messages, so those still go out. The broker
+    /// drops the last will on a clean disconnect, the offline message is
+    /// sent first.
+    fn drop(&mut self) {
+        if let Some((topic, offline)) = self.offline.take() {
+            self.client
+                .try_publish(topic, QoS::AtLeastOnce, true, offline)
+                .ok();
+        }
+        self.client.try_disconnect().ok();
+    }
+}
+
+fn rfc3339(ts: SystemTime) -> String {
+    DateTime::<Utc>::from(ts).to_rfc3339_opts(SecondsFormat::Millis, true)
+}
@@ -0,0 +1,57 @@
+//! Rate of change of a signal, dP/dt for pressures.
+
+use std::collections::VecDeque;
+
+/// Unit of pressure rates, samples being in Pa.
+pub const RATE_UNIT: &str = "Pa/s";
+
+/// Least squares slope over the samples of a sliding time window, much less
+/// noisy than the difference of two consecutive samples.
+#[derive(Debug, Clone)]
+pub struct RateOfChange {
+    /// Seconds
+    window: f64,
+    samples: VecDeque<(f64, f64)>,
+    last: Option<f64>,
+}
+
+impl RateOfChange {
+    pub fn new(window: f64) -> RateOfChange {
+        RateOfChange {
+            window: window.max(0.0),
+            samples: VecDeque::new(),
+            last: None,
+        }
+    }
+
+    /// Adds the sample at `t` seconds. Returns the slope, in units per
+    /// second, once the window holds samples at two different times.
+    pub fn update(&mut self, t: f64, value: f64) -> Option<f64> {
+        self.samples.push_back((t, value));
+        while self.samples.front().is_some_and(|s| s.0 < t - self.window) {
+            self.samples.pop_front();
+        }
+
+        let n = self.samples.len() as f64;
+        let (sum_t, sum_v) = self
+            .samples
+            .iter()
+            .fold((0.0, 0.0), |(st, sv), &(t, v)| (st + t, sv + v));
+        let (mean_t, mean_v) = (sum_t / n, sum_v / n);
+        let (cov, var) = self.samples.iter().fold((0.0, 0.0), |(cov, var), &(t, v)| {
+            let dt = t - mean_t;
+            (cov + dt * (v - mean_v), var + dt * dt)
+        });
+        if var > 0.0 {
+            self.last = Some(cov / var);
+            self.last
+        } else {
+            None
+        }
+    }
+
+    /// The latest slope.
+    pub fn last(&self) -> Option<f64> {
+        self.last
+    }
+}
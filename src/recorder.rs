@@ -0,0 +1,99 @@
+//! Append-only CSV log of every received sample, always in Pa.
+
+use crate::config::RotationConfig;
+use crate::rotate::Rotation;
+use crate::store::Store;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub struct Recorder {
+    path: PathBuf,
+    /// Closed while rotating, reopened by the next write
+    writer: Option<csv::Writer<File>>,
+    rotation: Rotation,
+    flush_interval: Duration,
+    last_flush: Instant,
+}
+
+impl Recorder {
+    /// Appends to `path`, writing the header only when the file is new.
+    pub fn open(
+        path: &Path,
+        flush_interval: Duration,
+        rotation: &RotationConfig,
+    ) -> Result<Recorder, Box<dyn Error>> {
+        Ok(Recorder {
+            path: path.to_path_buf(),
+            writer: Some(open_writer(path)?),
+            rotation: Rotation::new(path, rotation),
+            flush_interval,
+            last_flush: Instant::now(),
+        })
+    }
+
+    fn writer(&mut self) -> Result<&mut csv::Writer<File>, Box<dyn Error>> {
+        let writer = match self.writer.take() {
+            Some(writer) => writer,
+            None => open_writer(&self.path)?,
+        };
+        Ok(self.writer.insert(writer))
+    }
+
+    fn rotate(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        self.rotation.rotate()
+    }
+}
+
+fn open_writer(path: &Path) -> Result<csv::Writer<File>, Box<dyn Error>> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Cannot open log file {}: {}", path.display(), e))?;
+    let is_new = file.metadata()?.len() == 0;
+
+    let mut writer = csv::Writer::from_writer(file);
+    if is_new {
+        writer.write_record(&["Time(unix s)", "Topic", "Pressure(Pa)"])?;
+        writer.flush()?;
+    }
+    Ok(writer)
+}
+
+impl Store for Recorder {
+    fn write(&mut self, ts: SystemTime, topic: &str, value: f64) -> Result<(), Box<dyn Error>> {
+        // The first sample of a day starts its file
+        if self.rotation.new_day() {
+            self.rotate()?;
+        }
+
+        let unix = ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.writer()?.write_record(&[
+            format!("{:.3}", unix.as_secs_f64()),
+            topic.to_string(),
+            value.to_string(),
+        ])?;
+
+        if self.last_flush.elapsed() >= self.flush_interval {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(writer) = &mut self.writer {
+            writer.flush()?;
+            let size = writer.get_ref().metadata()?.len();
+            if self.rotation.oversized(size) {
+                self.rotate()?;
+            }
+        }
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+}
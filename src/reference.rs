@@ -0,0 +1,156 @@
+//! A qualified "golden" pressure curve, overlaid from the last marker on with
+//! its tolerance band, and every sample compared against it. Leaving the band
+//! marks the chart and is counted per series.
+//!
+//! The curve is a CSV file of seconds from the start, pressure and an
+//! optional tolerance per row, a header row and `#` comments allowed.
+
+use crate::config::ReferenceCurveConfig;
+use crate::decode;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+/// How a series compares to the curve since it was aligned.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Deviation {
+    /// Outside the band with the last sample
+    pub outside: bool,
+    /// Times it left the band
+    pub excursions: usize,
+    /// Largest difference to the curve, Pa
+    pub max: f64,
+}
+
+pub struct ReferenceCurve {
+    /// File name, for the overlay
+    pub name: String,
+    /// Seconds from the start, pressure and tolerance, both Pa
+    points: Vec<(f64, f64, f64)>,
+    topic: Option<String>,
+    /// On the `Clock` time line, `None` until aligned
+    start: Option<f64>,
+    deviations: BTreeMap<String, Deviation>,
+}
+
+impl ReferenceCurve {
+    pub fn load(
+        path: &Path,
+        config: &ReferenceCurveConfig,
+    ) -> Result<ReferenceCurve, Box<dyn Error>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .comment(Some(b'#'))
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+        let unit = config.unit;
+        let mut points = Vec::new();
+        for (row, record) in reader.records().enumerate() {
+            let record = record?;
+            let number = |i: usize| record.get(i).and_then(|v| v.parse::<f64>().ok());
+            match (number(0), number(1)) {
+                (Some(t), Some(p)) => {
+                    let tolerance = number(2).unwrap_or(config.tolerance);
+                    points.push((t, unit.to_pa(p), unit.to_pa(tolerance.abs())));
+                }
+                // The header
+                _ if row == 0 => {}
+                _ => {
+                    return Err(format!(
+                        "{} row {}: expected seconds and a pressure",
+                        path.display(),
+                        row + 1
+                    )
+                    .into())
+                }
+            }
+        }
+        if points.len() < 2 {
+            return Err(format!("{} has fewer than two points", path.display()).into());
+        }
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Ok(ReferenceCurve {
+            name: path.file_name().map_or_else(
+                || path.display().to_string(),
+                |n| n.to_string_lossy().into_owned(),
+            ),
+            points,
+            topic: config.topic.clone(),
+            start: None,
+            deviations: BTreeMap::new(),
+        })
+    }
+
+    /// Starts the curve at `t`, and the comparisons over.
+    pub fn align(&mut self, t: f64) {
+        self.start = Some(t);
+        self.deviations.clear();
+    }
+
+    pub fn start(&self) -> Option<f64> {
+        self.start
+    }
+
+    /// Whether the series `topic` is compared.
+    pub fn applies(&self, topic: &str) -> bool {
+        self.topic
+            .as_deref()
+            .is_none_or(|filter| decode::topic_matches(filter, topic))
+    }
+
+    /// The points on the `Clock` time line, empty unless aligned.
+    pub fn points(&self) -> Vec<(f64, f64, f64)> {
+        let Some(start) = self.start else {
+            return Vec::new();
+        };
+        self.points
+            .iter()
+            .map(|&(t, p, tolerance)| (start + t, p, tolerance))
+            .collect()
+    }
+
+    /// The curve and its tolerance at `t`, interpolated, `None` outside it.
+    fn at(&self, t: f64) -> Option<(f64, f64)> {
+        let dt = t - self.start?;
+        let i = self.points.partition_point(|&(at, _, _)| at <= dt);
+        if i == 0 || (i == self.points.len() && dt > self.points[i - 1].0) {
+            return None;
+        }
+        let (t0, p0, tol0) = self.points[i - 1];
+        let Some(&(t1, p1, tol1)) = self.points.get(i) else {
+            return Some((p0, tol0));
+        };
+        let k = (dt - t0) / (t1 - t0);
+        Some((p0 + (p1 - p0) * k, tol0 + (tol1 - tol0) * k))
+    }
+
+    /// A sample of `topic` at `t` in Pa. `Some(true)` when it left the band,
+    /// `Some(false)` when it came back.
+    pub fn update(&mut self, topic: &str, t: f64, pa: f64) -> Option<bool> {
+        if !self.applies(topic) {
+            return None;
+        }
+        let (expected, tolerance) = self.at(t)?;
+        let difference = (pa - expected).abs();
+        let deviation = self.deviations.entry(topic.to_string()).or_default();
+        deviation.max = deviation.max.max(difference);
+        // Without a tolerance only the largest deviation is kept
+        let outside = tolerance > 0.0 && difference > tolerance;
+        if outside == deviation.outside {
+            return None;
+        }
+        deviation.outside = outside;
+        if outside {
+            deviation.excursions += 1;
+        }
+        Some(outside)
+    }
+
+    /// By series, of those compared since aligned.
+    pub fn deviations(&self) -> &BTreeMap<String, Deviation> {
+        &self.deviations
+    }
+}
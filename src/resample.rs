@@ -0,0 +1,185 @@
+//! Resampling onto a fixed time grid, for what assumes evenly spaced
+//! samples: the spectrum, the filters counting samples and some consumers of
+//! the exports. Each sample fills in the grid points since the previous one
+//! of its series, so nothing waits for later samples.
+//!
+//! The message counters of resampled series aren't checked, their samples
+//! no longer are messages.
+
+use crate::config::{ResampleConfig, ResampleMethod};
+use crate::decode;
+use crate::source::Sample;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub struct Resampler {
+    /// Seconds between grid points
+    interval: f64,
+    method: ResampleMethod,
+    /// Seconds between samples not filled in
+    max_gap: f64,
+    topics: Vec<String>,
+    /// Time, as seconds since the epoch, and value of the last sample per
+    /// series
+    last: HashMap<String, (f64, f64)>,
+}
+
+impl Resampler {
+    /// `None` when off.
+    pub fn new(config: &ResampleConfig) -> Option<Resampler> {
+        let rate = config.rate.filter(|&rate| rate > 0.0)?;
+        Some(Resampler {
+            interval: 1.0 / rate,
+            method: config.method,
+            max_gap: config.max_gap.unwrap_or(10.0 / rate),
+            topics: config.topics.clone(),
+            last: HashMap::new(),
+        })
+    }
+
+    /// The grid points from the previous sample of its series up to this
+    /// one, the sample itself if its series isn't resampled.
+    pub fn resample(&mut self, sample: Sample) -> Vec<Sample> {
+        let series = sample.series();
+        let resampled = self.topics.is_empty()
+            || self
+                .topics
+                .iter()
+                .any(|filter| decode::topic_matches(filter, &series));
+        if !resampled {
+            return vec![sample];
+        }
+
+        let t = sample
+            .timestamp
+            .unwrap_or_else(SystemTime::now)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let Some((previous, before)) = self.last.get(&series).copied() else {
+            self.last.insert(series, (t, sample.value));
+            return Vec::new();
+        };
+        // Late ones would go back in time
+        if t <= previous {
+            return Vec::new();
+        }
+        self.last.insert(series, (t, sample.value));
+        if t - previous > self.max_gap {
+            return Vec::new();
+        }
+
+        let first = (previous / self.interval).floor() as i64 + 1;
+        let last = (t / self.interval).floor() as i64;
+        (first..=last)
+            .map(|k| {
+                let at = k as f64 * self.interval;
+                let value = match self.method {
+                    ResampleMethod::Hold if at < t => before,
+                    ResampleMethod::Hold => sample.value,
+                    ResampleMethod::Linear => {
+                        before + (sample.value - before) * (at - previous) / (t - previous)
+                    }
+                };
+                Sample {
+                    topic: sample.topic.clone(),
+                    value,
+                    timestamp: Some(UNIX_EPOCH + Duration::from_secs_f64(at.max(0.0))),
+                    sequence: None,
+                    sensor: sample.sensor.clone(),
+                    expires: sample.expires,
+                    retained: sample.retained,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(topic: &str, t: f64, value: f64) -> Sample {
+        Sample {
+            topic: topic.to_string(),
+            value,
+            timestamp: Some(UNIX_EPOCH + Duration::from_secs_f64(t)),
+            sequence: None,
+            sensor: None,
+            expires: None,
+            retained: false,
+        }
+    }
+
+    fn resampler(method: ResampleMethod) -> Resampler {
+        Resampler::new(&ResampleConfig {
+            rate: Some(1.0),
+            method,
+            topics: vec!["pressure/+".to_string()],
+            max_gap: Some(5.0),
+        })
+        .unwrap()
+    }
+
+    /// Seconds since the epoch and values.
+    fn points(samples: &[Sample]) -> Vec<(f64, f64)> {
+        let seconds = |s: &Sample| {
+            s.timestamp
+                .and_then(|ts| ts.duration_since(UNIX_EPOCH).ok())
+                .map_or(f64::NAN, |d| d.as_secs_f64())
+        };
+        samples.iter().map(|s| (seconds(s), s.value)).collect()
+    }
+
+    #[test]
+    fn off_without_a_rate() {
+        assert!(Resampler::new(&ResampleConfig::default()).is_none());
+        let config = ResampleConfig {
+            rate: Some(0.0),
+            ..ResampleConfig::default()
+        };
+        assert!(Resampler::new(&config).is_none());
+    }
+
+    #[test]
+    fn linear() {
+        let mut resampler = resampler(ResampleMethod::Linear);
+        assert!(resampler
+            .resample(sample("pressure/a", 0.5, 0.0))
+            .is_empty());
+        let grid = resampler.resample(sample("pressure/a", 2.5, 20.0));
+        assert_eq!(points(&grid), [(1.0, 5.0), (2.0, 15.0)]);
+    }
+
+    #[test]
+    fn hold() {
+        let mut resampler = resampler(ResampleMethod::Hold);
+        resampler.resample(sample("pressure/a", 0.5, 0.0));
+        let grid = resampler.resample(sample("pressure/a", 2.5, 20.0));
+        assert_eq!(points(&grid), [(1.0, 0.0), (2.0, 0.0)]);
+        // On a grid point the sample itself
+        let grid = resampler.resample(sample("pressure/a", 3.0, 30.0));
+        assert_eq!(points(&grid), [(3.0, 30.0)]);
+    }
+
+    #[test]
+    fn gaps_and_late_samples_are_not_filled() {
+        let mut resampler = resampler(ResampleMethod::Linear);
+        resampler.resample(sample("pressure/a", 0.5, 0.0));
+        assert!(resampler
+            .resample(sample("pressure/a", 10.5, 1.0))
+            .is_empty());
+        assert!(resampler
+            .resample(sample("pressure/a", 9.0, 1.0))
+            .is_empty());
+        let grid = resampler.resample(sample("pressure/a", 11.5, 2.0));
+        assert_eq!(points(&grid), [(11.0, 1.5)]);
+    }
+
+    #[test]
+    fn other_series_pass_through() {
+        let mut resampler = resampler(ResampleMethod::Linear);
+        let passed = resampler.resample(sample("temperature", 0.5, 21.0));
+        assert_eq!(points(&passed), [(0.5, 21.0)]);
+    }
+}
@@ -0,0 +1,251 @@
+//! Rotation of log files by day or size, for runs over days.
+//!
+//! The file being written keeps its configured name. Rotated ones get the
+//! day, or the time they were started, appended, e.g.
+//! `pressure_log_2024-05-03.csv`, and are optionally gzipped.
+
+use crate::config::RotationConfig;
+use chrono::{DateTime, Local};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use tracing::{error, info};
+
+pub struct Rotation {
+    path: PathBuf,
+    config: RotationConfig,
+    /// When the current file was started
+    started: DateTime<Local>,
+    /// Compressing and pruning the last rotated file
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Rotation {
+    pub fn new(path: &Path, config: &RotationConfig) -> Rotation {
+        // An existing file is continued, as if started when last written
+        let started = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map_or_else(|_| Local::now(), DateTime::from);
+        Rotation {
+            path: path.to_path_buf(),
+            config: config.clone(),
+            started,
+            worker: None,
+        }
+    }
+
+    /// Daily rotation is on and the day changed since the file was started.
+    pub fn new_day(&self) -> bool {
+        self.config.daily && day(&Local::now()) != day(&self.started)
+    }
+
+    /// The file is larger than the configured maximum.
+    pub fn oversized(&self, size: u64) -> bool {
+        self.config
+            .max_size_mb
+            .is_some_and(|max| size as f64 > max * 1e6)
+    }
+
+    /// Moves the file, which must be closed, aside. Compressing it and
+    /// removing old ones happens in the background, after what is left of
+    /// the previous rotation, so a file half compressed isn't pruned.
+    pub fn rotate(&mut self) -> Result<(), Box<dyn Error>> {
+        let rotated = self.rotated_path();
+        let started = std::mem::replace(&mut self.started, Local::now());
+        if !self.path.exists() {
+            return Ok(());
+        }
+        fs::rename(&self.path, &rotated)
+            .map_err(|e| format!("Cannot rotate {}: {}", self.path.display(), e))?;
+        info!(
+            "Rotated {} started {} to {}",
+            self.path.display(),
+            started.format("%Y-%m-%d %H:%M:%S"),
+            rotated.display()
+        );
+
+        if let Some(worker) = self.worker.take() {
+            worker.join().ok();
+        }
+        let (path, gzip, keep) = (self.path.clone(), self.config.gzip, self.config.keep);
+        self.worker = Some(thread::spawn(move || {
+            if gzip {
+                if let Err(e) = compress(&rotated) {
+                    error!("Cannot compress {}: {}", rotated.display(), e);
+                }
+            }
+            if let Some(keep) = keep {
+                if let Err(e) = prune(&path, keep) {
+                    error!("Cannot remove old logs of {}: {}", path.display(), e);
+                }
+            }
+        }));
+        Ok(())
+    }
+
+    /// A free name for the current file, stamped with its day or, when
+    /// rotating by size, its start time.
+    fn rotated_path(&self) -> PathBuf {
+        let format = match self.config.max_size_mb {
+            Some(_) => "%Y-%m-%dT%H-%M-%S",
+            None => "%Y-%m-%d",
+        };
+        let stamp = self.started.format(format).to_string();
+        let (stem, extension) = name_parts(&self.path);
+
+        (0..)
+            .map(|n| match n {
+                0 => format!("{}_{}{}", stem, stamp, extension),
+                n => format!("{}_{}_{}{}", stem, stamp, n, extension),
+            })
+            .map(|name| self.path.with_file_name(name))
+            .find(|path| !path.exists() && !gz_path(path).exists())
+            .unwrap_or_else(|| self.path.with_extension("old"))
+    }
+}
+
+fn day(time: &DateTime<Local>) -> String {
+    time.format("%Y-%m-%d").to_string()
+}
+
+/// Stem and extension including the dot, `("pressure_log", ".csv")`.
+fn name_parts(path: &Path) -> (String, String) {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    (stem, extension)
+}
+
+fn gz_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".gz");
+    PathBuf::from(name)
+}
+
+/// Replaces the file with a gzipped copy named `<file>.gz`.
+fn compress(path: &Path) -> io::Result<()> {
+    let mut encoder = GzEncoder::new(File::create(gz_path(path))?, Compression::default());
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)
+}
+
+/// Removes all but the newest `keep` rotated files of `path`.
+fn prune(path: &Path, keep: usize) -> io::Result<()> {
+    let (stem, extension) = name_parts(path);
+    let prefix = format!("{}_", stem);
+    // The stamp and the number of a rotated file, `None` for other files
+    let rotation = |name: &str| -> Option<(String, u64)> {
+        let rest = name.strip_prefix(&prefix)?;
+        let rest = rest.strip_suffix(".gz").unwrap_or(rest);
+        let rest = rest.strip_suffix(extension.as_str())?;
+        // The stamp follows the prefix, so other files sharing it are left alone
+        if !rest.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        match rest.split_once('_') {
+            Some((stamp, n)) => Some((stamp.to_string(), n.parse().ok()?)),
+            None => Some((rest.to_string(), 0)),
+        }
+    };
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut rotated: Vec<((String, u64), PathBuf)> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter_map(|path| {
+            let key = rotation(path.file_name()?.to_str()?)?;
+            Some((key, path))
+        })
+        .collect();
+    // Oldest first, the stamps sort by time and the numbers count up
+    rotated.sort();
+
+    let excess = rotated.len().saturating_sub(keep);
+    for (_, old) in &rotated[..excess] {
+        fs::remove_file(old)?;
+        info!("Removed old log {}", old.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory of its own per test, they run in parallel.
+    fn dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("pressure_monitor_{}_{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn prune_keeps_the_newest() {
+        let dir = dir("prune");
+        for name in [
+            "log.csv",
+            "log_2024-05-01.csv.gz",
+            "log_2024-05-02.csv",
+            "log_2024-05-02_2.csv",
+            "log_2024-05-02_10.csv.gz",
+            "log_other.csv",
+            "log_2024-05-01.txt",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        prune(&dir.join("log.csv"), 2).unwrap();
+        let left = names(&dir);
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!(
+            left,
+            [
+                "log.csv",
+                "log_2024-05-01.txt",
+                "log_2024-05-02_10.csv.gz",
+                "log_2024-05-02_2.csv",
+                "log_other.csv",
+            ]
+        );
+    }
+
+    #[test]
+    fn rotated_names_are_free() {
+        let dir = dir("rotated_path");
+        let path = dir.join("log.csv");
+        let config = RotationConfig {
+            daily: true,
+            ..RotationConfig::default()
+        };
+        let rotation = Rotation::new(&path, &config);
+        let day = day(&rotation.started);
+        let first = rotation.rotated_path();
+        assert_eq!(first, dir.join(format!("log_{}.csv", day)));
+        fs::write(gz_path(&first), "").unwrap();
+        let second = rotation.rotated_path();
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!(second, dir.join(format!("log_{}_1.csv", day)));
+    }
+}
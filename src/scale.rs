@@ -0,0 +1,45 @@
+//! Y axis autoscaling.
+
+/// Fraction of the data span added above and below it.
+const PADDING: f64 = 0.1;
+
+/// The range only shrinks once the padded data covers less than this
+/// fraction of it, so small fluctuations don't make the axis jitter.
+const SHRINK_BELOW: f64 = 0.5;
+
+#[derive(Debug, Default)]
+pub struct AutoScale {
+    range: Option<(f64, f64)>,
+}
+
+impl AutoScale {
+    /// Returns the Y range to draw for data between `min` and `max`.
+    pub fn update(&mut self, min: f64, max: f64) -> (f64, f64) {
+        let span = max - min;
+        // A flat signal still needs a non-empty range
+        let pad = if span > 0.0 {
+            span * PADDING
+        } else {
+            (min.abs() * PADDING).max(1.0)
+        };
+        let target = (min - pad, max + pad);
+
+        let range = match self.range {
+            Some((lo, hi)) if lo <= min && max <= hi => {
+                if target.1 - target.0 < (hi - lo) * SHRINK_BELOW {
+                    target
+                } else {
+                    (lo, hi)
+                }
+            }
+            _ => target,
+        };
+
+        self.range = Some(range);
+        range
+    }
+
+    pub fn reset(&mut self) {
+        self.range = None;
+    }
+}
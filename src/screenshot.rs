@@ -0,0 +1,44 @@
+//! PNG snapshots of the window contents.
+
+use chrono::Local;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// `pressure_2024-05-03T10-22-31.png` in `dir`, from the local time.
+pub fn timestamped_path(dir: &Path) -> PathBuf {
+    dir.join(
+        Local::now()
+            .format("pressure_%Y-%m-%dT%H-%M-%S.png")
+            .to_string(),
+    )
+}
+
+/// Writes a `w` x `h` frame of `0x00RRGGBB` pixels, as handed to minifb.
+pub fn save_png(path: &Path, frame: &[u32], w: usize, h: usize) -> Result<(), Box<dyn Error>> {
+    let file = File::create(path)
+        .map_err(|e| format!("Cannot create screenshot {}: {}", path.display(), e))?;
+    write_png(BufWriter::new(file), frame, w, h)
+}
+
+/// The frame as PNG file contents, for embedding in reports.
+pub fn encode_png(frame: &[u32], w: usize, h: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut png = Vec::new();
+    write_png(&mut png, frame, w, h)?;
+    Ok(png)
+}
+
+fn write_png(out: impl Write, frame: &[u32], w: usize, h: usize) -> Result<(), Box<dyn Error>> {
+    let rgb: Vec<u8> = frame[..w * h]
+        .iter()
+        .flat_map(|&p| [(p >> 16) as u8, (p >> 8) as u8, p as u8])
+        .collect();
+
+    let mut encoder = png::Encoder::new(out, w as u32, h as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&rgb)?;
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+//! Long term history of one series, kept beyond the live buffer so the chart
+//! can be scrolled back by hours. It is decimated into buckets of time that
+//! keep their lowest and highest sample, so spikes still show.
+//!
+//! Samples are `(t, value)` with `t` in seconds on the `Clock` time line.
+
+use std::collections::VecDeque;
+use std::iter;
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    /// `t` divided by the bucket width
+    index: i64,
+    min: (f64, f64),
+    max: (f64, f64),
+}
+
+#[derive(Debug, Clone)]
+pub struct Scrollback {
+    buckets: VecDeque<Bucket>,
+    /// Seconds per bucket
+    width: f64,
+    /// Seconds kept, off when zero
+    span: f64,
+}
+
+impl Scrollback {
+    pub fn new(width: f64, span: f64) -> Scrollback {
+        Scrollback {
+            buckets: VecDeque::new(),
+            width: width.max(1e-3),
+            span: span.max(0.0),
+        }
+    }
+
+    /// Samples older than the newest bucket go into that one.
+    pub fn push(&mut self, t: f64, value: f64) {
+        if self.span == 0.0 {
+            return;
+        }
+        let index = (t / self.width).floor() as i64;
+        match self.buckets.back_mut() {
+            Some(bucket) if index <= bucket.index => {
+                if value < bucket.min.1 {
+                    bucket.min = (t, value);
+                }
+                if value > bucket.max.1 {
+                    bucket.max = (t, value);
+                }
+            }
+            _ => self.buckets.push_back(Bucket {
+                index,
+                min: (t, value),
+                max: (t, value),
+            }),
+        }
+
+        let oldest = ((t - self.span) / self.width).floor() as i64;
+        while self.buckets.front().is_some_and(|b| b.index < oldest) {
+            self.buckets.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.clear();
+    }
+
+    /// The lowest and highest sample of each bucket from before `until`,
+    /// oldest first.
+    pub fn before(&self, until: f64) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.buckets
+            .iter()
+            .flat_map(|b| {
+                let (first, second) = if b.min.0 <= b.max.0 {
+                    (b.min, b.max)
+                } else {
+                    (b.max, b.min)
+                };
+                // Once for a bucket of one sample
+                iter::once(first).chain((second.0 != first.0).then_some(second))
+            })
+            .filter(move |&(t, _)| t < until)
+    }
+}
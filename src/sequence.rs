@@ -0,0 +1,515 @@
+//! Pass/fail test sequences. The steps of `[sequence]` run one after the
+//! other on a single sensor, each prompting the operator and judging the
+//! pressure by itself, and a failed step ends the sequence. The report is an
+//! HTML page with the chart embedded and room for the sign-off.
+
+use crate::units::PressureUnit;
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Pressures are in Pa, as the alarm thresholds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Step {
+    /// Waits for the operator to press Enter, e.g. after connecting a part
+    Confirm { prompt: String },
+    /// Waits until the pressure is within `min`..`max`, failing after
+    /// `timeout` seconds when given
+    Reach {
+        prompt: String,
+        min: Option<f64>,
+        max: Option<f64>,
+        timeout: Option<f64>,
+    },
+    /// Passes once the pressure stayed within `min`..`max` for `duration`
+    /// seconds, fails as soon as it leaves
+    Hold {
+        prompt: String,
+        min: Option<f64>,
+        max: Option<f64>,
+        duration: f64,
+    },
+    /// Passes when the pressure fell by at most `max_drop` over `duration`
+    /// seconds from the first sample of the step
+    Decay {
+        prompt: String,
+        max_drop: f64,
+        duration: f64,
+    },
+}
+
+impl Step {
+    pub fn prompt(&self) -> &str {
+        match self {
+            Step::Confirm { prompt }
+            | Step::Reach { prompt, .. }
+            | Step::Hold { prompt, .. }
+            | Step::Decay { prompt, .. } => prompt,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Pass,
+    Fail,
+}
+
+impl Verdict {
+    pub fn label(self) -> &'static str {
+        match self {
+            Verdict::Pass => "PASS",
+            Verdict::Fail => "FAIL",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StepResult {
+    pub prompt: String,
+    pub verdict: Verdict,
+    /// What was measured, in the unit the sequence was started with
+    pub detail: String,
+    /// Seconds the step took
+    pub duration: f64,
+}
+
+/// A sequence being run, fed the samples of the tested sensor.
+pub struct Runner {
+    /// Of the tested sensor
+    pub topic: String,
+    pub started: SystemTime,
+    steps: Vec<Step>,
+    results: Vec<StepResult>,
+    unit: PressureUnit,
+    /// On the time line, of the running step
+    step_start: f64,
+    /// First, last and extreme pressures of the running step
+    first: Option<f64>,
+    last: Option<f64>,
+    low: f64,
+    high: f64,
+}
+
+impl Runner {
+    pub fn new(steps: &[Step], topic: &str, t: f64, unit: PressureUnit) -> Runner {
+        Runner {
+            topic: topic.to_string(),
+            started: SystemTime::now(),
+            steps: steps.to_vec(),
+            results: Vec::new(),
+            unit,
+            step_start: t,
+            first: None,
+            last: None,
+            low: f64::INFINITY,
+            high: f64::NEG_INFINITY,
+        }
+    }
+
+    /// The running step, `None` once the sequence is over.
+    pub fn current(&self) -> Option<&Step> {
+        if self.failed() {
+            return None;
+        }
+        self.steps.get(self.results.len())
+    }
+
+    pub fn finished(&self) -> bool {
+        self.current().is_none()
+    }
+
+    /// Whether every step passed, so far.
+    pub fn passed(&self) -> bool {
+        !self.failed() && self.results.len() == self.steps.len()
+    }
+
+    fn failed(&self) -> bool {
+        self.results.iter().any(|r| r.verdict == Verdict::Fail)
+    }
+
+    pub fn results(&self) -> &[StepResult] {
+        &self.results
+    }
+
+    /// The operator's go ahead, completing a `Confirm` step.
+    pub fn confirm(&mut self, t: f64) -> Option<&StepResult> {
+        match self.current()? {
+            Step::Confirm { .. } => self.complete(t, Verdict::Pass, "Confirmed".to_string()),
+            _ => None,
+        }
+    }
+
+    /// Fails the running step.
+    pub fn abort(&mut self, t: f64) -> Option<&StepResult> {
+        if self.finished() {
+            return None;
+        }
+        self.complete(t, Verdict::Fail, "Aborted by the operator".to_string())
+    }
+
+    /// Judges a sample of the tested sensor, returning the result of the
+    /// step it completed.
+    pub fn update(&mut self, t: f64, pressure: f64) -> Option<&StepResult> {
+        let first = *self.first.get_or_insert(pressure);
+        self.last = Some(pressure);
+        self.low = self.low.min(pressure);
+        self.high = self.high.max(pressure);
+        let elapsed = t - self.step_start;
+        let p = |pa: f64| format!("{:.3} {}", self.unit.from_pa(pa), self.unit);
+
+        let (verdict, detail) = match *self.current()? {
+            Step::Confirm { .. } => return None,
+            Step::Reach {
+                min, max, timeout, ..
+            } => {
+                if within(pressure, min, max) {
+                    (Verdict::Pass, format!("Reached {}", p(pressure)))
+                } else if timeout.is_some_and(|timeout| elapsed >= timeout) {
+                    let detail = format!("Not reached in {:.0} s, at {}", elapsed, p(pressure));
+                    (Verdict::Fail, detail)
+                } else {
+                    return None;
+                }
+            }
+            Step::Hold {
+                min, max, duration, ..
+            } => {
+                if !within(pressure, min, max) {
+                    let detail =
+                        format!("Left the range after {:.1} s at {}", elapsed, p(pressure));
+                    (Verdict::Fail, detail)
+                } else if elapsed >= duration {
+                    let detail = format!(
+                        "Held for {:.0} s between {} and {}",
+                        elapsed,
+                        p(self.low),
+                        p(self.high)
+                    );
+                    (Verdict::Pass, detail)
+                } else {
+                    return None;
+                }
+            }
+            Step::Decay {
+                max_drop, duration, ..
+            } => {
+                let drop = first - pressure;
+                let detail = format!(
+                    "Fell by {} in {:.0} s, at most {}",
+                    p(drop),
+                    elapsed,
+                    p(max_drop)
+                );
+                if drop > max_drop {
+                    (Verdict::Fail, detail)
+                } else if elapsed >= duration {
+                    (Verdict::Pass, detail)
+                } else {
+                    return None;
+                }
+            }
+        };
+        self.complete(t, verdict, detail)
+    }
+
+    fn complete(&mut self, t: f64, verdict: Verdict, detail: String) -> Option<&StepResult> {
+        let prompt = self
+            .current()
+            .map_or_else(String::new, |s| s.prompt().to_string());
+        self.results.push(StepResult {
+            prompt,
+            verdict,
+            detail,
+            duration: t - self.step_start,
+        });
+        self.step_start = t;
+        self.first = None;
+        self.last = None;
+        self.low = f64::INFINITY;
+        self.high = f64::NEG_INFINITY;
+        self.results.last()
+    }
+
+    /// Lines for the on-screen prompt at `t`: the running step and its
+    /// progress, or the results once over.
+    pub fn status(&self, name: &str, t: f64) -> Vec<String> {
+        let step = match self.current() {
+            Some(step) => step,
+            None => {
+                let verdict = if self.passed() { "PASSED" } else { "FAILED" };
+                let mut lines = vec![format!("{}  {}", name, verdict)];
+                lines.extend(self.results.iter().enumerate().map(|(i, r)| {
+                    format!(
+                        "{}. {}  {}  {}",
+                        i + 1,
+                        r.prompt,
+                        r.verdict.label(),
+                        r.detail
+                    )
+                }));
+                return lines;
+            }
+        };
+
+        let elapsed = t - self.step_start;
+        let p = |pa: f64| format!("{:.3} {}", self.unit.from_pa(pa), self.unit);
+        let now = self.last.map_or_else(|| "no data".to_string(), p);
+        let progress = match *step {
+            Step::Confirm { .. } => "Press Enter to continue".to_string(),
+            Step::Reach { timeout, .. } => match timeout {
+                Some(timeout) => format!("At {}, {:.0} s left", now, timeout - elapsed),
+                None => format!("At {}", now),
+            },
+            Step::Hold { duration, .. } => {
+                format!("At {}, {:.0} s left", now, duration - elapsed)
+            }
+            Step::Decay { duration, .. } => {
+                let drop = match (self.first, self.last) {
+                    (Some(first), Some(last)) => p(first - last),
+                    _ => "-".to_string(),
+                };
+                format!("Fell by {}, {:.0} s left", drop, duration - elapsed)
+            }
+        };
+        vec![
+            format!(
+                "{}  step {} of {}  (g aborts)",
+                name,
+                self.results.len() + 1,
+                self.steps.len()
+            ),
+            step.prompt().to_string(),
+            progress,
+        ]
+    }
+}
+
+fn within(pressure: f64, min: Option<f64>, max: Option<f64>) -> bool {
+    min.is_none_or(|min| pressure >= min) && max.is_none_or(|max| pressure <= max)
+}
+
+/// Writes the HTML report of a finished sequence, with the `png` of the
+/// chart embedded and the `operator` named in the sign-off.
+pub fn write_report(
+    path: &Path,
+    name: &str,
+    runner: &Runner,
+    operator: Option<&str>,
+    png: &[u8],
+) -> Result<(), Box<dyn Error>> {
+    let format = |ts: SystemTime| DateTime::<Local>::from(ts).format("%Y-%m-%d %H:%M:%S");
+    let (verdict, class) = if runner.passed() {
+        ("PASSED", Verdict::Pass)
+    } else {
+        ("FAILED", Verdict::Fail)
+    };
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>")?;
+    writeln!(html, "<html><head><meta charset=\"utf-8\">")?;
+    writeln!(html, "<title>{}</title>", escape(name))?;
+    writeln!(
+        html,
+        "<style>body{{font-family:sans-serif}}table{{border-collapse:collapse}}\
+         td,th{{border:1px solid #888;padding:4px 8px;text-align:left}}\
+         .PASS{{color:#080}}.FAIL{{color:#c00}}</style>"
+    )?;
+    writeln!(html, "</head><body>")?;
+    writeln!(
+        html,
+        "<h1>{}: <span class=\"{}\">{}</span></h1>",
+        escape(name),
+        class.label(),
+        verdict
+    )?;
+    writeln!(html, "<p>Sensor {}<br>", escape(&runner.topic))?;
+    writeln!(html, "Started {}<br>", format(runner.started))?;
+    writeln!(html, "Finished {}</p>", format(SystemTime::now()))?;
+
+    writeln!(html, "<table>")?;
+    writeln!(
+        html,
+        "<tr><th>#</th><th>Step</th><th>Result</th><th>Details</th><th>Duration</th></tr>"
+    )?;
+    for (i, step) in runner.steps.iter().enumerate() {
+        match runner.results.get(i) {
+            Some(r) => writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td class=\"{}\">{}</td><td>{}</td><td>{:.1} s</td></tr>",
+                i + 1,
+                escape(&r.prompt),
+                r.verdict.label(),
+                r.verdict.label(),
+                escape(&r.detail),
+                r.duration
+            )?,
+            None => writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>-</td><td>Not run</td><td></td></tr>",
+                i + 1,
+                escape(step.prompt())
+            )?,
+        }
+    }
+    writeln!(html, "</table>")?;
+
+    writeln!(
+        html,
+        "<p><img alt=\"Chart\" src=\"data:image/png;base64,{}\"></p>",
+        base64(png)
+    )?;
+    writeln!(html, "<h2>Sign-off</h2>")?;
+    writeln!(
+        html,
+        "<p>Operator: {}</p>",
+        operator.map_or_else(|| "_".repeat(30), escape)
+    )?;
+    writeln!(
+        html,
+        "<p>Signature: {}&emsp;Date: {}</p>",
+        "_".repeat(30),
+        "_".repeat(15)
+    )?;
+    writeln!(html, "</body></html>")?;
+
+    fs::write(path, html)
+        .map_err(|e| format!("Cannot write sequence report {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Standard base64 with padding, for the data URI of the chart.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[((n >> (18 - 6 * i)) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps() -> Vec<Step> {
+        vec![
+            Step::Confirm {
+                prompt: "Connect the part".to_string(),
+            },
+            Step::Reach {
+                prompt: "Pressurize".to_string(),
+                min: Some(200_000.0),
+                max: None,
+                timeout: Some(60.0),
+            },
+            Step::Hold {
+                prompt: "Hold".to_string(),
+                min: Some(190_000.0),
+                max: Some(210_000.0),
+                duration: 10.0,
+            },
+            Step::Decay {
+                prompt: "Decay".to_string(),
+                max_drop: 500.0,
+                duration: 30.0,
+            },
+        ]
+    }
+
+    fn verdict(result: Option<&StepResult>) -> Option<Verdict> {
+        result.map(|r| r.verdict)
+    }
+
+    #[test]
+    fn steps_pass_in_order() {
+        let mut runner = Runner::new(&steps(), "pressure/data", 0.0, PressureUnit::KPa);
+        // Samples don't confirm
+        assert_eq!(verdict(runner.update(1.0, 100_000.0)), None);
+        assert_eq!(verdict(runner.confirm(2.0)), Some(Verdict::Pass));
+        assert!(runner.confirm(3.0).is_none());
+
+        assert_eq!(verdict(runner.update(10.0, 150_000.0)), None);
+        assert_eq!(verdict(runner.update(20.0, 200_000.0)), Some(Verdict::Pass));
+
+        assert_eq!(verdict(runner.update(25.0, 205_000.0)), None);
+        assert_eq!(verdict(runner.update(30.0, 200_000.0)), Some(Verdict::Pass));
+
+        assert_eq!(verdict(runner.update(40.0, 200_000.0)), None);
+        assert_eq!(verdict(runner.update(60.0, 199_800.0)), Some(Verdict::Pass));
+
+        assert!(runner.finished());
+        assert!(runner.passed());
+        let durations: Vec<f64> = runner.results().iter().map(|r| r.duration).collect();
+        assert_eq!(durations, [2.0, 18.0, 10.0, 30.0]);
+        assert_eq!(
+            runner.results()[3].detail,
+            "Fell by 0.200 kPa in 30 s, at most 0.500 kPa"
+        );
+    }
+
+    #[test]
+    fn a_failed_step_ends_the_sequence() {
+        let mut runner = Runner::new(&steps()[1..], "pressure/data", 0.0, PressureUnit::Pa);
+        assert_eq!(verdict(runner.update(30.0, 100_000.0)), None);
+        assert_eq!(verdict(runner.update(60.0, 100_000.0)), Some(Verdict::Fail));
+        assert!(runner.finished());
+        assert!(!runner.passed());
+        assert_eq!(runner.results().len(), 1);
+        assert_eq!(verdict(runner.update(70.0, 200_000.0)), None);
+    }
+
+    #[test]
+    fn leaving_the_range_fails_a_hold() {
+        let mut runner = Runner::new(&steps()[2..], "pressure/data", 0.0, PressureUnit::Pa);
+        assert_eq!(verdict(runner.update(1.0, 200_000.0)), None);
+        assert_eq!(verdict(runner.update(2.0, 180_000.0)), Some(Verdict::Fail));
+    }
+
+    #[test]
+    fn too_large_a_drop_fails_a_decay() {
+        let mut runner = Runner::new(&steps()[3..], "pressure/data", 0.0, PressureUnit::Pa);
+        assert_eq!(verdict(runner.update(1.0, 200_000.0)), None);
+        assert_eq!(verdict(runner.update(5.0, 199_000.0)), Some(Verdict::Fail));
+    }
+
+    #[test]
+    fn abort() {
+        let mut runner = Runner::new(&steps(), "pressure/data", 0.0, PressureUnit::Pa);
+        assert_eq!(verdict(runner.abort(5.0)), Some(Verdict::Fail));
+        assert!(runner.finished());
+        assert_eq!(verdict(runner.abort(6.0)), None);
+    }
+
+    #[test]
+    fn report_encoding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(escape("<a & \"b\">"), "&lt;a &amp; &quot;b&quot;&gt;");
+    }
+}
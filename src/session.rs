@@ -0,0 +1,211 @@
+//! Recording sessions, for audits. Each one is a directory holding the
+//! samples, the markers set meanwhile, a copy of the config file and a
+//! `session.json` describing them: operator, sensors, units and
+//! calibration. With `[session] parquet` the samples are also written to
+//! `data.parquet`, along with their filtered value and quality.
+
+use crate::config::{Config, RotationConfig};
+use crate::recorder::Recorder;
+#[cfg(feature = "parquet")]
+use crate::store::parquet::ParquetWriter;
+use crate::store::Store;
+use crate::units::PressureUnit;
+use chrono::Local;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const METADATA: &str = "session.json";
+const DATA: &str = "data.csv";
+const ANNOTATIONS: &str = "annotations.csv";
+const CONFIG: &str = "config.toml";
+const PARQUET: &str = "data.parquet";
+
+/// Bits of the quality of a sample: an alarm was active
+pub const QUALITY_ALARM: u8 = 1;
+/// Messages were lost right before it
+pub const QUALITY_AFTER_LOSS: u8 = 2;
+/// The first one after the watchdog flagged the series as stale
+pub const QUALITY_AFTER_STALE: u8 = 4;
+/// A retained message, which the broker may have kept for long
+pub const QUALITY_RETAINED: u8 = 8;
+
+pub struct Session {
+    dir: PathBuf,
+    /// Written at the start, completed by `finish`
+    metadata: Value,
+    recorder: Recorder,
+    #[cfg(feature = "parquet")]
+    parquet: Option<ParquetWriter>,
+    annotations: csv::Writer<File>,
+    topics: BTreeSet<String>,
+    samples: u64,
+}
+
+impl Session {
+    /// Creates a directory in the `[session]` one, named after the time.
+    pub fn start(
+        config: &Config,
+        config_path: Option<&Path>,
+        unit: PressureUnit,
+    ) -> Result<Session, Box<dyn Error>> {
+        let started = Local::now();
+        let dir = config
+            .session
+            .dir
+            .join(started.format("%Y-%m-%dT%H-%M-%S").to_string());
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Cannot create session {}: {}", dir.display(), e))?;
+
+        // The file as written, comments included, when there is one
+        let config_copy = match config_path.filter(|path| path.exists()) {
+            Some(path) => {
+                fs::copy(path, dir.join(CONFIG))
+                    .map_err(|e| format!("Cannot copy {}: {}", path.display(), e))?;
+                Some(CONFIG)
+            }
+            None => None,
+        };
+
+        let flush_interval = Duration::from_secs_f64(config.log.flush_interval);
+        let recorder = Recorder::open(&dir.join(DATA), flush_interval, &RotationConfig::default())?;
+        let mut annotations = csv::Writer::from_path(dir.join(ANNOTATIONS))?;
+        annotations.write_record(["Time(unix s)", "Marker"])?;
+        annotations.flush()?;
+
+        #[cfg(feature = "parquet")]
+        let parquet = if config.session.parquet {
+            Some(ParquetWriter::create(&dir.join(PARQUET))?)
+        } else {
+            None
+        };
+        #[cfg(not(feature = "parquet"))]
+        if config.session.parquet {
+            return Err("Built without the parquet feature, no data.parquet".into());
+        }
+
+        let calibration: Map<String, Value> = config
+            .payload
+            .calibration
+            .iter()
+            .map(|(topic, c)| (topic.clone(), json!({"scale": c.scale, "offset": c.offset})))
+            .collect();
+        let sources: Vec<Value> = if config.sources.is_empty() {
+            vec![json!({"source": config.source})]
+        } else {
+            config
+                .sources
+                .iter()
+                .map(|s| json!({"name": s.name, "source": s.source}))
+                .collect()
+        };
+        let metadata = json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "started": started.to_rfc3339(),
+            "ended": null,
+            "operator": config.session.operator,
+            "sources": sources,
+            "sensors": config.session.sensors,
+            "units": {"data": "Pa", "display": unit.to_string()},
+            "calibration": calibration,
+            "files": {
+                "data": DATA,
+                "parquet": config.session.parquet.then_some(PARQUET),
+                "annotations": ANNOTATIONS,
+                "config": config_copy,
+            },
+            "topics": [],
+            "samples": 0,
+        });
+        write_metadata(&dir, &metadata)?;
+
+        Ok(Session {
+            dir,
+            metadata,
+            recorder,
+            #[cfg(feature = "parquet")]
+            parquet,
+            annotations,
+            topics: BTreeSet::new(),
+            samples: 0,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The CSV log of the samples.
+    pub fn data_path(&self) -> PathBuf {
+        self.dir.join(DATA)
+    }
+
+    /// Records a sample with its value through the filters, if any, and
+    /// `QUALITY_*` bits.
+    #[cfg_attr(not(feature = "parquet"), allow(unused_variables))]
+    pub fn record(
+        &mut self,
+        ts: SystemTime,
+        topic: &str,
+        value: f64,
+        filtered: Option<f64>,
+        quality: u8,
+    ) -> Result<(), Box<dyn Error>> {
+        self.recorder.write(ts, topic, value)?;
+        #[cfg(feature = "parquet")]
+        if let Some(parquet) = &mut self.parquet {
+            parquet.write(ts, topic, value, filtered, quality)?;
+        }
+        if !self.topics.contains(topic) {
+            self.topics.insert(topic.to_string());
+        }
+        self.samples += 1;
+        Ok(())
+    }
+
+    /// Records a marker set at `ts`.
+    pub fn annotate(&mut self, ts: SystemTime, label: &str) -> Result<(), Box<dyn Error>> {
+        let unix = ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.annotations
+            .write_record([format!("{:.3}", unix.as_secs_f64()), label.to_string()])?;
+        self.annotations.flush()?;
+        Ok(())
+    }
+
+    /// Completes the metadata with the end time and what was recorded.
+    pub fn finish(mut self) -> Result<PathBuf, Box<dyn Error>> {
+        self.flush()?;
+        #[cfg(feature = "parquet")]
+        if let Some(parquet) = self.parquet.take() {
+            parquet.close()?;
+        }
+        self.metadata["ended"] = json!(Local::now().to_rfc3339());
+        self.metadata["topics"] = json!(self.topics);
+        self.metadata["samples"] = json!(self.samples);
+        write_metadata(&self.dir, &self.metadata)?;
+        Ok(self.dir)
+    }
+}
+
+impl Store for Session {
+    /// Without a filtered value or quality, see `record`.
+    fn write(&mut self, ts: SystemTime, topic: &str, value: f64) -> Result<(), Box<dyn Error>> {
+        self.record(ts, topic, value, None, 0)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.recorder.flush()?;
+        self.annotations.flush()?;
+        Ok(())
+    }
+}
+
+fn write_metadata(dir: &Path, metadata: &Value) -> Result<(), Box<dyn Error>> {
+    let path = dir.join(METADATA);
+    fs::write(&path, serde_json::to_string_pretty(metadata)?)
+        .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+    Ok(())
+}
@@ -0,0 +1,285 @@
+//! The in-window settings menu, key `o`, for the options most often changed
+//! while watching. Changes apply at once, saving writes them to the config
+//! file.
+
+use crate::config::{self, Config};
+use crate::filter::FilterStage;
+use crate::units::PressureUnit;
+use std::error::Error;
+use std::path::Path;
+use toml_edit::{value, Array, ArrayOfTables, Item, Table};
+
+/// Fraction of the Y range one step moves its ends by.
+const RANGE_STEP: f64 = 0.05;
+
+/// Fraction of the Y range one step moves a threshold by.
+const THRESHOLD_STEP: f64 = 0.01;
+
+/// Factor one step grows or shrinks the retention by.
+const RETENTION_STEP: f64 = 1.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    Autoscale,
+    YMin,
+    YMax,
+    Unit,
+    /// Window of the moving average filter stage
+    Smoothing,
+    HighAlarm,
+    LowAlarm,
+    Retention,
+}
+
+impl Setting {
+    const ALL: [Setting; 8] = [
+        Setting::Autoscale,
+        Setting::YMin,
+        Setting::YMax,
+        Setting::Unit,
+        Setting::Smoothing,
+        Setting::HighAlarm,
+        Setting::LowAlarm,
+        Setting::Retention,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Setting::Autoscale => "Autoscale",
+            Setting::YMin => "Y min",
+            Setting::YMax => "Y max",
+            Setting::Unit => "Unit",
+            Setting::Smoothing => "Smoothing",
+            Setting::HighAlarm => "High alarm",
+            Setting::LowAlarm => "Low alarm",
+            Setting::Retention => "Retention",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Adjust {
+    Increase,
+    Decrease,
+    /// Turns off what can be, e.g. an alarm threshold
+    Off,
+}
+
+#[derive(Debug, Default)]
+pub struct Menu {
+    selected: usize,
+    /// Outcome of the last save, shown below the settings
+    pub message: Option<String>,
+}
+
+impl Menu {
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % Setting::ALL.len();
+    }
+
+    pub fn select_prev(&mut self) {
+        self.selected = (self.selected + Setting::ALL.len() - 1) % Setting::ALL.len();
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Changes the selected setting and returns it, for the monitor to
+    /// apply the change to what it already built from the config.
+    pub fn adjust(
+        &mut self,
+        adjust: Adjust,
+        config: &mut Config,
+        unit: &mut PressureUnit,
+        autoscale: &mut bool,
+    ) -> Setting {
+        let setting = Setting::ALL[self.selected];
+        self.message = None;
+        let sign = match adjust {
+            Adjust::Increase => 1.0,
+            Adjust::Decrease => -1.0,
+            Adjust::Off => 0.0,
+        };
+        let (lo, hi) = config.chart.y_range;
+
+        match setting {
+            Setting::Autoscale => *autoscale = adjust != Adjust::Off && !*autoscale,
+            Setting::YMin => {
+                let lo = lo + sign * (hi - lo) * RANGE_STEP;
+                if lo < hi {
+                    config.chart.y_range.0 = lo;
+                }
+            }
+            Setting::YMax => {
+                let hi = hi + sign * (hi - lo) * RANGE_STEP;
+                if lo < hi {
+                    config.chart.y_range.1 = hi;
+                }
+            }
+            Setting::Unit => match adjust {
+                Adjust::Increase => *unit = unit.next(),
+                Adjust::Decrease => *unit = unit.prev(),
+                Adjust::Off => {}
+            },
+            Setting::Smoothing => {
+                let stages = &mut config.filter.stages;
+                let index = stages
+                    .iter()
+                    .position(|stage| matches!(stage, FilterStage::Sma { .. }));
+                let window = match index.map(|i| stages[i]) {
+                    Some(FilterStage::Sma { window }) => window,
+                    _ => 0,
+                };
+                let window = match adjust {
+                    Adjust::Increase => (window + 1).max(window * 5 / 4),
+                    Adjust::Decrease => window.saturating_sub(1).min(window * 4 / 5),
+                    Adjust::Off => 0,
+                };
+                match (index, window) {
+                    (Some(i), 0) => {
+                        stages.remove(i);
+                    }
+                    (Some(i), window) => stages[i] = FilterStage::Sma { window },
+                    (None, 0) => {}
+                    // Smooth before any other stage
+                    (None, window) => stages.insert(0, FilterStage::Sma { window }),
+                }
+            }
+            Setting::HighAlarm | Setting::LowAlarm => {
+                let (threshold, start) = match setting {
+                    Setting::HighAlarm => (&mut config.alarm.high, hi),
+                    _ => (&mut config.alarm.low, lo),
+                };
+                *threshold = match (adjust, *threshold) {
+                    (Adjust::Off, _) => None,
+                    // Switched on at the edge of the chart
+                    (_, None) => Some(start),
+                    (_, Some(t)) => Some(t + sign * (hi - lo) * THRESHOLD_STEP),
+                };
+            }
+            Setting::Retention => {
+                let factor = RETENTION_STEP.powf(sign);
+                match &mut config.data.window {
+                    Some(window) => *window = (*window * factor).max(1.0),
+                    None => {
+                        let length = (config.data.length as f64 * factor).round() as usize;
+                        config.data.length = length.max(10);
+                    }
+                }
+            }
+        }
+        setting
+    }
+
+    /// A `(label, value)` row per setting, as currently in effect.
+    pub fn rows(
+        &self,
+        config: &Config,
+        unit: PressureUnit,
+        autoscale: bool,
+    ) -> Vec<(&'static str, String)> {
+        let pressure = |pa: f64| format!("{:.3} {}", unit.from_pa(pa), unit);
+        let threshold = |pa: Option<f64>| pa.map_or_else(|| "off".to_string(), pressure);
+        let smoothing = config.filter.stages.iter().find_map(|stage| match stage {
+            FilterStage::Sma { window } => Some(format!("{} samples", window)),
+            _ => None,
+        });
+
+        Setting::ALL
+            .iter()
+            .map(|&setting| {
+                let value = match setting {
+                    Setting::Autoscale => String::from(if autoscale { "on" } else { "off" }),
+                    Setting::YMin => pressure(config.chart.y_range.0),
+                    Setting::YMax => pressure(config.chart.y_range.1),
+                    Setting::Unit => unit.to_string(),
+                    Setting::Smoothing => smoothing.clone().unwrap_or_else(|| "off".to_string()),
+                    Setting::HighAlarm => threshold(config.alarm.high),
+                    Setting::LowAlarm => threshold(config.alarm.low),
+                    Setting::Retention => match config.data.window {
+                        Some(window) => format!("{:.0} s", window),
+                        None => format!("{} samples", config.data.length),
+                    },
+                };
+                (setting.label(), value)
+            })
+            .collect()
+    }
+}
+
+/// Writes the menu's settings to the config file at `path`, leaving the
+/// rest of it as it is.
+pub fn save(
+    path: &Path,
+    config: &Config,
+    unit: PressureUnit,
+    autoscale: bool,
+) -> Result<(), Box<dyn Error>> {
+    config::edit(path, |doc| {
+        let chart = config::child(doc, "chart")?;
+        let (lo, hi) = config.chart.y_range;
+        chart.insert("y_range", value([lo, hi].into_iter().collect::<Array>()));
+        chart.insert("unit", value(unit.key()));
+        chart.insert("autoscale", value(autoscale));
+
+        let mut stages = ArrayOfTables::new();
+        for stage in &config.filter.stages {
+            stages.push(stage_table(*stage));
+        }
+        config::child(doc, "filter")?.insert("stages", Item::ArrayOfTables(stages));
+
+        let alarm = config::child(doc, "alarm")?;
+        set_or_remove(alarm, "high", config.alarm.high);
+        set_or_remove(alarm, "low", config.alarm.low);
+
+        let data = config::child(doc, "data")?;
+        data.insert("length", value(config.data.length as i64));
+        set_or_remove(data, "window", config.data.window);
+        Ok(())
+    })
+}
+
+/// As written in a `[[filter.stages]]` table.
+fn stage_table(stage: FilterStage) -> Table {
+    let mut table = Table::new();
+    match stage {
+        FilterStage::Sma { window } => {
+            table.insert("kind", value("sma"));
+            table.insert("window", value(window as i64));
+        }
+        FilterStage::Median { window } => {
+            table.insert("kind", value("median"));
+            table.insert("window", value(window as i64));
+        }
+        FilterStage::Ema { alpha } => {
+            table.insert("kind", value("ema"));
+            table.insert("alpha", value(alpha));
+        }
+        FilterStage::DoubleEma { alpha, beta } => {
+            table.insert("kind", value("double_ema"));
+            table.insert("alpha", value(alpha));
+            table.insert("beta", value(beta));
+        }
+        FilterStage::Kalman {
+            process_noise,
+            measurement_noise,
+        } => {
+            table.insert("kind", value("kalman"));
+            table.insert("process_noise", value(process_noise));
+            table.insert("measurement_noise", value(measurement_noise));
+        }
+    }
+    table
+}
+
+fn set_or_remove(table: &mut Table, key: &str, setting: Option<f64>) {
+    match setting {
+        Some(setting) => {
+            table.insert(key, value(setting));
+        }
+        None => {
+            table.remove(key);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Bounded channel from the sources to the render loop. When the loop
+//! stalls and the channel is full, the oldest samples make room for new
+//! ones instead of memory growing without limit, and they are counted.
+
+use super::Sample;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::SendError;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+struct Queue {
+    samples: Mutex<VecDeque<Sample>>,
+    capacity: usize,
+    overflowed: AtomicU64,
+    /// Set when the receiver is dropped
+    closed: AtomicBool,
+}
+
+/// A channel holding at most `capacity` samples.
+pub fn bounded(capacity: usize) -> (Sender, Receiver) {
+    let queue = Arc::new(Queue {
+        samples: Mutex::new(VecDeque::new()),
+        capacity: capacity.max(1),
+        overflowed: AtomicU64::new(0),
+        closed: AtomicBool::new(false),
+    });
+    let sender = Sender {
+        queue: queue.clone(),
+        sensor: None,
+    };
+    (sender, Receiver { queue })
+}
+
+#[derive(Debug, Clone)]
+pub struct Sender {
+    queue: Arc<Queue>,
+    /// Set on the samples sent, see `tagged`
+    sensor: Option<String>,
+}
+
+impl Sender {
+    /// Queues `sample`, dropping the oldest one if the channel is full.
+    /// Fails only once the receiver is gone.
+    pub fn send(&self, mut sample: Sample) -> Result<(), SendError<Sample>> {
+        if self.queue.closed.load(Ordering::Relaxed) {
+            return Err(SendError(sample));
+        }
+        if let Some(sensor) = &self.sensor {
+            sample.sensor = Some(sensor.clone());
+        }
+
+        let mut samples = self.queue.samples.lock().unwrap();
+        if samples.len() >= self.queue.capacity {
+            samples.pop_front();
+            self.queue.overflowed.fetch_add(1, Ordering::Relaxed);
+        }
+        samples.push_back(sample);
+        Ok(())
+    }
+
+    /// A sender to the same channel that marks its samples as coming from
+    /// `sensor`.
+    pub fn tagged(&self, sensor: &str) -> Sender {
+        Sender {
+            queue: self.queue.clone(),
+            sensor: Some(sensor.to_string()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Receiver {
+    queue: Arc<Queue>,
+}
+
+impl Receiver {
+    /// Takes everything queued so far, without waiting. The queue keeps its
+    /// storage for the next burst.
+    pub fn try_iter(&self) -> impl Iterator<Item = Sample> {
+        let samples: Vec<Sample> = self.queue.samples.lock().unwrap().drain(..).collect();
+        samples.into_iter()
+    }
+
+    /// Samples waiting to be taken.
+    pub fn queued(&self) -> usize {
+        self.queue.samples.lock().unwrap().len()
+    }
+
+    /// Samples dropped because the channel was full.
+    pub fn overflowed(&self) -> u64 {
+        self.queue.overflowed.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        self.queue.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(value: f64) -> Sample {
+        Sample {
+            topic: "pressure/data".to_string(),
+            value,
+            timestamp: None,
+            sequence: None,
+            sensor: None,
+            expires: None,
+            retained: false,
+        }
+    }
+
+    fn values(rx: &Receiver) -> Vec<f64> {
+        rx.try_iter().map(|s| s.value).collect()
+    }
+
+    #[test]
+    fn drops_the_oldest_when_full() {
+        let (tx, rx) = bounded(3);
+        for value in 0..5 {
+            tx.send(sample(value as f64)).unwrap();
+        }
+        assert_eq!(rx.queued(), 3);
+        assert_eq!(rx.overflowed(), 2);
+        assert_eq!(values(&rx), [2.0, 3.0, 4.0]);
+        assert_eq!(rx.queued(), 0);
+
+        tx.send(sample(5.0)).unwrap();
+        assert_eq!(values(&rx), [5.0]);
+        assert_eq!(rx.overflowed(), 2);
+    }
+
+    #[test]
+    fn tagged_senders_name_their_sensor() {
+        let (tx, rx) = bounded(8);
+        tx.tagged("inlet").send(sample(1.0)).unwrap();
+        tx.send(sample(2.0)).unwrap();
+        let sensors: Vec<Option<String>> = rx.try_iter().map(|s| s.sensor).collect();
+        assert_eq!(sensors, [Some("inlet".to_string()), None]);
+    }
+
+    #[test]
+    fn sending_fails_without_a_receiver() {
+        let (tx, rx) = bounded(1);
+        drop(rx);
+        assert!(tx.send(sample(1.0)).is_err());
+    }
+}
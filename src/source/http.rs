@@ -0,0 +1,122 @@
+//! Polls a REST endpoint of a gauge, taking the value out of the JSON
+//! response with a JSON pointer such as `/data/pressure`.
+
+use super::{
+    accept, channel::Sender, ConnectionState, DataSource, Sample, Shutdown, Status, BACKOFF_MAX,
+};
+use crate::config::HttpConfig;
+use crate::decode::{self, Decoder};
+use serde_json::Value;
+use std::error::Error;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::{info, info_span, warn};
+
+#[derive(Debug, Clone)]
+pub struct HttpSource {
+    config: HttpConfig,
+    decoder: Decoder,
+    status: Status,
+}
+
+impl HttpSource {
+    pub fn new(config: &HttpConfig, decoder: Decoder, status: Status) -> HttpSource {
+        HttpSource {
+            config: config.clone(),
+            decoder,
+            status,
+        }
+    }
+
+    fn poll(&self, agent: &ureq::Agent) -> Result<Sample, Box<dyn Error>> {
+        let mut request = agent.get(&self.config.url);
+        for (name, value) in &self.config.headers {
+            request = request.set(name, value);
+        }
+        let json: Value = serde_json::from_str(&request.call()?.into_string()?)?;
+
+        let value = json
+            .pointer(&self.config.pointer)
+            .and_then(Value::as_f64)
+            .ok_or_else(|| format!("No number at {} in the response", self.config.pointer))?;
+        let timestamp = self
+            .config
+            .timestamp_pointer
+            .as_deref()
+            .and_then(|pointer| json.pointer(pointer))
+            .and_then(Value::as_f64)
+            .and_then(decode::epoch_time);
+
+        Ok(Sample {
+            topic: self.config.topic.clone(),
+            value: self.decoder.calibrate(&self.config.topic, value),
+            timestamp,
+            sequence: None,
+            sensor: None,
+            expires: None,
+            retained: false,
+        })
+    }
+}
+
+impl DataSource for HttpSource {
+    /// Requests the URL every `interval` seconds. Failed requests are
+    /// retried at the interval, or the doubled delay of the last retry up
+    /// to the usual maximum, whichever is longer.
+    fn spawn(&self, tx: Sender, shutdown: Shutdown) -> Result<JoinHandle<()>, Box<dyn Error>> {
+        if !self.config.interval.is_finite() || self.config.interval <= 0.0 {
+            return Err(format!(
+                "Invalid http interval {}, expected seconds",
+                self.config.interval
+            )
+            .into());
+        }
+        let source = self.clone();
+
+        Ok(thread::spawn(move || {
+            let _span = info_span!("http", url = %source.config.url).entered();
+            let agent = ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs_f64(source.config.timeout))
+                .build();
+            let interval = Duration::from_secs_f64(source.config.interval);
+            let mut decoder = source.decoder.clone();
+            let mut backoff = interval;
+            info!("Polling every {:?}", interval);
+
+            loop {
+                let started = Instant::now();
+                let delay = match source.poll(&agent) {
+                    Ok(sample) => {
+                        if source.status.get() != ConnectionState::Connected {
+                            info!("Gauge responding");
+                        }
+                        source.status.set(ConnectionState::Connected);
+                        backoff = interval;
+                        if accept(&mut decoder, &source.status, &sample.topic, sample.value)
+                            && tx.send(sample).is_err()
+                        {
+                            break;
+                        }
+                        interval.saturating_sub(started.elapsed())
+                    }
+                    Err(e) => {
+                        if backoff >= BACKOFF_MAX {
+                            source.status.set(ConnectionState::Offline);
+                        } else {
+                            source.status.set(ConnectionState::Reconnecting);
+                        }
+                        warn!("Request failed: {}, retrying in {:?}", e, backoff);
+                        let delay = backoff;
+                        backoff = (backoff * 2).min(BACKOFF_MAX.max(interval));
+                        delay
+                    }
+                };
+                if shutdown.wait_timeout(delay) {
+                    break;
+                }
+            }
+
+            source.status.set(ConnectionState::Offline);
+        }))
+    }
+}
@@ -0,0 +1,253 @@
+//! BMP280 or BME280 on the I2C bus of a Raspberry Pi, for bench tests
+//! without sensor firmware. Only the pressure is read, the humidity of a
+//! BME280 is left off. Needs the `i2c` feature.
+
+use super::{
+    accept, channel::Sender, ConnectionState, DataSource, Sample, Shutdown, Status, BACKOFF_MAX,
+    BACKOFF_MIN,
+};
+use crate::config::I2cConfig;
+use crate::decode::Decoder;
+use rppal::i2c::I2c;
+use std::error::Error;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::{info, info_span, warn};
+
+const REG_CALIBRATION: u8 = 0x88;
+const REG_CHIP_ID: u8 = 0xD0;
+const REG_RESET: u8 = 0xE0;
+const REG_CTRL_MEAS: u8 = 0xF4;
+const REG_CONFIG: u8 = 0xF5;
+const REG_DATA: u8 = 0xF7;
+
+const CHIP_BMP280: u8 = 0x58;
+const CHIP_BME280: u8 = 0x60;
+
+/// Temperature x2, pressure x16 oversampling, normal mode.
+const CTRL_MEAS: u8 = (0b010 << 5) | (0b101 << 2) | 0b11;
+/// 0.5 ms standby, IIR filter coefficient 16. Together with `CTRL_MEAS`
+/// a measurement takes about 40 ms, faster rates repeat readings.
+const CONFIG: u8 = 0b100 << 2;
+
+/// Raw reading of a measurement that was skipped, e.g. right after reset.
+const SKIPPED: i32 = 0x80000;
+
+#[derive(Debug, Clone)]
+pub struct I2cSource {
+    config: I2cConfig,
+    decoder: Decoder,
+    status: Status,
+}
+
+impl I2cSource {
+    pub fn new(config: &I2cConfig, decoder: Decoder, status: Status) -> I2cSource {
+        I2cSource {
+            config: config.clone(),
+            decoder,
+            status,
+        }
+    }
+
+    fn run(&self, tx: Sender, shutdown: Shutdown, mut sensor: Option<Sensor>) {
+        let _span = info_span!("i2c", bus = self.config.bus).entered();
+        let interval = Duration::from_secs_f64(1.0 / self.config.rate);
+        let topic = &self.config.topic;
+        let mut decoder = self.decoder.clone();
+        let mut backoff = BACKOFF_MIN;
+
+        loop {
+            let started = Instant::now();
+            let delay = match self.measure(&mut sensor) {
+                // Nothing measured yet
+                Ok(None) => interval,
+                Ok(Some(value)) => {
+                    self.status.set(ConnectionState::Connected);
+                    backoff = BACKOFF_MIN;
+                    let value = decoder.calibrate(topic, value);
+                    if accept(&mut decoder, &self.status, topic, value) {
+                        let sample = Sample {
+                            topic: topic.clone(),
+                            value,
+                            timestamp: None,
+                            sequence: None,
+                            sensor: None,
+                            expires: None,
+                            retained: false,
+                        };
+                        if tx.send(sample).is_err() {
+                            break;
+                        }
+                    }
+                    interval.saturating_sub(started.elapsed())
+                }
+                Err(e) => {
+                    if backoff >= BACKOFF_MAX {
+                        self.status.set(ConnectionState::Offline);
+                    } else {
+                        self.status.set(ConnectionState::Reconnecting);
+                    }
+                    warn!("{}, retrying in {:?}", e, backoff);
+                    let delay = backoff;
+                    backoff = (backoff * 2).min(BACKOFF_MAX);
+                    delay
+                }
+            };
+            if shutdown.wait_timeout(delay) {
+                break;
+            }
+        }
+
+        self.status.set(ConnectionState::Offline);
+    }
+
+    /// Reads the open sensor, or opens it again. After an error it is
+    /// initialized anew, it may have lost power.
+    fn measure(&self, sensor: &mut Option<Sensor>) -> Result<Option<f64>, Box<dyn Error>> {
+        let open = match sensor.take() {
+            Some(open) => open,
+            None => Sensor::open(&self.config)?,
+        };
+        let value = open.pressure()?;
+        *sensor = Some(open);
+        Ok(value)
+    }
+}
+
+impl DataSource for I2cSource {
+    /// Opens the sensor right away, so a wrong bus or address fails at
+    /// startup.
+    fn spawn(&self, tx: Sender, shutdown: Shutdown) -> Result<JoinHandle<()>, Box<dyn Error>> {
+        if !self.config.rate.is_finite() || self.config.rate <= 0.0 {
+            return Err(format!(
+                "Invalid i2c rate {}, expected samples per second",
+                self.config.rate
+            )
+            .into());
+        }
+        let sensor = Sensor::open(&self.config)?;
+        let source = self.clone();
+        Ok(thread::spawn(move || {
+            source.run(tx, shutdown, Some(sensor))
+        }))
+    }
+}
+
+/// Trimming parameters, read from the sensor's NVM.
+struct Calibration {
+    t1: f64,
+    t2: f64,
+    t3: f64,
+    p1: f64,
+    p2: f64,
+    p3: f64,
+    p4: f64,
+    p5: f64,
+    p6: f64,
+    p7: f64,
+    p8: f64,
+    p9: f64,
+}
+
+impl Calibration {
+    fn parse(data: &[u8; 24]) -> Calibration {
+        let unsigned = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]) as f64;
+        let signed = |i: usize| i16::from_le_bytes([data[i], data[i + 1]]) as f64;
+        Calibration {
+            t1: unsigned(0),
+            t2: signed(2),
+            t3: signed(4),
+            p1: unsigned(6),
+            p2: signed(8),
+            p3: signed(10),
+            p4: signed(12),
+            p5: signed(14),
+            p6: signed(16),
+            p7: signed(18),
+            p8: signed(20),
+            p9: signed(22),
+        }
+    }
+
+    /// Pa from the raw readings, the floating point formulas of the
+    /// datasheet. The temperature is only needed to compensate the
+    /// pressure.
+    fn pressure(&self, raw_temperature: i32, raw_pressure: i32) -> Option<f64> {
+        let t = raw_temperature as f64;
+        let var1 = (t / 16384.0 - self.t1 / 1024.0) * self.t2;
+        let var2 = (t / 131072.0 - self.t1 / 8192.0).powi(2) * self.t3;
+        let t_fine = var1 + var2;
+
+        let var1 = t_fine / 2.0 - 64000.0;
+        let var2 = var1 * var1 * self.p6 / 32768.0 + var1 * self.p5 * 2.0;
+        let var2 = var2 / 4.0 + self.p4 * 65536.0;
+        let var1 = (self.p3 * var1 * var1 / 524288.0 + self.p2 * var1) / 524288.0;
+        let var1 = (1.0 + var1 / 32768.0) * self.p1;
+        if var1 == 0.0 {
+            return None;
+        }
+        let p = 1048576.0 - raw_pressure as f64;
+        let p = (p - var2 / 4096.0) * 6250.0 / var1;
+        let var1 = self.p9 * p * p / 2147483648.0;
+        let var2 = p * self.p8 / 32768.0;
+        Some(p + (var1 + var2 + self.p7) / 16.0)
+    }
+}
+
+struct Sensor {
+    i2c: I2c,
+    calibration: Calibration,
+}
+
+impl Sensor {
+    /// Resets the sensor and starts continuous measurements.
+    fn open(config: &I2cConfig) -> Result<Sensor, Box<dyn Error>> {
+        let mut i2c = I2c::with_bus(config.bus)
+            .map_err(|e| format!("Cannot open I2C bus {}: {}", config.bus, e))?;
+        i2c.set_slave_address(config.address)?;
+
+        let mut id = [0u8];
+        i2c.write_read(&[REG_CHIP_ID], &mut id)
+            .map_err(|e| format!("No sensor at {:#04x}: {}", config.address, e))?;
+        let chip = match id[0] {
+            CHIP_BMP280 => "BMP280",
+            CHIP_BME280 => "BME280",
+            other => {
+                return Err(format!(
+                    "Unknown chip id {:#04x} at {:#04x}, expected a BMP280 or BME280",
+                    other, config.address
+                )
+                .into())
+            }
+        };
+
+        i2c.write(&[REG_RESET, 0xB6])?;
+        // The NVM is copied to the registers within 2 ms of a reset
+        thread::sleep(Duration::from_millis(10));
+        let mut data = [0u8; 24];
+        i2c.write_read(&[REG_CALIBRATION], &mut data)?;
+        // Written in sleep mode, so the configuration is not ignored
+        i2c.write(&[REG_CONFIG, CONFIG])?;
+        i2c.write(&[REG_CTRL_MEAS, CTRL_MEAS])?;
+        info!("{} at {:#04x} on bus {}", chip, config.address, config.bus);
+
+        Ok(Sensor {
+            i2c,
+            calibration: Calibration::parse(&data),
+        })
+    }
+
+    /// The latest measurement in Pa, `None` before the first one.
+    fn pressure(&self) -> Result<Option<f64>, Box<dyn Error>> {
+        let mut data = [0u8; 6];
+        self.i2c.write_read(&[REG_DATA], &mut data)?;
+        let raw = |i: usize| {
+            ((data[i] as i32) << 12) | ((data[i + 1] as i32) << 4) | ((data[i + 2] as i32) >> 4)
+        };
+        let (pressure, temperature) = (raw(0), raw(3));
+        if pressure == SKIPPED || temperature == SKIPPED {
+            return Ok(None);
+        }
+        Ok(self.calibration.pressure(temperature, pressure))
+    }
+}
@@ -0,0 +1,512 @@
+//! Data sources. Each one runs on its own thread and sends samples to the
+//! render loop over a channel, so new kinds of input don't touch the UI.
+
+use crate::config::{
+    Config, HttpConfig, I2cConfig, ModbusConfig, MqttConfig, MqttVersion, SerialConfig, SimConfig,
+    SourceConfig, UdpConfig, WebSocketConfig,
+};
+use crate::decode::Decoder;
+use crate::error::MonitorError;
+use channel::Sender;
+use http::HttpSource;
+#[cfg(feature = "i2c")]
+use i2c::I2cSource;
+use modbus::ModbusSource;
+use mqtt::MqttSource;
+#[cfg(feature = "mqtt5")]
+use mqtt5::Mqtt5Source;
+use multi::MultiSource;
+use serial::SerialSource;
+use sim::SimSource;
+use std::collections::HashSet;
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, warn};
+use udp::UdpSource;
+use websocket::WebSocketSource;
+
+pub mod channel;
+pub mod http;
+#[cfg(feature = "i2c")]
+pub mod i2c;
+pub mod modbus;
+pub mod mqtt;
+#[cfg(feature = "mqtt5")]
+pub mod mqtt5;
+pub mod multi;
+pub mod replay;
+pub mod serial;
+pub mod sim;
+pub mod udp;
+pub mod websocket;
+
+/// Reconnect delays, doubling after each failure.
+pub(crate) const BACKOFF_MIN: Duration = Duration::from_millis(500);
+pub(crate) const BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct Sample {
+    /// Series the sample belongs to, e.g. the MQTT topic
+    pub topic: String,
+    /// In Pa
+    pub value: f64,
+    /// Sensor side timestamp, the time of arrival is used when missing
+    pub timestamp: Option<SystemTime>,
+    /// Message counter of the sensor, gaps in it are lost messages
+    pub sequence: Option<u64>,
+    /// Name of the source among several, see `Sender::tagged`
+    pub sensor: Option<String>,
+    /// Dropped if not taken from the channel by then, see MQTT 5 message
+    /// expiry
+    pub expires: Option<SystemTime>,
+    /// A retained message, flagged on the chart, see `[mqtt] retained`
+    pub retained: bool,
+}
+
+impl Sample {
+    /// Name of the series the sample is drawn in, the topic prefixed with
+    /// the sensor name if there is one.
+    pub fn series(&self) -> String {
+        match &self.sensor {
+            Some(sensor) => format!("{}/{}", sensor, self.topic),
+            None => self.topic.clone(),
+        }
+    }
+}
+
+pub trait DataSource {
+    /// Starts producing samples on a background thread, which ends soon
+    /// after `shutdown` is requested.
+    fn spawn(&self, tx: Sender, shutdown: Shutdown) -> Result<JoinHandle<()>, Box<dyn Error>>;
+
+    /// Where topics are added and removed while running, for the sources
+    /// that subscribe to topics.
+    fn topics(&self) -> Option<Topics> {
+        None
+    }
+}
+
+/// Builds the source selected by `spec`:
+///
+/// - `mqtt`: the `[mqtt]` broker
+/// - `serial` or `serial:<port>`: the `[serial]` port, or the one given
+/// - `sim`: the synthetic `[sim]` waveform
+/// - `http`: polling the `[http]` endpoint
+/// - `udp` or `udp:<address>`: datagrams to the `[udp]` address, or the
+///   one given
+/// - `websocket`, or a `ws://` or `wss://` URL: the `[websocket]` endpoint,
+///   or the one given
+/// - `modbus` or `modbus:<address>`: the `[modbus]` transmitter, or the one
+///   at the given host and port
+/// - `i2c`: the `[i2c]` sensor, with the `i2c` feature
+pub fn from_spec(
+    spec: &str,
+    config: &Config,
+    status: Status,
+) -> Result<Box<dyn DataSource>, Box<dyn Error>> {
+    build(spec, &Sections::of(config, None), config, status)
+}
+
+/// Builds the `[[sources]]` of `config`, each with a status of its own
+/// that is merged into `status`.
+pub fn from_sources(
+    config: &Config,
+    status: Status,
+) -> Result<Box<dyn DataSource>, Box<dyn Error>> {
+    let mut multi = MultiSource::new(status);
+    let mut names = HashSet::new();
+    for entry in &config.sources {
+        if entry.name.is_empty() || entry.name.contains('/') {
+            return Err(format!(
+                "Invalid sensor name {:?}, expected one without slashes",
+                entry.name
+            )
+            .into());
+        }
+        if !names.insert(entry.name.as_str()) {
+            return Err(format!("Sensor {} is configured twice", entry.name).into());
+        }
+        let status = Status::default();
+        let source = build(
+            &entry.source,
+            &Sections::of(config, Some(entry)),
+            config,
+            status.clone(),
+        )
+        .map_err(|e| format!("Source of {}: {}", entry.name, e))?;
+        multi.add(&entry.name, source, status);
+    }
+    Ok(Box::new(multi))
+}
+
+/// The source sections to build from, those of a `[[sources]]` entry where
+/// it has them and the top level ones otherwise.
+struct Sections<'a> {
+    mqtt: &'a MqttConfig,
+    serial: &'a SerialConfig,
+    sim: &'a SimConfig,
+    http: &'a HttpConfig,
+    udp: &'a UdpConfig,
+    websocket: &'a WebSocketConfig,
+    modbus: &'a ModbusConfig,
+    #[cfg_attr(not(feature = "i2c"), allow(dead_code))]
+    i2c: &'a I2cConfig,
+}
+
+impl<'a> Sections<'a> {
+    fn of(config: &'a Config, entry: Option<&'a SourceConfig>) -> Sections<'a> {
+        Sections {
+            mqtt: entry.and_then(|e| e.mqtt.as_ref()).unwrap_or(&config.mqtt),
+            serial: entry
+                .and_then(|e| e.serial.as_ref())
+                .unwrap_or(&config.serial),
+            sim: entry.and_then(|e| e.sim.as_ref()).unwrap_or(&config.sim),
+            http: entry.and_then(|e| e.http.as_ref()).unwrap_or(&config.http),
+            udp: entry.and_then(|e| e.udp.as_ref()).unwrap_or(&config.udp),
+            websocket: entry
+                .and_then(|e| e.websocket.as_ref())
+                .unwrap_or(&config.websocket),
+            modbus: entry
+                .and_then(|e| e.modbus.as_ref())
+                .unwrap_or(&config.modbus),
+            i2c: entry.and_then(|e| e.i2c.as_ref()).unwrap_or(&config.i2c),
+        }
+    }
+}
+
+/// As `from_spec`, from the sections given.
+fn build(
+    spec: &str,
+    sections: &Sections,
+    config: &Config,
+    status: Status,
+) -> Result<Box<dyn DataSource>, Box<dyn Error>> {
+    let decoder = Decoder::new(&config.payload)?;
+    let (kind, arg) = match spec.split_once(':') {
+        Some((kind, arg)) => (kind, Some(arg)),
+        None => (spec, None),
+    };
+
+    match (kind, arg) {
+        ("mqtt", None) => match sections.mqtt.version {
+            MqttVersion::V311 => Ok(Box::new(MqttSource::new(sections.mqtt, decoder, status)?)),
+            #[cfg(feature = "mqtt5")]
+            MqttVersion::V5 => Ok(Box::new(Mqtt5Source::new(sections.mqtt, decoder, status)?)),
+            #[cfg(not(feature = "mqtt5"))]
+            MqttVersion::V5 => Err("Built without the mqtt5 feature, no MQTT 5".into()),
+        },
+        ("serial", port) => {
+            let mut serial = sections.serial.clone();
+            if let Some(port) = port {
+                serial.port = port.to_string();
+            }
+            Ok(Box::new(SerialSource::new(&serial, decoder, status)))
+        }
+        ("sim", None) => Ok(Box::new(SimSource::new(sections.sim, status))),
+        ("http", None) => Ok(Box::new(HttpSource::new(sections.http, decoder, status))),
+        ("udp", listen) => {
+            let mut udp = sections.udp.clone();
+            if let Some(listen) = listen {
+                udp.listen = listen.to_string();
+            }
+            Ok(Box::new(UdpSource::new(&udp, decoder, status)))
+        }
+        ("websocket", None) | ("ws" | "wss", Some(_)) => {
+            let mut websocket = sections.websocket.clone();
+            if arg.is_some() {
+                websocket.url = spec.to_string();
+            }
+            Ok(Box::new(WebSocketSource::new(&websocket, decoder, status)?))
+        }
+        ("modbus", address) => {
+            let mut modbus = sections.modbus.clone();
+            if let Some(address) = address {
+                modbus.address = address.to_string();
+            }
+            Ok(Box::new(ModbusSource::new(&modbus, decoder, status)))
+        }
+        #[cfg(feature = "i2c")]
+        ("i2c", None) => Ok(Box::new(I2cSource::new(sections.i2c, decoder, status))),
+        #[cfg(not(feature = "i2c"))]
+        ("i2c", None) => Err("Built without the i2c feature, no I2C sensors".into()),
+        _ => Err(format!(
+            "Unknown source {}, expected mqtt, serial[:<port>], sim, http, \
+             udp[:<address>], websocket, a ws(s):// URL, modbus[:<address>] or i2c",
+            spec
+        )
+        .into()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    /// Lost the source and retrying with a growing delay
+    Reconnecting,
+    /// Not connected yet, finished, or retrying at the maximum delay
+    Offline,
+}
+
+impl ConnectionState {
+    pub fn label(self) -> &'static str {
+        match self {
+            ConnectionState::Connected => "CONNECTED",
+            ConnectionState::Reconnecting => "RECONNECTING",
+            ConnectionState::Offline => "OFFLINE",
+        }
+    }
+}
+
+/// Connection state and health counters shared between a source thread and
+/// the UI.
+#[derive(Debug, Clone)]
+pub struct Status(Arc<Shared>);
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<ConnectionState>,
+    connects: AtomicU64,
+    dropped: AtomicU64,
+    rejected: AtomicU64,
+    errors: AtomicU64,
+    /// Those of the parts of a merged status, see `merge`
+    merged_errors: AtomicU64,
+    /// The latest error and when it happened
+    last_error: Mutex<Option<(Instant, String)>>,
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Status(Arc::new(Shared {
+            state: Mutex::new(ConnectionState::Offline),
+            connects: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            merged_errors: AtomicU64::new(0),
+            last_error: Mutex::new(None),
+        }))
+    }
+}
+
+impl Status {
+    pub fn get(&self) -> ConnectionState {
+        *lock(&self.0.state)
+    }
+
+    pub fn set(&self, state: ConnectionState) {
+        let mut current = lock(&self.0.state);
+        if state == ConnectionState::Connected && *current != ConnectionState::Connected {
+            self.0.connects.fetch_add(1, Ordering::Relaxed);
+        }
+        *current = state;
+    }
+
+    /// Connections made after the first one.
+    pub fn reconnects(&self) -> u64 {
+        self.0.connects.load(Ordering::Relaxed).saturating_sub(1)
+    }
+
+    /// Counts a message that couldn't be turned into a sample.
+    pub fn drop_message(&self) {
+        self.0.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.0.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Counts a reading the sanity filter rejected.
+    pub fn reject_sample(&self) {
+        self.0.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn rejected(&self) -> u64 {
+        self.0.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Logs and counts an error that was recovered from, for the status bar.
+    pub fn report(&self, error: MonitorError) {
+        warn!("{}", error);
+        self.0.errors.fetch_add(1, Ordering::Relaxed);
+        *lock(&self.0.last_error) = Some((Instant::now(), error.to_string()));
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.0.errors.load(Ordering::Relaxed) + self.0.merged_errors.load(Ordering::Relaxed)
+    }
+
+    /// The latest error, unless it is older than `max_age`.
+    pub fn last_error(&self, max_age: Duration) -> Option<String> {
+        lock(&self.0.last_error)
+            .as_ref()
+            .filter(|(at, _)| at.elapsed() <= max_age)
+            .map(|(_, error)| error.clone())
+    }
+
+    /// Takes the counters summed over `parts`, and their state when they
+    /// agree on it, `Reconnecting` otherwise. Errors reported to this status
+    /// itself are kept.
+    pub(crate) fn merge(&self, parts: &[Status]) {
+        let states: Vec<ConnectionState> = parts.iter().map(Status::get).collect();
+        let state = match states.split_first() {
+            Some((first, rest)) if rest.iter().all(|s| s == first) => *first,
+            Some(_) => ConnectionState::Reconnecting,
+            None => ConnectionState::Offline,
+        };
+        *lock(&self.0.state) = state;
+
+        let (mut connects, mut dropped, mut rejected, mut errors) = (0, 0, 0, 0);
+        let mut last_error = lock(&self.0.last_error).clone();
+        for part in parts {
+            connects += part.0.connects.load(Ordering::Relaxed);
+            dropped += part.dropped();
+            rejected += part.rejected();
+            errors += part.errors();
+            if let Some((at, error)) = &*lock(&part.0.last_error) {
+                if last_error.as_ref().is_none_or(|(latest, _)| at > latest) {
+                    last_error = Some((*at, error.clone()));
+                }
+            }
+        }
+        self.0.connects.store(connects, Ordering::Relaxed);
+        self.0.dropped.store(dropped, Ordering::Relaxed);
+        self.0.rejected.store(rejected, Ordering::Relaxed);
+        self.0.merged_errors.store(errors, Ordering::Relaxed);
+        *lock(&self.0.last_error) = last_error;
+    }
+}
+
+/// Locks `mutex` even if a thread panicked holding it, the shared state is
+/// plain values that stay consistent.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Whether a reading of `topic` passes the sanity filter of `decoder`,
+/// counting and optionally logging the rejected ones.
+pub(crate) fn accept(decoder: &mut Decoder, status: &Status, topic: &str, value: f64) -> bool {
+    match decoder.check(topic, value) {
+        Ok(()) => true,
+        Err(reason) => {
+            status.reject_sample();
+            if decoder.log_rejected() {
+                warn!("Rejected {} from {}: {}", value, topic, reason);
+            } else {
+                debug!("Rejected {} from {}: {}", value, topic, reason);
+            }
+            false
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TopicChange {
+    Subscribe(String),
+    Unsubscribe(String),
+}
+
+/// Topics subscribed to, changed while running from the UI and taken up by
+/// the source thread with the next packet it handles. Shared like `Status`.
+#[derive(Debug, Clone, Default)]
+pub struct Topics(Arc<Mutex<TopicRequests>>);
+
+#[derive(Debug, Default)]
+struct TopicRequests {
+    /// As asked for, in order
+    subscribed: Vec<String>,
+    /// Not taken up by the source yet
+    changes: Vec<TopicChange>,
+}
+
+impl Topics {
+    /// Those subscribed to from the start.
+    pub(crate) fn new(topics: &[String]) -> Topics {
+        Topics(Arc::new(Mutex::new(TopicRequests {
+            subscribed: topics.to_vec(),
+            changes: Vec::new(),
+        })))
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        lock(&self.0).subscribed.clone()
+    }
+
+    /// Returns whether `topic` is a new one.
+    pub fn subscribe(&self, topic: &str) -> bool {
+        let mut list = lock(&self.0);
+        if list.subscribed.iter().any(|t| t == topic) {
+            return false;
+        }
+        list.subscribed.push(topic.to_string());
+        list.changes.push(TopicChange::Subscribe(topic.to_string()));
+        true
+    }
+
+    /// Returns whether `topic` was subscribed to.
+    pub fn unsubscribe(&self, topic: &str) -> bool {
+        let mut list = lock(&self.0);
+        let Some(i) = list.subscribed.iter().position(|t| t == topic) else {
+            return false;
+        };
+        list.subscribed.remove(i);
+        list.changes
+            .push(TopicChange::Unsubscribe(topic.to_string()));
+        true
+    }
+
+    /// The changes since the last call, oldest first.
+    pub(crate) fn take_changes(&self) -> Vec<TopicChange> {
+        std::mem::take(&mut lock(&self.0).changes)
+    }
+}
+
+/// Asks source threads to stop, shared like `Status`.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown(Arc<(Mutex<bool>, Condvar)>);
+
+impl Shutdown {
+    pub fn request(&self) {
+        let (requested, wakeup) = &*self.0;
+        *lock(requested) = true;
+        wakeup.notify_all();
+    }
+
+    pub fn requested(&self) -> bool {
+        *lock(&self.0 .0)
+    }
+
+    /// Blocks until shutdown is requested.
+    pub fn wait(&self) {
+        let (requested, wakeup) = &*self.0;
+        let _guard = wakeup
+            .wait_while(lock(requested), |requested| !*requested)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+
+    /// Sleeps for `timeout`, or less when shutdown is requested meanwhile.
+    /// Returns whether it was.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let (requested, wakeup) = &*self.0;
+        let (guard, _) = wakeup
+            .wait_timeout_while(lock(requested), timeout, |requested| !*requested)
+            .unwrap_or_else(PoisonError::into_inner);
+        *guard
+    }
+}
+
+/// Joins a source thread, giving up after `timeout` so a stuck read can't
+/// hold up the exit. Returns whether the thread finished.
+pub fn join_timeout(handle: JoinHandle<()>, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    while !handle.is_finished() {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    handle.join().is_ok()
+}
@@ -0,0 +1,197 @@
+//! Modbus TCP source for industrial transmitters, polling one holding or
+//! input register, or a pair of them for 32 bit values.
+
+use super::{
+    accept, channel::Sender, ConnectionState, DataSource, Sample, Shutdown, Status, BACKOFF_MAX,
+};
+use crate::config::ModbusConfig;
+use crate::decode::Decoder;
+use serde::Deserialize;
+use std::error::Error;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tokio_modbus::client::sync::{self, Context, Reader};
+use tokio_modbus::prelude::Slave;
+use tracing::{info, info_span, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegisterKind {
+    /// Function code 3
+    Holding,
+    /// Function code 4
+    Input,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataType {
+    U16,
+    I16,
+    /// Two registers, see `WordOrder`
+    U32,
+    I32,
+    F32,
+}
+
+impl DataType {
+    fn registers(self) -> u16 {
+        match self {
+            DataType::U16 | DataType::I16 => 1,
+            DataType::U32 | DataType::I32 | DataType::F32 => 2,
+        }
+    }
+}
+
+/// Of 32 bit values, the bytes within a register are always big endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WordOrder {
+    /// High word in the first register
+    Big,
+    /// Low word first, as many transmitters do
+    Little,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModbusSource {
+    config: ModbusConfig,
+    decoder: Decoder,
+    status: Status,
+}
+
+impl ModbusSource {
+    pub fn new(config: &ModbusConfig, decoder: Decoder, status: Status) -> ModbusSource {
+        ModbusSource {
+            config: config.clone(),
+            decoder,
+            status,
+        }
+    }
+
+    fn connect(&self) -> Result<Context, Box<dyn Error>> {
+        let addr: SocketAddr = self
+            .config
+            .address
+            .to_socket_addrs()
+            .map_err(|e| format!("Cannot resolve {}: {}", self.config.address, e))?
+            .next()
+            .ok_or_else(|| format!("{} has no addresses", self.config.address))?;
+        let context = sync::tcp::connect_slave(addr, Slave(self.config.unit_id))
+            .map_err(|e| format!("Cannot connect to {}: {}", addr, e))?;
+        Ok(context)
+    }
+
+    /// The scaled value of the register, in Pa.
+    fn read(&self, context: &mut Context) -> Result<f64, Box<dyn Error>> {
+        let config = &self.config;
+        let count = config.data_type.registers();
+        let words = match config.kind {
+            RegisterKind::Holding => context.read_holding_registers(config.register, count)?,
+            RegisterKind::Input => context.read_input_registers(config.register, count)?,
+        };
+        if words.len() != count as usize {
+            return Err(format!("Expected {} registers, got {}", count, words.len()).into());
+        }
+
+        let long = || {
+            let (high, low) = match config.word_order {
+                WordOrder::Big => (words[0], words[1]),
+                WordOrder::Little => (words[1], words[0]),
+            };
+            ((high as u32) << 16) | low as u32
+        };
+        let raw = match config.data_type {
+            DataType::U16 => words[0] as f64,
+            DataType::I16 => words[0] as i16 as f64,
+            DataType::U32 => long() as f64,
+            DataType::I32 => long() as i32 as f64,
+            DataType::F32 => f32::from_bits(long()) as f64,
+        };
+        Ok(raw * config.scale + config.offset)
+    }
+
+    /// Reads over the open connection, or a new one. The connection is kept
+    /// only when the read succeeded, the transmitter may be out of step with
+    /// it after an error.
+    fn poll(&self, context: &mut Option<Context>) -> Result<f64, Box<dyn Error>> {
+        let mut connected = match context.take() {
+            Some(connected) => connected,
+            None => {
+                let connected = self.connect()?;
+                info!("Connected, unit {}", self.config.unit_id);
+                connected
+            }
+        };
+        let value = self.read(&mut connected)?;
+        *context = Some(connected);
+        Ok(value)
+    }
+}
+
+impl DataSource for ModbusSource {
+    /// Reads the register every `interval` seconds. Failed reads are retried
+    /// as failed requests of the HTTP source.
+    fn spawn(&self, tx: Sender, shutdown: Shutdown) -> Result<JoinHandle<()>, Box<dyn Error>> {
+        if !self.config.interval.is_finite() || self.config.interval <= 0.0 {
+            return Err(format!(
+                "Invalid modbus interval {}, expected seconds",
+                self.config.interval
+            )
+            .into());
+        }
+        let source = self.clone();
+
+        Ok(thread::spawn(move || {
+            let _span = info_span!("modbus", address = %source.config.address).entered();
+            let interval = Duration::from_secs_f64(source.config.interval);
+            let topic = source.config.topic.clone();
+            let mut decoder = source.decoder.clone();
+            let mut context = None;
+            let mut backoff = interval;
+
+            loop {
+                let started = Instant::now();
+                let delay = match source.poll(&mut context) {
+                    Ok(value) => {
+                        source.status.set(ConnectionState::Connected);
+                        backoff = interval;
+                        let value = decoder.calibrate(&topic, value);
+                        if accept(&mut decoder, &source.status, &topic, value) {
+                            let sample = Sample {
+                                topic: topic.clone(),
+                                value,
+                                timestamp: None,
+                                sequence: None,
+                                sensor: None,
+                                expires: None,
+                                retained: false,
+                            };
+                            if tx.send(sample).is_err() {
+                                break;
+                            }
+                        }
+                        interval.saturating_sub(started.elapsed())
+                    }
+                    Err(e) => {
+                        if backoff >= BACKOFF_MAX {
+                            source.status.set(ConnectionState::Offline);
+                        } else {
+                            source.status.set(ConnectionState::Reconnecting);
+                        }
+                        warn!("{}, retrying in {:?}", e, backoff);
+                        let delay = backoff;
+                        backoff = (backoff * 2).min(BACKOFF_MAX.max(interval));
+                        delay
+                    }
+                };
+                if shutdown.wait_timeout(delay) {
+                    break;
+                }
+            }
+
+            source.status.set(ConnectionState::Offline);
+        }))
+    }
+}
@@ -0,0 +1,578 @@
+//! MQTT source: broker address parsing, reachability check, TLS, WebSocket
+//! transport and the background reader thread.
+
+use super::{
+    accept, channel::Sender, ConnectionState, DataSource, Sample, Shutdown, Status, TopicChange,
+    Topics, BACKOFF_MAX, BACKOFF_MIN,
+};
+use crate::config::{MqttConfig, RetainedMessages, TlsConfig};
+use crate::decode::Decoder;
+use crate::error::MonitorError;
+use rumqttc::v4::{Packet, SubscribeReasonCode};
+use rumqttc::{Client, Connection, Event, MqttOptions, Outgoing, QoS, TlsConfiguration, Transport};
+use rustls::internal::pemfile;
+use rustls::{
+    Certificate, ClientConfig, RootCertStore, ServerCertVerified, ServerCertVerifier, TLSError,
+    WebPKIVerifier,
+};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, info_span, warn};
+
+/// Delay before subscribing again to topics the broker refused, or that
+/// didn't fit into the request queue.
+pub(super) const SUBSCRIBE_RETRY: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub(super) struct Broker {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    /// The HTTP path of MQTT over WebSockets, `None` for plain MQTT
+    pub websocket: Option<String>,
+}
+
+impl Broker {
+    /// Accepts `host`, `host:port`, `mqtt://host[:port]`,
+    /// `mqtts://host[:port]`, and `ws://host[:port][/path]` and
+    /// `wss://host[:port][/path]` for MQTT over WebSockets, at `/mqtt` when
+    /// the path is left out. IPv6 addresses need brackets.
+    ///
+    /// An explicit `port` wins over the one in the address, which in turn
+    /// wins over the scheme default (1883, 8883 for TLS, 80 for `ws://` and
+    /// 443 for `wss://`).
+    pub fn parse(addr: &str, port: Option<u16>) -> Result<Broker, Box<dyn Error>> {
+        let (tls, websocket, rest) = if let Some(rest) = addr.strip_prefix("mqtts://") {
+            (true, false, rest)
+        } else if let Some(rest) = addr.strip_prefix("mqtt://") {
+            (false, false, rest)
+        } else if let Some(rest) = addr.strip_prefix("wss://") {
+            (true, true, rest)
+        } else if let Some(rest) = addr.strip_prefix("ws://") {
+            (false, true, rest)
+        } else if addr.contains("://") {
+            return Err(format!("Unsupported broker address: {}", addr).into());
+        } else {
+            (false, false, addr)
+        };
+        let (rest, path) = match rest.find('/') {
+            Some(i) if websocket => (&rest[..i], Some(&rest[i..])),
+            _ => (rest.trim_end_matches('/'), None),
+        };
+
+        let (host, addr_port) = match rest.rsplit_once(':') {
+            Some((host, p)) if !p.contains(']') => {
+                let p = p
+                    .parse::<u16>()
+                    .map_err(|_| format!("Invalid port in broker address: {}", addr))?;
+                (host, Some(p))
+            }
+            _ => (rest, None),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(format!("Missing host in broker address: {}", addr).into());
+        }
+
+        let default_port = match (websocket, tls) {
+            (false, false) => 1883,
+            (false, true) => 8883,
+            (true, false) => 80,
+            (true, true) => 443,
+        };
+        Ok(Broker {
+            host: host.to_string(),
+            port: port.or(addr_port).unwrap_or(default_port),
+            tls,
+            websocket: websocket.then(|| path.unwrap_or("/mqtt").to_string()),
+        })
+    }
+
+    /// Fail early with a readable message instead of silently waiting for data
+    /// that never comes.
+    pub fn check_reachable(&self) -> Result<(), Box<dyn Error>> {
+        let addrs = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(|e| format!("Cannot resolve MQTT broker {}: {}", self, e))?;
+
+        let mut last_err = None;
+        for addr in addrs {
+            match TcpStream::connect_timeout(&addr, Duration::from_secs(3)) {
+                Ok(_) => return Ok(()),
+                Err(e) => last_err = Some(e),
+            }
+        }
+
+        match last_err {
+            Some(e) => Err(format!("MQTT broker {} is unreachable: {}", self, e).into()),
+            None => Err(format!("MQTT broker {} has no addresses", self).into()),
+        }
+    }
+}
+
+impl std::fmt::Display for Broker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let scheme = match (&self.websocket, self.tls) {
+            (None, false) => "mqtt",
+            (None, true) => "mqtts",
+            (Some(_), false) => "ws",
+            (Some(_), true) => "wss",
+        };
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        write!(f, "{}://{}:{}", scheme, host, self.port)?;
+        if let Some(path) = &self.websocket {
+            f.write_str(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// The topics to subscribe to with their QoS level, 0 to 2.
+pub(super) fn subscriptions(config: &MqttConfig) -> Result<Vec<(String, u8)>, Box<dyn Error>> {
+    if let Some(topic) = config.topic_qos.keys().find(|t| !config.topics.contains(t)) {
+        return Err(format!("[mqtt.topic_qos] has {}, which is not in topics", topic).into());
+    }
+    config
+        .topics
+        .iter()
+        .map(|topic| {
+            let qos = config.topic_qos.get(topic).copied().unwrap_or(config.qos);
+            if qos > 2 {
+                return Err(
+                    format!("Invalid QoS {} for {}, expected 0, 1 or 2", qos, topic).into(),
+                );
+            }
+            Ok((topic.clone(), qos))
+        })
+        .collect()
+}
+
+fn qos(level: u8) -> QoS {
+    match level {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    }
+}
+
+/// Options of another client of the configured broker, such as the
+/// publisher, with the same credentials and TLS settings.
+pub(crate) fn client_options(
+    config: &MqttConfig,
+    client_id: String,
+) -> Result<MqttOptions, Box<dyn Error>> {
+    let broker = Broker::parse(&config.broker, config.port)?;
+    options(config, client_id, &broker)
+}
+
+fn options(
+    config: &MqttConfig,
+    client_id: String,
+    broker: &Broker,
+) -> Result<MqttOptions, Box<dyn Error>> {
+    // Over WebSockets the client connects to the URL
+    let host = match broker.websocket {
+        Some(_) => broker.to_string(),
+        None => broker.host.clone(),
+    };
+    let mut options = MqttOptions::new(client_id, host, broker.port);
+    options.set_keep_alive(Duration::from_secs(5));
+    options.set_clean_session(true);
+
+    match (&config.username, &config.password) {
+        (Some(username), password) => {
+            options.set_credentials(username.clone(), password.clone().unwrap_or_default());
+        }
+        (None, Some(_)) => return Err("MQTT password given without a user name".into()),
+        (None, None) => {}
+    }
+
+    if broker.tls {
+        let tls = TlsConfiguration::Rustls(Arc::new(tls_config(&config.tls)?));
+        options.set_transport(match broker.websocket {
+            Some(_) => Transport::Wss(tls),
+            None => Transport::Tls(tls),
+        });
+    } else if broker.websocket.is_some() {
+        options.set_transport(Transport::Ws);
+    }
+
+    Ok(options)
+}
+
+pub(crate) fn tls_config(tls: &TlsConfig) -> Result<ClientConfig, Box<dyn Error>> {
+    let mut config = ClientConfig::new();
+
+    match &tls.ca {
+        Some(path) => {
+            let mut reader = open(path)?;
+            let (added, _) = config
+                .root_store
+                .add_pem_file(&mut reader)
+                .map_err(|_| format!("Invalid CA bundle {}", path.display()))?;
+            if added == 0 {
+                return Err(
+                    format!("No certificates found in CA bundle {}", path.display()).into(),
+                );
+            }
+        }
+        None => config
+            .root_store
+            .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS),
+    }
+
+    match (&tls.cert, &tls.key) {
+        (Some(cert), Some(key)) => {
+            let certs = read_pem(cert, pemfile::certs)?;
+            let mut keys = read_pem(key, pemfile::pkcs8_private_keys)?;
+            if keys.is_empty() {
+                keys = read_pem(key, pemfile::rsa_private_keys)?;
+            }
+            let key = keys
+                .into_iter()
+                .next()
+                .ok_or_else(|| format!("No private key found in {}", key.display()))?;
+            config.set_single_client_cert(certs, key)?;
+        }
+        (None, None) => {}
+        _ => return Err("Mutual TLS needs both a client certificate and a key".into()),
+    }
+
+    if !tls.verify_hostname {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(IgnoreHostname(WebPKIVerifier::new())));
+    }
+
+    Ok(config)
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+    Ok(BufReader::new(file))
+}
+
+fn read_pem<T>(
+    path: &Path,
+    parse: fn(&mut dyn BufRead) -> Result<Vec<T>, ()>,
+) -> Result<Vec<T>, Box<dyn Error>> {
+    let mut reader = open(path)?;
+    parse(&mut reader).map_err(|_| format!("Invalid PEM file {}", path.display()).into())
+}
+
+/// Validates the certificate chain as usual but accepts a certificate issued
+/// for another name, for lab brokers reached by IP or an ad-hoc host name.
+struct IgnoreHostname(WebPKIVerifier);
+
+impl ServerCertVerifier for IgnoreHostname {
+    fn verify_server_cert(
+        &self,
+        roots: &RootCertStore,
+        presented_certs: &[Certificate],
+        dns_name: webpki::DNSNameRef<'_>,
+        ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        match self
+            .0
+            .verify_server_cert(roots, presented_certs, dns_name, ocsp_response)
+        {
+            Err(TLSError::WebPKIError(webpki::Error::CertNotValidForName)) => {
+                Ok(ServerCertVerified::assertion())
+            }
+            result => result,
+        }
+    }
+}
+
+pub struct MqttSource {
+    options: MqttOptions,
+    topics: Vec<(String, QoS)>,
+    /// Added and removed while running, those added with `[mqtt] qos`
+    requested: Topics,
+    qos: QoS,
+    retained: RetainedMessages,
+    decoder: Decoder,
+    status: Status,
+}
+
+impl MqttSource {
+    /// Checks the broker is reachable so a wrong address fails right away.
+    pub fn new(
+        config: &MqttConfig,
+        decoder: Decoder,
+        status: Status,
+    ) -> Result<MqttSource, Box<dyn Error>> {
+        let broker = Broker::parse(&config.broker, config.port)?;
+        broker.check_reachable()?;
+
+        let topics: Vec<_> = subscriptions(config)?
+            .into_iter()
+            .map(|(topic, level)| (topic, qos(level)))
+            .collect();
+        let names: Vec<String> = topics.iter().map(|(topic, _)| topic.clone()).collect();
+
+        Ok(MqttSource {
+            options: options(config, config.client_id.clone(), &broker)?,
+            topics,
+            requested: Topics::new(&names),
+            qos: qos(config.qos),
+            retained: config.retained,
+            decoder,
+            status,
+        })
+    }
+}
+
+impl DataSource for MqttSource {
+    /// Drives the MQTT event loop, reconnecting with exponential backoff and
+    /// sending every decoded reading tagged with the topic it came on.
+    /// Shutdown disconnects from the broker cleanly.
+    fn spawn(&self, tx: Sender, shutdown: Shutdown) -> Result<JoinHandle<()>, Box<dyn Error>> {
+        let (mut client, mut connection) = Client::new(self.options.clone(), 10);
+        let mut topics = self.topics.clone();
+        let requested = self.requested.clone();
+        let default_qos = self.qos;
+        let retained = self.retained;
+        let mut decoder = self.decoder.clone();
+        let status = self.status.clone();
+
+        // The event loop blocks until the next packet, queueing a disconnect
+        // wakes it up.
+        let mut disconnect = client.clone();
+        let stop = shutdown.clone();
+        thread::spawn(move || {
+            stop.wait();
+            disconnect.try_disconnect().ok();
+        });
+
+        Ok(thread::spawn(move || {
+            let _span = info_span!("mqtt").entered();
+            let mut backoff = BACKOFF_MIN;
+            // Topics still to subscribe to, those queued for sending and
+            // those sent, by packet id, until the broker acknowledges them
+            let mut pending: Vec<(String, QoS)> = Vec::new();
+            let mut queued: VecDeque<(String, QoS)> = VecDeque::new();
+            let mut sent: HashMap<u16, (String, QoS)> = HashMap::new();
+            let mut retry_at = Instant::now();
+
+            // The iterator only ends once the client is dropped, errors make
+            // the next poll reconnect.
+            for notification in connection.iter() {
+                debug!(?notification);
+
+                let event = match notification {
+                    Ok(event) => event,
+                    Err(_) if shutdown.requested() => break,
+                    Err(e) => {
+                        if backoff >= BACKOFF_MAX {
+                            status.set(ConnectionState::Offline);
+                        } else {
+                            status.set(ConnectionState::Reconnecting);
+                        }
+                        warn!("Connection error: {}, retrying in {:?}", e, backoff);
+                        if shutdown.wait_timeout(backoff) {
+                            break;
+                        }
+                        backoff = (backoff * 2).min(BACKOFF_MAX);
+                        continue;
+                    }
+                };
+
+                match event {
+                    Event::Outgoing(Outgoing::Disconnect) => {
+                        info!("Disconnected from broker");
+                        break;
+                    }
+                    Event::Incoming(Packet::ConnAck(_)) => {
+                        backoff = BACKOFF_MIN;
+                        status.set(ConnectionState::Connected);
+                        info!("Connected to broker");
+
+                        // Clean sessions drop subscriptions, so (re)subscribe
+                        // on every connect
+                        pending.clone_from(&topics);
+                        queued.clear();
+                        sent.clear();
+                        retry_at = Instant::now();
+                    }
+                    Event::Outgoing(Outgoing::Subscribe(pkid)) => {
+                        if let Some(topic) = queued.pop_front() {
+                            sent.insert(pkid, topic);
+                        }
+                    }
+                    Event::Incoming(Packet::SubAck(ack)) => {
+                        if let Some((topic, qos)) = sent.remove(&ack.pkid) {
+                            let refused = ack
+                                .return_codes
+                                .iter()
+                                .any(|code| matches!(code, SubscribeReasonCode::Failure));
+                            // Not retried once unsubscribed from meanwhile
+                            if refused && topics.iter().any(|(t, _)| *t == topic) {
+                                status.report(MonitorError::Subscribe {
+                                    topic: topic.clone(),
+                                    reason: "refused by the broker".to_string(),
+                                });
+                                pending.push((topic, qos));
+                                retry_at = Instant::now() + SUBSCRIBE_RETRY;
+                            } else if refused {
+                                debug!("Refused {}, unsubscribed from meanwhile", topic);
+                            } else {
+                                debug!("Subscribed to {}", topic);
+                            }
+                        }
+                    }
+                    Event::Incoming(Packet::Publish(publish))
+                        if publish.retain && retained == RetainedMessages::Ignore =>
+                    {
+                        debug!("Ignoring the retained message on {}", publish.topic);
+                    }
+                    // get pressure data
+                    Event::Incoming(Packet::Publish(publish)) => {
+                        let mark = publish.retain && retained == RetainedMessages::Mark;
+                        let topic = &publish.topic;
+                        match decoder.decode_all(topic, &publish.payload, None) {
+                            Ok(readings) => {
+                                for (series, reading) in readings {
+                                    // Unless rejected by the sanity filter
+                                    if !accept(&mut decoder, &status, &series, reading.value) {
+                                        continue;
+                                    }
+                                    let sample = Sample {
+                                        topic: series,
+                                        value: reading.value,
+                                        timestamp: reading.timestamp,
+                                        sequence: reading.sequence,
+                                        sensor: None,
+                                        expires: None,
+                                        retained: mark,
+                                    };
+                                    tx.send(sample).ok();
+                                }
+                            }
+                            Err(e) => {
+                                status.drop_message();
+                                warn!("Bad payload on {}: {}", publish.topic, e);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+
+                for change in requested.take_changes() {
+                    match change {
+                        TopicChange::Subscribe(topic) => {
+                            info!("Subscribing to {}", topic);
+                            topics.push((topic.clone(), default_qos));
+                            pending.push((topic, default_qos));
+                            retry_at = Instant::now();
+                        }
+                        TopicChange::Unsubscribe(topic) => {
+                            info!("Unsubscribing from {}", topic);
+                            topics.retain(|(t, _)| *t != topic);
+                            pending.retain(|(t, _)| *t != topic);
+                            if let Err(e) = client.try_unsubscribe(topic.as_str()) {
+                                status.report(MonitorError::Unsubscribe {
+                                    topic,
+                                    reason: e.to_string(),
+                                });
+                            }
+                        }
+                    }
+                }
+
+                // `try_` as this thread is also the one draining the request
+                // queue, a full one is retried like a refusal
+                if !pending.is_empty() && Instant::now() >= retry_at {
+                    pending.retain(|(topic, qos)| {
+                        match client.try_subscribe(topic.as_str(), *qos) {
+                            Ok(()) => {
+                                queued.push_back((topic.clone(), *qos));
+                                false
+                            }
+                            Err(e) => {
+                                status.report(MonitorError::Subscribe {
+                                    topic: topic.clone(),
+                                    reason: e.to_string(),
+                                });
+                                true
+                            }
+                        }
+                    });
+                    retry_at = Instant::now() + SUBSCRIBE_RETRY;
+                }
+            }
+
+            status.set(ConnectionState::Offline);
+        }))
+    }
+
+    fn topics(&self) -> Option<Topics> {
+        Some(self.requested.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(addr: &str) -> Broker {
+        Broker::parse(addr, None).unwrap()
+    }
+
+    #[test]
+    fn scheme_defaults() {
+        let broker = parse("localhost");
+        assert_eq!((broker.host.as_str(), broker.port), ("localhost", 1883));
+        assert!(!broker.tls);
+        assert_eq!(broker.websocket, None);
+
+        let broker = parse("mqtts://broker.local");
+        assert_eq!((broker.port, broker.tls), (8883, true));
+    }
+
+    #[test]
+    fn ports() {
+        assert_eq!(parse("mqtt://broker.local:1884").port, 1884);
+        assert_eq!(parse("mqtt://broker.local/").port, 1883);
+        let broker = Broker::parse("broker.local:1884", Some(1999)).unwrap();
+        assert_eq!(broker.port, 1999);
+    }
+
+    #[test]
+    fn ipv6() {
+        let broker = parse("[::1]:1884");
+        assert_eq!((broker.host.as_str(), broker.port), ("::1", 1884));
+        let broker = parse("[::1]");
+        assert_eq!((broker.host.as_str(), broker.port), ("::1", 1883));
+    }
+
+    #[test]
+    fn invalid_addresses() {
+        assert!(Broker::parse("http://broker.local", None).is_err());
+        assert!(Broker::parse("mqtt://:1883", None).is_err());
+        assert!(Broker::parse("broker.local:port", None).is_err());
+    }
+
+    #[test]
+    fn websockets() {
+        let broker = parse("ws://broker.local");
+        assert_eq!((broker.port, broker.tls), (80, false));
+        assert_eq!(broker.websocket.as_deref(), Some("/mqtt"));
+
+        let broker = parse("wss://broker.local:9001/ws");
+        assert_eq!((broker.port, broker.tls), (9001, true));
+        assert_eq!(broker.websocket.as_deref(), Some("/ws"));
+        assert_eq!(parse("wss://broker.local").port, 443);
+    }
+}
@@ -0,0 +1,373 @@
+//! MQTT 5 source, for `[mqtt] version = "5"`. Subscribes like the 3.1.1 one
+//! and also reads the properties of each message:
+//!
+//! - message expiry: a sample still waiting in the channel once its message
+//!   expired is dropped
+//! - a `unit` user property, e.g. `kPa`, is the unit of the payload value
+//! - topic aliases the broker sends instead of repeated topics, up to
+//!   `[mqtt] topic_aliases`
+
+use super::mqtt::{self, Broker, SUBSCRIBE_RETRY};
+use super::{
+    accept, channel::Sender, ConnectionState, DataSource, Sample, Shutdown, Status, TopicChange,
+    Topics, BACKOFF_MAX, BACKOFF_MIN,
+};
+use crate::config::{MqttConfig, RetainedMessages, TlsConfig};
+use crate::decode::Decoder;
+use crate::error::MonitorError;
+use crate::units::PressureUnit;
+use rumqttc5::v5::mqttbytes::v5::{Packet, Publish, SubscribeReasonCode};
+use rumqttc5::v5::mqttbytes::QoS;
+use rumqttc5::v5::{Client, Event, MqttOptions};
+use rumqttc5::{Outgoing, TlsConfiguration, Transport};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, info, info_span, warn};
+
+/// User property holding the unit of the payload value.
+const UNIT_PROPERTY: &str = "unit";
+
+fn options(config: &MqttConfig, broker: &Broker) -> Result<MqttOptions, Box<dyn Error>> {
+    let host = match broker.websocket {
+        Some(_) => broker.to_string(),
+        None => broker.host.clone(),
+    };
+    let mut options = MqttOptions::new(config.client_id.clone(), host, broker.port);
+    options.set_keep_alive(Duration::from_secs(5));
+    options.set_clean_start(true);
+    if config.topic_aliases > 0 {
+        options.set_topic_alias_max(Some(config.topic_aliases));
+    }
+
+    match (&config.username, &config.password) {
+        (Some(username), password) => {
+            options.set_credentials(username.clone(), password.clone().unwrap_or_default());
+        }
+        (None, Some(_)) => return Err("MQTT password given without a user name".into()),
+        (None, None) => {}
+    }
+
+    if broker.tls {
+        let tls = tls_config(&config.tls)?;
+        options.set_transport(match broker.websocket {
+            Some(_) => Transport::Wss(tls),
+            None => Transport::Tls(tls),
+        });
+    } else if broker.websocket.is_some() {
+        options.set_transport(Transport::Ws);
+    }
+
+    Ok(options)
+}
+
+/// From the PEM files as they are, this client comes with its own TLS
+/// library.
+fn tls_config(tls: &TlsConfig) -> Result<TlsConfiguration, Box<dyn Error>> {
+    if !tls.verify_hostname {
+        return Err("verify_hostname = false is not supported with MQTT 5".into());
+    }
+    let ca = match &tls.ca {
+        Some(path) => read(path)?,
+        // The system's trusted roots
+        None if tls.cert.is_none() && tls.key.is_none() => {
+            return Ok(TlsConfiguration::default());
+        }
+        None => return Err("Mutual TLS with MQTT 5 needs a CA bundle, see [mqtt.tls] ca".into()),
+    };
+    let client_auth = match (&tls.cert, &tls.key) {
+        (Some(cert), Some(key)) => Some((read(cert)?, read(key)?)),
+        (None, None) => None,
+        _ => return Err("Mutual TLS needs both a client certificate and a key".into()),
+    };
+
+    Ok(TlsConfiguration::Simple {
+        ca,
+        alpn: None,
+        client_auth,
+    })
+}
+
+fn qos(level: u8) -> QoS {
+    match level {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    fs::read(path).map_err(|e| format!("Cannot open {}: {}", path.display(), e).into())
+}
+
+pub struct Mqtt5Source {
+    options: MqttOptions,
+    topics: Vec<(String, QoS)>,
+    /// Added and removed while running, those added with `[mqtt] qos`
+    requested: Topics,
+    qos: QoS,
+    retained: RetainedMessages,
+    decoder: Decoder,
+    status: Status,
+}
+
+impl Mqtt5Source {
+    /// Checks the broker is reachable so a wrong address fails right away.
+    pub fn new(
+        config: &MqttConfig,
+        decoder: Decoder,
+        status: Status,
+    ) -> Result<Mqtt5Source, Box<dyn Error>> {
+        let broker = Broker::parse(&config.broker, config.port)?;
+        broker.check_reachable()?;
+
+        let topics: Vec<_> = mqtt::subscriptions(config)?
+            .into_iter()
+            .map(|(topic, level)| (topic, qos(level)))
+            .collect();
+        let names: Vec<String> = topics.iter().map(|(topic, _)| topic.clone()).collect();
+
+        Ok(Mqtt5Source {
+            options: options(config, &broker)?,
+            topics,
+            requested: Topics::new(&names),
+            qos: qos(config.qos),
+            retained: config.retained,
+            decoder,
+            status,
+        })
+    }
+}
+
+impl DataSource for Mqtt5Source {
+    /// As the 3.1.1 source, reconnecting with exponential backoff and
+    /// disconnecting cleanly on shutdown.
+    fn spawn(&self, tx: Sender, shutdown: Shutdown) -> Result<JoinHandle<()>, Box<dyn Error>> {
+        let (client, mut connection) = Client::new(self.options.clone(), 10);
+        let mut topics = self.topics.clone();
+        let requested = self.requested.clone();
+        let default_qos = self.qos;
+        let retained = self.retained;
+        let mut decoder = self.decoder.clone();
+        let status = self.status.clone();
+
+        let disconnect = client.clone();
+        let stop = shutdown.clone();
+        thread::spawn(move || {
+            stop.wait();
+            disconnect.try_disconnect().ok();
+        });
+
+        Ok(thread::spawn(move || {
+            let _span = info_span!("mqtt5").entered();
+            let mut backoff = BACKOFF_MIN;
+            let mut pending: Vec<(String, QoS)> = Vec::new();
+            let mut queued: VecDeque<(String, QoS)> = VecDeque::new();
+            let mut sent: HashMap<u16, (String, QoS)> = HashMap::new();
+            let mut retry_at = Instant::now();
+            // Set by the broker per connection
+            let mut aliases: HashMap<u16, String> = HashMap::new();
+
+            for notification in connection.iter() {
+                debug!(?notification);
+
+                let event = match notification {
+                    Ok(event) => event,
+                    Err(_) if shutdown.requested() => break,
+                    Err(e) => {
+                        if backoff >= BACKOFF_MAX {
+                            status.set(ConnectionState::Offline);
+                        } else {
+                            status.set(ConnectionState::Reconnecting);
+                        }
+                        warn!("Connection error: {}, retrying in {:?}", e, backoff);
+                        if shutdown.wait_timeout(backoff) {
+                            break;
+                        }
+                        backoff = (backoff * 2).min(BACKOFF_MAX);
+                        continue;
+                    }
+                };
+
+                match event {
+                    Event::Outgoing(Outgoing::Disconnect) => {
+                        info!("Disconnected from broker");
+                        break;
+                    }
+                    Event::Incoming(Packet::ConnAck(_)) => {
+                        backoff = BACKOFF_MIN;
+                        status.set(ConnectionState::Connected);
+                        info!("Connected to broker with MQTT 5");
+
+                        pending.clone_from(&topics);
+                        queued.clear();
+                        sent.clear();
+                        aliases.clear();
+                        retry_at = Instant::now();
+                    }
+                    Event::Outgoing(Outgoing::Subscribe(pkid)) => {
+                        if let Some(topic) = queued.pop_front() {
+                            sent.insert(pkid, topic);
+                        }
+                    }
+                    Event::Incoming(Packet::SubAck(ack)) => {
+                        if let Some((topic, qos)) = sent.remove(&ack.pkid) {
+                            let refused = ack
+                                .return_codes
+                                .iter()
+                                .find(|code| !matches!(code, SubscribeReasonCode::Success(_)));
+                            // Not retried once unsubscribed from meanwhile
+                            let subscribed = topics.iter().any(|(t, _)| *t == topic);
+                            if let Some(code) = refused.filter(|_| subscribed) {
+                                status.report(MonitorError::Subscribe {
+                                    topic: topic.clone(),
+                                    reason: format!("refused by the broker: {:?}", code),
+                                });
+                                pending.push((topic, qos));
+                                retry_at = Instant::now() + SUBSCRIBE_RETRY;
+                            } else if refused.is_some() {
+                                debug!("Refused {}, unsubscribed from meanwhile", topic);
+                            } else {
+                                debug!("Subscribed to {}", topic);
+                            }
+                        }
+                    }
+                    Event::Incoming(Packet::Publish(publish)) => {
+                        match topic(&publish, &mut aliases) {
+                            Some(topic)
+                                if publish.retain && retained == RetainedMessages::Ignore =>
+                            {
+                                debug!("Ignoring the retained message on {}", topic);
+                            }
+                            Some(topic) => {
+                                let mark = publish.retain && retained == RetainedMessages::Mark;
+                                receive(&publish, topic, mark, &mut decoder, &status, &tx);
+                            }
+                            None => {
+                                status.drop_message();
+                                warn!("Message with an unknown topic alias");
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+
+                for change in requested.take_changes() {
+                    match change {
+                        TopicChange::Subscribe(topic) => {
+                            info!("Subscribing to {}", topic);
+                            topics.push((topic.clone(), default_qos));
+                            pending.push((topic, default_qos));
+                            retry_at = Instant::now();
+                        }
+                        TopicChange::Unsubscribe(topic) => {
+                            info!("Unsubscribing from {}", topic);
+                            topics.retain(|(t, _)| *t != topic);
+                            pending.retain(|(t, _)| *t != topic);
+                            if let Err(e) = client.try_unsubscribe(topic.as_str()) {
+                                status.report(MonitorError::Unsubscribe {
+                                    topic,
+                                    reason: e.to_string(),
+                                });
+                            }
+                        }
+                    }
+                }
+
+                if !pending.is_empty() && Instant::now() >= retry_at {
+                    pending.retain(|(topic, qos)| {
+                        match client.try_subscribe(topic.as_str(), *qos) {
+                            Ok(()) => {
+                                queued.push_back((topic.clone(), *qos));
+                                false
+                            }
+                            Err(e) => {
+                                status.report(MonitorError::Subscribe {
+                                    topic: topic.clone(),
+                                    reason: e.to_string(),
+                                });
+                                true
+                            }
+                        }
+                    });
+                    retry_at = Instant::now() + SUBSCRIBE_RETRY;
+                }
+            }
+
+            status.set(ConnectionState::Offline);
+        }))
+    }
+
+    fn topics(&self) -> Option<Topics> {
+        Some(self.requested.clone())
+    }
+}
+
+/// The topic of `publish`, looked up by its alias if left empty.
+fn topic(publish: &Publish, aliases: &mut HashMap<u16, String>) -> Option<String> {
+    let topic = String::from_utf8_lossy(&publish.topic).into_owned();
+    let alias = publish.properties.as_ref().and_then(|p| p.topic_alias);
+    match alias {
+        Some(alias) if topic.is_empty() => aliases.get(&alias).cloned(),
+        Some(alias) => {
+            aliases.insert(alias, topic.clone());
+            Some(topic)
+        }
+        None => Some(topic),
+    }
+}
+
+/// Decodes `publish` in the unit its properties give, and sends it on,
+/// `retained` to be marked.
+fn receive(
+    publish: &Publish,
+    topic: String,
+    retained: bool,
+    decoder: &mut Decoder,
+    status: &Status,
+    tx: &Sender,
+) {
+    let properties = publish.properties.as_ref();
+    let unit = properties
+        .and_then(|p| {
+            p.user_properties
+                .iter()
+                .find(|(key, _)| key == UNIT_PROPERTY)
+        })
+        .map(|(_, unit)| unit.parse::<PressureUnit>())
+        .transpose();
+    let readings = unit
+        .map_err(Box::<dyn Error>::from)
+        .and_then(|unit| decoder.decode_all(&topic, &publish.payload, unit));
+
+    match readings {
+        Ok(readings) => {
+            let expires = properties
+                .and_then(|p| p.message_expiry_interval)
+                .map(|seconds| SystemTime::now() + Duration::from_secs(seconds.into()));
+            for (series, reading) in readings {
+                // Unless rejected by the sanity filter
+                if !accept(decoder, status, &series, reading.value) {
+                    continue;
+                }
+                let sample = Sample {
+                    topic: series,
+                    value: reading.value,
+                    timestamp: reading.timestamp,
+                    sequence: reading.sequence,
+                    sensor: None,
+                    expires,
+                    retained,
+                };
+                tx.send(sample).ok();
+            }
+        }
+        Err(e) => {
+            status.drop_message();
+            warn!("Bad payload on {}: {}", topic, e);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Several sources at once, e.g. two brokers and a serial port. Their
+//! samples are tagged with the sensor name of their source and merged into
+//! one stream, so each becomes a series of its own.
+
+use super::channel::Sender;
+use super::{DataSource, Shutdown, Status};
+use std::error::Error;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::info;
+
+/// How often the states of the sources are summed up into the shared one.
+const STATUS_INTERVAL: Duration = Duration::from_millis(500);
+
+pub struct MultiSource {
+    /// Sensor name, source and the status only it reports to
+    sources: Vec<(String, Box<dyn DataSource>, Status)>,
+    status: Status,
+}
+
+impl MultiSource {
+    /// `status` is shown for all of them, see `Status::merge`.
+    pub fn new(status: Status) -> MultiSource {
+        MultiSource {
+            sources: Vec::new(),
+            status,
+        }
+    }
+
+    /// Adds a source built with `status`, which no other source may use.
+    pub fn add(&mut self, sensor: &str, source: Box<dyn DataSource>, status: Status) {
+        self.sources.push((sensor.to_string(), source, status));
+    }
+}
+
+impl DataSource for MultiSource {
+    /// Starts every source, each sending with its sensor name. The returned
+    /// thread ends once all of them have.
+    fn spawn(&self, tx: Sender, shutdown: Shutdown) -> Result<JoinHandle<()>, Box<dyn Error>> {
+        let mut threads = Vec::new();
+        for (sensor, source, _) in &self.sources {
+            threads.push(
+                source
+                    .spawn(tx.tagged(sensor), shutdown.clone())
+                    .map_err(|e| format!("Cannot start {}: {}", sensor, e))?,
+            );
+        }
+
+        let sensors: Vec<(String, Status)> = self
+            .sources
+            .iter()
+            .map(|(sensor, _, status)| (sensor.clone(), status.clone()))
+            .collect();
+        let parts: Vec<Status> = sensors.iter().map(|(_, s)| s.clone()).collect();
+        let status = self.status.clone();
+        Ok(thread::spawn(move || {
+            let mut states: Vec<_> = sensors.iter().map(|(_, s)| s.get()).collect();
+            loop {
+                let stopping = shutdown.wait_timeout(STATUS_INTERVAL);
+                for ((sensor, part), state) in sensors.iter().zip(&mut states) {
+                    if part.get() != *state {
+                        *state = part.get();
+                        info!("{} {}", sensor, state.label());
+                    }
+                }
+                status.merge(&parts);
+                if stopping || threads.iter().all(JoinHandle::is_finished) {
+                    break;
+                }
+            }
+            for thread in threads {
+                thread.join().ok();
+            }
+        }))
+    }
+}
@@ -0,0 +1,177 @@
+//! Plays back a CSV written by this tool, either the `--log-file` format or
+//! a `s` snapshot, through the same channel the MQTT reader uses.
+
+use super::{channel::Sender, ConnectionState, DataSource, Sample, Shutdown, Status};
+use crate::units::PressureUnit;
+use chrono::DateTime;
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, info_span};
+
+/// Playback rate, `1x` is real time.
+#[derive(Debug, Clone, Copy)]
+pub struct Speed(pub f64);
+
+impl FromStr for Speed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.strip_suffix(['x', 'X']).unwrap_or(s);
+        match value.parse::<f64>() {
+            Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(Speed(speed)),
+            _ => Err(format!("Invalid speed {}, expected e.g. 1x or 10x", s)),
+        }
+    }
+}
+
+/// A sample as read back from a CSV.
+/// First column of the snapshots, before their relative time.
+pub const WALL_CLOCK: &str = "Time(RFC 3339)";
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub ts: SystemTime,
+    pub topic: String,
+    /// In Pa
+    pub value: f64,
+}
+
+/// The samples of a log or snapshot, oldest first. Older snapshots have no
+/// wall clock time, theirs start now.
+pub fn load(path: &Path) -> Result<Vec<Record>, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        // Snapshots note the tare above the header
+        .comment(Some(b'#'))
+        .from_path(path)
+        .map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+    let header = reader.headers()?.clone();
+
+    let mut records = Vec::new();
+    match header.get(0) {
+        // Continuous log: absolute time, topic, Pa
+        Some("Time(unix s)") => {
+            for row in reader.records() {
+                let row = row?;
+                let secs: f64 = row.get(0).unwrap_or_default().parse()?;
+                records.push(Record {
+                    ts: UNIX_EPOCH + Duration::from_secs_f64(secs.max(0.0)),
+                    topic: row.get(1).unwrap_or_default().to_string(),
+                    value: row.get(2).unwrap_or_default().parse()?,
+                });
+            }
+        }
+        // Snapshot: wall clock and relative time, only the latter in older
+        // ones, then one `topic (unit)` column per series and the markers
+        Some(first @ (WALL_CLOCK | "Time(s)")) => {
+            let wall_clock = first == WALL_CLOCK;
+            let skip = if wall_clock { 2 } else { 1 };
+            let columns: Vec<_> = header.iter().skip(skip).map(parse_column).collect();
+            let base = SystemTime::now();
+
+            for row in reader.records() {
+                let row = row?;
+                let time = row.get(0).unwrap_or_default();
+                let ts = if wall_clock {
+                    DateTime::parse_from_rfc3339(time)?.into()
+                } else {
+                    base + Duration::from_secs_f64(time.parse::<f64>()?.max(0.0))
+                };
+                for (column, field) in columns.iter().zip(row.iter().skip(skip)) {
+                    let (topic, unit) = match column {
+                        Some(column) if !field.is_empty() => column,
+                        _ => continue,
+                    };
+                    let value: f64 = field.parse()?;
+                    records.push(Record {
+                        ts,
+                        topic: topic.clone(),
+                        value: unit.map_or(value, |unit| unit.to_pa(value)),
+                    });
+                }
+            }
+        }
+        _ => return Err(format!("{} is not a pressure_monitor CSV", path.display()).into()),
+    }
+
+    records.sort_by_key(|r| r.ts);
+    Ok(records)
+}
+
+/// Splits `pressure/data (kPa)` into the topic and its unit, `None` for
+/// columns without one such as the markers and the filtered values. Values
+/// of other units, e.g. a temperature, are played back as they are.
+fn parse_column(name: &str) -> Option<(String, Option<PressureUnit>)> {
+    let (topic, unit) = name.rsplit_once('(')?;
+    let unit = unit.trim_end_matches(')').parse::<PressureUnit>().ok();
+    Some((topic.trim().to_string(), unit))
+}
+
+pub struct ReplaySource {
+    records: Vec<Record>,
+    speed: Speed,
+    status: Status,
+}
+
+impl ReplaySource {
+    /// Reads the whole file up front so format errors show before the window
+    /// opens.
+    pub fn open(path: &Path, speed: Speed, status: Status) -> Result<ReplaySource, Box<dyn Error>> {
+        let records = load(path)?;
+        if records.is_empty() {
+            return Err(format!("No samples in {}", path.display()).into());
+        }
+
+        Ok(ReplaySource {
+            records,
+            speed,
+            status,
+        })
+    }
+}
+
+impl DataSource for ReplaySource {
+    /// Sends the samples keeping their original spacing divided by the speed.
+    fn spawn(&self, tx: Sender, shutdown: Shutdown) -> Result<JoinHandle<()>, Box<dyn Error>> {
+        let records = self.records.clone();
+        let speed = self.speed;
+        let status = self.status.clone();
+
+        Ok(thread::spawn(move || {
+            let _span = info_span!("replay").entered();
+            status.set(ConnectionState::Connected);
+            info!("Replaying {} samples at {}x", records.len(), speed.0);
+
+            let start = Instant::now();
+            let first_ts = records[0].ts;
+
+            for record in records {
+                let offset = record.ts.duration_since(first_ts).unwrap_or_default();
+                let due = start + offset.div_f64(speed.0);
+                let wait = due.saturating_duration_since(Instant::now());
+                if shutdown.wait_timeout(wait) {
+                    break;
+                }
+
+                let sample = Sample {
+                    topic: record.topic,
+                    value: record.value,
+                    timestamp: Some(record.ts),
+                    sequence: None,
+                    sensor: None,
+                    expires: None,
+                    retained: false,
+                };
+                if tx.send(sample).is_err() {
+                    break;
+                }
+            }
+
+            info!("Replay finished");
+            status.set(ConnectionState::Offline);
+        }))
+    }
+}
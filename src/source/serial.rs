@@ -0,0 +1,176 @@
+//! Serial port source, for transducers on an RS-485/USB adapter.
+//!
+//! The port delivers either text lines holding a number (or anything the
+//! payload decoder understands, such as JSON), or fixed size binary frames
+//! decoded with the payload format.
+
+use super::{
+    accept, channel::Sender, ConnectionState, DataSource, Sample, Shutdown, Status, BACKOFF_MAX,
+    BACKOFF_MIN,
+};
+use crate::config::{Framing, SerialConfig};
+use crate::decode::{Decoder, Reading};
+use std::error::Error;
+use std::io::{self, Read};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{info, info_span, warn};
+
+#[derive(Debug, Clone)]
+pub struct SerialSource {
+    path: String,
+    baud: u32,
+    framing: Framing,
+    frame_len: usize,
+    topic: String,
+    decoder: Decoder,
+    status: Status,
+}
+
+impl SerialSource {
+    pub fn new(config: &SerialConfig, decoder: Decoder, status: Status) -> SerialSource {
+        SerialSource {
+            path: config.port.clone(),
+            baud: config.baud,
+            framing: config.framing,
+            frame_len: config.frame_len.max(1),
+            topic: config.topic.clone(),
+            decoder,
+            status,
+        }
+    }
+
+    fn run(&self, tx: Sender, shutdown: Shutdown) {
+        let _span = info_span!("serial", port = %self.path).entered();
+        let mut backoff = BACKOFF_MIN;
+        // Its sanity filter keeps the recent readings across reconnects
+        let mut decoder = self.decoder.clone();
+
+        loop {
+            match self.read_port(&mut decoder, &tx, &shutdown, &mut backoff) {
+                // Shut down, or the receiver is gone
+                Ok(()) => break,
+                Err(e) => {
+                    if backoff >= BACKOFF_MAX {
+                        self.status.set(ConnectionState::Offline);
+                    } else {
+                        self.status.set(ConnectionState::Reconnecting);
+                    }
+                    warn!("{}, retrying in {:?}", e, backoff);
+                    if shutdown.wait_timeout(backoff) {
+                        break;
+                    }
+                    backoff = (backoff * 2).min(BACKOFF_MAX);
+                }
+            }
+        }
+
+        self.status.set(ConnectionState::Offline);
+    }
+
+    /// Reads until the port fails, or returns `Ok` on shutdown or once
+    /// nobody listens.
+    fn read_port(
+        &self,
+        decoder: &mut Decoder,
+        tx: &Sender,
+        shutdown: &Shutdown,
+        backoff: &mut Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut port = serialport::new(&self.path, self.baud)
+            .timeout(Duration::from_secs(1))
+            .open()?;
+        self.status.set(ConnectionState::Connected);
+        info!("Port opened at {} baud", self.baud);
+        *backoff = BACKOFF_MIN;
+
+        let mut pending = Vec::new();
+        let mut chunk = [0u8; 256];
+
+        // Reads time out every second to look at the shutdown flag
+        loop {
+            if shutdown.requested() {
+                return Ok(());
+            }
+            let n = match port.read(&mut chunk) {
+                Ok(0) => return Err("port closed".into()),
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                Err(e) => return Err(e.into()),
+            };
+            pending.extend_from_slice(&chunk[..n]);
+
+            while let Some(frame) = self.next_frame(&mut pending) {
+                if frame.is_empty() {
+                    continue;
+                }
+                match self.decode(decoder, &frame) {
+                    Ok(sample) if accept(decoder, &self.status, &sample.topic, sample.value) => {
+                        if tx.send(sample).is_err() {
+                            return Ok(());
+                        }
+                    }
+                    // Rejected by the sanity filter
+                    Ok(_) => {}
+                    Err(e) => {
+                        self.status.drop_message();
+                        warn!("Bad data: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    fn next_frame(&self, pending: &mut Vec<u8>) -> Option<Vec<u8>> {
+        match self.framing {
+            Framing::Line => {
+                let end = pending.iter().position(|&b| b == b'\n')?;
+                let mut line: Vec<u8> = pending.drain(..=end).collect();
+                while line.last().is_some_and(|b| b.is_ascii_whitespace()) {
+                    line.pop();
+                }
+                Some(line)
+            }
+            Framing::Frame if pending.len() >= self.frame_len => {
+                Some(pending.drain(..self.frame_len).collect())
+            }
+            Framing::Frame => None,
+        }
+    }
+
+    fn decode(&self, decoder: &Decoder, frame: &[u8]) -> Result<Sample, Box<dyn Error>> {
+        // Plain numbers are the common case for line based transmitters
+        let number = match self.framing {
+            Framing::Line => std::str::from_utf8(frame)
+                .ok()
+                .and_then(|text| text.trim().parse::<f64>().ok()),
+            Framing::Frame => None,
+        };
+
+        let reading = match number {
+            Some(value) => Reading {
+                value,
+                timestamp: None,
+                sequence: None,
+            },
+            None => decoder.decode(&self.topic, frame)?,
+        };
+
+        Ok(Sample {
+            topic: self.topic.clone(),
+            value: reading.value,
+            timestamp: reading.timestamp,
+            sequence: reading.sequence,
+            sensor: None,
+            expires: None,
+            retained: false,
+        })
+    }
+}
+
+impl DataSource for SerialSource {
+    fn spawn(&self, tx: Sender, shutdown: Shutdown) -> Result<JoinHandle<()>, Box<dyn Error>> {
+        let source = self.clone();
+        Ok(thread::spawn(move || source.run(tx, shutdown)))
+    }
+}
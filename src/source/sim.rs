@@ -0,0 +1,93 @@
+//! Synthetic waveform, for developing and demoing without a broker or
+//! sensor: a sine around an offset, plus noise and an occasional step.
+
+use super::{channel::Sender, ConnectionState, DataSource, Sample, Shutdown, Status};
+use crate::config::SimConfig;
+use rand::Rng;
+use std::error::Error;
+use std::f64::consts::TAU;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::{info, info_span};
+
+#[derive(Debug, Clone)]
+pub struct SimSource {
+    config: SimConfig,
+    status: Status,
+}
+
+impl SimSource {
+    pub fn new(config: &SimConfig, status: Status) -> SimSource {
+        SimSource {
+            config: config.clone(),
+            status,
+        }
+    }
+}
+
+impl DataSource for SimSource {
+    fn spawn(&self, tx: Sender, shutdown: Shutdown) -> Result<JoinHandle<()>, Box<dyn Error>> {
+        let config = self.config.clone();
+        let status = self.status.clone();
+        if !config.rate.is_finite() || config.rate <= 0.0 {
+            return Err(format!(
+                "Invalid sim rate {}, expected samples per second",
+                config.rate
+            )
+            .into());
+        }
+
+        Ok(thread::spawn(move || {
+            let _span = info_span!("sim").entered();
+            status.set(ConnectionState::Connected);
+            info!("Generating {} samples a second", config.rate);
+
+            let mut rng = rand::thread_rng();
+            let interval = Duration::from_secs_f64(1.0 / config.rate);
+            let start = Instant::now();
+            let mut step = 0.0;
+
+            for tick in 0u64.. {
+                let t = tick as f64 / config.rate;
+                // On average one step every `step_interval` seconds, toggling
+                // between the base line and `step_size` above it
+                if config.step_interval > 0.0
+                    && rng.gen::<f64>() < 1.0 / (config.rate * config.step_interval)
+                {
+                    step = if step == 0.0 { config.step_size } else { 0.0 };
+                }
+
+                let wave = match config.period {
+                    period if period > 0.0 => config.amplitude * (TAU * t / period).sin(),
+                    _ => 0.0,
+                };
+                let sample = Sample {
+                    topic: config.topic.clone(),
+                    value: config.offset + wave + step + config.noise * gaussian(&mut rng),
+                    timestamp: None,
+                    sequence: None,
+                    sensor: None,
+                    expires: None,
+                    retained: false,
+                };
+                if tx.send(sample).is_err() {
+                    break;
+                }
+
+                let due = start + interval.mul_f64((tick + 1) as f64);
+                if shutdown.wait_timeout(due.saturating_duration_since(Instant::now())) {
+                    break;
+                }
+            }
+
+            status.set(ConnectionState::Offline);
+        }))
+    }
+}
+
+/// Standard normal noise, Box-Muller.
+fn gaussian(rng: &mut impl Rng) -> f64 {
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (TAU * u2).cos()
+}
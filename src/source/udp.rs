@@ -0,0 +1,108 @@
+//! Datagrams from sensors streaming on the LAN without a broker, one
+//! reading per datagram in any of the payload formats.
+
+use super::{accept, channel::Sender, ConnectionState, DataSource, Sample, Shutdown, Status};
+use crate::config::UdpConfig;
+use crate::decode::Decoder;
+use std::error::Error;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{info, info_span, warn};
+
+/// Larger than any reading, datagrams are cut off beyond it.
+const MAX_DATAGRAM: usize = 2048;
+
+#[derive(Debug, Clone)]
+pub struct UdpSource {
+    config: UdpConfig,
+    decoder: Decoder,
+    status: Status,
+}
+
+impl UdpSource {
+    pub fn new(config: &UdpConfig, decoder: Decoder, status: Status) -> UdpSource {
+        UdpSource {
+            config: config.clone(),
+            decoder,
+            status,
+        }
+    }
+
+    /// The series of a datagram from `sender`.
+    fn topic(&self, sender: SocketAddr) -> String {
+        if self.config.per_sender {
+            format!("{}/{}", self.config.topic, sender.ip())
+        } else {
+            self.config.topic.clone()
+        }
+    }
+}
+
+impl DataSource for UdpSource {
+    /// Binds right away, so an address in use fails at startup.
+    fn spawn(&self, tx: Sender, shutdown: Shutdown) -> Result<JoinHandle<()>, Box<dyn Error>> {
+        let socket = UdpSocket::bind(&self.config.listen)
+            .map_err(|e| format!("Cannot listen on {}: {}", self.config.listen, e))?;
+        // Receives time out every second to look at the shutdown flag
+        socket.set_read_timeout(Some(Duration::from_secs(1)))?;
+        let source = self.clone();
+
+        Ok(thread::spawn(move || {
+            let _span = info_span!("udp", listen = %source.config.listen).entered();
+            info!("Listening for datagrams");
+            // There is no connection, listening is as connected as it gets
+            source.status.set(ConnectionState::Connected);
+            let mut decoder = source.decoder.clone();
+            let mut datagram = [0u8; MAX_DATAGRAM];
+
+            while !shutdown.requested() {
+                let (n, sender) = match socket.recv_from(&mut datagram) {
+                    Ok(received) => received,
+                    Err(e)
+                        if matches!(
+                            e.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                        ) =>
+                    {
+                        continue
+                    }
+                    Err(e) => {
+                        warn!("Receive failed: {}", e);
+                        if shutdown.wait_timeout(Duration::from_secs(1)) {
+                            break;
+                        }
+                        continue;
+                    }
+                };
+
+                let topic = source.topic(sender);
+                match decoder.decode(&topic, &datagram[..n]) {
+                    Ok(reading) if accept(&mut decoder, &source.status, &topic, reading.value) => {
+                        let sample = Sample {
+                            topic,
+                            value: reading.value,
+                            timestamp: reading.timestamp,
+                            sequence: reading.sequence,
+                            sensor: None,
+                            expires: None,
+                            retained: false,
+                        };
+                        if tx.send(sample).is_err() {
+                            break;
+                        }
+                    }
+                    // Rejected by the sanity filter
+                    Ok(_) => {}
+                    Err(e) => {
+                        source.status.drop_message();
+                        warn!("Bad datagram from {}: {}", sender, e);
+                    }
+                }
+            }
+
+            source.status.set(ConnectionState::Offline);
+        }))
+    }
+}
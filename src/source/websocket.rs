@@ -0,0 +1,194 @@
+//! WebSocket source, for gateways that stream readings over `ws://` or
+//! `wss://` instead of MQTT. Text and binary messages are decoded with
+//! the payload formats, one reading per message.
+
+use super::mqtt::tls_config;
+use super::{
+    accept, channel::Sender, ConnectionState, DataSource, Sample, Shutdown, Status, BACKOFF_MAX,
+    BACKOFF_MIN,
+};
+use crate::config::WebSocketConfig;
+use crate::decode::Decoder;
+use rustls::{ClientSession, StreamOwned};
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{info, info_span, warn};
+use tungstenite::http::Uri;
+use tungstenite::{Message, WebSocket};
+
+/// Plain or TLS, whichever the URL asks for.
+trait Stream: Read + Write {}
+
+impl<S: Read + Write> Stream for S {}
+
+#[derive(Debug, Clone)]
+pub struct WebSocketSource {
+    config: WebSocketConfig,
+    decoder: Decoder,
+    status: Status,
+}
+
+impl WebSocketSource {
+    /// Checks the URL, so a typo fails right away.
+    pub fn new(
+        config: &WebSocketConfig,
+        decoder: Decoder,
+        status: Status,
+    ) -> Result<WebSocketSource, Box<dyn Error>> {
+        endpoint(&config.url)?;
+        Ok(WebSocketSource {
+            config: config.clone(),
+            decoder,
+            status,
+        })
+    }
+
+    fn run(&self, tx: Sender, shutdown: Shutdown) {
+        let _span = info_span!("websocket", url = %self.config.url).entered();
+        let mut backoff = BACKOFF_MIN;
+        let mut decoder = self.decoder.clone();
+
+        loop {
+            match self.stream(&mut decoder, &tx, &shutdown, &mut backoff) {
+                // Shut down, or the receiver is gone
+                Ok(()) => break,
+                Err(e) => {
+                    if backoff >= BACKOFF_MAX {
+                        self.status.set(ConnectionState::Offline);
+                    } else {
+                        self.status.set(ConnectionState::Reconnecting);
+                    }
+                    warn!("{}, retrying in {:?}", e, backoff);
+                    if shutdown.wait_timeout(backoff) {
+                        break;
+                    }
+                    backoff = (backoff * 2).min(BACKOFF_MAX);
+                }
+            }
+        }
+
+        self.status.set(ConnectionState::Offline);
+    }
+
+    /// Receives until the connection fails, or returns `Ok` on shutdown or
+    /// once nobody listens.
+    fn stream(
+        &self,
+        decoder: &mut Decoder,
+        tx: &Sender,
+        shutdown: &Shutdown,
+        backoff: &mut Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut socket = self.connect()?;
+        self.status.set(ConnectionState::Connected);
+        info!("Connected");
+        *backoff = BACKOFF_MIN;
+        if let Some(subscribe) = &self.config.subscribe {
+            socket.write_message(Message::Text(subscribe.clone()))?;
+        }
+
+        let topic = &self.config.topic;
+        loop {
+            if shutdown.requested() {
+                socket.close(None).ok();
+                return Ok(());
+            }
+            let payload = match socket.read_message() {
+                Ok(Message::Text(text)) => text.into_bytes(),
+                Ok(Message::Binary(data)) => data,
+                Ok(Message::Close(_)) => return Err("Closed by the server".into()),
+                // Pings are answered by the next read
+                Ok(_) => continue,
+                Err(tungstenite::Error::Io(e))
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            match decoder.decode(topic, &payload) {
+                Ok(reading) if accept(decoder, &self.status, topic, reading.value) => {
+                    let sample = Sample {
+                        topic: topic.clone(),
+                        value: reading.value,
+                        timestamp: reading.timestamp,
+                        sequence: reading.sequence,
+                        sensor: None,
+                        expires: None,
+                        retained: false,
+                    };
+                    if tx.send(sample).is_err() {
+                        socket.close(None).ok();
+                        return Ok(());
+                    }
+                }
+                // Rejected by the sanity filter
+                Ok(_) => {}
+                Err(e) => {
+                    self.status.drop_message();
+                    warn!("Bad message: {}", e);
+                }
+            }
+        }
+    }
+
+    fn connect(&self) -> Result<WebSocket<Box<dyn Stream + Send>>, Box<dyn Error>> {
+        let (host, port, tls) = endpoint(&self.config.url)?;
+        let addr = (host.as_str(), port)
+            .to_socket_addrs()
+            .map_err(|e| format!("Cannot resolve {}: {}", host, e))?
+            .next()
+            .ok_or_else(|| format!("{} has no addresses", host))?;
+        let tcp = TcpStream::connect_timeout(&addr, Duration::from_secs(5))?;
+        // Reads time out every second to look at the shutdown flag
+        tcp.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+        let stream: Box<dyn Stream + Send> = if tls {
+            let config = Arc::new(tls_config(&self.config.tls)?);
+            let name = webpki::DNSNameRef::try_from_ascii_str(&host)
+                .map_err(|_| format!("Invalid host name {} for TLS", host))?;
+            Box::new(StreamOwned::new(ClientSession::new(&config, name), tcp))
+        } else {
+            Box::new(tcp)
+        };
+
+        let (socket, _) = tungstenite::client(self.config.url.as_str(), stream)
+            .map_err(|e| format!("WebSocket handshake failed: {}", e))?;
+        Ok(socket)
+    }
+}
+
+impl DataSource for WebSocketSource {
+    fn spawn(&self, tx: Sender, shutdown: Shutdown) -> Result<JoinHandle<()>, Box<dyn Error>> {
+        let source = self.clone();
+        Ok(thread::spawn(move || source.run(tx, shutdown)))
+    }
+}
+
+/// Host, port and whether to use TLS of a `ws://` or `wss://` URL.
+fn endpoint(url: &str) -> Result<(String, u16, bool), Box<dyn Error>> {
+    let uri: Uri = url
+        .parse()
+        .map_err(|e| format!("Invalid WebSocket URL {}: {}", url, e))?;
+    let tls = match uri.scheme_str() {
+        Some("ws") => false,
+        Some("wss") => true,
+        _ => return Err(format!("Expected a ws:// or wss:// URL, got {}", url).into()),
+    };
+    let host = uri
+        .host()
+        .ok_or_else(|| format!("Missing host in WebSocket URL {}", url))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+    Ok((host, port, tls))
+}
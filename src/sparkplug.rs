@@ -0,0 +1,386 @@
+//! Sparkplug B payloads, the protobuf messages of `spBv1.0/...` topics:
+//!
+//! ```text
+//! spBv1.0/<group>/<NBIRTH|NDATA|NDEATH|DBIRTH|DDATA|DDEATH>/<edge node>[/<device>]
+//! ```
+//!
+//! Births name every metric and give the alias the data messages may send
+//! instead. Each numeric metric becomes a series, named as configured in
+//! `[payload.sparkplug.metrics]`, or `<edge node>[/<device>]/<metric>` when
+//! none are.
+
+use crate::config::SparkplugConfig;
+use crate::decode::epoch_time;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::time::SystemTime;
+
+/// A metric value with the series it is drawn in.
+#[derive(Debug, Clone)]
+pub struct Value {
+    pub series: String,
+    pub value: f64,
+    pub timestamp: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Sparkplug {
+    /// Metric name to series, all metrics are taken when empty
+    metrics: BTreeMap<String, String>,
+    /// Metric names by alias, per edge node or device as of its birth
+    aliases: HashMap<String, HashMap<u64, String>>,
+}
+
+impl Sparkplug {
+    pub fn new(config: &SparkplugConfig) -> Sparkplug {
+        Sparkplug {
+            metrics: config.metrics.clone(),
+            aliases: HashMap::new(),
+        }
+    }
+
+    /// The values of the numeric metrics in the message, none for messages
+    /// without any, e.g. deaths and host states.
+    pub fn decode(&mut self, topic: &str, payload: &[u8]) -> Result<Vec<Value>, Box<dyn Error>> {
+        let levels: Vec<&str> = topic.split('/').collect();
+        let (kind, node) = match levels.as_slice() {
+            ["spBv1.0", "STATE", ..] => return Ok(Vec::new()),
+            ["spBv1.0", _, kind, edge] => (*kind, edge.to_string()),
+            ["spBv1.0", _, kind, edge, device] => (*kind, format!("{}/{}", edge, device)),
+            _ => return Err(format!("{} is not a Sparkplug B topic", topic).into()),
+        };
+        let aliases = self.aliases.entry(node.clone()).or_default();
+        match kind {
+            "NBIRTH" | "DBIRTH" => aliases.clear(),
+            "NDATA" | "DDATA" => {}
+            "NDEATH" | "DDEATH" => {
+                aliases.clear();
+                return Ok(Vec::new());
+            }
+            // Commands to the node
+            "NCMD" | "DCMD" => return Ok(Vec::new()),
+            _ => return Err(format!("Unknown Sparkplug B message type {}", kind).into()),
+        }
+
+        let payload = Payload::parse(payload)?;
+        let mut values = Vec::new();
+        for metric in payload.metrics {
+            let name = match (metric.name, metric.alias) {
+                (Some(name), Some(alias)) => {
+                    aliases.insert(alias, name.clone());
+                    name
+                }
+                (Some(name), None) => name,
+                (None, Some(alias)) => match aliases.get(&alias) {
+                    Some(name) => name.clone(),
+                    None => {
+                        return Err(
+                            format!("Metric alias {} of {} before its birth", alias, node).into(),
+                        )
+                    }
+                },
+                (None, None) => continue,
+            };
+            let series = if self.metrics.is_empty() {
+                format!("{}/{}", node, name)
+            } else {
+                match self.metrics.get(&name) {
+                    Some(series) => series.clone(),
+                    None => continue,
+                }
+            };
+            if let Some(value) = metric.value {
+                let timestamp = metric.timestamp.or(payload.timestamp);
+                values.push(Value {
+                    series,
+                    value,
+                    timestamp: timestamp.and_then(|ms| epoch_time(ms as f64 / 1000.0)),
+                });
+            }
+        }
+        Ok(values)
+    }
+}
+
+/// The fields of a Sparkplug B payload read here.
+#[derive(Debug, Default)]
+struct Payload {
+    /// Milliseconds since the epoch
+    timestamp: Option<u64>,
+    metrics: Vec<Metric>,
+}
+
+#[derive(Debug, Default)]
+struct Metric {
+    name: Option<String>,
+    alias: Option<u64>,
+    timestamp: Option<u64>,
+    datatype: u32,
+    /// Of the numeric and boolean types, `None` when null
+    value: Option<f64>,
+}
+
+impl Payload {
+    fn parse(bytes: &[u8]) -> Result<Payload, Box<dyn Error>> {
+        let mut payload = Payload::default();
+        let mut reader = Reader(bytes);
+        while let Some((field, value)) = reader.field()? {
+            match (field, value) {
+                (1, Field::Varint(ts)) => payload.timestamp = Some(ts),
+                (2, Field::Bytes(metric)) => payload.metrics.push(Metric::parse(metric)?),
+                _ => {}
+            }
+        }
+        Ok(payload)
+    }
+}
+
+impl Metric {
+    fn parse(bytes: &[u8]) -> Result<Metric, Box<dyn Error>> {
+        let mut metric = Metric::default();
+        // The value field, read once the data type is known
+        let mut raw = None;
+        let mut null = false;
+        let mut reader = Reader(bytes);
+        while let Some((field, value)) = reader.field()? {
+            match (field, value) {
+                (1, Field::Bytes(name)) => {
+                    metric.name = Some(String::from_utf8_lossy(name).into_owned());
+                }
+                (2, Field::Varint(alias)) => metric.alias = Some(alias),
+                (3, Field::Varint(ts)) => metric.timestamp = Some(ts),
+                (4, Field::Varint(datatype)) => metric.datatype = datatype as u32,
+                (7, Field::Varint(is_null)) => null = is_null != 0,
+                (10..=14, value) => raw = Some((field, value)),
+                _ => {}
+            }
+        }
+
+        metric.value = match raw.filter(|_| !null) {
+            Some((10, Field::Varint(v))) => Some(match metric.datatype {
+                // Int8, Int16 and Int32, two's complement in 32 bits
+                1..=3 => v as u32 as i32 as f64,
+                _ => v as u32 as f64,
+            }),
+            Some((11, Field::Varint(v))) => Some(match metric.datatype {
+                // Int64, likewise in 64 bits
+                4 => v as i64 as f64,
+                _ => v as f64,
+            }),
+            Some((12, Field::Fixed32(v))) => Some(f32::from_bits(v) as f64),
+            Some((13, Field::Fixed64(v))) => Some(f64::from_bits(v)),
+            Some((14, Field::Varint(v))) => Some(if v != 0 { 1.0 } else { 0.0 }),
+            _ => None,
+        };
+        Ok(metric)
+    }
+}
+
+/// A protobuf field value by wire type.
+#[derive(Debug, Clone, Copy)]
+enum Field<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+/// Reads protobuf fields off the front of a message.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    /// The next field number and value, `None` at the end.
+    fn field(&mut self) -> Result<Option<(u64, Field<'a>)>, Box<dyn Error>> {
+        if self.0.is_empty() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let value = match key & 7 {
+            0 => Field::Varint(self.varint()?),
+            1 => Field::Fixed64(u64::from_le_bytes(self.take(8)?.try_into()?)),
+            2 => {
+                let len = self.varint()? as usize;
+                Field::Bytes(self.take(len)?)
+            }
+            5 => Field::Fixed32(u32::from_le_bytes(self.take(4)?.try_into()?)),
+            wire => return Err(format!("Unsupported protobuf wire type {}", wire).into()),
+        };
+        Ok(Some((key >> 3, value)))
+    }
+
+    fn varint(&mut self) -> Result<u64, Box<dyn Error>> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.0.split_first().ok_or("Truncated protobuf varint")?;
+            self.0 = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("Protobuf varint longer than 10 bytes".into())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        if len > self.0.len() {
+            return Err("Truncated protobuf field".into());
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn key(out: &mut Vec<u8>, field: u64, wire: u64) {
+        varint(out, (field << 3) | wire);
+    }
+
+    fn bytes(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+        key(out, field, 2);
+        varint(out, bytes.len() as u64);
+        out.extend_from_slice(bytes);
+    }
+
+    /// A metric of the name and alias given, with a double or int32 value.
+    fn metric(name: Option<&str>, alias: Option<u64>, value: Number) -> Vec<u8> {
+        let mut out = Vec::new();
+        if let Some(name) = name {
+            bytes(&mut out, 1, name.as_bytes());
+        }
+        if let Some(alias) = alias {
+            key(&mut out, 2, 0);
+            varint(&mut out, alias);
+        }
+        match value {
+            Number::Double(v) => {
+                key(&mut out, 4, 0);
+                varint(&mut out, 10);
+                key(&mut out, 13, 1);
+                out.extend_from_slice(&v.to_bits().to_le_bytes());
+            }
+            Number::Int32(v) => {
+                key(&mut out, 4, 0);
+                varint(&mut out, 3);
+                key(&mut out, 10, 0);
+                varint(&mut out, v as u32 as u64);
+            }
+        }
+        out
+    }
+
+    enum Number {
+        Double(f64),
+        Int32(i32),
+    }
+
+    fn payload(timestamp: u64, metrics: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = Vec::new();
+        key(&mut out, 1, 0);
+        varint(&mut out, timestamp);
+        for metric in metrics {
+            bytes(&mut out, 2, metric);
+        }
+        out
+    }
+
+    fn values(values: &[Value]) -> Vec<(&str, f64)> {
+        values
+            .iter()
+            .map(|v| (v.series.as_str(), v.value))
+            .collect()
+    }
+
+    #[test]
+    fn birth_then_data_by_alias() {
+        let mut sparkplug = Sparkplug::default();
+        let birth = payload(
+            1_700_000_000_000,
+            &[
+                metric(Some("Pressure"), Some(1), Number::Double(101_325.0)),
+                metric(Some("Count"), Some(2), Number::Int32(-5)),
+            ],
+        );
+        let decoded = sparkplug
+            .decode("spBv1.0/plant/NBIRTH/edge1", &birth)
+            .unwrap();
+        assert_eq!(
+            values(&decoded),
+            [("edge1/Pressure", 101_325.0), ("edge1/Count", -5.0)]
+        );
+        let at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(decoded[0].timestamp, Some(at));
+
+        let data = payload(0, &[metric(None, Some(1), Number::Double(99_000.0))]);
+        let decoded = sparkplug
+            .decode("spBv1.0/plant/NDATA/edge1", &data)
+            .unwrap();
+        assert_eq!(values(&decoded), [("edge1/Pressure", 99_000.0)]);
+
+        // The aliases are of that node only, and gone with its death
+        assert!(sparkplug
+            .decode("spBv1.0/plant/NDATA/edge2", &data)
+            .is_err());
+        let death = sparkplug.decode("spBv1.0/plant/NDEATH/edge1", &[]).unwrap();
+        assert!(death.is_empty());
+        assert!(sparkplug
+            .decode("spBv1.0/plant/NDATA/edge1", &data)
+            .is_err());
+    }
+
+    #[test]
+    fn configured_metrics_only() {
+        let mut config = SparkplugConfig::default();
+        config
+            .metrics
+            .insert("Pressure".to_string(), "inlet".to_string());
+        let mut sparkplug = Sparkplug::new(&config);
+        let birth = payload(
+            0,
+            &[
+                metric(Some("Pressure"), None, Number::Double(1.0)),
+                metric(Some("Count"), None, Number::Int32(2)),
+            ],
+        );
+        let decoded = sparkplug
+            .decode("spBv1.0/plant/DBIRTH/edge1/dev", &birth)
+            .unwrap();
+        assert_eq!(values(&decoded), [("inlet", 1.0)]);
+    }
+
+    #[test]
+    fn topics() {
+        let mut sparkplug = Sparkplug::default();
+        assert!(sparkplug
+            .decode("spBv1.0/STATE/host", b"ONLINE")
+            .unwrap()
+            .is_empty());
+        assert!(sparkplug
+            .decode("spBv1.0/plant/NCMD/edge1", &[])
+            .unwrap()
+            .is_empty());
+        assert!(sparkplug.decode("pressure/data", &[]).is_err());
+        assert!(sparkplug.decode("spBv1.0/plant/NFOO/edge1", &[]).is_err());
+    }
+
+    #[test]
+    fn truncated_payloads() {
+        let mut birth = payload(0, &[metric(Some("Pressure"), None, Number::Double(1.0))]);
+        birth.truncate(birth.len() - 3);
+        assert!(Sparkplug::default()
+            .decode("spBv1.0/plant/NBIRTH/edge1", &birth)
+            .is_err());
+    }
+}
@@ -0,0 +1,94 @@
+//! Amplitude spectrum of a series, for finding the frequencies of
+//! oscillations such as pump pulsation.
+
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use std::f64::consts::PI;
+
+/// Only the latest this many samples are transformed.
+const MAX_SAMPLES: usize = 8192;
+
+/// Fewer samples don't make a meaningful spectrum.
+const MIN_SAMPLES: usize = 8;
+
+pub struct Spectrum {
+    /// `(frequency in Hz, amplitude)` from 0 Hz up to the Nyquist
+    /// frequency, amplitudes in the unit of the samples
+    pub bins: Vec<(f64, f64)>,
+}
+
+impl Spectrum {
+    /// Of `(t, value)` samples in time order. The FFT needs evenly spaced
+    /// samples, so they are resampled at their median interval first.
+    pub fn of(samples: &[(f64, f64)]) -> Option<Spectrum> {
+        let samples = &samples[samples.len().saturating_sub(MAX_SAMPLES)..];
+        let mut intervals: Vec<f64> = samples
+            .windows(2)
+            .map(|w| w[1].0 - w[0].0)
+            .filter(|&dt| dt > 0.0)
+            .collect();
+        if intervals.len() < MIN_SAMPLES {
+            return None;
+        }
+        let mid = intervals.len() / 2;
+        let (_, &mut dt, _) = intervals.select_nth_unstable_by(mid, |a, b| a.total_cmp(b));
+
+        let start = samples[0].0;
+        let span = samples[samples.len() - 1].0 - start;
+        let n = ((span / dt) as usize + 1).min(MAX_SAMPLES);
+        if n < MIN_SAMPLES {
+            return None;
+        }
+
+        // Linear interpolation onto `start + i * dt`
+        let mut values = Vec::with_capacity(n);
+        let mut j = 0;
+        for i in 0..n {
+            let t = start + i as f64 * dt;
+            while j + 2 < samples.len() && samples[j + 1].0 < t {
+                j += 1;
+            }
+            let ((t0, v0), (t1, v1)) = (samples[j], samples[j + 1]);
+            values.push(match t1 - t0 {
+                d if d > 0.0 => v0 + (v1 - v0) * ((t - t0) / d).clamp(0.0, 1.0),
+                _ => v1,
+            });
+        }
+
+        // Without the mean a large DC bin doesn't dwarf everything, and a
+        // Hann window keeps the leakage of a partial period down
+        let mean = values.iter().sum::<f64>() / n as f64;
+        let window: Vec<f64> = (0..n)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / (n - 1) as f64).cos())
+            .collect();
+        let gain: f64 = window.iter().sum();
+        let mut buffer: Vec<Complex<f64>> = values
+            .iter()
+            .zip(&window)
+            .map(|(v, w)| Complex::new((v - mean) * w, 0.0))
+            .collect();
+        FftPlanner::<f64>::new()
+            .plan_fft_forward(n)
+            .process(&mut buffer);
+
+        let bins = buffer[..n / 2 + 1]
+            .iter()
+            .enumerate()
+            .map(|(k, c)| (k as f64 / (n as f64 * dt), 2.0 * c.norm() / gain))
+            .collect();
+        Some(Spectrum { bins })
+    }
+
+    /// The strongest bin above 0 Hz.
+    pub fn peak(&self) -> Option<(f64, f64)> {
+        self.bins
+            .iter()
+            .skip(1)
+            .copied()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    pub fn max_frequency(&self) -> f64 {
+        self.bins.last().map_or(0.0, |&(f, _)| f)
+    }
+}
@@ -0,0 +1,110 @@
+//! Summary statistics of the samples on screen, and of the intervals of
+//! an export.
+
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    /// The newest value
+    pub current: f64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// Population standard deviation
+    pub std_dev: f64,
+}
+
+impl Stats {
+    /// None when there are no values.
+    pub fn of(values: impl IntoIterator<Item = f64>) -> Option<Stats> {
+        let mut count = 0usize;
+        let (mut current, mut min, mut max) = (0.0, f64::INFINITY, f64::NEG_INFINITY);
+        // Welford's algorithm, stable for the large offsets of absolute pressure
+        let (mut mean, mut m2) = (0.0, 0.0);
+
+        for value in values {
+            count += 1;
+            current = value;
+            min = min.min(value);
+            max = max.max(value);
+            let delta = value - mean;
+            mean += delta / count as f64;
+            m2 += delta * (value - mean);
+        }
+
+        if count == 0 {
+            return None;
+        }
+        Some(Stats {
+            current,
+            min,
+            max,
+            mean,
+            std_dev: (m2 / count as f64).sqrt(),
+        })
+    }
+}
+
+/// The distribution of the values of an interval.
+#[derive(Debug, Clone, Copy)]
+pub struct Summary {
+    pub count: usize,
+    pub mean: f64,
+    /// Population variance
+    pub variance: f64,
+    pub min: f64,
+    pub max: f64,
+    pub p5: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+impl Summary {
+    /// None when there are no values.
+    pub fn of(values: impl IntoIterator<Item = f64>) -> Option<Summary> {
+        let mut sorted: Vec<f64> = values.into_iter().collect();
+        let stats = Stats::of(sorted.iter().copied())?;
+        sorted.sort_by(f64::total_cmp);
+        Some(Summary {
+            count: sorted.len(),
+            mean: stats.mean,
+            variance: stats.std_dev * stats.std_dev,
+            min: stats.min,
+            max: stats.max,
+            p5: percentile(&sorted, 0.05),
+            p50: percentile(&sorted, 0.5),
+            p95: percentile(&sorted, 0.95),
+            p99: percentile(&sorted, 0.99),
+        })
+    }
+
+    pub fn peak_to_peak(&self) -> f64 {
+        self.max - self.min
+    }
+}
+
+/// The `q` quantile of ascending `sorted` values, interpolated between the
+/// two nearest ranks, NaN of none.
+pub fn percentile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let rank = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (below, above) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[below] + (sorted[above] - sorted[below]) * (rank - below as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles() {
+        let sorted = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 0.5), 2.5);
+        assert_eq!(percentile(&sorted, 1.0), 4.0);
+        assert_eq!(percentile(&sorted, 2.0), 4.0);
+        assert_eq!(percentile(&[7.0], 0.95), 7.0);
+        assert!(percentile(&[], 0.5).is_nan());
+    }
+}
@@ -0,0 +1,190 @@
+//! InfluxDB v2 storage over the HTTP write API, in line protocol.
+//!
+//! Samples are handed to a writer thread, so a slow or unreachable server
+//! never stalls the render loop. Failed batches stay buffered, up to a
+//! limit, and are retried with a growing delay.
+
+use super::Store;
+use crate::config::InfluxConfig;
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info_span, warn};
+
+const RETRY_MIN: Duration = Duration::from_secs(1);
+const RETRY_MAX: Duration = Duration::from_secs(60);
+
+/// How long `flush` waits for the last batch on exit.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+enum Message {
+    Line(String),
+    Flush(Sender<()>),
+}
+
+pub struct InfluxStore {
+    tx: Sender<Message>,
+    /// `measurement,tag=value,...` shared by every line
+    prefix: String,
+}
+
+struct Writer {
+    url: String,
+    org: String,
+    bucket: String,
+    token: Option<String>,
+    batch_size: usize,
+    flush_interval: Duration,
+    max_buffer: usize,
+}
+
+impl InfluxStore {
+    /// The token comes from the config or the `INFLUX_TOKEN` variable.
+    pub fn open(url: &str, config: &InfluxConfig) -> InfluxStore {
+        let writer = Writer {
+            url: format!("{}/api/v2/write", url.trim_end_matches('/')),
+            org: config.org.clone(),
+            bucket: config.bucket.clone(),
+            token: config
+                .token
+                .clone()
+                .or_else(|| env::var("INFLUX_TOKEN").ok()),
+            batch_size: config.batch_size.max(1),
+            flush_interval: Duration::from_secs_f64(config.flush_interval),
+            max_buffer: config.max_buffer.max(config.batch_size),
+        };
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || writer.run(rx));
+
+        InfluxStore {
+            tx,
+            prefix: prefix(&config.measurement, &config.tags),
+        }
+    }
+}
+
+impl Store for InfluxStore {
+    fn write(&mut self, ts: SystemTime, topic: &str, value: f64) -> Result<(), Box<dyn Error>> {
+        let nanos = ts.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        let line = format!(
+            "{},topic={} value={} {}",
+            self.prefix,
+            escape_tag(topic),
+            value,
+            nanos
+        );
+        self.tx
+            .send(Message::Line(line))
+            .map_err(|_| "InfluxDB writer stopped")?;
+        Ok(())
+    }
+
+    /// Waits a little for the buffered samples to be written.
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        let (ack, done) = mpsc::channel();
+        self.tx
+            .send(Message::Flush(ack))
+            .map_err(|_| "InfluxDB writer stopped")?;
+        done.recv_timeout(FLUSH_TIMEOUT)
+            .map_err(|_| "Timed out writing to InfluxDB")?;
+        Ok(())
+    }
+}
+
+impl Writer {
+    fn run(self, rx: Receiver<Message>) {
+        let _span = info_span!("influx", url = %self.url).entered();
+        let mut pending: Vec<String> = Vec::with_capacity(self.batch_size);
+        let mut last_write = Instant::now();
+        let mut retry = RETRY_MIN;
+        let mut retry_at = Instant::now();
+
+        loop {
+            let wait = self.flush_interval.saturating_sub(last_write.elapsed());
+            match rx.recv_timeout(wait) {
+                Ok(Message::Line(line)) => {
+                    pending.push(line);
+                    if pending.len() > self.max_buffer {
+                        let excess = pending.len() - self.max_buffer;
+                        pending.drain(..excess);
+                        warn!("Buffer full, dropped {} samples", excess);
+                    }
+                }
+                Ok(Message::Flush(ack)) => {
+                    if self.send(&pending).is_ok() {
+                        pending.clear();
+                    }
+                    ack.send(()).ok();
+                    continue;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.send(&pending).ok();
+                    break;
+                }
+            }
+
+            let due =
+                pending.len() >= self.batch_size || last_write.elapsed() >= self.flush_interval;
+            if due && !pending.is_empty() && Instant::now() >= retry_at {
+                last_write = Instant::now();
+                match self.send(&pending) {
+                    Ok(()) => {
+                        pending.clear();
+                        retry = RETRY_MIN;
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Write of {} samples failed: {}, retrying in {:?}",
+                            pending.len(),
+                            e,
+                            retry
+                        );
+                        retry_at = Instant::now() + retry;
+                        retry = (retry * 2).min(RETRY_MAX);
+                    }
+                }
+            }
+        }
+    }
+
+    fn send(&self, lines: &[String]) -> Result<(), Box<dyn Error>> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        let mut request = ureq::post(&self.url)
+            .query("org", &self.org)
+            .query("bucket", &self.bucket)
+            .query("precision", "ns")
+            .set("Content-Type", "text/plain; charset=utf-8")
+            .timeout(Duration::from_secs(10));
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("Token {}", token));
+        }
+        request.send_string(&lines.join("\n"))?;
+        Ok(())
+    }
+}
+
+fn prefix(measurement: &str, tags: &BTreeMap<String, String>) -> String {
+    let mut prefix = measurement.replace(',', "\\,").replace(' ', "\\ ");
+    for (key, value) in tags {
+        prefix.push(',');
+        prefix.push_str(&escape_tag(key));
+        prefix.push('=');
+        prefix.push_str(&escape_tag(value));
+    }
+    prefix
+}
+
+/// Tag keys and values escape commas, equals signs and spaces.
+fn escape_tag(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
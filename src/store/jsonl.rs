@@ -0,0 +1,98 @@
+//! JSON Lines log, one object per sample, easy to ship to log pipelines:
+//!
+//! ```text
+//! {"ts":"2024-05-03T12:00:00.123Z","topic":"pressure/data","pressure_pa":101325.0}
+//! ```
+
+use super::Store;
+use crate::config::RotationConfig;
+use crate::rotate::Rotation;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::json;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+pub struct JsonlStore {
+    path: PathBuf,
+    /// Closed while rotating, reopened by the next write
+    writer: Option<BufWriter<File>>,
+    rotation: Rotation,
+    flush_interval: Duration,
+    last_flush: Instant,
+}
+
+impl JsonlStore {
+    /// Appends to `path`.
+    pub fn open(
+        path: &Path,
+        flush_interval: Duration,
+        rotation: &RotationConfig,
+    ) -> Result<JsonlStore, Box<dyn Error>> {
+        Ok(JsonlStore {
+            path: path.to_path_buf(),
+            writer: Some(open_writer(path)?),
+            rotation: Rotation::new(path, rotation),
+            flush_interval,
+            last_flush: Instant::now(),
+        })
+    }
+
+    fn writer(&mut self) -> Result<&mut BufWriter<File>, Box<dyn Error>> {
+        let writer = match self.writer.take() {
+            Some(writer) => writer,
+            None => open_writer(&self.path)?,
+        };
+        Ok(self.writer.insert(writer))
+    }
+
+    fn rotate(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        self.rotation.rotate()
+    }
+}
+
+fn open_writer(path: &Path) -> Result<BufWriter<File>, Box<dyn Error>> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Cannot open log file {}: {}", path.display(), e))?;
+    Ok(BufWriter::new(file))
+}
+
+impl Store for JsonlStore {
+    fn write(&mut self, ts: SystemTime, topic: &str, value: f64) -> Result<(), Box<dyn Error>> {
+        if self.rotation.new_day() {
+            self.rotate()?;
+        }
+
+        let line = json!({
+            "ts": DateTime::<Utc>::from(ts).to_rfc3339_opts(SecondsFormat::Millis, true),
+            "topic": topic,
+            "pressure_pa": value,
+        });
+        writeln!(self.writer()?, "{}", line)?;
+
+        if self.last_flush.elapsed() >= self.flush_interval {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(writer) = &mut self.writer {
+            writer.flush()?;
+            let size = writer.get_ref().metadata()?.len();
+            if self.rotation.oversized(size) {
+                self.rotate()?;
+            }
+        }
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+}
@@ -0,0 +1,19 @@
+//! Persistent sample storage backends.
+
+use std::error::Error;
+use std::time::SystemTime;
+
+pub mod influx;
+pub mod jsonl;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod ring;
+pub mod sqlite;
+
+/// Receives every sample, in Pa, as it arrives.
+pub trait Store {
+    fn write(&mut self, ts: SystemTime, topic: &str, value: f64) -> Result<(), Box<dyn Error>>;
+
+    /// Writes out anything still buffered.
+    fn flush(&mut self) -> Result<(), Box<dyn Error>>;
+}
@@ -0,0 +1,121 @@
+//! Parquet copy of a session, for analysis in e.g. pandas. One row per
+//! sample:
+//!
+//! | column    | type                      |                                   |
+//! |-----------|---------------------------|-----------------------------------|
+//! | timestamp | timestamp[ms], UTC        |                                   |
+//! | sensor    | string                    | the series                        |
+//! | raw       | double                    | Pa, before any filter             |
+//! | filtered  | double, null without one  | Pa, through `[filter]`            |
+//! | quality   | uint8                     | `session::QUALITY_*` bits         |
+//!
+//! Rows are handed to the writer `ROW_GROUP` at a time. The file is only
+//! readable once closed, the CSV next to it is kept as it goes.
+
+use arrow::array::{ArrayRef, Float64Array, StringArray, TimestampMillisecondArray, UInt8Array};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Samples buffered before they are written out.
+const ROW_GROUP: usize = 100_000;
+
+#[derive(Default)]
+struct Rows {
+    timestamp: Vec<i64>,
+    sensor: Vec<String>,
+    raw: Vec<f64>,
+    filtered: Vec<Option<f64>>,
+    quality: Vec<u8>,
+}
+
+pub struct ParquetWriter {
+    writer: ArrowWriter<File>,
+    schema: Arc<Schema>,
+    rows: Rows,
+}
+
+impl ParquetWriter {
+    /// Creates `path`, replacing any file there.
+    pub fn create(path: &Path) -> Result<ParquetWriter, Box<dyn Error>> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new(
+                "timestamp",
+                DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".to_string())),
+                false,
+            ),
+            Field::new("sensor", DataType::Utf8, false),
+            Field::new("raw", DataType::Float64, false),
+            Field::new("filtered", DataType::Float64, true),
+            Field::new("quality", DataType::UInt8, false),
+        ]));
+        let file =
+            File::create(path).map_err(|e| format!("Cannot create {}: {}", path.display(), e))?;
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+
+        Ok(ParquetWriter {
+            writer: ArrowWriter::try_new(file, schema.clone(), Some(properties))?,
+            schema,
+            rows: Rows::default(),
+        })
+    }
+
+    pub fn write(
+        &mut self,
+        ts: SystemTime,
+        sensor: &str,
+        raw: f64,
+        filtered: Option<f64>,
+        quality: u8,
+    ) -> Result<(), Box<dyn Error>> {
+        let millis = ts
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        self.rows.timestamp.push(millis as i64);
+        self.rows.sensor.push(sensor.to_string());
+        self.rows.raw.push(raw);
+        self.rows.filtered.push(filtered);
+        self.rows.quality.push(quality);
+
+        if self.rows.raw.len() >= ROW_GROUP {
+            self.write_rows()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the rows still buffered and the footer.
+    pub fn close(mut self) -> Result<(), Box<dyn Error>> {
+        self.write_rows()?;
+        self.writer.close()?;
+        Ok(())
+    }
+
+    fn write_rows(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.rows.raw.is_empty() {
+            return Ok(());
+        }
+        let rows = std::mem::take(&mut self.rows);
+        let timestamp =
+            TimestampMillisecondArray::from(rows.timestamp).with_timezone("UTC".to_string());
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(timestamp),
+            Arc::new(StringArray::from(rows.sensor)),
+            Arc::new(Float64Array::from(rows.raw)),
+            Arc::new(Float64Array::from(rows.filtered)),
+            Arc::new(UInt8Array::from(rows.quality)),
+        ];
+        self.writer
+            .write(&RecordBatch::try_new(self.schema.clone(), columns)?)?;
+        Ok(())
+    }
+}
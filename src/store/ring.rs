@@ -0,0 +1,144 @@
+//! The latest hours of samples on disk, reloaded into the chart on startup so
+//! a restart or a crash doesn't wipe the recent history. An append-only CSV
+//! in the `[log] file` format, cut down to the hours kept once its oldest
+//! sample is twice as old.
+
+use super::Store;
+use crate::source::replay;
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub struct RingStore {
+    path: PathBuf,
+    keep: Duration,
+    /// Closed while compacting, reopened by the next write
+    writer: Option<csv::Writer<File>>,
+    /// Of the samples in the file, `None` while it has none
+    oldest: Option<SystemTime>,
+    flush_interval: Duration,
+    last_flush: Instant,
+}
+
+impl RingStore {
+    /// Opens `path`, dropping what is older than `keep`.
+    pub fn open(
+        path: &Path,
+        keep: Duration,
+        flush_interval: Duration,
+    ) -> Result<RingStore, Box<dyn Error>> {
+        let oldest = compact(path, keep)?;
+        Ok(RingStore {
+            path: path.to_path_buf(),
+            keep,
+            writer: Some(open_writer(path)?),
+            oldest,
+            flush_interval,
+            last_flush: Instant::now(),
+        })
+    }
+
+    /// Samples of the last `keep`, oldest first, as `(timestamp, topic, Pa)`.
+    pub fn recent(&self) -> Result<Vec<(SystemTime, String, f64)>, Box<dyn Error>> {
+        let since = cutoff(self.keep);
+        Ok(replay::load(&self.path)?
+            .into_iter()
+            .filter(|r| r.ts >= since)
+            .map(|r| (r.ts, r.topic, r.value))
+            .collect())
+    }
+
+    fn writer(&mut self) -> Result<&mut csv::Writer<File>, Box<dyn Error>> {
+        let writer = match self.writer.take() {
+            Some(writer) => writer,
+            None => open_writer(&self.path)?,
+        };
+        Ok(self.writer.insert(writer))
+    }
+}
+
+impl Store for RingStore {
+    fn write(&mut self, ts: SystemTime, topic: &str, value: f64) -> Result<(), Box<dyn Error>> {
+        let unix = ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.writer()?.write_record(&[
+            format!("{:.3}", unix.as_secs_f64()),
+            topic.to_string(),
+            value.to_string(),
+        ])?;
+        self.oldest.get_or_insert(ts);
+
+        if self.last_flush.elapsed() >= self.flush_interval {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(writer) = &mut self.writer {
+            writer.flush()?;
+        }
+        self.last_flush = Instant::now();
+
+        if self
+            .oldest
+            .is_some_and(|oldest| oldest < cutoff(self.keep * 2))
+        {
+            self.writer = None;
+            self.oldest = compact(&self.path, self.keep)?;
+        }
+        Ok(())
+    }
+}
+
+fn cutoff(age: Duration) -> SystemTime {
+    SystemTime::now().checked_sub(age).unwrap_or(UNIX_EPOCH)
+}
+
+/// Rewrites `path` with only the samples of the last `keep`, returning the
+/// time of the oldest one left.
+fn compact(path: &Path, keep: Duration) -> Result<Option<SystemTime>, Box<dyn Error>> {
+    if !fs::metadata(path).is_ok_and(|m| m.len() > 0) {
+        return Ok(None);
+    }
+    let since = cutoff(keep);
+    let records: Vec<_> = replay::load(path)?
+        .into_iter()
+        .filter(|r| r.ts >= since)
+        .collect();
+
+    // Replaced in one go, a crash meanwhile leaves the old file
+    let tmp = path.with_extension("tmp");
+    let mut writer = csv::Writer::from_path(&tmp)
+        .map_err(|e| format!("Cannot write {}: {}", tmp.display(), e))?;
+    writer.write_record(["Time(unix s)", "Topic", "Pressure(Pa)"])?;
+    for r in &records {
+        let unix = r.ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+        writer.write_record(&[
+            format!("{:.3}", unix.as_secs_f64()),
+            r.topic.clone(),
+            r.value.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&tmp, path).map_err(|e| format!("Cannot replace {}: {}", path.display(), e))?;
+
+    Ok(records.first().map(|r| r.ts))
+}
+
+fn open_writer(path: &Path) -> Result<csv::Writer<File>, Box<dyn Error>> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Cannot open history {}: {}", path.display(), e))?;
+    let is_new = file.metadata()?.len() == 0;
+
+    let mut writer = csv::Writer::from_writer(file);
+    if is_new {
+        writer.write_record(["Time(unix s)", "Topic", "Pressure(Pa)"])?;
+        writer.flush()?;
+    }
+    Ok(writer)
+}
@@ -0,0 +1,111 @@
+//! SQLite storage. Samples are buffered and inserted in one transaction per
+//! batch, which keeps up with high sample rates on slow SD cards.
+
+use super::Store;
+use rusqlite::{params, Connection};
+use std::error::Error;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::error;
+
+pub struct SqliteStore {
+    conn: Connection,
+    pending: Vec<(f64, String, f64)>,
+    batch_size: usize,
+    flush_interval: Duration,
+    last_flush: Instant,
+}
+
+impl SqliteStore {
+    pub fn open(
+        path: &Path,
+        batch_size: usize,
+        flush_interval: Duration,
+    ) -> Result<SqliteStore, Box<dyn Error>> {
+        let conn = Connection::open(path)
+            .map_err(|e| format!("Cannot open database {}: {}", path.display(), e))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS samples (
+                ts REAL NOT NULL,
+                topic TEXT NOT NULL,
+                value REAL NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS samples_ts ON samples (ts);",
+        )?;
+
+        Ok(SqliteStore {
+            conn,
+            pending: Vec::with_capacity(batch_size),
+            batch_size: batch_size.max(1),
+            flush_interval,
+            last_flush: Instant::now(),
+        })
+    }
+
+    /// Samples stored during the last `span`, oldest first, as
+    /// `(timestamp, topic, Pa)`.
+    pub fn recent(&self, span: Duration) -> Result<Vec<(SystemTime, String, f64)>, Box<dyn Error>> {
+        let since = SystemTime::now()
+            .checked_sub(span)
+            .unwrap_or(UNIX_EPOCH)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT ts, topic, value FROM samples WHERE ts >= ?1 ORDER BY ts")?;
+        let rows = stmt.query_map(params![since], |row| {
+            let ts: f64 = row.get(0)?;
+            Ok((
+                UNIX_EPOCH + Duration::from_secs_f64(ts.max(0.0)),
+                row.get(1)?,
+                row.get(2)?,
+            ))
+        })?;
+
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+}
+
+impl Store for SqliteStore {
+    fn write(&mut self, ts: SystemTime, topic: &str, value: f64) -> Result<(), Box<dyn Error>> {
+        let unix = ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.pending
+            .push((unix.as_secs_f64(), topic.to_string(), value));
+
+        if self.pending.len() >= self.batch_size || self.last_flush.elapsed() >= self.flush_interval
+        {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.last_flush = Instant::now();
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt =
+                tx.prepare_cached("INSERT INTO samples (ts, topic, value) VALUES (?1, ?2, ?3)")?;
+            for (ts, topic, value) in &self.pending {
+                stmt.execute(params![ts, topic, value])?;
+            }
+        }
+        tx.commit()?;
+
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl Drop for SqliteStore {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            error!("Cannot write samples to the database: {}", e);
+        }
+    }
+}
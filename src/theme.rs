@@ -0,0 +1,105 @@
+//! Color themes. A preset gives every color, the `[colors]` config
+//! overrides single ones and assigns colors to topics.
+
+use crate::config::ColorConfig;
+use crate::decode;
+use plotters::style::RGBColor;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    /// Green on black, as on a scope
+    Dark,
+    /// Dark on white, for printed reports
+    Light,
+}
+
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub background: RGBColor,
+    /// Axes, labels and the text of the overlays
+    pub axis: RGBColor,
+    /// Series colors by order of appearance
+    pub palette: Vec<RGBColor>,
+    /// Raised alarms
+    pub alarm: RGBColor,
+    /// Paused and reconnecting states
+    pub warning: RGBColor,
+    /// The connected state
+    pub ok: RGBColor,
+    /// Topic filters with their own color, the first match in key order wins
+    series: Vec<(String, RGBColor)>,
+}
+
+impl Theme {
+    pub fn preset(preset: Preset) -> Theme {
+        match preset {
+            Preset::Dark => Theme {
+                background: RGBColor(0, 0, 0),
+                axis: RGBColor(0, 255, 0),
+                palette: vec![
+                    RGBColor(0, 255, 0),
+                    RGBColor(0, 255, 255),
+                    RGBColor(255, 0, 255),
+                    RGBColor(255, 255, 0),
+                    RGBColor(255, 128, 0),
+                    RGBColor(128, 128, 255),
+                    RGBColor(255, 255, 255),
+                ],
+                alarm: RGBColor(255, 0, 0),
+                warning: RGBColor(255, 255, 0),
+                ok: RGBColor(0, 255, 0),
+                series: Vec::new(),
+            },
+            // Darker shades, the bright ones vanish on white
+            Preset::Light => Theme {
+                background: RGBColor(255, 255, 255),
+                axis: RGBColor(40, 40, 40),
+                palette: vec![
+                    RGBColor(0, 90, 200),
+                    RGBColor(0, 140, 60),
+                    RGBColor(150, 0, 150),
+                    RGBColor(200, 100, 0),
+                    RGBColor(0, 140, 140),
+                    RGBColor(110, 80, 40),
+                    RGBColor(0, 0, 0),
+                ],
+                alarm: RGBColor(210, 0, 0),
+                warning: RGBColor(190, 120, 0),
+                ok: RGBColor(0, 150, 0),
+                series: Vec::new(),
+            },
+        }
+    }
+
+    pub fn new(config: &ColorConfig) -> Theme {
+        let mut theme = Theme::preset(config.theme);
+        if let Some(background) = config.background {
+            theme.background = background.rgb();
+        }
+        if let Some(axis) = config.axis {
+            theme.axis = axis.rgb();
+        }
+        if let Some(trace) = config.trace {
+            theme.palette[0] = trace.rgb();
+        }
+        theme.series = config
+            .series
+            .iter()
+            .map(|(filter, color)| (filter.clone(), color.rgb()))
+            .collect();
+        theme
+    }
+
+    /// The configured color of `topic`, or the next one of the palette for
+    /// the `index`th series.
+    pub fn series_color(&self, index: usize, topic: &str) -> RGBColor {
+        self.series
+            .iter()
+            .find(|(filter, _)| decode::topic_matches(filter, topic))
+            .map_or(self.palette[index % self.palette.len()], |&(_, color)| {
+                color
+            })
+    }
+}
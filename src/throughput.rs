@@ -0,0 +1,141 @@
+//! Counters of the status bar, telling where a sluggish chart loses time:
+//! samples published long before they arrive point at the network, a
+//! growing channel backlog at decoding and recording, a low frame rate or a
+//! long wait from arrival to drawing at rendering.
+//!
+//! Times are in seconds on the `Clock` time line.
+
+use std::time::{Duration, Instant};
+
+/// Sum and count of seconds.
+#[derive(Debug, Default, Clone, Copy)]
+struct Mean {
+    sum: f64,
+    count: u64,
+}
+
+impl Mean {
+    fn add(&mut self, seconds: f64) {
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    fn get(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
+
+    /// Adds the seconds from each of the times in `since` to `now`, and
+    /// empties it.
+    fn add_until(&mut self, since: &mut Mean, now: f64) {
+        self.sum += since.count as f64 * now - since.sum;
+        self.count += since.count;
+        *since = Mean::default();
+    }
+}
+
+/// One second of counts.
+#[derive(Debug, Clone, Copy)]
+pub struct Summary {
+    pub fps: f64,
+    pub samples_per_sec: f64,
+    /// Most samples waiting in the channel at once
+    pub backlog: usize,
+    /// Mean seconds from publishing to drawn, of the samples with a sensor
+    /// timestamp
+    pub latency: Option<f64>,
+    /// Mean seconds from arrival to drawn
+    pub draw_latency: Option<f64>,
+}
+
+impl Summary {
+    pub fn text(&self) -> String {
+        let ms = |seconds: Option<f64>| match seconds {
+            Some(seconds) => format!("{:.0} ms", seconds * 1000.0),
+            None => "-".to_string(),
+        };
+        format!(
+            "{:.1} fps  {:.0} samples/s  backlog {}  latency {}  arrival to drawn {}",
+            self.fps,
+            self.samples_per_sec,
+            self.backlog,
+            ms(self.latency),
+            ms(self.draw_latency)
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct Throughput {
+    since: Instant,
+    frames: u32,
+    samples: u64,
+    backlog: usize,
+    /// Times of the samples not drawn yet
+    published: Mean,
+    arrived: Mean,
+    latency: Mean,
+    draw_latency: Mean,
+}
+
+impl Default for Throughput {
+    fn default() -> Self {
+        Throughput {
+            since: Instant::now(),
+            frames: 0,
+            samples: 0,
+            backlog: 0,
+            published: Mean::default(),
+            arrived: Mean::default(),
+            latency: Mean::default(),
+            draw_latency: Mean::default(),
+        }
+    }
+}
+
+impl Throughput {
+    /// `queued` samples waited in the channel when it was drained.
+    pub fn queued(&mut self, queued: usize) {
+        self.backlog = self.backlog.max(queued);
+    }
+
+    /// A sample taken from the channel at `arrived`, published at
+    /// `published` if the sensor timestamped it.
+    pub fn received(&mut self, arrived: f64, published: Option<f64>) {
+        self.samples += 1;
+        self.arrived.add(arrived);
+        if let Some(published) = published {
+            self.published.add(published);
+        }
+    }
+
+    /// A frame was drawn at `now`, with the samples received so far.
+    pub fn drawn(&mut self, now: f64) {
+        self.frames += 1;
+        self.latency.add_until(&mut self.published, now);
+        self.draw_latency.add_until(&mut self.arrived, now);
+    }
+
+    /// The counts of the last second once it is over, starting the next.
+    pub fn summary(&mut self) -> Option<Summary> {
+        let elapsed = self.since.elapsed();
+        if elapsed < Duration::from_secs(1) {
+            return None;
+        }
+        let seconds = elapsed.as_secs_f64();
+        let summary = Summary {
+            fps: self.frames as f64 / seconds,
+            samples_per_sec: self.samples as f64 / seconds,
+            backlog: self.backlog,
+            latency: self.latency.get(),
+            draw_latency: self.draw_latency.get(),
+        };
+
+        // Samples waiting for a frame count towards the next second
+        *self = Throughput {
+            published: self.published,
+            arrived: self.arrived,
+            ..Throughput::default()
+        };
+        Some(summary)
+    }
+}
@@ -0,0 +1,110 @@
+//! The in-window topic list, key F3, to subscribe to sensors coming online
+//! mid-test and drop those that went away, without a restart. The samples
+//! received so far stay on the chart.
+
+use crate::source::Topics;
+use minifb::{InputCallback, Window};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+#[derive(Debug)]
+pub struct TopicList {
+    /// Of the source, `None` for those without topics
+    topics: Option<Topics>,
+    selected: usize,
+    /// The topic being typed
+    pub entry: String,
+    /// Outcome of the last change, shown below the list
+    pub message: Option<String>,
+}
+
+impl TopicList {
+    pub fn new(topics: Option<Topics>) -> TopicList {
+        let message = topics
+            .is_none()
+            .then(|| "This source has no topics".to_string());
+        TopicList {
+            topics,
+            selected: 0,
+            entry: String::new(),
+            message,
+        }
+    }
+
+    pub fn rows(&self) -> Vec<String> {
+        self.topics.as_ref().map(Topics::list).unwrap_or_default()
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn select_next(&mut self) {
+        let n = self.rows().len().max(1);
+        self.selected = (self.selected + 1) % n;
+    }
+
+    pub fn select_prev(&mut self) {
+        let n = self.rows().len().max(1);
+        self.selected = (self.selected + n - 1) % n;
+    }
+
+    /// Control characters, e.g. those of Backspace and Enter, are left to
+    /// the keys.
+    pub fn type_char(&mut self, c: char) {
+        if !c.is_control() {
+            self.entry.push(c);
+        }
+    }
+
+    pub fn erase(&mut self) {
+        self.entry.pop();
+    }
+
+    /// Subscribes to the typed topic.
+    pub fn add(&mut self) {
+        let Some(topics) = &self.topics else {
+            return;
+        };
+        let topic = self.entry.trim();
+        if topic.is_empty() {
+            return;
+        }
+        self.message = Some(if topics.subscribe(topic) {
+            format!("Subscribed to {}", topic)
+        } else {
+            format!("Already subscribed to {}", topic)
+        });
+        self.entry.clear();
+    }
+
+    /// Unsubscribes from the selected topic.
+    pub fn remove(&mut self) {
+        let Some(topics) = &self.topics else {
+            return;
+        };
+        if let Some(topic) = self.rows().get(self.selected) {
+            if topics.unsubscribe(topic) {
+                self.message = Some(format!("Unsubscribed from {}", topic));
+            }
+        }
+        self.selected = self.selected.min(self.rows().len().saturating_sub(1));
+    }
+}
+
+/// Hands the characters typed into the window to the monitor loop.
+struct Typed(Sender<char>);
+
+impl InputCallback for Typed {
+    fn add_char(&mut self, uni_char: u32) {
+        if let Some(c) = char::from_u32(uni_char) {
+            self.0.send(c).ok();
+        }
+    }
+}
+
+/// The characters typed into `window` from now on.
+pub fn typed(window: &mut Window) -> Receiver<char> {
+    let (tx, rx) = mpsc::channel();
+    window.set_input_callback(Box::new(Typed(tx)));
+    rx
+}
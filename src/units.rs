@@ -0,0 +1,96 @@
+//! Pressure units. Samples are kept in Pa and converted for display.
+
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PressureUnit {
+    #[default]
+    Pa,
+    KPa,
+    Bar,
+    Psi,
+    MmHg,
+}
+
+impl PressureUnit {
+    pub const ALL: [PressureUnit; 5] = [
+        PressureUnit::Pa,
+        PressureUnit::KPa,
+        PressureUnit::Bar,
+        PressureUnit::Psi,
+        PressureUnit::MmHg,
+    ];
+
+    /// Pascal per one of this unit
+    fn pascals(self) -> f64 {
+        match self {
+            PressureUnit::Pa => 1.0,
+            PressureUnit::KPa => 1_000.0,
+            PressureUnit::Bar => 100_000.0,
+            PressureUnit::Psi => 6_894.757_293_168,
+            PressureUnit::MmHg => 133.322_387_415,
+        }
+    }
+
+    pub fn from_pa(self, pa: f64) -> f64 {
+        pa / self.pascals()
+    }
+
+    pub fn to_pa(self, value: f64) -> f64 {
+        value * self.pascals()
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            PressureUnit::Pa => "Pa",
+            PressureUnit::KPa => "kPa",
+            PressureUnit::Bar => "bar",
+            PressureUnit::Psi => "psi",
+            PressureUnit::MmHg => "mmHg",
+        }
+    }
+
+    /// The following unit, wrapping around.
+    pub fn next(self) -> PressureUnit {
+        let i = PressureUnit::ALL
+            .iter()
+            .position(|&u| u == self)
+            .unwrap_or(0);
+        PressureUnit::ALL[(i + 1) % PressureUnit::ALL.len()]
+    }
+
+    /// The preceding unit, wrapping around.
+    pub fn prev(self) -> PressureUnit {
+        let i = PressureUnit::ALL
+            .iter()
+            .position(|&u| u == self)
+            .unwrap_or(0);
+        PressureUnit::ALL[(i + PressureUnit::ALL.len() - 1) % PressureUnit::ALL.len()]
+    }
+
+    /// As written in the config file.
+    pub fn key(self) -> String {
+        self.symbol().to_lowercase()
+    }
+}
+
+impl fmt::Display for PressureUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+impl FromStr for PressureUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PressureUnit::ALL
+            .iter()
+            .copied()
+            .find(|u| u.symbol().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown unit {}, expected Pa, kPa, bar, psi or mmHg", s))
+    }
+}
@@ -0,0 +1,124 @@
+//! Zoom and pan of the chart.
+//!
+//! The chart follows the data until the user zooms or pans, from then on
+//! it shows a fixed range until reset.
+
+use std::ops::Range;
+
+/// Axis ranges in chart coordinates, seconds and the display unit.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Bounds {
+    pub x: (f64, f64),
+    pub y: (f64, f64),
+}
+
+#[derive(Debug, Default)]
+pub struct View {
+    manual: Option<Bounds>,
+    /// What the last frame showed, and where in pixels
+    shown: Bounds,
+    area: (Range<i32>, Range<i32>),
+}
+
+impl View {
+    /// The ranges to draw, `live` unless zoomed or panned.
+    pub fn bounds(&self, live: Bounds) -> Bounds {
+        self.manual.unwrap_or(live)
+    }
+
+    /// Records the ranges and plotting area of the frame just drawn, which
+    /// the next zoom or pan starts from.
+    pub fn drawn(&mut self, shown: Bounds, area: (Range<i32>, Range<i32>)) {
+        self.shown = shown;
+        self.area = area;
+    }
+
+    /// Following the data, not zoomed or panned.
+    pub fn is_live(&self) -> bool {
+        self.manual.is_none()
+    }
+
+    /// The ranges the last frame showed.
+    pub fn shown(&self) -> Bounds {
+        self.shown
+    }
+
+    /// Whether the pixel lies in the plotting area of the last frame.
+    pub fn contains(&self, (px, py): (i32, i32)) -> bool {
+        self.area.0.contains(&px) && self.area.1.contains(&py)
+    }
+
+    /// Moves a zoomed or panned range along the time axis by `dx`.
+    pub fn shift(&mut self, dx: f64) {
+        if let Some(manual) = &mut self.manual {
+            manual.x = (manual.x.0 + dx, manual.x.1 + dx);
+            self.shown = *manual;
+        }
+    }
+
+    /// Back to following the data.
+    pub fn reset(&mut self) {
+        self.manual = None;
+    }
+
+    /// Scales both spans by `factor`, below 1 zooms in. The point under
+    /// `at`, or the center without one, stays in place.
+    pub fn zoom(&mut self, factor: f64, at: Option<(i32, i32)>) {
+        let Bounds { x, y } = self.shown;
+        let (cx, cy) = match at {
+            Some(pos) => self.to_chart(pos),
+            None => ((x.0 + x.1) / 2.0, (y.0 + y.1) / 2.0),
+        };
+        let scale = |(lo, hi): (f64, f64), c: f64| (c + (lo - c) * factor, c + (hi - c) * factor);
+
+        self.set(Bounds {
+            x: scale(x, cx),
+            y: scale(y, cy),
+        });
+    }
+
+    /// Shifts by fractions of the spans, positive moves right and up.
+    pub fn pan(&mut self, fx: f64, fy: f64) {
+        let Bounds { x, y } = self.shown;
+        let (dx, dy) = ((x.1 - x.0) * fx, (y.1 - y.0) * fy);
+
+        self.set(Bounds {
+            x: (x.0 + dx, x.1 + dx),
+            y: (y.0 + dy, y.1 + dy),
+        });
+    }
+
+    /// Drags the chart with the mouse from one pixel to another.
+    pub fn drag(&mut self, from: (i32, i32), to: (i32, i32)) {
+        let (width, height) = self.size();
+        self.pan(
+            (from.0 - to.0) as f64 / width,
+            (to.1 - from.1) as f64 / height,
+        );
+    }
+
+    fn set(&mut self, bounds: Bounds) {
+        // Keep the ranges drawable however far the user zooms in
+        if bounds.x.1 - bounds.x.0 > 1e-9 && bounds.y.1 - bounds.y.0 > 1e-12 {
+            self.shown = bounds;
+            self.manual = Some(bounds);
+        }
+    }
+
+    fn size(&self) -> (f64, f64) {
+        let (xs, ys) = &self.area;
+        (
+            (xs.end - xs.start).max(1) as f64,
+            (ys.end - ys.start).max(1) as f64,
+        )
+    }
+
+    /// Pixel to chart coordinates, see `ReverseCoordTranslate`.
+    fn to_chart(&self, (px, py): (i32, i32)) -> (f64, f64) {
+        let Bounds { x, y } = self.shown;
+        let (width, height) = self.size();
+        let fx = (px - self.area.0.start) as f64 / width;
+        let fy = (py - self.area.1.start) as f64 / height;
+        (x.0 + fx * (x.1 - x.0), y.1 - fy * (y.1 - y.0))
+    }
+}
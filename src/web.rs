@@ -0,0 +1,102 @@
+//! Small web dashboard: `/` serves a page plotting the live samples, which it
+//! receives as JSON over a WebSocket on `/ws`.
+
+use serde_json::json;
+use std::error::Error;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::debug;
+use tungstenite::Message;
+
+const INDEX_HTML: &str = include_str!("web.html");
+
+#[derive(Default)]
+pub struct WebServer {
+    clients: Arc<Mutex<Vec<Sender<String>>>>,
+}
+
+impl WebServer {
+    /// Binds `addr` and serves clients on background threads.
+    pub fn start(addr: &str) -> Result<WebServer, Box<dyn Error>> {
+        let listener =
+            TcpListener::bind(addr).map_err(|e| format!("Cannot listen on {}: {}", addr, e))?;
+        let server = WebServer::default();
+        let clients = server.clients.clone();
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let clients = clients.clone();
+                thread::spawn(move || {
+                    if let Err(e) = handle(stream, &clients) {
+                        debug!("Web client error: {}", e);
+                    }
+                });
+            }
+        });
+
+        Ok(server)
+    }
+
+    /// Forwards a sample to every connected browser.
+    pub fn publish(&self, ts: SystemTime, topic: &str, value: f64) {
+        let unix = ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let message = json!({
+            "ts": unix.as_secs_f64(),
+            "topic": topic,
+            "pressure_pa": value,
+        })
+        .to_string();
+
+        // Clients that went away have dropped their receiver
+        self.clients
+            .lock()
+            .unwrap()
+            .retain(|client| client.send(message.clone()).is_ok());
+    }
+}
+
+fn handle(
+    mut stream: TcpStream,
+    clients: &Mutex<Vec<Sender<String>>>,
+) -> Result<(), Box<dyn Error>> {
+    // Look at the request line without consuming it, the WebSocket handshake
+    // needs to read the whole request itself.
+    let mut head = [0u8; 512];
+    let n = stream.peek(&mut head)?;
+    let request = String::from_utf8_lossy(&head[..n]);
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+
+    if path == "/ws" {
+        let (tx, rx) = mpsc::channel();
+        clients.lock().unwrap().push(tx);
+        stream_samples(stream, rx)
+    } else {
+        stream.read_exact(&mut head[..n])?;
+
+        let (status, body) = if path == "/" {
+            ("200 OK", INDEX_HTML)
+        } else {
+            ("404 Not Found", "Not found")
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )?;
+        Ok(())
+    }
+}
+
+fn stream_samples(stream: TcpStream, rx: Receiver<String>) -> Result<(), Box<dyn Error>> {
+    let mut socket = tungstenite::accept(stream)?;
+    for message in rx {
+        socket.write_message(Message::Text(message))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,68 @@
+//! Alarm notifications POSTed as JSON to webhooks, e.g. of Slack, Teams or
+//! PagerDuty.
+//!
+//! Requests go out from a thread of their own, the render loop never waits
+//! for them. Failed ones are retried a few times with a growing delay.
+
+use crate::alarm::AlarmEvent;
+use crate::config::WebhookConfig;
+use serde_json::Value;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, info_span, warn};
+
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+pub struct Webhooks {
+    tx: Sender<Value>,
+}
+
+impl Webhooks {
+    pub fn start(config: &WebhookConfig) -> Webhooks {
+        let urls = config.urls.clone();
+        let timeout = Duration::from_secs_f64(config.timeout);
+        let retries = config.retries;
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || run(rx, &urls, timeout, retries));
+        Webhooks { tx }
+    }
+
+    pub fn notify(&self, event: &AlarmEvent) {
+        // Only fails once the thread is gone
+        self.tx.send(event.to_json()).ok();
+    }
+}
+
+fn run(rx: Receiver<Value>, urls: &[String], timeout: Duration, retries: u32) {
+    let _span = info_span!("webhook").entered();
+    for payload in rx {
+        let body = payload.to_string();
+        for url in urls {
+            post(url, &body, timeout, retries);
+        }
+    }
+}
+
+fn post(url: &str, body: &str, timeout: Duration, retries: u32) {
+    let mut delay = RETRY_DELAY;
+    for attempt in 0..=retries {
+        let result = ureq::post(url)
+            .set("Content-Type", "application/json")
+            .timeout(timeout)
+            .send_string(body);
+        match result {
+            Ok(_) => {
+                debug!("Notified {}", url);
+                return;
+            }
+            Err(e) if attempt < retries => {
+                warn!("POST to {} failed: {}, retrying in {:?}", url, e, delay);
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            Err(e) => warn!("POST to {} failed: {}, giving up", url, e),
+        }
+    }
+}